@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use k::cst_parser;
+use k::incremental::{Document, Edit};
+
+/// About 20,000 lines, made of copies of the samples.
+fn large_file() -> String {
+    let samples = [include_str!("../samples/fib.k"), include_str!("../samples/arithmetic.k")];
+    samples.iter().cycle().take(1000).map(|sample| format!("{}\n\n", sample)).collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let source = large_file();
+
+    c.bench_function("parse large file", |b| b.iter(|| cst_parser::parse(&source)));
+}
+
+fn edit(c: &mut Criterion) {
+    let source = large_file();
+    let mut document = Document::new(&source);
+
+    // Somewhere in the middle of the file, inside a function
+    let offset = source[source.len() / 2..].find("fib(n - 1)").unwrap() + source.len() / 2 + 4;
+
+    c.bench_function("type and delete a character in a function", |b| {
+        b.iter(|| {
+            document.apply(&Edit::new(offset..offset, "x"));
+            document.apply(&Edit::new(offset..offset + 1, ""));
+        })
+    });
+
+    c.bench_function("add and remove a line in a function", |b| {
+        b.iter(|| {
+            document.apply(&Edit::new(offset..offset, "\n"));
+            document.apply(&Edit::new(offset..offset + 1, ""));
+        })
+    });
+}
+
+criterion_group!(benches, parse, edit);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use k::lexer::Lexer;
+
+/// About 4 MB of source, made of copies of the samples.
+fn large_input() -> String {
+    let samples = [
+        include_str!("../samples/fib.k"),
+        include_str!("../samples/arithmetic.k"),
+        include_str!("../samples/hello.k"),
+    ];
+
+    samples.iter().cycle().take(12_000).map(|sample| format!("{}\n\n", sample)).collect()
+}
+
+fn lex(c: &mut Criterion) {
+    let input = large_input();
+    let mut group = c.benchmark_group("lexer");
+
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+
+    group.bench_function("tokens", |b| b.iter(|| Lexer::new(&input).count()));
+    group.bench_function("spanned tokens", |b| {
+        b.iter(|| {
+            let mut lexer = Lexer::new(&input);
+            let mut len = 0;
+
+            while let Some((_, text)) = lexer.next_spanned() {
+                len += text.len();
+            }
+
+            len
+        })
+    });
+    group.bench_function("lossless tokens", |b| b.iter(|| Lexer::tokenize_lossless(&input)));
+
+    group.finish();
+}
+
+criterion_group!(benches, lex);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+#![no_main]
+
+use k::builtins;
+use k::checker::Checker;
+use k::evaluator::{self, Evaluator};
+use k::lexer::Lexer;
+use k::parser::Parser;
+use k::resolver::Resolver;
+use libfuzzer_sys::fuzz_target;
+use std::thread;
+
+// Enough for small loops and recursion while keeping each run short
+const FUEL: usize = 100_000;
+
+fuzz_target!(|source: &str| {
+    let mut parser = Parser::new(Lexer::new(source));
+    let mut program = parser.parse();
+
+    if !parser.errors().is_empty()
+        || Resolver::new().resolve_program(&mut program).is_err()
+        || Checker::new().check_program(&mut program).is_err()
+    {
+        return;
+    }
+
+    // Runs on a thread big enough for the evaluator's deepest recursion,
+    // like the interpreter itself
+    let (_result, _output) = thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(move || builtins::capture_output(|| Evaluator::with_fuel(FUEL).run(&program)))
+        .unwrap()
+        .join()
+        .unwrap();
+});
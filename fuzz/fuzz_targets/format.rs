@@ -0,0 +1,37 @@
+#![no_main]
+
+use k::formatter::{format_source, FormatConfig};
+use k::lexer::Lexer;
+use k::parser::Parser;
+use libfuzzer_sys::fuzz_target;
+
+/// The syntax tree of a program with every position removed.
+fn strip_positions(source: &str) -> String {
+    let tree = format!("{:?}", Parser::new(Lexer::new(source)).parse());
+    let mut stripped = String::new();
+    let mut rest = tree.as_str();
+
+    while let Some(index) = rest.find("BufferPosition {") {
+        stripped.push_str(&rest[..index]);
+        rest = &rest[index..];
+        rest = &rest[rest.find('}').unwrap() + 1..];
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
+
+fuzz_target!(|source: &str| {
+    let config = FormatConfig::default();
+
+    // Only programs that parse can be formatted
+    let output = match format_source(source, &config) {
+        Ok(output) => output,
+        Err(_) => return,
+    };
+
+    let again = format_source(&output, &config).expect("formatted output should parse");
+
+    assert_eq!(output, again, "formatting is not idempotent");
+    assert_eq!(strip_positions(source), strip_positions(&output), "formatting changed the program");
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use k::lexer::Lexer;
+use k::token::TokenKind;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let mut lexer = Lexer::new(source);
+    while lexer.next_token().kind != TokenKind::EOF {}
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use k::cst_parser;
+use k::lexer::Lexer;
+use k::parser::Parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    Parser::new(Lexer::new(source)).parse();
+
+    // The concrete syntax tree must keep every byte of the input
+    let parse = cst_parser::parse(source);
+    assert_eq!(parse.syntax().text(), source);
+});
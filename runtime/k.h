@@ -0,0 +1,356 @@
+/*
+ * Runtime support for the C that `k build --target c` generates.
+ *
+ * Strings are immutable UTF-8 byte slices, and arrays are a length and a
+ * pointer to elements that are never written after they are filled in, so
+ * both can be copied freely. Nothing is ever freed.
+ */
+
+#ifndef K_RUNTIME_H
+#define K_RUNTIME_H
+
+#include <inttypes.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef uint32_t k_char;
+
+typedef struct {
+    const char *data;
+    int64_t len;
+} k_str;
+
+/* A string literal, which may contain null bytes. */
+#define K_STR(literal) ((k_str){ literal, sizeof(literal) - 1 })
+
+/* Stops the program with a runtime error. */
+static void k_trap(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "runtime error: %s\n", message);
+    exit(1);
+}
+
+static void *k_alloc(size_t size) {
+    void *memory = malloc(size ? size : 1);
+
+    if (!memory) {
+        k_trap("out of memory");
+    }
+
+    return memory;
+}
+
+static k_str k_copy(const char *data, int64_t len) {
+    char *copy = k_alloc((size_t) len);
+
+    memcpy(copy, data, (size_t) len);
+    return (k_str){ copy, len };
+}
+
+static void k_write(k_str s) {
+    fwrite(s.data, 1, (size_t) s.len, stdout);
+}
+
+/* Checked integer arithmetic, which stops the program on overflow. */
+#define K_INTEGER_OPS(name, type, min, max) \
+    static type k_add_##name(type a, type b) { \
+        if ((b > 0 && a > max - b) || (b < 0 && a < min - b)) k_trap("integer overflow"); \
+        return a + b; \
+    } \
+    static type k_sub_##name(type a, type b) { \
+        if ((b < 0 && a > max + b) || (b > 0 && a < min + b)) k_trap("integer overflow"); \
+        return a - b; \
+    } \
+    static type k_mul_##name(type a, type b) { \
+        bool overflow = a > 0 ? (b > 0 ? a > max / b : b < min / a) \
+                              : (b > 0 ? a < min / b : a != 0 && b < max / a); \
+        if (overflow) k_trap("integer overflow"); \
+        return a * b; \
+    } \
+    static type k_div_##name(type a, type b) { \
+        if (b == 0) k_trap("attempt to divide by zero"); \
+        if (a == min && b == -1) k_trap("integer overflow"); \
+        return a / b; \
+    } \
+    static type k_rem_##name(type a, type b) { \
+        if (b == 0) k_trap("attempt to divide by zero"); \
+        if (a == min && b == -1) k_trap("integer overflow"); \
+        return a % b; \
+    } \
+    static type k_neg_##name(type a) { \
+        if (a == min) k_trap("integer overflow"); \
+        return -a; \
+    } \
+    static type k_abs_##name(type a) { \
+        if (a == min) k_trap("integer overflow"); \
+        return a < 0 ? -a : a; \
+    }
+
+K_INTEGER_OPS(i32, int32_t, INT32_MIN, INT32_MAX)
+K_INTEGER_OPS(i64, int64_t, INT64_MIN, INT64_MAX)
+
+/* Checks an index into something `len` long. */
+static int64_t k_index(int64_t i, int64_t len) {
+    if (i < 0 || i >= len) {
+        char message[96];
+
+        snprintf(message, sizeof message, "index out of bounds: the length is %" PRId64 " but the index is %" PRId64,
+                 len, i);
+        k_trap(message);
+    }
+
+    return i;
+}
+
+/* Decodes the character starting at `s` and returns how many bytes it takes. */
+static int k_decode(const char *s, k_char *c) {
+    const unsigned char *u = (const unsigned char *) s;
+
+    if (u[0] < 0x80) {
+        *c = u[0];
+        return 1;
+    } else if (u[0] < 0xe0) {
+        *c = (k_char) (u[0] & 0x1f) << 6 | (u[1] & 0x3f);
+        return 2;
+    } else if (u[0] < 0xf0) {
+        *c = (k_char) (u[0] & 0x0f) << 12 | (k_char) (u[1] & 0x3f) << 6 | (u[2] & 0x3f);
+        return 3;
+    } else {
+        *c = (k_char) (u[0] & 0x07) << 18 | (k_char) (u[1] & 0x3f) << 12 | (k_char) (u[2] & 0x3f) << 6 | (u[3] & 0x3f);
+        return 4;
+    }
+}
+
+static int k_encode(k_char c, char *out) {
+    if (c < 0x80) {
+        out[0] = (char) c;
+        return 1;
+    } else if (c < 0x800) {
+        out[0] = (char) (0xc0 | c >> 6);
+        out[1] = (char) (0x80 | (c & 0x3f));
+        return 2;
+    } else if (c < 0x10000) {
+        out[0] = (char) (0xe0 | c >> 12);
+        out[1] = (char) (0x80 | (c >> 6 & 0x3f));
+        out[2] = (char) (0x80 | (c & 0x3f));
+        return 3;
+    } else {
+        out[0] = (char) (0xf0 | c >> 18);
+        out[1] = (char) (0x80 | (c >> 12 & 0x3f));
+        out[2] = (char) (0x80 | (c >> 6 & 0x3f));
+        out[3] = (char) (0x80 | (c & 0x3f));
+        return 4;
+    }
+}
+
+static k_str k_concat(k_str a, k_str b) {
+    char *data = k_alloc((size_t) (a.len + b.len));
+
+    memcpy(data, a.data, (size_t) a.len);
+    memcpy(data + a.len, b.data, (size_t) b.len);
+    return (k_str){ data, a.len + b.len };
+}
+
+static int k_str_compare(k_str a, k_str b) {
+    int order = memcmp(a.data, b.data, (size_t) (a.len < b.len ? a.len : b.len));
+
+    if (order != 0) {
+        return order;
+    }
+
+    return (a.len > b.len) - (a.len < b.len);
+}
+
+/* The number of characters in a string. */
+static int32_t k_str_len(k_str s) {
+    int32_t count = 0;
+
+    for (int64_t i = 0; i < s.len; i++) {
+        count += ((unsigned char) s.data[i] & 0xc0) != 0x80;
+    }
+
+    return count;
+}
+
+/* The character at index `i`, counting in characters rather than bytes. */
+static k_char k_str_index(k_str s, int64_t i) {
+    k_char c = 0;
+    int64_t offset = 0;
+
+    k_index(i, k_str_len(s));
+
+    for (int64_t count = 0; count <= i; count++) {
+        offset += k_decode(s.data + offset, &c);
+    }
+
+    return c;
+}
+
+#define K_PRIMITIVE_EQ(name, type) \
+    static bool k_eq_##name(type a, type b) { \
+        return a == b; \
+    }
+
+K_PRIMITIVE_EQ(i32, int32_t)
+K_PRIMITIVE_EQ(i64, int64_t)
+K_PRIMITIVE_EQ(f64, double)
+K_PRIMITIVE_EQ(bool, bool)
+K_PRIMITIVE_EQ(char, k_char)
+
+static bool k_eq_str(k_str a, k_str b) {
+    return a.len == b.len && memcmp(a.data, b.data, (size_t) a.len) == 0;
+}
+
+/*
+ * Converting values to strings. `display` is what `std.print` writes, and
+ * `inspect` is how a value is written inside an array, struct or variant.
+ */
+
+static k_str k_display_i32(int32_t value) {
+    char buffer[16];
+
+    return k_copy(buffer, snprintf(buffer, sizeof buffer, "%" PRId32, value));
+}
+
+static k_str k_display_i64(int64_t value) {
+    char buffer[24];
+
+    return k_copy(buffer, snprintf(buffer, sizeof buffer, "%" PRId64, value));
+}
+
+/*
+ * Writes the shortest digits that read back as the same value, in plain
+ * notation with at least one decimal place unless the value is very large
+ * or very small.
+ */
+static k_str k_display_f64(double value) {
+    char scientific[32], digits[24], buffer[64];
+    char *out = buffer;
+    const char *p = scientific;
+    int count = 0, exponent;
+
+    if (isnan(value)) {
+        return K_STR("NaN");
+    } else if (isinf(value)) {
+        return value > 0 ? K_STR("inf") : K_STR("-inf");
+    }
+
+    for (int precision = 0; precision < 17; precision++) {
+        snprintf(scientific, sizeof scientific, "%.*e", precision, value);
+
+        if (strtod(scientific, NULL) == value) {
+            break;
+        }
+    }
+
+    if (*p == '-') {
+        *out++ = *p++;
+    }
+
+    for (; *p != 'e'; p++) {
+        if (*p != '.') {
+            digits[count++] = *p;
+        }
+    }
+
+    exponent = atoi(p + 1);
+
+    if (value != 0 && (fabs(value) < 1e-4 || fabs(value) >= 1e16)) {
+        *out++ = digits[0];
+
+        if (count > 1) {
+            *out++ = '.';
+            memcpy(out, digits + 1, (size_t) count - 1);
+            out += count - 1;
+        }
+
+        out += sprintf(out, "e%d", exponent);
+    } else if (exponent >= 0) {
+        for (int i = 0; i <= exponent || i < count; i++) {
+            if (i == exponent + 1) {
+                *out++ = '.';
+            }
+
+            *out++ = i < count ? digits[i] : '0';
+        }
+
+        if (count <= exponent + 1) {
+            *out++ = '.';
+            *out++ = '0';
+        }
+    } else {
+        *out++ = '0';
+        *out++ = '.';
+
+        for (int i = -1; i > exponent; i--) {
+            *out++ = '0';
+        }
+
+        memcpy(out, digits, (size_t) count);
+        out += count;
+    }
+
+    return k_copy(buffer, out - buffer);
+}
+
+static k_str k_display_bool(bool value) {
+    return value ? K_STR("true") : K_STR("false");
+}
+
+static k_str k_display_char(k_char value) {
+    char buffer[4];
+
+    return k_copy(buffer, k_encode(value, buffer));
+}
+
+static k_str k_display_str(k_str value) {
+    return value;
+}
+
+#define k_inspect_i32 k_display_i32
+#define k_inspect_i64 k_display_i64
+#define k_inspect_f64 k_display_f64
+#define k_inspect_bool k_display_bool
+
+/* Quotes a string, escaping the quote and control characters. */
+static k_str k_escape(k_str s, char quote) {
+    char *buffer = k_alloc((size_t) s.len * 10 + 2);
+    char *out = buffer;
+    int64_t i = 0;
+
+    *out++ = quote;
+
+    while (i < s.len) {
+        k_char c;
+        int size = k_decode(s.data + i, &c);
+
+        if (c == '\0' || c == '\t' || c == '\r' || c == '\n' || c == '\\' || c == (k_char) quote) {
+            *out++ = '\\';
+            *out++ = c == '\0' ? '0' : c == '\t' ? 't' : c == '\r' ? 'r' : c == '\n' ? 'n' : (char) c;
+        } else if (c < 0x20 || (c >= 0x7f && c < 0xa0)) {
+            out += sprintf(out, "\\u{%x}", (unsigned) c);
+        } else {
+            memcpy(out, s.data + i, (size_t) size);
+            out += size;
+        }
+
+        i += size;
+    }
+
+    *out++ = quote;
+    return (k_str){ buffer, out - buffer };
+}
+
+static k_str k_inspect_str(k_str value) {
+    return k_escape(value, '"');
+}
+
+static k_str k_inspect_char(k_char value) {
+    return k_escape(k_display_char(value), '\'');
+}
+
+#endif
@@ -0,0 +1,315 @@
+# Runtime support for the x86-64 assembly that `k build --target x86-64`
+# generates, assembled next to it and linked into a static executable for
+# Linux. It talks to the kernel directly, so no C library is needed.
+#
+# Every function follows the System V calling convention. Strings are a
+# pointer to their length in bytes, as a quad, followed by their UTF-8
+# bytes. Memory is allocated by bumping a pointer through chunks mapped
+# with mmap, and never freed.
+
+    .set SYS_WRITE, 1
+    .set SYS_MMAP, 9
+    .set SYS_EXIT, 60
+    .set CHUNK_SIZE, 0x100000
+
+    .text
+
+# Runs the program's entry function, which the generated code names
+# `k_main`, and exits.
+    .globl _start
+_start:
+    xorl %ebp, %ebp
+    andq $-16, %rsp
+    call k_main
+    movl $SYS_EXIT, %eax
+    xorl %edi, %edi
+    syscall
+
+# Writes the %rdx bytes at %rsi to the file descriptor in %edi.
+k_write_all:
+    testq %rdx, %rdx
+    jle 2f
+    movl $SYS_WRITE, %eax
+    syscall
+    cmpq $-4, %rax
+    je k_write_all
+    testq %rax, %rax
+    jle 2f
+    addq %rax, %rsi
+    subq %rax, %rdx
+    jmp k_write_all
+2:
+    ret
+
+# Writes the string in %rdi to standard output.
+    .globl k_print
+k_print:
+    movq (%rdi), %rdx
+    leaq 8(%rdi), %rsi
+    movl $1, %edi
+    jmp k_write_all
+
+# Stops the program with the runtime error whose message is the string in
+# %rdi.
+    .globl k_trap
+k_trap:
+    movq %rdi, %rbx
+    movl $2, %edi
+    leaq k_runtime_error(%rip), %rsi
+    movl $15, %edx
+    call k_write_all
+    movl $2, %edi
+    leaq 8(%rbx), %rsi
+    movq (%rbx), %rdx
+    call k_write_all
+    movl $2, %edi
+    leaq k_newline(%rip), %rsi
+    movl $1, %edx
+    call k_write_all
+    movl $SYS_EXIT, %eax
+    movl $1, %edi
+    syscall
+
+# Checked arithmetic jumps to these when it fails.
+    .globl k_overflow
+k_overflow:
+    leaq k_str_integer_overflow(%rip), %rdi
+    jmp k_trap
+
+    .globl k_divide_by_zero
+k_divide_by_zero:
+    leaq k_str_divide_by_zero(%rip), %rdi
+    jmp k_trap
+
+# Allocates %rdi bytes, aligned to eight.
+    .globl k_alloc
+k_alloc:
+    addq $7, %rdi
+    andq $-8, %rdi
+    movq k_heap(%rip), %rax
+    leaq (%rax,%rdi), %rdx
+    cmpq k_heap_end(%rip), %rdx
+    ja 1f
+    movq %rdx, k_heap(%rip)
+    ret
+1:
+    # Maps a new chunk big enough for the allocation, leaving the rest of
+    # the old one unused.
+    movq %rdi, %rsi
+    cmpq $CHUNK_SIZE, %rsi
+    jae 2f
+    movq $CHUNK_SIZE, %rsi
+2:
+    pushq %rdi
+    pushq %rsi
+    xorl %edi, %edi
+    movl $3, %edx
+    movl $0x22, %r10d
+    movq $-1, %r8
+    xorl %r9d, %r9d
+    movl $SYS_MMAP, %eax
+    syscall
+    popq %rsi
+    popq %rdi
+    cmpq $-4095, %rax
+    jae 3f
+    leaq (%rax,%rsi), %rdx
+    movq %rdx, k_heap_end(%rip)
+    leaq (%rax,%rdi), %rdx
+    movq %rdx, k_heap(%rip)
+    ret
+3:
+    leaq k_str_out_of_memory(%rip), %rdi
+    jmp k_trap
+
+# Copies the %rsi bytes at %rdi into a new string.
+k_str_from:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    leaq 8(%rsi), %rdi
+    call k_alloc
+    movq %r12, (%rax)
+    leaq 8(%rax), %rdi
+    movq %rbx, %rsi
+    movq %r12, %rcx
+    rep movsb
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# Joins the strings in %rdi and %rsi.
+    .globl k_concat
+k_concat:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    movq %rdi, %r12
+    movq %rsi, %r13
+    movq (%rdi), %rbx
+    addq (%rsi), %rbx
+    leaq 8(%rbx), %rdi
+    call k_alloc
+    movq %rbx, (%rax)
+    leaq 8(%rax), %rdi
+    leaq 8(%r12), %rsi
+    movq (%r12), %rcx
+    rep movsb
+    leaq 8(%r13), %rsi
+    movq (%r13), %rcx
+    rep movsb
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# Whether the strings in %rdi and %rsi are equal, as 0 or 1.
+    .globl k_str_eq
+k_str_eq:
+    movq (%rdi), %rcx
+    cmpq (%rsi), %rcx
+    jne 1f
+    # Comparing no bytes leaves the flags of the lengths being equal
+    leaq 8(%rdi), %rdi
+    leaq 8(%rsi), %rsi
+    repe cmpsb
+    jne 1f
+    movl $1, %eax
+    ret
+1:
+    xorl %eax, %eax
+    ret
+
+# Compares the strings in %rdi and %rsi by their bytes, which orders them by
+# their characters, giving -1, 0 or 1.
+    .globl k_str_compare
+k_str_compare:
+    movq (%rdi), %r8
+    movq (%rsi), %r9
+    movq %r8, %rcx
+    cmpq %r9, %rcx
+    cmovaq %r9, %rcx
+    # cmpsb compares the byte at %rsi with the one at %rdi
+    movq %rdi, %rax
+    leaq 8(%rsi), %rdi
+    leaq 8(%rax), %rsi
+    testq %rcx, %rcx
+    jz 1f
+    repe cmpsb
+    je 1f
+    jb 2f
+    movl $1, %eax
+    ret
+1:
+    cmpq %r9, %r8
+    jb 2f
+    seta %al
+    movzbl %al, %eax
+    ret
+2:
+    movq $-1, %rax
+    ret
+
+# The number of characters in the string in %rdi.
+    .globl k_str_len
+k_str_len:
+    movq (%rdi), %rcx
+    leaq 8(%rdi), %rsi
+    xorl %eax, %eax
+1:
+    testq %rcx, %rcx
+    jz 3f
+    movzbl (%rsi), %edx
+    andl $0xc0, %edx
+    cmpl $0x80, %edx
+    je 2f
+    incq %rax
+2:
+    incq %rsi
+    decq %rcx
+    jmp 1b
+3:
+    ret
+
+# Writes the integer in %rdi in decimal. Also used for i32, which is kept
+# sign extended.
+    .globl k_display_i64
+k_display_i64:
+    subq $40, %rsp
+    movq %rdi, %rax
+    movq %rdi, %r8
+    leaq 32(%rsp), %rsi
+    testq %rax, %rax
+    jns 1f
+    # Negating the smallest i64 leaves it as is, which is still right as an
+    # unsigned number.
+    negq %rax
+1:
+    movl $10, %ecx
+2:
+    xorl %edx, %edx
+    divq %rcx
+    addb $'0', %dl
+    decq %rsi
+    movb %dl, (%rsi)
+    testq %rax, %rax
+    jnz 2b
+    testq %r8, %r8
+    jns 3f
+    decq %rsi
+    movb $'-', (%rsi)
+3:
+    movq %rsi, %rdi
+    leaq 32(%rsp), %rsi
+    subq %rdi, %rsi
+    call k_str_from
+    addq $40, %rsp
+    ret
+
+# Writes the boolean in %rdi as `true` or `false`.
+    .globl k_display_bool
+k_display_bool:
+    leaq k_str_true(%rip), %rax
+    testq %rdi, %rdi
+    jnz 1f
+    leaq k_str_false(%rip), %rax
+1:
+    ret
+
+    .data
+    .balign 8
+k_heap:
+    .quad 0
+k_heap_end:
+    .quad 0
+
+    .section .rodata
+    .balign 8
+k_str_true:
+    .quad 4
+    .ascii "true"
+    .balign 8
+k_str_false:
+    .quad 5
+    .ascii "false"
+    .balign 8
+k_str_integer_overflow:
+    .quad 16
+    .ascii "integer overflow"
+    .balign 8
+k_str_divide_by_zero:
+    .quad 25
+    .ascii "attempt to divide by zero"
+    .balign 8
+k_str_out_of_memory:
+    .quad 13
+    .ascii "out of memory"
+k_runtime_error:
+    .ascii "runtime error: "
+k_newline:
+    .ascii "\n"
+
+    .section .note.GNU-stack,"",@progbits
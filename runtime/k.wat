@@ -0,0 +1,1097 @@
+;; Runtime support for the WebAssembly that `k build --target wat` generates,
+;; pasted into every module it generates.
+;;
+;; Strings are a pointer to their length in bytes followed by their UTF-8
+;; bytes. Memory below address 64 is scratch space, and everything else is
+;; allocated by bumping `$k_heap` and never freed. The `$k_str_*` globals
+;; are strings the generator puts in the module's data for this runtime.
+
+(func $k_alloc (param $size i32) (result i32)
+  (local $ptr i32)
+  global.get $k_heap
+  local.set $ptr
+  global.get $k_heap
+  local.get $size
+  i32.add
+  i32.const 7
+  i32.add
+  i32.const -8
+  i32.and
+  global.set $k_heap
+  block $done
+    loop $grow
+      global.get $k_heap
+      memory.size
+      i32.const 16
+      i32.shl
+      i32.le_u
+      br_if $done
+      i32.const 1
+      memory.grow
+      i32.const -1
+      i32.eq
+      if
+        global.get $k_str_out_of_memory
+        call $k_trap
+      end
+      br $grow
+    end
+  end
+  local.get $ptr)
+
+;; A copy of the `size` bytes at `src`.
+(func $k_copy (param $src i32) (param $size i32) (result i32)
+  (local $ptr i32)
+  local.get $size
+  call $k_alloc
+  local.tee $ptr
+  local.get $src
+  local.get $size
+  memory.copy
+  local.get $ptr)
+
+;; Stops the program with a runtime error.
+(func $k_trap (param $message i32)
+  local.get $message
+  i32.const 4
+  i32.add
+  local.get $message
+  i32.load
+  call $k_host_trap
+  unreachable)
+
+(func $k_print (param $s i32)
+  local.get $s
+  i32.const 4
+  i32.add
+  local.get $s
+  i32.load
+  call $k_host_write)
+
+;; A string of the `len` bytes at `ptr`.
+(func $k_str_from (param $ptr i32) (param $len i32) (result i32)
+  (local $s i32)
+  local.get $len
+  i32.const 4
+  i32.add
+  call $k_alloc
+  local.tee $s
+  local.get $len
+  i32.store
+  local.get $s
+  i32.const 4
+  i32.add
+  local.get $ptr
+  local.get $len
+  memory.copy
+  local.get $s)
+
+(func $k_concat (param $a i32) (param $b i32) (result i32)
+  (local $s i32)
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.add
+  i32.const 4
+  i32.add
+  call $k_alloc
+  local.tee $s
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.add
+  i32.store
+  local.get $s
+  i32.const 4
+  i32.add
+  local.get $a
+  i32.const 4
+  i32.add
+  local.get $a
+  i32.load
+  memory.copy
+  local.get $s
+  i32.const 4
+  i32.add
+  local.get $a
+  i32.load
+  i32.add
+  local.get $b
+  i32.const 4
+  i32.add
+  local.get $b
+  i32.load
+  memory.copy
+  local.get $s)
+
+;; Compares strings byte by byte, giving -1, 0 or 1.
+(func $k_str_compare (param $a i32) (param $b i32) (result i32)
+  (local $i i32) (local $len i32) (local $x i32) (local $y i32)
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.lt_u
+  select
+  local.set $len
+  block $done
+    loop $next
+      local.get $i
+      local.get $len
+      i32.ge_u
+      br_if $done
+      local.get $a
+      local.get $i
+      i32.add
+      i32.load8_u offset=4
+      local.set $x
+      local.get $b
+      local.get $i
+      i32.add
+      i32.load8_u offset=4
+      local.set $y
+      local.get $x
+      local.get $y
+      i32.ne
+      if
+        local.get $x
+        local.get $y
+        i32.gt_u
+        local.get $x
+        local.get $y
+        i32.lt_u
+        i32.sub
+        return
+      end
+      local.get $i
+      i32.const 1
+      i32.add
+      local.set $i
+      br $next
+    end
+  end
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.gt_u
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.lt_u
+  i32.sub)
+
+(func $k_eq_str (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.eq
+  if (result i32)
+    local.get $a
+    local.get $b
+    call $k_str_compare
+    i32.eqz
+  else
+    i32.const 0
+  end)
+
+;; The number of characters in a string.
+(func $k_str_len (param $s i32) (result i32)
+  (local $i i32) (local $count i32)
+  block $done
+    loop $next
+      local.get $i
+      local.get $s
+      i32.load
+      i32.ge_u
+      br_if $done
+      local.get $s
+      local.get $i
+      i32.add
+      i32.load8_u offset=4
+      i32.const 0xc0
+      i32.and
+      i32.const 0x80
+      i32.ne
+      local.get $count
+      i32.add
+      local.set $count
+      local.get $i
+      i32.const 1
+      i32.add
+      local.set $i
+      br $next
+    end
+  end
+  local.get $count)
+
+;; Checks an index into something `len` long.
+(func $k_index (param $i i64) (param $len i32) (result i32)
+  local.get $i
+  i64.const 0
+  i64.lt_s
+  local.get $i
+  local.get $len
+  i64.extend_i32_u
+  i64.ge_s
+  i32.or
+  if
+    global.get $k_str_index_out_of_bounds
+    local.get $len
+    i64.extend_i32_u
+    call $k_display_i64
+    call $k_concat
+    global.get $k_str_but_the_index_is
+    call $k_concat
+    local.get $i
+    call $k_display_i64
+    call $k_concat
+    call $k_trap
+  end
+  local.get $i
+  i32.wrap_i64)
+
+;; The character at index `i`, counting in characters rather than bytes.
+(func $k_str_index (param $s i32) (param $i i64) (result i32)
+  (local $n i32) (local $p i32)
+  local.get $i
+  local.get $s
+  call $k_str_len
+  call $k_index
+  local.set $n
+  local.get $s
+  i32.const 4
+  i32.add
+  local.set $p
+  ;; Skip `n` characters by skipping bytes that start one
+  block $found
+    loop $next
+      local.get $p
+      i32.load8_u
+      i32.const 0xc0
+      i32.and
+      i32.const 0x80
+      i32.ne
+      if
+        local.get $n
+        i32.eqz
+        br_if $found
+        local.get $n
+        i32.const 1
+        i32.sub
+        local.set $n
+      end
+      local.get $p
+      i32.const 1
+      i32.add
+      local.set $p
+      br $next
+    end
+  end
+  local.get $p
+  call $k_decode)
+
+(func $k_decode (param $p i32) (result i32)
+  (local $byte i32)
+  local.get $p
+  i32.load8_u
+  local.tee $byte
+  i32.const 0x80
+  i32.lt_u
+  if
+    local.get $byte
+    return
+  end
+  local.get $byte
+  i32.const 0xe0
+  i32.lt_u
+  if
+    local.get $byte
+    i32.const 0x1f
+    i32.and
+    i32.const 6
+    i32.shl
+    local.get $p
+    i32.load8_u offset=1
+    i32.const 0x3f
+    i32.and
+    i32.or
+    return
+  end
+  local.get $byte
+  i32.const 0xf0
+  i32.lt_u
+  if
+    local.get $byte
+    i32.const 0x0f
+    i32.and
+    i32.const 12
+    i32.shl
+    local.get $p
+    i32.load8_u offset=1
+    i32.const 0x3f
+    i32.and
+    i32.const 6
+    i32.shl
+    i32.or
+    local.get $p
+    i32.load8_u offset=2
+    i32.const 0x3f
+    i32.and
+    i32.or
+    return
+  end
+  local.get $byte
+  i32.const 0x07
+  i32.and
+  i32.const 18
+  i32.shl
+  local.get $p
+  i32.load8_u offset=1
+  i32.const 0x3f
+  i32.and
+  i32.const 12
+  i32.shl
+  i32.or
+  local.get $p
+  i32.load8_u offset=2
+  i32.const 0x3f
+  i32.and
+  i32.const 6
+  i32.shl
+  i32.or
+  local.get $p
+  i32.load8_u offset=3
+  i32.const 0x3f
+  i32.and
+  i32.or)
+
+;; Checked integer arithmetic, which stops the program on overflow.
+
+(func $k_overflow
+  global.get $k_str_integer_overflow
+  call $k_trap)
+
+(func $k_check_divisor (param $b i64)
+  local.get $b
+  i64.eqz
+  if
+    global.get $k_str_divide_by_zero
+    call $k_trap
+  end)
+
+(func $k_narrow (param $value i64) (result i32)
+  local.get $value
+  local.get $value
+  i32.wrap_i64
+  i64.extend_i32_s
+  i64.ne
+  if
+    call $k_overflow
+  end
+  local.get $value
+  i32.wrap_i64)
+
+(func $k_add_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  i64.extend_i32_s
+  local.get $b
+  i64.extend_i32_s
+  i64.add
+  call $k_narrow)
+
+(func $k_sub_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  i64.extend_i32_s
+  local.get $b
+  i64.extend_i32_s
+  i64.sub
+  call $k_narrow)
+
+(func $k_mul_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  i64.extend_i32_s
+  local.get $b
+  i64.extend_i32_s
+  i64.mul
+  call $k_narrow)
+
+(func $k_div_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  i64.extend_i32_s
+  local.get $b
+  i64.extend_i32_s
+  call $k_div_i64
+  call $k_narrow)
+
+(func $k_rem_i32 (param $a i32) (param $b i32) (result i32)
+  ;; Dividing checks for the same mistakes
+  local.get $a
+  local.get $b
+  call $k_div_i32
+  drop
+  local.get $a
+  local.get $b
+  i32.rem_s)
+
+(func $k_neg_i32 (param $a i32) (result i32)
+  i64.const 0
+  local.get $a
+  i64.extend_i32_s
+  i64.sub
+  call $k_narrow)
+
+(func $k_abs_i32 (param $a i32) (result i32)
+  local.get $a
+  i64.extend_i32_s
+  call $k_abs_i64
+  call $k_narrow)
+
+(func $k_add_i64 (param $a i64) (param $b i64) (result i64)
+  (local $r i64)
+  local.get $a
+  local.get $b
+  i64.add
+  local.set $r
+  local.get $a
+  local.get $r
+  i64.xor
+  local.get $b
+  local.get $r
+  i64.xor
+  i64.and
+  i64.const 0
+  i64.lt_s
+  if
+    call $k_overflow
+  end
+  local.get $r)
+
+(func $k_sub_i64 (param $a i64) (param $b i64) (result i64)
+  (local $r i64)
+  local.get $a
+  local.get $b
+  i64.sub
+  local.set $r
+  local.get $a
+  local.get $b
+  i64.xor
+  local.get $a
+  local.get $r
+  i64.xor
+  i64.and
+  i64.const 0
+  i64.lt_s
+  if
+    call $k_overflow
+  end
+  local.get $r)
+
+(func $k_mul_i64 (param $a i64) (param $b i64) (result i64)
+  (local $r i64)
+  local.get $a
+  local.get $b
+  i64.mul
+  local.set $r
+  local.get $a
+  i64.const -1
+  i64.eq
+  if
+    local.get $b
+    i64.const 0x8000000000000000
+    i64.eq
+    if
+      call $k_overflow
+    end
+  else
+    local.get $a
+    i64.eqz
+    i32.eqz
+    if
+      local.get $r
+      local.get $a
+      i64.div_s
+      local.get $b
+      i64.ne
+      if
+        call $k_overflow
+      end
+    end
+  end
+  local.get $r)
+
+(func $k_div_i64 (param $a i64) (param $b i64) (result i64)
+  local.get $b
+  call $k_check_divisor
+  local.get $a
+  i64.const 0x8000000000000000
+  i64.eq
+  local.get $b
+  i64.const -1
+  i64.eq
+  i32.and
+  if
+    call $k_overflow
+  end
+  local.get $a
+  local.get $b
+  i64.div_s)
+
+(func $k_rem_i64 (param $a i64) (param $b i64) (result i64)
+  local.get $b
+  call $k_check_divisor
+  local.get $a
+  i64.const 0x8000000000000000
+  i64.eq
+  local.get $b
+  i64.const -1
+  i64.eq
+  i32.and
+  if
+    call $k_overflow
+  end
+  local.get $a
+  local.get $b
+  i64.rem_s)
+
+(func $k_neg_i64 (param $a i64) (result i64)
+  i64.const 0
+  local.get $a
+  call $k_sub_i64)
+
+(func $k_abs_i64 (param $a i64) (result i64)
+  local.get $a
+  i64.const 0
+  i64.lt_s
+  if (result i64)
+    local.get $a
+    call $k_neg_i64
+  else
+    local.get $a
+  end)
+
+(func $k_min_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  local.get $b
+  local.get $a
+  local.get $b
+  i32.lt_s
+  select)
+
+(func $k_max_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  local.get $b
+  local.get $a
+  local.get $b
+  i32.gt_s
+  select)
+
+(func $k_min_i64 (param $a i64) (param $b i64) (result i64)
+  local.get $a
+  local.get $b
+  local.get $a
+  local.get $b
+  i64.lt_s
+  select)
+
+(func $k_max_i64 (param $a i64) (param $b i64) (result i64)
+  local.get $a
+  local.get $b
+  local.get $a
+  local.get $b
+  i64.gt_s
+  select)
+
+;; Unlike `f64.min` and `f64.max`, these ignore a NaN argument.
+(func $k_min_f64 (param $a f64) (param $b f64) (result f64)
+  local.get $a
+  local.get $a
+  f64.ne
+  if
+    local.get $b
+    return
+  end
+  local.get $b
+  local.get $b
+  f64.ne
+  if
+    local.get $a
+    return
+  end
+  local.get $a
+  local.get $b
+  f64.min)
+
+(func $k_max_f64 (param $a f64) (param $b f64) (result f64)
+  local.get $a
+  local.get $a
+  f64.ne
+  if
+    local.get $b
+    return
+  end
+  local.get $b
+  local.get $b
+  f64.ne
+  if
+    local.get $a
+    return
+  end
+  local.get $a
+  local.get $b
+  f64.max)
+
+;; The remainder of a truncating division, which can be off in the last
+;; place when the quotient is very large.
+(func $k_rem_f64 (param $a f64) (param $b f64) (result f64)
+  local.get $a
+  local.get $b
+  local.get $a
+  local.get $b
+  f64.div
+  f64.trunc
+  f64.mul
+  f64.sub)
+
+(func $k_eq_i32 (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  local.get $b
+  i32.eq)
+
+(func $k_eq_i64 (param $a i64) (param $b i64) (result i32)
+  local.get $a
+  local.get $b
+  i64.eq)
+
+(func $k_eq_f64 (param $a f64) (param $b f64) (result i32)
+  local.get $a
+  local.get $b
+  f64.eq)
+
+(func $k_eq_bool (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  local.get $b
+  i32.eq)
+
+(func $k_eq_char (param $a i32) (param $b i32) (result i32)
+  local.get $a
+  local.get $b
+  i32.eq)
+
+;; Converting values to strings. `display` is what `std.print` writes, and
+;; `inspect` is how a value is written inside an array, struct or variant.
+
+(func $k_display_i64 (param $value i64) (result i32)
+  (local $p i32) (local $digit i64) (local $negative i32)
+  local.get $value
+  i64.const 0
+  i64.lt_s
+  local.set $negative
+  ;; Digits are written backwards from the end of the scratch space, using
+  ;; the remainders of a negative value as they are so the most negative
+  ;; value needs no special case.
+  i32.const 32
+  local.set $p
+  loop $next
+    local.get $value
+    i64.const 10
+    i64.rem_s
+    local.set $digit
+    local.get $p
+    i32.const 1
+    i32.sub
+    local.tee $p
+    i64.const 0
+    local.get $digit
+    i64.sub
+    local.get $digit
+    local.get $negative
+    select
+    i32.wrap_i64
+    i32.const 48
+    i32.add
+    i32.store8
+    local.get $value
+    i64.const 10
+    i64.div_s
+    local.tee $value
+    i64.eqz
+    i32.eqz
+    br_if $next
+  end
+  local.get $negative
+  if
+    local.get $p
+    i32.const 1
+    i32.sub
+    local.tee $p
+    i32.const 45
+    i32.store8
+  end
+  local.get $p
+  i32.const 32
+  local.get $p
+  i32.sub
+  call $k_str_from)
+
+(func $k_display_i32 (param $value i32) (result i32)
+  local.get $value
+  i64.extend_i32_s
+  call $k_display_i64)
+
+(func $k_display_f64 (param $value f64) (result i32)
+  i32.const 0
+  local.get $value
+  i32.const 0
+  call $k_host_format_f64
+  call $k_str_from)
+
+(func $k_display_bool (param $value i32) (result i32)
+  global.get $k_str_true
+  global.get $k_str_false
+  local.get $value
+  select)
+
+(func $k_display_char (param $c i32) (result i32)
+  (local $len i32)
+  local.get $c
+  i32.const 0x80
+  i32.lt_u
+  if
+    i32.const 0
+    local.get $c
+    i32.store8
+    i32.const 1
+    local.set $len
+  else
+    local.get $c
+    i32.const 0x800
+    i32.lt_u
+    if
+      i32.const 0
+      local.get $c
+      i32.const 6
+      i32.shr_u
+      i32.const 0xc0
+      i32.or
+      i32.store8
+      i32.const 2
+      local.set $len
+    else
+      local.get $c
+      i32.const 0x10000
+      i32.lt_u
+      if
+        i32.const 0
+        local.get $c
+        i32.const 12
+        i32.shr_u
+        i32.const 0xe0
+        i32.or
+        i32.store8
+        i32.const 3
+        local.set $len
+      else
+        i32.const 0
+        local.get $c
+        i32.const 18
+        i32.shr_u
+        i32.const 0xf0
+        i32.or
+        i32.store8
+        i32.const 4
+        local.set $len
+      end
+    end
+  end
+  ;; Every byte after the first holds the next six bits
+  local.get $len
+  i32.const 1
+  i32.gt_u
+  if
+    local.get $len
+    i32.const 1
+    i32.sub
+    local.get $c
+    i32.const 0x3f
+    i32.and
+    i32.const 0x80
+    i32.or
+    i32.store8
+  end
+  local.get $len
+  i32.const 2
+  i32.gt_u
+  if
+    local.get $len
+    i32.const 2
+    i32.sub
+    local.get $c
+    i32.const 6
+    i32.shr_u
+    i32.const 0x3f
+    i32.and
+    i32.const 0x80
+    i32.or
+    i32.store8
+  end
+  local.get $len
+  i32.const 3
+  i32.gt_u
+  if
+    i32.const 1
+    local.get $c
+    i32.const 12
+    i32.shr_u
+    i32.const 0x3f
+    i32.and
+    i32.const 0x80
+    i32.or
+    i32.store8
+  end
+  i32.const 0
+  local.get $len
+  call $k_str_from)
+
+(func $k_display_str (param $value i32) (result i32)
+  local.get $value)
+
+(func $k_inspect_i32 (param $value i32) (result i32)
+  local.get $value
+  call $k_display_i32)
+
+(func $k_inspect_i64 (param $value i64) (result i32)
+  local.get $value
+  call $k_display_i64)
+
+(func $k_inspect_f64 (param $value f64) (result i32)
+  local.get $value
+  call $k_display_f64)
+
+(func $k_inspect_bool (param $value i32) (result i32)
+  local.get $value
+  call $k_display_bool)
+
+(func $k_inspect_str (param $value i32) (result i32)
+  local.get $value
+  i32.const 34
+  call $k_escape)
+
+(func $k_inspect_char (param $value i32) (result i32)
+  local.get $value
+  call $k_display_char
+  i32.const 39
+  call $k_escape)
+
+(func $k_hex_digit (param $digit i32) (result i32)
+  local.get $digit
+  i32.const 48
+  i32.add
+  local.get $digit
+  i32.const 87
+  i32.add
+  local.get $digit
+  i32.const 10
+  i32.lt_u
+  select)
+
+;; Quotes a string, escaping the quote and control characters.
+(func $k_escape (param $s i32) (param $quote i32) (result i32)
+  (local $out i32) (local $o i32) (local $i i32) (local $byte i32) (local $escape i32)
+  local.get $s
+  i32.load
+  i32.const 10
+  i32.mul
+  i32.const 6
+  i32.add
+  call $k_alloc
+  local.tee $out
+  i32.const 4
+  i32.add
+  local.tee $o
+  local.get $quote
+  i32.store8
+  local.get $o
+  i32.const 1
+  i32.add
+  local.set $o
+  block $done
+    loop $next
+      local.get $i
+      local.get $s
+      i32.load
+      i32.ge_u
+      br_if $done
+      local.get $s
+      local.get $i
+      i32.add
+      i32.load8_u offset=4
+      local.set $byte
+      ;; The letter after a backslash, or 0 for none
+      i32.const 0
+      local.set $escape
+      local.get $byte
+      i32.eqz
+      if
+        i32.const 48
+        local.set $escape
+      end
+      local.get $byte
+      i32.const 9
+      i32.eq
+      if
+        i32.const 116
+        local.set $escape
+      end
+      local.get $byte
+      i32.const 10
+      i32.eq
+      if
+        i32.const 110
+        local.set $escape
+      end
+      local.get $byte
+      i32.const 13
+      i32.eq
+      if
+        i32.const 114
+        local.set $escape
+      end
+      local.get $byte
+      i32.const 92
+      i32.eq
+      local.get $byte
+      local.get $quote
+      i32.eq
+      i32.or
+      if
+        local.get $byte
+        local.set $escape
+      end
+      ;; Characters from U+0080 to U+009F are two bytes starting with 0xc2
+      local.get $byte
+      i32.const 0xc2
+      i32.eq
+      if
+        local.get $s
+        local.get $i
+        i32.add
+        i32.load8_u offset=5
+        i32.const 0xa0
+        i32.lt_u
+        if
+          local.get $i
+          i32.const 1
+          i32.add
+          local.tee $i
+          local.get $s
+          i32.add
+          i32.load8_u offset=4
+          local.set $byte
+          i32.const 117
+          local.set $escape
+        end
+      end
+      local.get $escape
+      i32.eqz
+      local.get $byte
+      i32.const 0x20
+      i32.lt_u
+      local.get $byte
+      i32.const 0x7f
+      i32.eq
+      i32.or
+      i32.and
+      if
+        i32.const 117
+        local.set $escape
+      end
+      local.get $escape
+      if
+        local.get $o
+        i32.const 92
+        i32.store8
+        local.get $o
+        local.get $escape
+        i32.store8 offset=1
+        local.get $o
+        i32.const 2
+        i32.add
+        local.set $o
+        local.get $escape
+        i32.const 117
+        i32.eq
+        if
+          local.get $o
+          i32.const 123
+          i32.store8
+          local.get $o
+          i32.const 1
+          i32.add
+          local.set $o
+          local.get $byte
+          i32.const 16
+          i32.ge_u
+          if
+            local.get $o
+            local.get $byte
+            i32.const 4
+            i32.shr_u
+            call $k_hex_digit
+            i32.store8
+            local.get $o
+            i32.const 1
+            i32.add
+            local.set $o
+          end
+          local.get $o
+          local.get $byte
+          i32.const 15
+          i32.and
+          call $k_hex_digit
+          i32.store8
+          local.get $o
+          i32.const 125
+          i32.store8 offset=1
+          local.get $o
+          i32.const 2
+          i32.add
+          local.set $o
+        end
+      else
+        local.get $o
+        local.get $byte
+        i32.store8
+        local.get $o
+        i32.const 1
+        i32.add
+        local.set $o
+      end
+      local.get $i
+      i32.const 1
+      i32.add
+      local.set $i
+      br $next
+    end
+  end
+  local.get $o
+  local.get $quote
+  i32.store8
+  local.get $out
+  local.get $o
+  i32.const 1
+  i32.add
+  local.get $out
+  i32.sub
+  i32.const 4
+  i32.sub
+  i32.store
+  local.get $out)
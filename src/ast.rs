@@ -1,31 +1,291 @@
+use crate::symbol::Symbol;
+use crate::token::BufferPosition;
+use crate::types::Type;
+
 #[derive(PartialEq, Clone, Debug)]
-pub struct Identifier(pub String);
+pub struct Identifier {
+    pub name: Symbol,
+    pub position: BufferPosition,
+    // The declaration this name introduces or refers to, filled in by the
+    // resolver. Field and variant names are never resolved.
+    pub binding: Option<BindingId>,
+}
+
+/// Identifies a declaration within the resolver that created it.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct BindingId(pub usize);
 
 #[derive(PartialEq, Clone, Debug)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub position: BufferPosition,
+    // Where the statement's last token ends
+    pub end: BufferPosition,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum StmtKind {
     Blank,
-    Let(Identifier, Expr),
+    Let(Identifier, Option<TypeExpr>, Expr),
+    Assign(Expr, AssignOp, Expr),
+    Increment(Expr),
+    Decrement(Expr),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    While(Expr, BlockStmt),
+    For(Box<Stmt>, Expr, Box<Stmt>, BlockStmt),
+    Fn(FnDecl),
+    Struct(StructDecl),
+    Enum(EnumDecl),
+    Import(Identifier),
+    Expr(Expr),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub position: BufferPosition,
+    // Where the expression's last token ends
+    pub end: BufferPosition,
+    // The type of the expression, filled in by the checker.
+    pub ty: Option<Type>,
 }
 
 #[derive(PartialEq, Clone, Debug)]
-pub enum Expr {
-    Empty,
+pub enum ExprKind {
     Identifier(Identifier),
+    IntegerLiteral(i64),
+    FloatLiteral(f64),
+    StringLiteral(String),
+    // A string with expressions embedded in it, like `"x is {x}"`
+    Interpolated(Vec<StringPart>),
+    CharLiteral(char),
+    BooleanLiteral(bool),
+    ArrayLiteral(Vec<Expr>),
+    StructLiteral(Identifier, Vec<(Identifier, Expr)>),
+    Prefix(PrefixOp, Box<Expr>),
+    Infix(InfixOp, Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Field(Box<Expr>, Identifier),
+    Block(BlockStmt),
+    If(Box<Expr>, BlockStmt, Option<Box<Expr>>),
+    Match(Box<Expr>, Vec<MatchArm>),
 }
 
-pub type BlockStmt = Vec<Stmt>;
+#[derive(PartialEq, Clone, Debug)]
+pub enum StringPart {
+    Text(String),
+    Expr(Expr),
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum PrefixOp {
+    Neg,
+    Not,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum InfixOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    NotEq,
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+    And,
+    Or,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AssignOp {
+    Assign,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum TypeExpr {
+    Named(Identifier),
+    // The element type and where the closing bracket ends
+    Array(Box<TypeExpr>, BufferPosition),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Param {
+    pub name: Identifier,
+    pub ty: TypeExpr,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct FnDecl {
+    pub name: Identifier,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeExpr>,
+    pub body: BlockStmt,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct StructDecl {
+    pub name: Identifier,
+    pub fields: Vec<Param>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct EnumDecl {
+    pub name: Identifier,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Variant {
+    pub name: Identifier,
+    pub fields: Vec<TypeExpr>,
+    pub end: BufferPosition,
+}
 
 #[derive(PartialEq, Clone, Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Pattern {
+    Wildcard(BufferPosition),
+    Literal(Expr),
+    Binding(Identifier),
+    // The enum, the variant, its fields and where the pattern ends
+    Variant(Identifier, Identifier, Vec<Pattern>, BufferPosition),
+}
+
+pub type BlockStmt = Vec<Stmt>;
+
+#[derive(PartialEq, Clone, Debug, Default)]
 pub struct Program(pub BlockStmt);
 
 impl Program {
     pub fn new() -> Program {
         Program(Vec::new())
-    }   
+    }
 }
 
 impl Identifier {
-    pub fn new(value: String) -> Identifier {
-        Identifier(value)
+    pub fn new(name: Symbol, position: BufferPosition) -> Identifier {
+        Identifier {
+            name,
+            position,
+            binding: None,
+        }
+    }
+}
+
+impl Stmt {
+    /// A statement that ends where it starts, for ones that aren't parsed.
+    pub fn new(kind: StmtKind, position: BufferPosition) -> Stmt {
+        Stmt::spanning(kind, position, position)
+    }
+
+    pub fn spanning(kind: StmtKind, position: BufferPosition, end: BufferPosition) -> Stmt {
+        Stmt { kind, position, end }
+    }
+}
+
+impl Expr {
+    /// An expression that ends where it starts, for ones that aren't parsed.
+    pub fn new(kind: ExprKind, position: BufferPosition) -> Expr {
+        Expr::spanning(kind, position, position)
+    }
+
+    pub fn spanning(kind: ExprKind, position: BufferPosition, end: BufferPosition) -> Expr {
+        Expr {
+            kind,
+            position,
+            end,
+            ty: None,
+        }
     }
-}
\ No newline at end of file
+}
+
+impl PrefixOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrefixOp::Neg => "-",
+            PrefixOp::Not => "!",
+        }
+    }
+}
+
+impl InfixOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InfixOp::Add => "+",
+            InfixOp::Sub => "-",
+            InfixOp::Mul => "*",
+            InfixOp::Div => "/",
+            InfixOp::Mod => "%",
+            InfixOp::Eq => "==",
+            InfixOp::NotEq => "!=",
+            InfixOp::Lt => "<",
+            InfixOp::Gt => ">",
+            InfixOp::LtEq => "<=",
+            InfixOp::GtEq => ">=",
+            InfixOp::And => "&&",
+            InfixOp::Or => "||",
+        }
+    }
+}
+
+impl AssignOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AssignOp::Assign => "=",
+            AssignOp::Add => "+=",
+            AssignOp::Sub => "-=",
+            AssignOp::Mul => "*=",
+            AssignOp::Div => "/=",
+            AssignOp::Mod => "%=",
+        }
+    }
+
+    /// The arithmetic operator applied by a compound assignment.
+    pub fn infix_op(&self) -> Option<InfixOp> {
+        match self {
+            AssignOp::Assign => None,
+            AssignOp::Add => Some(InfixOp::Add),
+            AssignOp::Sub => Some(InfixOp::Sub),
+            AssignOp::Mul => Some(InfixOp::Mul),
+            AssignOp::Div => Some(InfixOp::Div),
+            AssignOp::Mod => Some(InfixOp::Mod),
+        }
+    }
+}
+
+impl TypeExpr {
+    pub fn position(&self) -> BufferPosition {
+        match self {
+            TypeExpr::Named(name) => name.position,
+            TypeExpr::Array(inner, _) => inner.position(),
+        }
+    }
+}
+
+impl Pattern {
+    pub fn position(&self) -> BufferPosition {
+        match self {
+            Pattern::Wildcard(position) => *position,
+            Pattern::Literal(expr) => expr.position,
+            Pattern::Binding(name) => name.position,
+            Pattern::Variant(enum_name, _, _, _) => enum_name.position,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! What the backends that turn an IR module into a program have in common.
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BuildError {
+    pub message: String,
+}
+
+impl BuildError {
+    pub fn new(message: String) -> BuildError {
+        BuildError { message }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(error: io::Error) -> BuildError {
+        BuildError::new(error.to_string())
+    }
+}
+
+/// A directory for intermediate files that is removed when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new directory in the system's temporary directory. Names
+    /// that are taken are skipped, so a build never writes into a directory
+    /// it didn't create, like one another user made in its place.
+    pub fn new() -> io::Result<TempDir> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        // Random bits make the names hard to guess ahead of time
+        let random = RandomState::new().build_hasher().finish();
+
+        loop {
+            let count = COUNT.fetch_add(1, Ordering::Relaxed);
+            let name = format!("k-build-{}-{:016x}-{}", process::id(), random, count);
+            let path = env::temp_dir().join(name);
+
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Leaving the files behind is harmless
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Runs an external tool like a compiler or linker, failing with what it
+/// printed if it fails.
+pub fn run_tool(command: &mut Command) -> Result<(), BuildError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let output = command
+        .output()
+        .map_err(|error| BuildError::new(format!("could not run `{}`: {}", program, error)))?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(BuildError::new(format!(
+        "`{}` failed with {}:\n{}",
+        program,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim_end()
+    )))
+}
+
+/// Turns a name into an identifier that C and WebAssembly text accept.
+/// ASCII letters and digits are kept, underscores are doubled, and anything
+/// else is written as its code point in hex between underscores.
+pub fn mangle(name: &str) -> String {
+    let mut mangled = String::new();
+
+    for c in name.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => mangled.push(c),
+            '_' => mangled.push_str("__"),
+            _ => mangled.push_str(&format!("_{:x}_", c as u32)),
+        }
+    }
+
+    mangled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mangle() {
+        let tests = vec![
+            ("main", "main"),
+            ("snake_case", "snake__case"),
+            ("outer.inner", "outer_2e_inner"),
+            ("$entry", "_24_entry"),
+            ("wörld", "w_f6_rld"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(mangle(input), expected, "mangling {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_temp_dirs() {
+        let (a, b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+
+        assert_ne!(a.path(), b.path());
+        assert!(a.path().is_dir() && b.path().is_dir());
+
+        let path = a.path().to_owned();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,852 @@
+//! Generates portable C99 from the IR, which the system C compiler turns
+//! into an executable.
+//!
+//! Each IR value becomes a local variable and each block a label, with the
+//! arguments of a jump assigned to the target's parameters before the
+//! `goto`. Structs become C structs, enums a tag and a pointer to their
+//! payload, and arrays a length and a pointer to their elements. Strings,
+//! checked arithmetic and converting values to strings come from the
+//! runtime header in `runtime/k.h`.
+
+use crate::ast::{InfixOp, PrefixOp};
+use crate::backend::{mangle, run_tool, BuildError, TempDir};
+use crate::ir::*;
+use crate::symbol::Symbol;
+use crate::types::Type;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The runtime that generated C includes as `k.h`.
+pub const RUNTIME: &str = include_str!("../runtime/k.h");
+
+/// Compiles a module to an executable at `output` with the C compiler named
+/// by `CC`, or `cc` if it isn't set.
+pub fn build(module: &Module, output: &Path) -> Result<(), BuildError> {
+    let source = emit(module)?;
+    let dir = TempDir::new()?;
+    let main = dir.path().join("main.c");
+
+    fs::write(dir.path().join("k.h"), RUNTIME)?;
+    fs::write(&main, source)?;
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    run_tool(
+        Command::new(cc)
+            .args(["-std=c99", "-O2", "-o"])
+            .arg(output)
+            .arg(main)
+            .arg("-lm"),
+    )
+}
+
+/// Generates the C for a module.
+pub fn emit(module: &Module) -> Result<String, BuildError> {
+    let mut emitter = Emitter {
+        module,
+        arrays: Vec::new(),
+        out: String::new(),
+    };
+
+    emitter.emit_module()?;
+    Ok(emitter.out)
+}
+
+struct Emitter<'a> {
+    module: &'a Module,
+    // The element type of every array type in the module, each after the
+    // array types it contains.
+    arrays: Vec<Type>,
+    out: String,
+}
+
+impl<'a> Emitter<'a> {
+    fn line(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn emit_module(&mut self) -> Result<(), BuildError> {
+        let module = self.module;
+
+        for (_, fields) in &module.structs {
+            for (_, ty) in fields {
+                self.add_type(ty)?;
+            }
+        }
+
+        for (_, variants) in &module.enums {
+            for ty in variants.iter().flat_map(|(_, fields)| fields) {
+                self.add_type(ty)?;
+            }
+        }
+
+        for (_, ty) in &module.globals {
+            self.add_type(ty)?;
+        }
+
+        for function in &module.functions {
+            if function.ret != Type::Void {
+                self.add_type(&function.ret)?;
+            }
+
+            for ty in &function.types {
+                self.add_type(ty)?;
+            }
+        }
+
+        self.line("#include \"k.h\"");
+        self.line("");
+        self.emit_types()?;
+        self.emit_helpers();
+
+        for (i, (_, ty)) in module.globals.iter().enumerate() {
+            let ty = self.c_type(ty);
+            self.line(&format!("static {} k_g{};", ty, i));
+        }
+
+        if !module.globals.is_empty() {
+            self.line("");
+        }
+
+        for function in &module.functions {
+            let signature = self.signature(function);
+            self.line(&format!("{};", signature));
+        }
+
+        for function in &module.functions {
+            self.line("");
+            self.emit_function(function);
+        }
+
+        self.line("");
+        self.line("int main(void) {");
+        self.line(&format!("    {}();", function_name(&module.function(module.entry).name)));
+        self.line("    return 0;");
+        self.line("}");
+
+        Ok(())
+    }
+
+    /// Checks that values of a type can be represented, and records the
+    /// array types it uses.
+    fn add_type(&mut self, ty: &Type) -> Result<(), BuildError> {
+        match ty {
+            Type::I32 | Type::I64 | Type::F64 | Type::Bool | Type::Char | Type::Str => Ok(()),
+            Type::Struct(_) | Type::Enum(_) => Ok(()),
+            Type::Array(element) => {
+                self.add_type(element)?;
+
+                if !self.arrays.contains(element) {
+                    self.arrays.push((**element).clone());
+                }
+
+                Ok(())
+            }
+            _ => Err(BuildError::new(format!("cannot generate C for a value of type {}", ty))),
+        }
+    }
+
+    /// The name types are known by in the names of their helpers.
+    fn suffix(&self, ty: &Type) -> String {
+        match ty {
+            Type::Struct(name) => format!("s_{}", mangle(name.as_str())),
+            Type::Enum(name) => format!("e_{}", mangle(name.as_str())),
+            Type::Array(element) => {
+                let index = self.arrays.iter().position(|ty| ty == &**element);
+                format!("a{}", index.expect("array types are added before they are used"))
+            }
+            _ => ty.to_string(),
+        }
+    }
+
+    fn c_type(&self, ty: &Type) -> String {
+        match ty {
+            Type::I32 => "int32_t".to_owned(),
+            Type::I64 => "int64_t".to_owned(),
+            Type::F64 => "double".to_owned(),
+            Type::Bool => "bool".to_owned(),
+            Type::Char => "k_char".to_owned(),
+            Type::Str => "k_str".to_owned(),
+            Type::Void => "void".to_owned(),
+            _ => format!("k_{}", self.suffix(ty)),
+        }
+    }
+
+    fn emit_types(&mut self) -> Result<(), BuildError> {
+        let module = self.module;
+
+        for (name, _) in &module.structs {
+            let name = self.c_type(&Type::Struct(name.clone()));
+            self.line(&format!("typedef struct {} {};", name, name));
+        }
+
+        for (name, _) in &module.enums {
+            let name = self.c_type(&Type::Enum(name.clone()));
+            self.line(&format!("typedef struct {{ int32_t tag; void *payload; }} {};", name));
+        }
+
+        for i in 0..self.arrays.len() {
+            let element = self.c_type(&self.arrays[i]);
+            self.line(&format!("typedef struct {{ int64_t len; {} *data; }} k_a{};", element, i));
+        }
+
+        self.line("");
+
+        // Structs hold the structs in their fields directly, so those have
+        // to be defined first.
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+
+        for (name, _) in &module.structs {
+            struct_order(module, name.clone(), &mut visiting, &mut order)?;
+        }
+
+        for name in order {
+            let fields = module.struct_fields(&name).expect("structs are ordered by name");
+            self.line(&format!("struct {} {{", self.c_type(&Type::Struct(name))));
+
+            for (field, ty) in fields {
+                self.line(&format!("    {} f_{};", self.c_type(ty), mangle(field.as_str())));
+            }
+
+            if fields.is_empty() {
+                self.line("    char unused;");
+            }
+
+            self.line("};");
+            self.line("");
+        }
+
+        for (name, variants) in &module.enums {
+            for (variant, fields) in variants.iter().filter(|(_, fields)| !fields.is_empty()) {
+                let types: Vec<String> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ty)| format!("{} f{};", self.c_type(ty), i))
+                    .collect();
+
+                let payload = payload_name(name, variant);
+                self.line(&format!("typedef struct {{ {} }} {};", types.join(" "), payload));
+            }
+        }
+
+        if module.enums.iter().any(|(_, variants)| variants.iter().any(|(_, fields)| !fields.is_empty())) {
+            self.line("");
+        }
+
+        Ok(())
+    }
+
+    /// Defines how values of each struct, enum and array type are written
+    /// and compared.
+    fn emit_helpers(&mut self) {
+        let module = self.module;
+        let mut types: Vec<Type> = Vec::new();
+
+        types.extend(module.structs.iter().map(|(name, _)| Type::Struct(name.clone())));
+        types.extend(module.enums.iter().map(|(name, _)| Type::Enum(name.clone())));
+        types.extend(self.arrays.iter().map(|element| Type::Array(Box::new(element.clone()))));
+
+        for ty in &types {
+            let (suffix, c_type) = (self.suffix(ty), self.c_type(ty));
+
+            self.line(&format!("static k_str k_display_{}({} value);", suffix, c_type));
+            self.line(&format!("static k_str k_inspect_{}({} value);", suffix, c_type));
+            self.line(&format!("static bool k_eq_{}({} a, {} b);", suffix, c_type, c_type));
+        }
+
+        if !types.is_empty() {
+            self.line("");
+        }
+
+        for ty in &types {
+            match ty {
+                Type::Struct(name) => self.emit_struct_helpers(name),
+                Type::Enum(name) => self.emit_enum_helpers(name),
+                Type::Array(element) => self.emit_array_helpers(element),
+                _ => unreachable!("only aggregates need helpers"),
+            }
+        }
+    }
+
+    fn emit_struct_helpers(&mut self, name: &Symbol) {
+        let fields = self.module.struct_fields(name).expect("helpers are only made for structs that exist");
+        let (suffix, c_type) = (self.suffix(&Type::Struct(name.clone())), self.c_type(&Type::Struct(name.clone())));
+
+        self.line(&format!("static k_str k_display_{}({} value) {{", suffix, c_type));
+        self.line(&format!("    return k_inspect_{}(value);", suffix));
+        self.line("}");
+        self.line("");
+
+        self.line(&format!("static k_str k_inspect_{}({} value) {{", suffix, c_type));
+        self.line(&format!("    k_str s = K_STR({});", c_string(&format!("{} {{ ", name))));
+
+        for (i, (field, ty)) in fields.iter().enumerate() {
+            let label = format!("{}{}: ", if i > 0 { ", " } else { "" }, field);
+
+            self.line(&format!("    s = k_concat(s, K_STR({}));", c_string(&label)));
+            self.line(&format!(
+                "    s = k_concat(s, k_inspect_{}(value.f_{}));",
+                self.suffix(ty),
+                mangle(field.as_str())
+            ));
+        }
+
+        self.line("    return k_concat(s, K_STR(\" }\"));");
+        self.line("}");
+        self.line("");
+
+        let compared: Vec<String> = fields
+            .iter()
+            .map(|(field, ty)| {
+                let field = mangle(field.as_str());
+                format!("k_eq_{}(a.f_{}, b.f_{})", self.suffix(ty), field, field)
+            })
+            .collect();
+
+        self.line(&format!("static bool k_eq_{}({} a, {} b) {{", suffix, c_type, c_type));
+        self.line(&format!("    return {};", conjunction(&compared)));
+        self.line("}");
+        self.line("");
+    }
+
+    fn emit_enum_helpers(&mut self, name: &Symbol) {
+        let variants = self.module.enum_variants(name).expect("helpers are only made for enums that exist");
+        let (suffix, c_type) = (self.suffix(&Type::Enum(name.clone())), self.c_type(&Type::Enum(name.clone())));
+
+        // A variant is displayed with its fields displayed, and inspected
+        // with its fields inspected.
+        for how in &["display", "inspect"] {
+            self.line(&format!("static k_str k_{}_{}({} value) {{", how, suffix, c_type));
+            self.line("    switch (value.tag) {");
+
+            for (tag, (variant, fields)) in variants.iter().enumerate() {
+                let label = c_string(&format!("{}.{}", name, variant));
+
+                if fields.is_empty() {
+                    self.line(&format!("    case {}: return K_STR({});", tag, label));
+                    continue;
+                }
+
+                let payload = payload_name(name, variant);
+
+                self.line(&format!("    case {}: {{", tag));
+                self.line(&format!("        {} *payload = value.payload;", payload));
+                let opening = c_string(&format!("{}.{}(", name, variant));
+                self.line(&format!("        k_str s = K_STR({});", opening));
+
+                for (i, ty) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.line("        s = k_concat(s, K_STR(\", \"));");
+                    }
+
+                    let field = format!("k_{}_{}(payload->f{})", how, self.suffix(ty), i);
+                    self.line(&format!("        s = k_concat(s, {});", field));
+                }
+
+                self.line("        return k_concat(s, K_STR(\")\"));");
+                self.line("    }");
+            }
+
+            self.line("    }");
+            self.line("    return K_STR(\"\");");
+            self.line("}");
+            self.line("");
+        }
+
+        self.line(&format!("static bool k_eq_{}({} a, {} b) {{", suffix, c_type, c_type));
+        self.line("    if (a.tag != b.tag) {");
+        self.line("        return false;");
+        self.line("    }");
+        self.line("    switch (a.tag) {");
+
+        for (tag, (variant, fields)) in variants.iter().enumerate() {
+            if fields.is_empty() {
+                continue;
+            }
+
+            let payload = payload_name(name, variant);
+
+            let compared: Vec<String> = fields
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| {
+                    format!(
+                        "k_eq_{}((({} *) a.payload)->f{}, (({} *) b.payload)->f{})",
+                        self.suffix(ty),
+                        payload,
+                        i,
+                        payload,
+                        i
+                    )
+                })
+                .collect();
+
+            self.line(&format!("    case {}: return {};", tag, conjunction(&compared)));
+        }
+
+        self.line("    }");
+        self.line("    return true;");
+        self.line("}");
+        self.line("");
+    }
+
+    fn emit_array_helpers(&mut self, element: &Type) {
+        let ty = Type::Array(Box::new(element.clone()));
+        let (suffix, c_type, element) = (self.suffix(&ty), self.c_type(&ty), self.suffix(element));
+
+        self.line(&format!("static k_str k_display_{}({} value) {{", suffix, c_type));
+        self.line(&format!("    return k_inspect_{}(value);", suffix));
+        self.line("}");
+        self.line("");
+
+        self.line(&format!("static k_str k_inspect_{}({} value) {{", suffix, c_type));
+        self.line("    k_str s = K_STR(\"[\");");
+        self.line("    for (int64_t i = 0; i < value.len; i++) {");
+        self.line("        if (i > 0) {");
+        self.line("            s = k_concat(s, K_STR(\", \"));");
+        self.line("        }");
+        self.line(&format!("        s = k_concat(s, k_inspect_{}(value.data[i]));", element));
+        self.line("    }");
+        self.line("    return k_concat(s, K_STR(\"]\"));");
+        self.line("}");
+        self.line("");
+
+        self.line(&format!("static bool k_eq_{}({} a, {} b) {{", suffix, c_type, c_type));
+        self.line("    if (a.len != b.len) {");
+        self.line("        return false;");
+        self.line("    }");
+        self.line("    for (int64_t i = 0; i < a.len; i++) {");
+        self.line(&format!("        if (!k_eq_{}(a.data[i], b.data[i])) {{", element));
+        self.line("            return false;");
+        self.line("        }");
+        self.line("    }");
+        self.line("    return true;");
+        self.line("}");
+        self.line("");
+    }
+
+    fn signature(&self, function: &Function) -> String {
+        let params: Vec<String> = function
+            .params()
+            .iter()
+            .map(|param| format!("{} v{}", self.c_type(function.value_type(*param)), param.0))
+            .collect();
+
+        let params = if params.is_empty() { "void".to_owned() } else { params.join(", ") };
+
+        format!("static {} {}({})", self.c_type(&function.ret), function_name(&function.name), params)
+    }
+
+    fn emit_function(&mut self, function: &Function) {
+        let signature = self.signature(function);
+        self.line(&format!("{} {{", signature));
+
+        let locals = function.blocks[1..]
+            .iter()
+            .flat_map(|block| block.params.iter().copied())
+            .chain(function.blocks.iter().flat_map(|block| &block.insts).filter_map(|inst| inst.result));
+
+        for value in locals {
+            self.line(&format!("    {} v{};", self.c_type(function.value_type(value)), value.0));
+        }
+
+        let targeted: Vec<BlockId> = function
+            .blocks
+            .iter()
+            .flat_map(|block| block.terminator.targets())
+            .map(|target| target.block)
+            .collect();
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            if targeted.contains(&BlockId(i)) {
+                self.line(&format!("bb{}:", i));
+            }
+
+            for inst in &block.insts {
+                self.emit_inst(function, inst);
+            }
+
+            self.emit_terminator(function, &block.terminator);
+        }
+
+        self.line("}");
+    }
+
+    fn emit_inst(&mut self, function: &Function, inst: &Inst) {
+        let r = inst.result.map(|result| format!("v{}", result.0)).unwrap_or_default();
+        let ty = |value: &Value| function.value_type(*value);
+
+        match &inst.kind {
+            InstKind::Const(constant) => {
+                let result_type = function.value_type(inst.result.expect("constants have a value"));
+                self.line(&format!("    {} = {};", r, constant_value(constant, result_type)));
+            }
+            InstKind::Copy(value) => self.line(&format!("    {} = v{};", r, value.0)),
+            InstKind::Unary(op, value) => {
+                let expr = match (op, ty(value)) {
+                    (PrefixOp::Neg, Type::F64) => format!("-v{}", value.0),
+                    (PrefixOp::Neg, ty) => format!("k_neg_{}(v{})", ty, value.0),
+                    (PrefixOp::Not, _) => format!("!v{}", value.0),
+                };
+
+                self.line(&format!("    {} = {};", r, expr));
+            }
+            InstKind::Binary(op, left, right) => {
+                let expr = self.binary(*op, ty(left), &format!("v{}", left.0), &format!("v{}", right.0));
+                self.line(&format!("    {} = {};", r, expr));
+            }
+            InstKind::ToString(value) => {
+                self.line(&format!("    {} = k_display_{}(v{});", r, self.suffix(ty(value)), value.0));
+            }
+            InstKind::Call(Callee::Function(id), args) => {
+                let call = format!("{}({})", function_name(&self.module.function(*id).name), values(args));
+
+                match inst.result {
+                    Some(_) => self.line(&format!("    {} = {};", r, call)),
+                    None => self.line(&format!("    {};", call)),
+                }
+            }
+            InstKind::Call(Callee::Builtin(name), args) => self.emit_builtin(function, &r, name, args),
+            InstKind::Array(elements) => {
+                self.line(&format!("    {}.len = {};", r, elements.len()));
+
+                if elements.is_empty() {
+                    self.line(&format!("    {}.data = NULL;", r));
+                } else {
+                    self.line(&format!("    {}.data = k_alloc({} * sizeof *{}.data);", r, elements.len(), r));
+                }
+
+                for (i, element) in elements.iter().enumerate() {
+                    self.line(&format!("    {}.data[{}] = v{};", r, i, element.0));
+                }
+            }
+            InstKind::Index(value, index) => match ty(value) {
+                Type::Str => self.line(&format!("    {} = k_str_index(v{}, v{});", r, value.0, index.0)),
+                _ => self.line(&format!(
+                    "    {} = v{}.data[k_index(v{}, v{}.len)];",
+                    r, value.0, index.0, value.0
+                )),
+            },
+            InstKind::SetIndex(array, index, value) => {
+                let (array, index) = (array.0, index.0);
+
+                self.line(&format!("    k_index(v{}, v{}.len);", index, array));
+                self.line(&format!("    {}.len = v{}.len;", r, array));
+                self.line(&format!("    {}.data = k_alloc(v{}.len * sizeof *{}.data);", r, array, r));
+                self.line(&format!(
+                    "    memcpy({}.data, v{}.data, v{}.len * sizeof *{}.data);",
+                    r, array, array, r
+                ));
+                self.line(&format!("    {}.data[v{}] = v{};", r, index, value.0));
+            }
+            InstKind::Struct(name, fields) => {
+                let fields = if fields.is_empty() { "0".to_owned() } else { values(fields) };
+                self.line(&format!("    {} = ({}){{ {} }};", r, self.c_type(&Type::Struct(name.clone())), fields));
+            }
+            InstKind::Field(value, field) => {
+                self.line(&format!("    {} = v{}.f_{};", r, value.0, mangle(field.as_str())));
+            }
+            InstKind::SetField(value, field, new) => {
+                self.line(&format!("    {} = v{};", r, value.0));
+                self.line(&format!("    {}.f_{} = v{};", r, mangle(field.as_str()), new.0));
+            }
+            InstKind::Variant(name, variant, fields) => {
+                self.line(&format!("    {}.tag = {};", r, self.tag(name, variant)));
+
+                if fields.is_empty() {
+                    self.line(&format!("    {}.payload = NULL;", r));
+                    return;
+                }
+
+                let payload = payload_name(name, variant);
+                self.line(&format!("    {}.payload = k_alloc(sizeof({}));", r, payload));
+
+                for (i, field) in fields.iter().enumerate() {
+                    self.line(&format!("    (({} *) {}.payload)->f{} = v{};", payload, r, i, field.0));
+                }
+            }
+            InstKind::IsVariant(value, variant) => {
+                let tag = match ty(value) {
+                    Type::Enum(name) => self.tag(name, variant),
+                    _ => unreachable!("only enums have variants"),
+                };
+
+                self.line(&format!("    {} = v{}.tag == {};", r, value.0, tag));
+            }
+            InstKind::Payload(value, variant, i) => {
+                let payload = match ty(value) {
+                    Type::Enum(name) => payload_name(name, variant),
+                    _ => unreachable!("only enums have variants"),
+                };
+
+                self.line(&format!("    {} = (({} *) v{}.payload)->f{};", r, payload, value.0, i));
+            }
+            InstKind::Load(global) => self.line(&format!("    {} = k_g{};", r, global.0)),
+            InstKind::Store(global, value) => self.line(&format!("    k_g{} = v{};", global.0, value.0)),
+        }
+    }
+
+    fn binary(&self, op: InfixOp, ty: &Type, left: &str, right: &str) -> String {
+        let comparison = match op {
+            InfixOp::Lt => "<",
+            InfixOp::Gt => ">",
+            InfixOp::LtEq => "<=",
+            InfixOp::GtEq => ">=",
+            InfixOp::Eq => "==",
+            InfixOp::NotEq => "!=",
+            _ => "",
+        };
+
+        match (op, ty) {
+            (InfixOp::Add, Type::Str) => format!("k_concat({}, {})", left, right),
+            (InfixOp::Mod, Type::F64) => format!("fmod({}, {})", left, right),
+            (InfixOp::Add, Type::F64) => format!("{} + {}", left, right),
+            (InfixOp::Sub, Type::F64) => format!("{} - {}", left, right),
+            (InfixOp::Mul, Type::F64) => format!("{} * {}", left, right),
+            (InfixOp::Div, Type::F64) => format!("{} / {}", left, right),
+            (InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod, _) => {
+                format!("k_{}_{}({}, {})", binary_name(op), ty, left, right)
+            }
+            (InfixOp::Eq | InfixOp::NotEq, _) if !is_scalar(ty) => {
+                let not = if op == InfixOp::NotEq { "!" } else { "" };
+                format!("{}k_eq_{}({}, {})", not, self.suffix(ty), left, right)
+            }
+            (_, Type::Str) => format!("k_str_compare({}, {}) {} 0", left, right, comparison),
+            _ => format!("{} {} {}", left, comparison, right),
+        }
+    }
+
+    fn emit_builtin(&mut self, function: &Function, r: &str, name: &Symbol, args: &[Value]) {
+        let types: Vec<&Type> = args.iter().map(|arg| function.value_type(*arg)).collect();
+        let arg = |i: usize| format!("v{}", args[i].0);
+
+        let expr = match name.as_str() {
+            "print" | "println" => {
+                for (i, ty) in types.iter().enumerate() {
+                    if i > 0 {
+                        self.line("    k_write(K_STR(\" \"));");
+                    }
+
+                    self.line(&format!("    k_write(k_display_{}({}));", self.suffix(ty), arg(i)));
+                }
+
+                if name.as_str() == "println" {
+                    self.line("    k_write(K_STR(\"\\n\"));");
+                }
+
+                return;
+            }
+            "len" if *types[0] == Type::Str => format!("k_str_len({})", arg(0)),
+            "len" => format!("(int32_t) {}.len", arg(0)),
+            "to_string" => format!("k_display_{}({})", self.suffix(types[0]), arg(0)),
+            "abs" if *types[0] == Type::F64 => format!("fabs({})", arg(0)),
+            "abs" => format!("k_abs_{}({})", types[0], arg(0)),
+            "min" if *types[0] == Type::F64 => format!("fmin({}, {})", arg(0), arg(1)),
+            "max" if *types[0] == Type::F64 => format!("fmax({}, {})", arg(0), arg(1)),
+            "min" => format!("{} < {} ? {} : {}", arg(0), arg(1), arg(0), arg(1)),
+            "max" => format!("{} > {} ? {} : {}", arg(0), arg(1), arg(0), arg(1)),
+            "sqrt" => format!("sqrt({})", arg(0)),
+            _ => unreachable!("the verifier only allows builtins that exist"),
+        };
+
+        self.line(&format!("    {} = {};", r, expr));
+    }
+
+    fn emit_terminator(&mut self, function: &Function, terminator: &Terminator) {
+        match terminator {
+            Terminator::Jump(target) => self.emit_jump(function, target, "    "),
+            Terminator::Branch(condition, then, otherwise) => {
+                self.line(&format!("    if (v{}) {{", condition.0));
+                self.emit_jump(function, then, "        ");
+                self.line("    } else {");
+                self.emit_jump(function, otherwise, "        ");
+                self.line("    }");
+            }
+            Terminator::Return(Some(value)) => self.line(&format!("    return v{};", value.0)),
+            Terminator::Return(None) => self.line("    return;"),
+            Terminator::Trap(message) => self.line(&format!("    k_trap({});", c_string(message))),
+            Terminator::Unreachable => self.line("    abort();"),
+        }
+    }
+
+    /// Passes a jump's arguments to its target's parameters, through
+    /// temporaries since the arguments can be other parameters of the
+    /// target.
+    fn emit_jump(&mut self, function: &Function, target: &Target, indent: &str) {
+        let params = &function.block(target.block).params;
+        let moves: Vec<(Value, Value)> = params
+            .iter()
+            .copied()
+            .zip(target.args.iter().copied())
+            .filter(|(param, arg)| param != arg)
+            .collect();
+
+        if let [(param, arg)] = moves[..] {
+            self.line(&format!("{}v{} = v{};", indent, param.0, arg.0));
+        } else if !moves.is_empty() {
+            self.line(&format!("{}{{", indent));
+
+            for (i, (param, arg)) in moves.iter().enumerate() {
+                let ty = self.c_type(function.value_type(*param));
+                self.line(&format!("{}    {} t{} = v{};", indent, ty, i, arg.0));
+            }
+
+            for (i, (param, _)) in moves.iter().enumerate() {
+                self.line(&format!("{}    v{} = t{};", indent, param.0, i));
+            }
+
+            self.line(&format!("{}}}", indent));
+        }
+
+        self.line(&format!("{}goto bb{};", indent, target.block.0));
+    }
+
+    fn tag(&self, name: &Symbol, variant: &Symbol) -> usize {
+        self.module
+            .enum_variants(name)
+            .and_then(|variants| variants.iter().position(|(other, _)| other == variant))
+            .expect("the verifier only allows variants that exist")
+    }
+}
+
+/// Orders structs so each comes after the structs it holds, failing if a
+/// struct holds itself.
+fn struct_order(
+    module: &Module,
+    name: Symbol,
+    visiting: &mut Vec<Symbol>,
+    order: &mut Vec<Symbol>,
+) -> Result<(), BuildError> {
+    if order.contains(&name) {
+        return Ok(());
+    }
+
+    if visiting.contains(&name) {
+        let message = format!("cannot generate C for struct `{}`, which contains itself", name);
+        return Err(BuildError::new(message));
+    }
+
+    visiting.push(name.clone());
+
+    for (_, ty) in module.struct_fields(&name).into_iter().flatten() {
+        if let Type::Struct(field) = ty {
+            struct_order(module, field.clone(), visiting, order)?;
+        }
+    }
+
+    visiting.pop();
+    order.push(name);
+
+    Ok(())
+}
+
+fn is_scalar(ty: &Type) -> bool {
+    matches!(ty, Type::I32 | Type::I64 | Type::F64 | Type::Bool | Type::Char)
+}
+
+fn constant_value(constant: &Constant, ty: &Type) -> String {
+    match constant {
+        Constant::Int(value) if *ty == Type::I32 && *value == i64::from(i32::MIN) => "INT32_MIN".to_owned(),
+        Constant::Int(value) if *ty == Type::I64 && *value == i64::MIN => "INT64_MIN".to_owned(),
+        Constant::Int(value) if *ty == Type::I64 => format!("INT64_C({})", value),
+        Constant::Int(value) => value.to_string(),
+        Constant::Float(value) if value.is_nan() => "NAN".to_owned(),
+        Constant::Float(value) if value.is_infinite() => {
+            if *value > 0.0 { "INFINITY" } else { "-INFINITY" }.to_owned()
+        }
+        Constant::Float(value) => format!("{:?}", value),
+        Constant::Bool(value) => value.to_string(),
+        Constant::Char(value) => format!("{}", *value as u32),
+        Constant::Str(value) => format!("K_STR({})", c_string(value)),
+    }
+}
+
+fn values(values: &[Value]) -> String {
+    let values: Vec<String> = values.iter().map(|value| format!("v{}", value.0)).collect();
+    values.join(", ")
+}
+
+fn conjunction(terms: &[String]) -> String {
+    if terms.is_empty() {
+        "true".to_owned()
+    } else {
+        terms.join(" && ")
+    }
+}
+
+fn function_name(name: &str) -> String {
+    format!("k_f_{}", mangle(name))
+}
+
+fn payload_name(name: &Symbol, variant: &Symbol) -> String {
+    format!("k_p_{}_{}", mangle(name.as_str()), mangle(variant.as_str()))
+}
+
+/// Writes a string as a C string literal. Bytes other than printable ASCII
+/// are escaped in octal, and `?` is escaped so it can't start a trigraph.
+pub fn c_string(value: &str) -> String {
+    let mut literal = String::from("\"");
+
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' | b'?' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_string() {
+        let tests = vec![
+            ("hello", "\"hello\""),
+            ("say \"hi\"\n", "\"say \\\"hi\\\"\\012\""),
+            ("a\\b??=", "\"a\\\\b\\?\\?=\""),
+            ("\0", "\"\\000\""),
+            ("é", "\"\\303\\251\""),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(c_string(input), expected, "quoting {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let a = Symbol::intern("A");
+        let b = Symbol::intern("B");
+
+        let module = Module {
+            structs: vec![
+                (a.clone(), vec![(Symbol::intern("b"), Type::Struct(b.clone()))]),
+                (b, vec![(Symbol::intern("a"), Type::Struct(a))]),
+            ],
+            ..Module::default()
+        };
+
+        let error = emit(&module).unwrap_err();
+        assert_eq!(error.message, "cannot generate C for struct `A`, which contains itself");
+
+        let module = Module {
+            globals: vec![(Symbol::intern("f"), Type::Fn(Vec::new(), Box::new(Type::Void)))],
+            ..Module::default()
+        };
+
+        let error = emit(&module).unwrap_err();
+        assert_eq!(error.message, "cannot generate C for a value of type fn(): void");
+    }
+}
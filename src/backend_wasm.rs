@@ -0,0 +1,935 @@
+//! Generates WebAssembly from the IR, in the text format or as a binary.
+//!
+//! Values of type `i64` and `f64` are WebAssembly values of the same type,
+//! and everything else is an `i32`: booleans, characters, and pointers into
+//! linear memory for strings, arrays, structs and enums. Strings are their
+//! length in bytes followed by their UTF-8 bytes. Arrays are their length
+//! followed by their elements, structs their fields, and enums their tag
+//! followed by their payload, with every element, field and tag taking
+//! eight bytes. Like the IR, the program never writes to memory it has
+//! already handed out, so those pointers can be copied freely.
+//!
+//! Blocks become cases of a loop that dispatches on the block to run next.
+//!
+//! The module exports its `memory` and a function `run` that runs the
+//! program, and imports three functions from the host under `k`:
+//!
+//! - `write(ptr: i32, len: i32)` writes the UTF-8 bytes at `ptr` to the
+//!   program's output.
+//! - `trap(ptr: i32, len: i32)` stops the program with the runtime error
+//!   whose message is the UTF-8 bytes at `ptr`.
+//! - `format_f64(value: f64, ptr: i32): i32` writes `value` as the
+//!   interpreter would print it to `ptr`, which has room for 64 bytes, and
+//!   returns how many bytes it wrote.
+
+use crate::ast::{InfixOp, PrefixOp};
+use crate::backend::{mangle, BuildError};
+use crate::ir::*;
+use crate::symbol::Symbol;
+use crate::types::Type;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The runtime that is pasted into every generated module.
+pub const RUNTIME: &str = include_str!("../runtime/k.wat");
+
+/// Where string constants start. Memory below this is scratch space for
+/// the runtime.
+const DATA_START: usize = 64;
+
+/// Strings the runtime refers to by the names of globals.
+const RUNTIME_STRINGS: &[(&str, &str)] = &[
+    ("true", "true"),
+    ("false", "false"),
+    ("integer_overflow", "integer overflow"),
+    ("divide_by_zero", "attempt to divide by zero"),
+    ("index_out_of_bounds", "index out of bounds: the length is "),
+    ("but_the_index_is", " but the index is "),
+    ("out_of_memory", "out of memory"),
+];
+
+/// Writes a module in the WebAssembly text format to `output`.
+pub fn build_text(module: &Module, output: &Path) -> Result<(), BuildError> {
+    fs::write(output, emit(module)?)?;
+    Ok(())
+}
+
+/// Writes a module as a binary WebAssembly module to `output`.
+pub fn build_binary(module: &Module, output: &Path) -> Result<(), BuildError> {
+    fs::write(output, assemble(&emit(module)?)?)?;
+    Ok(())
+}
+
+/// Turns the text format into a binary module.
+pub fn assemble(text: &str) -> Result<Vec<u8>, BuildError> {
+    wat::parse_str(text).map_err(|error| BuildError::new(format!("invalid WebAssembly: {}", error)))
+}
+
+/// Generates the text format of a module.
+pub fn emit(module: &Module) -> Result<String, BuildError> {
+    let mut emitter = Emitter {
+        module,
+        arrays: Vec::new(),
+        data: Vec::new(),
+        strings: HashMap::new(),
+        out: String::new(),
+    };
+
+    emitter.emit_module()?;
+    Ok(emitter.out)
+}
+
+struct Emitter<'a> {
+    module: &'a Module,
+    // The element type of every array type in the module.
+    arrays: Vec<Type>,
+    // The contents of memory from `DATA_START`, and where each string
+    // constant is in it.
+    data: Vec<u8>,
+    strings: HashMap<String, usize>,
+    out: String,
+}
+
+impl<'a> Emitter<'a> {
+    fn line(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Writes an instruction inside a function.
+    fn op(&mut self, text: &str) {
+        self.out.push_str("  ");
+        self.line(text);
+    }
+
+    /// The address of a string constant, adding it to the data if it's new.
+    fn string(&mut self, value: &str) -> usize {
+        if let Some(address) = self.strings.get(value) {
+            return *address;
+        }
+
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+
+        let address = DATA_START + self.data.len();
+
+        self.data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(value.as_bytes());
+        self.strings.insert(value.to_owned(), address);
+
+        address
+    }
+
+    fn emit_module(&mut self) -> Result<(), BuildError> {
+        let module = self.module;
+        for (_, fields) in &module.structs {
+            for (_, ty) in fields {
+                self.add_type(ty)?;
+            }
+        }
+
+        for (_, variants) in &module.enums {
+            for ty in variants.iter().flat_map(|(_, fields)| fields) {
+                self.add_type(ty)?;
+            }
+        }
+
+        for (_, ty) in &module.globals {
+            self.add_type(ty)?;
+        }
+
+        for function in &module.functions {
+            if function.ret != Type::Void {
+                self.add_type(&function.ret)?;
+            }
+
+            for ty in &function.types {
+                self.add_type(ty)?;
+            }
+        }
+
+        // Functions are generated first, so every string constant is known
+        // by the time the data is written.
+        let header = std::mem::take(&mut self.out);
+
+        self.emit_helpers();
+
+        for function in &module.functions {
+            self.line("");
+            self.emit_function(function);
+        }
+
+        let code = std::mem::replace(&mut self.out, header);
+
+        let runtime_strings: Vec<(&str, usize)> =
+            RUNTIME_STRINGS.iter().map(|(name, value)| (*name, self.string(value))).collect();
+
+        let heap = (DATA_START + self.data.len()).div_ceil(8) * 8;
+        let pages = heap / 65536 + 1;
+
+        self.line("(module");
+        self.line("(import \"k\" \"write\" (func $k_host_write (param i32 i32)))");
+        self.line("(import \"k\" \"trap\" (func $k_host_trap (param i32 i32)))");
+        self.line("(import \"k\" \"format_f64\" (func $k_host_format_f64 (param f64 i32) (result i32)))");
+        self.line(&format!("(memory (export \"memory\") {})", pages));
+        self.line(&format!("(global $k_heap (mut i32) (i32.const {}))", heap));
+
+        for (name, address) in runtime_strings {
+            self.line(&format!("(global $k_str_{} i32 (i32.const {}))", name, address));
+        }
+
+        for (i, (_, ty)) in module.globals.iter().enumerate() {
+            let ty = valtype(ty);
+            self.line(&format!("(global $g{} (mut {}) ({}.const 0))", i, ty, ty));
+        }
+
+        let entry = function_name(&module.function(module.entry).name);
+        self.line(&format!("(export \"run\" (func {}))", entry));
+        self.line("");
+        self.out.push_str(RUNTIME);
+        self.out.push_str(&code);
+        self.line("");
+        self.line(&format!("(data (i32.const {}) \"{}\")", DATA_START, escape(&self.data)));
+        self.line(")");
+
+        Ok(())
+    }
+
+    /// Checks that values of a type can be represented, and records the
+    /// array types it uses.
+    fn add_type(&mut self, ty: &Type) -> Result<(), BuildError> {
+        match ty {
+            Type::I32 | Type::I64 | Type::F64 | Type::Bool | Type::Char | Type::Str => Ok(()),
+            Type::Struct(_) | Type::Enum(_) => Ok(()),
+            Type::Array(element) => {
+                self.add_type(element)?;
+
+                if !self.arrays.contains(element) {
+                    self.arrays.push((**element).clone());
+                }
+
+                Ok(())
+            }
+            _ => Err(BuildError::new(format!("cannot generate WebAssembly for a value of type {}", ty))),
+        }
+    }
+
+    /// The name types are known by in the names of their helpers.
+    fn suffix(&self, ty: &Type) -> String {
+        match ty {
+            Type::Struct(name) => format!("s_{}", mangle(name.as_str())),
+            Type::Enum(name) => format!("e_{}", mangle(name.as_str())),
+            Type::Array(element) => {
+                let index = self.arrays.iter().position(|ty| ty == &**element);
+                format!("a{}", index.expect("array types are added before they are used"))
+            }
+            _ => ty.to_string(),
+        }
+    }
+
+    /// Defines how values of each struct, enum and array type are written
+    /// and compared.
+    fn emit_helpers(&mut self) {
+        let module = self.module;
+
+        for (name, fields) in &module.structs {
+            self.emit_struct_helpers(name, fields);
+        }
+
+        for (name, variants) in &module.enums {
+            self.emit_enum_helpers(name, variants);
+        }
+
+        for element in self.arrays.clone() {
+            self.emit_array_helpers(&element);
+        }
+    }
+
+    fn emit_struct_helpers(&mut self, name: &Symbol, fields: &[(Symbol, Type)]) {
+        let suffix = self.suffix(&Type::Struct(name.clone()));
+
+        self.line("");
+        self.line(&format!("(func $k_display_{} (param $value i32) (result i32)", suffix));
+        self.op("local.get $value");
+        self.op(&format!("call $k_inspect_{})", suffix));
+
+        self.line("");
+        self.line(&format!("(func $k_inspect_{} (param $value i32) (result i32)", suffix));
+        let mut label = format!("{} {{ ", name);
+
+        for (i, (field, ty)) in fields.iter().enumerate() {
+            if i > 0 {
+                label.push_str(", ");
+            }
+
+            label.push_str(&format!("{}: ", field));
+            self.push_string(&label, i > 0);
+            self.op("local.get $value");
+            self.op(&format!("{} offset={}", load(ty), 8 * i));
+            self.op(&format!("call $k_inspect_{}", self.suffix(ty)));
+            self.op("call $k_concat");
+            label.clear();
+        }
+
+        label.push_str(" }");
+        self.push_string(&label, !fields.is_empty());
+        self.out.push_str(")\n");
+
+        self.line("");
+        self.line(&format!("(func $k_eq_{} (param $a i32) (param $b i32) (result i32)", suffix));
+        self.op("i32.const 1");
+
+        for (i, (_, ty)) in fields.iter().enumerate() {
+            self.emit_compare_slot(ty, 8 * i);
+        }
+
+        self.out.push_str(")\n");
+    }
+
+    fn emit_enum_helpers(&mut self, name: &Symbol, variants: &[(Symbol, Vec<Type>)]) {
+        let suffix = self.suffix(&Type::Enum(name.clone()));
+
+        // A variant is displayed with its fields displayed, and inspected
+        // with its fields inspected.
+        for how in &["display", "inspect"] {
+            self.line("");
+            self.line(&format!("(func $k_{}_{} (param $value i32) (result i32)", how, suffix));
+
+            for (tag, (variant, fields)) in variants.iter().enumerate() {
+                self.op("local.get $value");
+                self.op("i32.load");
+                self.op(&format!("i32.const {}", tag));
+                self.op("i32.eq");
+                self.op("if");
+
+                if fields.is_empty() {
+                    self.push_string(&format!("{}.{}", name, variant), false);
+                } else {
+                    for (i, ty) in fields.iter().enumerate() {
+                        let label = if i == 0 { format!("{}.{}(", name, variant) } else { ", ".to_owned() };
+
+                        self.push_string(&label, i > 0);
+                        self.op("local.get $value");
+                        self.op(&format!("{} offset={}", load(ty), 8 + 8 * i));
+                        self.op(&format!("call $k_{}_{}", how, self.suffix(ty)));
+                        self.op("call $k_concat");
+                    }
+
+                    self.push_string(")", true);
+                }
+
+                self.op("return");
+                self.op("end");
+            }
+
+            self.op("unreachable)");
+        }
+
+        self.line("");
+        self.line(&format!("(func $k_eq_{} (param $a i32) (param $b i32) (result i32)", suffix));
+        self.op("local.get $a");
+        self.op("i32.load");
+        self.op("local.get $b");
+        self.op("i32.load");
+        self.op("i32.ne");
+        self.op("if");
+        self.op("i32.const 0");
+        self.op("return");
+        self.op("end");
+
+        for (tag, (_, fields)) in variants.iter().enumerate() {
+            if fields.is_empty() {
+                continue;
+            }
+
+            self.op("local.get $a");
+            self.op("i32.load");
+            self.op(&format!("i32.const {}", tag));
+            self.op("i32.eq");
+            self.op("if");
+            self.op("i32.const 1");
+
+            for (i, ty) in fields.iter().enumerate() {
+                self.emit_compare_slot(ty, 8 + 8 * i);
+            }
+
+            self.op("return");
+            self.op("end");
+        }
+
+        self.op("i32.const 1)");
+    }
+
+    fn emit_array_helpers(&mut self, element: &Type) {
+        let suffix = self.suffix(&Type::Array(Box::new(element.clone())));
+        let element_suffix = self.suffix(element);
+
+        self.line("");
+        self.line(&format!("(func $k_display_{} (param $value i32) (result i32)", suffix));
+        self.op("local.get $value");
+        self.op(&format!("call $k_inspect_{})", suffix));
+
+        self.line("");
+        self.line(&format!("(func $k_inspect_{} (param $value i32) (result i32)", suffix));
+        self.op("(local $s i32) (local $i i32)");
+        self.push_string("[", false);
+        self.op("local.set $s");
+        self.op("block $done");
+        self.op("loop $next");
+        self.op("local.get $i");
+        self.op("local.get $value");
+        self.op("i32.load");
+        self.op("i32.ge_u");
+        self.op("br_if $done");
+        self.op("local.get $i");
+        self.op("if");
+        self.op("local.get $s");
+        self.push_string(", ", true);
+        self.op("local.set $s");
+        self.op("end");
+        self.op("local.get $s");
+        self.emit_element_address("$value", "$i");
+        self.op(&format!("{} offset=8", load(element)));
+        self.op(&format!("call $k_inspect_{}", element_suffix));
+        self.op("call $k_concat");
+        self.op("local.set $s");
+        self.op("local.get $i");
+        self.op("i32.const 1");
+        self.op("i32.add");
+        self.op("local.set $i");
+        self.op("br $next");
+        self.op("end");
+        self.op("end");
+        self.op("local.get $s");
+        self.push_string("]", true);
+        self.out.push_str(")\n");
+
+        self.line("");
+        self.line(&format!("(func $k_eq_{} (param $a i32) (param $b i32) (result i32)", suffix));
+        self.op("(local $i i32)");
+        self.op("local.get $a");
+        self.op("i32.load");
+        self.op("local.get $b");
+        self.op("i32.load");
+        self.op("i32.ne");
+        self.op("if");
+        self.op("i32.const 0");
+        self.op("return");
+        self.op("end");
+        self.op("block $done");
+        self.op("loop $next");
+        self.op("local.get $i");
+        self.op("local.get $a");
+        self.op("i32.load");
+        self.op("i32.ge_u");
+        self.op("br_if $done");
+        self.emit_element_address("$a", "$i");
+        self.op(&format!("{} offset=8", load(element)));
+        self.emit_element_address("$b", "$i");
+        self.op(&format!("{} offset=8", load(element)));
+        self.op(&format!("call $k_eq_{}", element_suffix));
+        self.op("i32.eqz");
+        self.op("if");
+        self.op("i32.const 0");
+        self.op("return");
+        self.op("end");
+        self.op("local.get $i");
+        self.op("i32.const 1");
+        self.op("i32.add");
+        self.op("local.set $i");
+        self.op("br $next");
+        self.op("end");
+        self.op("end");
+        self.op("i32.const 1)");
+    }
+
+    /// Pushes a string constant, concatenating it onto the string already
+    /// on the stack if `append` is set.
+    fn push_string(&mut self, value: &str, append: bool) {
+        let address = self.string(value);
+        self.op(&format!("i32.const {}", address));
+
+        if append {
+            self.op("call $k_concat");
+        }
+    }
+
+    /// Compares the values at `offset` in `$a` and `$b`, and ands the result
+    /// with the boolean on the stack.
+    fn emit_compare_slot(&mut self, ty: &Type, offset: usize) {
+        self.op("local.get $a");
+        self.op(&format!("{} offset={}", load(ty), offset));
+        self.op("local.get $b");
+        self.op(&format!("{} offset={}", load(ty), offset));
+        self.op(&format!("call $k_eq_{}", self.suffix(ty)));
+        self.op("i32.and");
+    }
+
+    /// Pushes the address of element `index` of `array`, less the eight
+    /// bytes of its length.
+    fn emit_element_address(&mut self, array: &str, index: &str) {
+        self.op(&format!("local.get {}", array));
+        self.op(&format!("local.get {}", index));
+        self.op("i32.const 3");
+        self.op("i32.shl");
+        self.op("i32.add");
+    }
+
+    fn emit_function(&mut self, function: &Function) {
+        let mut signature = format!("(func {}", function_name(&function.name));
+
+        for param in function.params() {
+            signature.push_str(&format!(" (param $v{} {})", param.0, valtype(function.value_type(*param))));
+        }
+
+        if function.ret != Type::Void {
+            signature.push_str(&format!(" (result {})", valtype(&function.ret)));
+        }
+
+        self.line(&signature);
+
+        let locals = function.blocks[1..]
+            .iter()
+            .flat_map(|block| block.params.iter().copied())
+            .chain(function.blocks.iter().flat_map(|block| &block.insts).filter_map(|inst| inst.result));
+
+        for value in locals {
+            self.op(&format!("(local $v{} {})", value.0, valtype(function.value_type(value))));
+        }
+
+        if function.blocks.len() == 1 {
+            self.emit_block(function, &function.blocks[0]);
+            self.out.push_str(")\n");
+            return;
+        }
+
+        // Each block's code follows the end of the WebAssembly block with
+        // its label, so branching there from the table runs it.
+        let labels: Vec<String> = (0..function.blocks.len()).map(|i| format!("$bb{}", i)).collect();
+
+        self.op("(local $block i32)");
+        self.op("loop $dispatch");
+
+        for label in labels.iter().rev() {
+            self.op(&format!("block {}", label));
+        }
+
+        self.op("local.get $block");
+        self.op(&format!("br_table {}", labels.join(" ")));
+
+        for block in &function.blocks {
+            self.op("end");
+            self.emit_block(function, block);
+        }
+
+        self.op("end");
+        self.op("unreachable)");
+    }
+
+    fn emit_block(&mut self, function: &Function, block: &Block) {
+        for inst in &block.insts {
+            self.emit_inst(function, inst);
+        }
+
+        match &block.terminator {
+            Terminator::Jump(target) => self.emit_jump(function, target),
+            Terminator::Branch(condition, then, otherwise) => {
+                self.op(&format!("local.get $v{}", condition.0));
+                self.op("if");
+                self.emit_jump(function, then);
+                self.op("else");
+                self.emit_jump(function, otherwise);
+                self.op("end");
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.op(&format!("local.get $v{}", value.0));
+                }
+
+                self.op("return");
+            }
+            Terminator::Trap(message) => {
+                self.push_string(message, false);
+                self.op("call $k_trap");
+                self.op("unreachable");
+            }
+            Terminator::Unreachable => self.op("unreachable"),
+        }
+    }
+
+    /// Passes a jump's arguments to its target's parameters through the
+    /// stack, since the arguments can be other parameters of the target.
+    fn emit_jump(&mut self, function: &Function, target: &Target) {
+        let params = &function.block(target.block).params;
+
+        for arg in &target.args {
+            self.op(&format!("local.get $v{}", arg.0));
+        }
+
+        for param in params.iter().rev() {
+            self.op(&format!("local.set $v{}", param.0));
+        }
+
+        self.op(&format!("i32.const {}", target.block.0));
+        self.op("local.set $block");
+        self.op("br $dispatch");
+    }
+
+    fn emit_inst(&mut self, function: &Function, inst: &Inst) {
+        let ty = |value: &Value| function.value_type(*value);
+
+        match &inst.kind {
+            InstKind::Const(constant) => {
+                let result_type = function.value_type(inst.result.expect("constants have a value"));
+
+                let op = match constant {
+                    Constant::Int(value) => format!("{}.const {}", valtype(result_type), value),
+                    Constant::Float(value) if value.is_nan() => "f64.const nan".to_owned(),
+                    Constant::Float(value) if value.is_infinite() => {
+                        format!("f64.const {}", if *value > 0.0 { "inf" } else { "-inf" })
+                    }
+                    Constant::Float(value) => format!("f64.const {:?}", value),
+                    Constant::Bool(value) => format!("i32.const {}", *value as i32),
+                    Constant::Char(value) => format!("i32.const {}", *value as u32),
+                    Constant::Str(value) => format!("i32.const {}", self.string(value)),
+                };
+
+                self.op(&op);
+            }
+            InstKind::Copy(value) => self.op(&format!("local.get $v{}", value.0)),
+            InstKind::Unary(op, value) => {
+                self.op(&format!("local.get $v{}", value.0));
+
+                match (op, ty(value)) {
+                    (PrefixOp::Neg, Type::F64) => self.op("f64.neg"),
+                    (PrefixOp::Neg, ty) => self.op(&format!("call $k_neg_{}", ty)),
+                    (PrefixOp::Not, _) => self.op("i32.eqz"),
+                }
+            }
+            InstKind::Binary(op, left, right) => {
+                self.op(&format!("local.get $v{}", left.0));
+                self.op(&format!("local.get $v{}", right.0));
+                self.emit_binary(*op, ty(left));
+            }
+            InstKind::ToString(value) => {
+                self.op(&format!("local.get $v{}", value.0));
+                self.op(&format!("call $k_display_{}", self.suffix(ty(value))));
+            }
+            InstKind::Call(Callee::Function(id), args) => {
+                for arg in args {
+                    self.op(&format!("local.get $v{}", arg.0));
+                }
+
+                self.op(&format!("call {}", function_name(&self.module.function(*id).name)));
+            }
+            InstKind::Call(Callee::Builtin(name), args) => self.emit_builtin(function, name, args),
+            InstKind::Array(elements) => {
+                let result = inst.result.expect("arrays have a value");
+                let element = match ty(&result) {
+                    Type::Array(element) => &**element,
+                    _ => unreachable!("arrays have an array type"),
+                };
+
+                self.op(&format!("i32.const {}", 8 + 8 * elements.len()));
+                self.op("call $k_alloc");
+                self.op(&format!("local.tee $v{}", result.0));
+                self.op(&format!("i32.const {}", elements.len()));
+                self.op("i32.store");
+
+                for (i, value) in elements.iter().enumerate() {
+                    self.op(&format!("local.get $v{}", result.0));
+                    self.op(&format!("local.get $v{}", value.0));
+                    self.op(&format!("{} offset={}", store(element), 8 + 8 * i));
+                }
+
+                return;
+            }
+            InstKind::Index(value, index) => {
+                let result_type = function.value_type(inst.result.expect("indexing has a value"));
+
+                if *ty(value) == Type::Str {
+                    self.op(&format!("local.get $v{}", value.0));
+                    self.emit_index(ty(index), index);
+                    self.op("call $k_str_index");
+                } else {
+                    self.op(&format!("local.get $v{}", value.0));
+                    self.emit_index(ty(index), index);
+                    self.op(&format!("local.get $v{}", value.0));
+                    self.op("i32.load");
+                    self.op("call $k_index");
+                    self.op("i32.const 3");
+                    self.op("i32.shl");
+                    self.op("i32.add");
+                    self.op(&format!("{} offset=8", load(result_type)));
+                }
+            }
+            InstKind::SetIndex(array, index, value) => {
+                let result = inst.result.expect("setting an element has a value");
+
+                self.op(&format!("local.get $v{}", array.0));
+                self.op(&format!("local.get $v{}", array.0));
+                self.op("i32.load");
+                self.op("i32.const 3");
+                self.op("i32.shl");
+                self.op("i32.const 8");
+                self.op("i32.add");
+                self.op("call $k_copy");
+                self.op(&format!("local.tee $v{}", result.0));
+                self.emit_index(ty(index), index);
+                self.op(&format!("local.get $v{}", array.0));
+                self.op("i32.load");
+                self.op("call $k_index");
+                self.op("i32.const 3");
+                self.op("i32.shl");
+                self.op("i32.add");
+                self.op(&format!("local.get $v{}", value.0));
+                self.op(&format!("{} offset=8", store(ty(value))));
+                return;
+            }
+            InstKind::Struct(_, fields) => {
+                let result = inst.result.expect("structs have a value");
+
+                self.op(&format!("i32.const {}", 8 * fields.len().max(1)));
+                self.op("call $k_alloc");
+                self.op(&format!("local.set $v{}", result.0));
+
+                for (i, value) in fields.iter().enumerate() {
+                    self.op(&format!("local.get $v{}", result.0));
+                    self.op(&format!("local.get $v{}", value.0));
+                    self.op(&format!("{} offset={}", store(ty(value)), 8 * i));
+                }
+
+                return;
+            }
+            InstKind::Field(value, field) => {
+                let (i, field_type) = self.field(ty(value), field);
+
+                self.op(&format!("local.get $v{}", value.0));
+                self.op(&format!("{} offset={}", load(field_type), 8 * i));
+            }
+            InstKind::SetField(value, field, new) => {
+                let result = inst.result.expect("setting a field has a value");
+                let (i, _) = self.field(ty(value), field);
+                let count = match ty(value) {
+                    Type::Struct(name) => self.module.struct_fields(name).map_or(0, |fields| fields.len()),
+                    _ => unreachable!("only structs have fields"),
+                };
+
+                self.op(&format!("local.get $v{}", value.0));
+                self.op(&format!("i32.const {}", 8 * count.max(1)));
+                self.op("call $k_copy");
+                self.op(&format!("local.tee $v{}", result.0));
+                self.op(&format!("local.get $v{}", new.0));
+                self.op(&format!("{} offset={}", store(ty(new)), 8 * i));
+                return;
+            }
+            InstKind::Variant(name, variant, fields) => {
+                let result = inst.result.expect("variants have a value");
+
+                self.op(&format!("i32.const {}", 8 + 8 * fields.len()));
+                self.op("call $k_alloc");
+                self.op(&format!("local.tee $v{}", result.0));
+                self.op(&format!("i32.const {}", self.tag(name, variant)));
+                self.op("i32.store");
+
+                for (i, value) in fields.iter().enumerate() {
+                    self.op(&format!("local.get $v{}", result.0));
+                    self.op(&format!("local.get $v{}", value.0));
+                    self.op(&format!("{} offset={}", store(ty(value)), 8 + 8 * i));
+                }
+
+                return;
+            }
+            InstKind::IsVariant(value, variant) => {
+                let tag = match ty(value) {
+                    Type::Enum(name) => self.tag(name, variant),
+                    _ => unreachable!("only enums have variants"),
+                };
+
+                self.op(&format!("local.get $v{}", value.0));
+                self.op("i32.load");
+                self.op(&format!("i32.const {}", tag));
+                self.op("i32.eq");
+            }
+            InstKind::Payload(value, _, i) => {
+                let result_type = function.value_type(inst.result.expect("payloads have a value"));
+
+                self.op(&format!("local.get $v{}", value.0));
+                self.op(&format!("{} offset={}", load(result_type), 8 + 8 * i));
+            }
+            InstKind::Load(global) => self.op(&format!("global.get $g{}", global.0)),
+            InstKind::Store(global, value) => {
+                self.op(&format!("local.get $v{}", value.0));
+                self.op(&format!("global.set $g{}", global.0));
+            }
+        }
+
+        if let Some(result) = inst.result {
+            self.op(&format!("local.set $v{}", result.0));
+        }
+    }
+
+    /// Pushes an index as an `i64`, which is what the runtime checks.
+    fn emit_index(&mut self, ty: &Type, index: &Value) {
+        self.op(&format!("local.get $v{}", index.0));
+
+        if *ty != Type::I64 {
+            self.op("i64.extend_i32_s");
+        }
+    }
+
+    fn emit_binary(&mut self, op: InfixOp, ty: &Type) {
+        let comparison = match op {
+            InfixOp::Lt => "lt",
+            InfixOp::Gt => "gt",
+            InfixOp::LtEq => "le",
+            InfixOp::GtEq => "ge",
+            InfixOp::Eq => "eq",
+            InfixOp::NotEq => "ne",
+            _ => "",
+        };
+
+        match (op, ty) {
+            (InfixOp::Add, Type::Str) => self.op("call $k_concat"),
+            (InfixOp::Mod, Type::F64) => self.op("call $k_rem_f64"),
+            (InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div, Type::F64) => {
+                self.op(&format!("f64.{}", binary_name(op)))
+            }
+            (InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod, _) => {
+                self.op(&format!("call $k_{}_{}", binary_name(op), ty))
+            }
+            (InfixOp::Eq | InfixOp::NotEq, Type::Str | Type::Array(_) | Type::Struct(_) | Type::Enum(_)) => {
+                self.op(&format!("call $k_eq_{}", self.suffix(ty)));
+
+                if op == InfixOp::NotEq {
+                    self.op("i32.eqz");
+                }
+            }
+            (_, Type::Str) => {
+                self.op("call $k_str_compare");
+                self.op("i32.const 0");
+                self.op(&format!("i32.{}_s", comparison));
+            }
+            (InfixOp::Eq | InfixOp::NotEq, _) | (_, Type::F64) => {
+                self.op(&format!("{}.{}", valtype(ty), comparison))
+            }
+            (_, Type::Char) => self.op(&format!("i32.{}_u", comparison)),
+            _ => self.op(&format!("{}.{}_s", valtype(ty), comparison)),
+        }
+    }
+
+    fn emit_builtin(&mut self, function: &Function, name: &Symbol, args: &[Value]) {
+        let types: Vec<&Type> = args.iter().map(|arg| function.value_type(*arg)).collect();
+
+        if let "print" | "println" = name.as_str() {
+            for (i, (arg, ty)) in args.iter().zip(&types).enumerate() {
+                if i > 0 {
+                    self.push_string(" ", false);
+                    self.op("call $k_print");
+                }
+
+                self.op(&format!("local.get $v{}", arg.0));
+                self.op(&format!("call $k_display_{}", self.suffix(ty)));
+                self.op("call $k_print");
+            }
+
+            if name.as_str() == "println" {
+                self.push_string("\n", false);
+                self.op("call $k_print");
+            }
+
+            return;
+        }
+
+        for arg in args {
+            self.op(&format!("local.get $v{}", arg.0));
+        }
+
+        match (name.as_str(), types[0]) {
+            ("len", Type::Str) => self.op("call $k_str_len"),
+            ("len", _) => self.op("i32.load"),
+            ("to_string", ty) => self.op(&format!("call $k_display_{}", self.suffix(ty))),
+            ("abs", Type::F64) => self.op("f64.abs"),
+            ("sqrt", _) => self.op("f64.sqrt"),
+            (name, ty) => self.op(&format!("call $k_{}_{}", name, ty)),
+        }
+    }
+
+    fn field(&self, ty: &Type, field: &Symbol) -> (usize, &'a Type) {
+        match ty {
+            Type::Struct(name) => self
+                .module
+                .struct_fields(name)
+                .and_then(|fields| fields.iter().enumerate().find(|(_, (other, _))| other == field))
+                .map(|(i, (_, ty))| (i, ty))
+                .expect("the verifier only allows fields that exist"),
+            _ => unreachable!("only structs have fields"),
+        }
+    }
+
+    fn tag(&self, name: &Symbol, variant: &Symbol) -> usize {
+        self.module
+            .enum_variants(name)
+            .and_then(|variants| variants.iter().position(|(other, _)| other == variant))
+            .expect("the verifier only allows variants that exist")
+    }
+}
+
+fn function_name(name: &str) -> String {
+    format!("$f_{}", mangle(name))
+}
+
+fn valtype(ty: &Type) -> &'static str {
+    match ty {
+        Type::I64 => "i64",
+        Type::F64 => "f64",
+        _ => "i32",
+    }
+}
+
+fn load(ty: &Type) -> String {
+    format!("{}.load", valtype(ty))
+}
+
+fn store(ty: &Type) -> String {
+    format!("{}.store", valtype(ty))
+}
+
+/// Writes bytes as the contents of a string in the text format.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => escaped.push_str(&format!("\\{}", *byte as char)),
+            b' '..=b'~' => escaped.push(*byte as char),
+            _ => escaped.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(b"say \"hi\"\n"), "say \\\"hi\\\"\\0a");
+        assert_eq!(escape(&[5, 0, 0, 0, 0xc3, 0xa9]), "\\05\\00\\00\\00\\c3\\a9");
+    }
+
+    #[test]
+    fn test_errors() {
+        let module = Module {
+            globals: vec![(Symbol::intern("f"), Type::Fn(Vec::new(), Box::new(Type::Void)))],
+            ..Module::default()
+        };
+
+        let error = emit(&module).unwrap_err();
+        assert_eq!(error.message, "cannot generate WebAssembly for a value of type fn(): void");
+    }
+}
@@ -0,0 +1,645 @@
+//! Generates x86-64 assembly for the GNU assembler from the IR, which is
+//! linked with the runtime in `runtime/k.s` into a static executable for
+//! Linux that doesn't need a C library.
+//!
+//! Only programs whose values are integers, booleans and strings can be
+//! compiled. Every value takes a whole register, with `i32`s kept sign
+//! extended, and gets a register or a stack slot from the linear scan
+//! allocator in `ir_regalloc`. Instructions work on their operands in the
+//! scratch registers `%rax`, `%rcx` and `%rdx`, which are never allocated.
+//! Functions follow the System V calling convention.
+
+use crate::ast::{InfixOp, PrefixOp};
+use crate::backend::{mangle, run_tool, BuildError, TempDir};
+use crate::ir::*;
+use crate::ir_regalloc::{self, Allocation, Location};
+use crate::types::Type;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The runtime that generated assembly is linked with.
+pub const RUNTIME: &str = include_str!("../runtime/k.s");
+
+/// The registers values are allocated, and whether calls preserve them.
+/// Those that calls clobber come first, so values that don't live across a
+/// call leave the others free.
+const REGISTERS: &[(&str, bool)] = &[
+    ("%rsi", false),
+    ("%rdi", false),
+    ("%r8", false),
+    ("%r9", false),
+    ("%r10", false),
+    ("%rbx", true),
+    ("%r12", true),
+    ("%r13", true),
+    ("%r14", true),
+    ("%r15", true),
+];
+
+/// Where the first arguments of a call go.
+const ARGUMENTS: &[&str] = &["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// Assembles a module into an executable at `output` with the assembler and
+/// linker named by `AS` and `LD`, or `as` and `ld` if they aren't set.
+pub fn build(module: &Module, output: &Path) -> Result<(), BuildError> {
+    let source = emit(module)?;
+    let dir = TempDir::new()?;
+    let assembler = env::var("AS").unwrap_or_else(|_| "as".to_owned());
+    let linker = env::var("LD").unwrap_or_else(|_| "ld".to_owned());
+    let mut objects = Vec::new();
+
+    for (name, source) in &[("main", source.as_str()), ("k", RUNTIME)] {
+        let path = dir.path().join(name).with_extension("s");
+        let object = path.with_extension("o");
+
+        fs::write(&path, source)?;
+        run_tool(Command::new(&assembler).arg("-o").arg(&object).arg(&path))?;
+        objects.push(object);
+    }
+
+    run_tool(Command::new(linker).arg("-o").arg(output).args(&objects))
+}
+
+/// Generates the assembly for a module.
+pub fn emit(module: &Module) -> Result<String, BuildError> {
+    let mut emitter = Emitter {
+        module,
+        strings: Vec::new(),
+        out: String::new(),
+    };
+
+    emitter.emit_module()?;
+    Ok(emitter.out)
+}
+
+struct Emitter<'a> {
+    module: &'a Module,
+    // Every string constant, labelled by its index.
+    strings: Vec<String>,
+    out: String,
+}
+
+/// What the emitter needs to know about the function being generated.
+struct Frame<'a> {
+    index: usize,
+    function: &'a Function,
+    allocation: Allocation,
+    // The registers calls preserve that the function uses, which it saves.
+    saved: Vec<&'static str>,
+}
+
+impl<'a> Frame<'a> {
+    fn operand(&self, value: Value) -> String {
+        match self.allocation.location(value) {
+            Location::Register(register) => REGISTERS[register].0.to_owned(),
+            Location::Stack(slot) => format!("{}(%rbp)", -8 * (self.saved.len() + slot + 1) as i64),
+        }
+    }
+
+    fn label(&self, block: BlockId) -> String {
+        format!(".Lf{}_b{}", self.index, block.0)
+    }
+}
+
+impl<'a> Emitter<'a> {
+    fn line(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn op(&mut self, text: &str) {
+        self.out.push_str("    ");
+        self.line(text);
+    }
+
+    /// The label of a string constant, adding it if it's new.
+    fn string(&mut self, value: &str) -> String {
+        let index = match self.strings.iter().position(|string| string == value) {
+            Some(index) => index,
+            None => {
+                self.strings.push(value.to_owned());
+                self.strings.len() - 1
+            }
+        };
+
+        format!(".Ls{}", index)
+    }
+
+    fn emit_module(&mut self) -> Result<(), BuildError> {
+        let module = self.module;
+
+        for (_, ty) in &module.globals {
+            check_type(ty)?;
+        }
+
+        for function in &module.functions {
+            if function.ret != Type::Void {
+                check_type(&function.ret)?;
+            }
+
+            for ty in &function.types {
+                check_type(ty)?;
+            }
+        }
+
+        self.op(".text");
+        self.op(".globl k_main");
+        self.line("k_main:");
+        self.op(&format!("jmp {}", function_name(&module.function(module.entry).name)));
+
+        for (i, function) in module.functions.iter().enumerate() {
+            self.line("");
+            self.emit_function(i, function);
+        }
+
+        self.line("");
+        self.op(".section .rodata");
+
+        for (i, value) in std::mem::take(&mut self.strings).iter().enumerate() {
+            self.op(".balign 8");
+            self.line(&format!(".Ls{}:", i));
+            self.op(&format!(".quad {}", value.len()));
+            self.op(&format!(".ascii {}", as_string(value)));
+        }
+
+        if !module.globals.is_empty() {
+            self.line("");
+            self.op(".data");
+            self.op(".balign 8");
+
+            for i in 0..module.globals.len() {
+                self.line(&format!("k_g{}:", i));
+                self.op(".quad 0");
+            }
+        }
+
+        self.line("");
+        self.op(".section .note.GNU-stack,\"\",@progbits");
+
+        Ok(())
+    }
+
+    fn emit_function(&mut self, index: usize, function: &Function) {
+        let is_call = |inst: &Inst| match &inst.kind {
+            InstKind::Call(..) => true,
+            InstKind::ToString(value) => *function.value_type(*value) != Type::Str,
+            InstKind::Binary(_, left, _) => *function.value_type(*left) == Type::Str,
+            _ => false,
+        };
+
+        let preserved: Vec<bool> = REGISTERS.iter().map(|(_, preserved)| *preserved).collect();
+        let allocation = ir_regalloc::allocate(function, &preserved, is_call);
+
+        let mut saved: Vec<&'static str> = allocation
+            .locations
+            .iter()
+            .flatten()
+            .filter_map(|location| match location {
+                Location::Register(register) if REGISTERS[*register].1 => Some(REGISTERS[*register].0),
+                _ => None,
+            })
+            .collect();
+
+        saved.sort_unstable();
+        saved.dedup();
+
+        let frame = Frame {
+            index,
+            function,
+            allocation,
+            saved,
+        };
+
+        self.line(&format!("{}:", function_name(&function.name)));
+        self.op("pushq %rbp");
+        self.op("movq %rsp, %rbp");
+
+        for register in &frame.saved {
+            self.op(&format!("pushq {}", register));
+        }
+
+        // The stack stays aligned to 16 bytes for calls.
+        let slots = frame.allocation.stack_slots + (frame.saved.len() + frame.allocation.stack_slots) % 2;
+
+        if slots > 0 {
+            self.op(&format!("subq ${}, %rsp", 8 * slots));
+        }
+
+        let params = function.params();
+        let in_registers = params.len().min(ARGUMENTS.len());
+
+        for register in &ARGUMENTS[..in_registers] {
+            self.op(&format!("pushq {}", register));
+        }
+
+        for param in params[..in_registers].iter().rev() {
+            self.op(&format!("popq {}", frame.operand(*param)));
+        }
+
+        for (i, param) in params.iter().enumerate().skip(in_registers) {
+            self.op(&format!("movq {}(%rbp), %rax", 16 + 8 * (i - in_registers)));
+            self.op(&format!("movq %rax, {}", frame.operand(*param)));
+        }
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            let next = Some(BlockId(i + 1)).filter(|next| next.0 < function.blocks.len());
+
+            self.line(&format!("{}:", frame.label(BlockId(i))));
+
+            for inst in &block.insts {
+                self.emit_inst(&frame, inst);
+            }
+
+            self.emit_terminator(&frame, BlockId(i), &block.terminator, next);
+        }
+
+        self.line(&format!(".Lf{}_return:", index));
+
+        if !frame.saved.is_empty() {
+            self.op(&format!("leaq {}(%rbp), %rsp", -8 * frame.saved.len() as i64));
+        }
+
+        for register in frame.saved.iter().rev() {
+            self.op(&format!("popq {}", register));
+        }
+
+        if frame.saved.is_empty() {
+            self.op("movq %rbp, %rsp");
+        }
+
+        self.op("popq %rbp");
+        self.op("ret");
+    }
+
+    fn emit_terminator(
+        &mut self,
+        frame: &Frame,
+        block: BlockId,
+        terminator: &Terminator,
+        next: Option<BlockId>,
+    ) {
+        match terminator {
+            Terminator::Jump(target) => self.emit_jump(frame, target, next),
+            Terminator::Branch(condition, then, otherwise) => {
+                let label = format!("{}_else", frame.label(block));
+
+                self.op(&format!("cmpq $0, {}", frame.operand(*condition)));
+                self.op(&format!("je {}", label));
+                self.emit_jump(frame, then, None);
+                self.line(&format!("{}:", label));
+                self.emit_jump(frame, otherwise, next);
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.op(&format!("movq {}, %rax", frame.operand(*value)));
+                }
+
+                if next.is_some() {
+                    self.op(&format!("jmp .Lf{}_return", frame.index));
+                }
+            }
+            Terminator::Trap(message) => {
+                let label = self.string(message);
+                self.op(&format!("leaq {}(%rip), %rdi", label));
+                self.op("call k_trap");
+            }
+            Terminator::Unreachable => self.op("ud2"),
+        }
+    }
+
+    /// Passes a jump's arguments to its target's parameters through the
+    /// stack, since the arguments can share locations with the parameters.
+    fn emit_jump(&mut self, frame: &Frame, target: &Target, next: Option<BlockId>) {
+        let moves: Vec<(String, String)> = frame
+            .function
+            .block(target.block)
+            .params
+            .iter()
+            .zip(&target.args)
+            .map(|(param, arg)| (frame.operand(*param), frame.operand(*arg)))
+            .filter(|(param, arg)| param != arg)
+            .collect();
+
+        for (_, arg) in &moves {
+            self.op(&format!("pushq {}", arg));
+        }
+
+        for (param, _) in moves.iter().rev() {
+            self.op(&format!("popq {}", param));
+        }
+
+        if next != Some(target.block) {
+            self.op(&format!("jmp {}", frame.label(target.block)));
+        }
+    }
+
+    fn emit_inst(&mut self, frame: &Frame, inst: &Inst) {
+        let function = frame.function;
+        let ty = |value: &Value| function.value_type(*value);
+
+        match &inst.kind {
+            InstKind::Const(Constant::Int(value)) if i64::from(*value as i32) != *value => {
+                self.op(&format!("movabsq ${}, %rax", value));
+            }
+            InstKind::Const(Constant::Int(value)) => self.op(&format!("movq ${}, %rax", value)),
+            InstKind::Const(Constant::Bool(value)) => self.op(&format!("movq ${}, %rax", *value as i32)),
+            InstKind::Const(Constant::Str(value)) => {
+                let label = self.string(value);
+                self.op(&format!("leaq {}(%rip), %rax", label));
+            }
+            InstKind::Copy(value) => self.op(&format!("movq {}, %rax", frame.operand(*value))),
+            InstKind::Unary(op, value) => {
+                self.op(&format!("movq {}, %rax", frame.operand(*value)));
+
+                match (op, ty(value)) {
+                    (PrefixOp::Neg, Type::I32) => {
+                        self.op("negl %eax");
+                        self.op("jo k_overflow");
+                        self.op("movslq %eax, %rax");
+                    }
+                    (PrefixOp::Neg, _) => {
+                        self.op("negq %rax");
+                        self.op("jo k_overflow");
+                    }
+                    (PrefixOp::Not, _) => self.op("xorq $1, %rax"),
+                }
+            }
+            InstKind::Binary(op, left, right) if *ty(left) == Type::Str => {
+                let callee = match op {
+                    InfixOp::Add => "k_concat",
+                    InfixOp::Eq | InfixOp::NotEq => "k_str_eq",
+                    _ => "k_str_compare",
+                };
+
+                self.emit_call(frame, callee, &[*left, *right]);
+
+                match op {
+                    InfixOp::Add | InfixOp::Eq => {}
+                    InfixOp::NotEq => self.op("xorq $1, %rax"),
+                    _ => {
+                        self.op("cmpq $0, %rax");
+                        self.emit_set(*op);
+                    }
+                }
+            }
+            InstKind::Binary(op, left, right) => {
+                self.op(&format!("movq {}, %rax", frame.operand(*left)));
+                self.op(&format!("movq {}, %rcx", frame.operand(*right)));
+                self.emit_binary(*op, ty(left));
+            }
+            InstKind::ToString(value) => self.emit_to_string(frame, *value),
+            InstKind::Call(Callee::Function(id), args) => {
+                self.emit_call(frame, &function_name(&self.module.function(*id).name), args);
+            }
+            InstKind::Call(Callee::Builtin(name), args) => self.emit_builtin(frame, name.as_str(), args),
+            InstKind::Load(global) => self.op(&format!("movq k_g{}(%rip), %rax", global.0)),
+            InstKind::Store(global, value) => {
+                self.op(&format!("movq {}, %rax", frame.operand(*value)));
+                self.op(&format!("movq %rax, k_g{}(%rip)", global.0));
+            }
+            kind => unreachable!("only integers, booleans and strings get through, not {:?}", kind),
+        }
+
+        if let Some(result) = inst.result {
+            self.op(&format!("movq %rax, {}", frame.operand(result)));
+        }
+    }
+
+    /// Combines `%rax` and `%rcx`, leaving the result in `%rax`.
+    fn emit_binary(&mut self, op: InfixOp, ty: &Type) {
+        let (suffix, rax, rcx) = match ty {
+            Type::I32 => ("l", "%eax", "%ecx"),
+            _ => ("q", "%rax", "%rcx"),
+        };
+
+        match op {
+            InfixOp::Add | InfixOp::Sub | InfixOp::Mul => {
+                let name = match op {
+                    InfixOp::Add => "add",
+                    InfixOp::Sub => "sub",
+                    _ => "imul",
+                };
+
+                self.op(&format!("{}{} {}, {}", name, suffix, rcx, rax));
+                self.op("jo k_overflow");
+
+                if *ty == Type::I32 {
+                    self.op("movslq %eax, %rax");
+                }
+            }
+            InfixOp::Div | InfixOp::Mod => {
+                // Dividing the smallest integer by -1 overflows. Since i32s
+                // are sign extended the division itself can be done in 64
+                // bits either way.
+                self.op("testq %rcx, %rcx");
+                self.op("jz k_divide_by_zero");
+                self.op("cmpq $-1, %rcx");
+                self.op("jne 1f");
+
+                if *ty == Type::I32 {
+                    self.op("cmpq $-2147483648, %rax");
+                } else {
+                    self.op("movabsq $-9223372036854775808, %rdx");
+                    self.op("cmpq %rdx, %rax");
+                }
+
+                self.op("je k_overflow");
+                self.line("1:");
+                self.op("cqto");
+                self.op("idivq %rcx");
+
+                if op == InfixOp::Mod {
+                    self.op("movq %rdx, %rax");
+                }
+            }
+            _ => {
+                self.op("cmpq %rcx, %rax");
+                self.emit_set(op);
+            }
+        }
+    }
+
+    /// Sets `%rax` to whether the flags of a signed comparison satisfy `op`.
+    fn emit_set(&mut self, op: InfixOp) {
+        let condition = match op {
+            InfixOp::Lt => "l",
+            InfixOp::Gt => "g",
+            InfixOp::LtEq => "le",
+            InfixOp::GtEq => "ge",
+            InfixOp::Eq => "e",
+            InfixOp::NotEq => "ne",
+            _ => unreachable!("{} is not a comparison", binary_name(op)),
+        };
+
+        self.op(&format!("set{} %al", condition));
+        self.op("movzbl %al, %eax");
+    }
+
+    /// Leaves a value written as a string in `%rax`.
+    fn emit_to_string(&mut self, frame: &Frame, value: Value) {
+        match frame.function.value_type(value) {
+            Type::Str => self.op(&format!("movq {}, %rax", frame.operand(value))),
+            ty => self.emit_call(frame, display(ty), &[value]),
+        }
+    }
+
+    fn emit_builtin(&mut self, frame: &Frame, name: &str, args: &[Value]) {
+        match name {
+            "print" | "println" => {
+                // Each argument is written with its own calls, so they're
+                // all copied to the stack first.
+                let padded = args.len() + args.len() % 2;
+
+                if padded > args.len() {
+                    self.op("subq $8, %rsp");
+                }
+
+                for arg in args.iter().rev() {
+                    self.op(&format!("pushq {}", frame.operand(*arg)));
+                }
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        let label = self.string(" ");
+                        self.op(&format!("leaq {}(%rip), %rdi", label));
+                        self.op("call k_print");
+                    }
+
+                    self.op(&format!("movq {}(%rsp), %rdi", 8 * i));
+
+                    if *frame.function.value_type(*arg) != Type::Str {
+                        self.op(&format!("call {}", display(frame.function.value_type(*arg))));
+                        self.op("movq %rax, %rdi");
+                    }
+
+                    self.op("call k_print");
+                }
+
+                if name == "println" {
+                    let label = self.string("\n");
+                    self.op(&format!("leaq {}(%rip), %rdi", label));
+                    self.op("call k_print");
+                }
+
+                if padded > 0 {
+                    self.op(&format!("addq ${}, %rsp", 8 * padded));
+                }
+            }
+            "len" => self.emit_call(frame, "k_str_len", args),
+            "to_string" => self.emit_to_string(frame, args[0]),
+            "abs" => {
+                self.op(&format!("movq {}, %rax", frame.operand(args[0])));
+                self.op("testq %rax, %rax");
+                self.op("jns 1f");
+
+                if *frame.function.value_type(args[0]) == Type::I32 {
+                    self.op("negl %eax");
+                    self.op("jo k_overflow");
+                    self.op("movslq %eax, %rax");
+                } else {
+                    self.op("negq %rax");
+                    self.op("jo k_overflow");
+                }
+
+                self.line("1:");
+            }
+            "min" | "max" => {
+                self.op(&format!("movq {}, %rax", frame.operand(args[0])));
+                self.op(&format!("movq {}, %rcx", frame.operand(args[1])));
+                self.op("cmpq %rcx, %rax");
+                self.op(&format!("cmov{}q %rcx, %rax", if name == "min" { "g" } else { "l" }));
+            }
+            _ => unreachable!("`std.{}` only takes floats or arrays", name),
+        }
+    }
+
+    /// Calls a function with the System V calling convention, leaving its
+    /// result in `%rax`. The arguments go through the stack, since they can
+    /// be in the registers they're passed in.
+    fn emit_call(&mut self, frame: &Frame, callee: &str, args: &[Value]) {
+        let on_stack = args.len().saturating_sub(ARGUMENTS.len());
+        let padded = on_stack + on_stack % 2;
+
+        if padded > on_stack {
+            self.op("subq $8, %rsp");
+        }
+
+        for arg in args.iter().rev() {
+            self.op(&format!("pushq {}", frame.operand(*arg)));
+        }
+
+        for register in &ARGUMENTS[..args.len() - on_stack] {
+            self.op(&format!("popq {}", register));
+        }
+
+        self.op(&format!("call {}", callee));
+
+        if padded > 0 {
+            self.op(&format!("addq ${}, %rsp", 8 * padded));
+        }
+    }
+}
+
+fn check_type(ty: &Type) -> Result<(), BuildError> {
+    match ty {
+        Type::I32 | Type::I64 | Type::Bool | Type::Str => Ok(()),
+        _ => Err(BuildError::new(format!("cannot generate x86-64 for a value of type {}", ty))),
+    }
+}
+
+/// The runtime function that writes values of a type as a string.
+fn display(ty: &Type) -> &'static str {
+    match ty {
+        Type::Bool => "k_display_bool",
+        _ => "k_display_i64",
+    }
+}
+
+fn function_name(name: &str) -> String {
+    format!("k_f_{}", mangle(name))
+}
+
+/// Writes a string as a string literal for the assembler. Bytes other than
+/// printable ASCII are escaped in octal.
+fn as_string(value: &str) -> String {
+    let mut literal = String::from("\"");
+
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+
+    #[test]
+    fn test_as_string() {
+        assert_eq!(as_string("say \"hi\"\n"), "\"say \\\"hi\\\"\\012\"");
+        assert_eq!(as_string("a\\é"), "\"a\\\\\\303\\251\"");
+    }
+
+    #[test]
+    fn test_errors() {
+        let module = Module {
+            globals: vec![(Symbol::intern("f"), Type::F64)],
+            ..Module::default()
+        };
+
+        let error = emit(&module).unwrap_err();
+        assert_eq!(error.message, "cannot generate x86-64 for a value of type f64");
+    }
+}
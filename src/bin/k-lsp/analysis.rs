@@ -0,0 +1,792 @@
+use crate::line_index::LineIndex;
+use k::ast::*;
+use k::builtins;
+use k::checker::{Checker, TypeError};
+use k::cst::{NodeKind, SyntaxNode};
+use k::formatter::{self, FormatConfig};
+use k::incremental::Document;
+use k::lexer::Lexer;
+use k::parser::Parser;
+use k::resolver::{ResolveError, Resolver};
+use k::symbol::Symbol;
+use k::token::{BufferPosition, TokenKind};
+use k::types::Type;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol, Position, Range,
+    SymbolKind, TextEdit,
+};
+use std::collections::HashMap;
+use std::mem;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DefKind {
+    Function,
+    Variable,
+    Parameter,
+    Struct,
+    Field,
+    Enum,
+    Variant,
+    Module,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefKind,
+    pub position: BufferPosition,
+    // Fields of a struct or variants of an enum
+    pub members: Vec<usize>,
+    pub top_level: bool,
+}
+
+/// A name in the source, either declaring a definition or referring to it.
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub position: BufferPosition,
+    pub len: usize,
+    pub def: usize,
+    pub declaration: bool,
+}
+
+/// Everything the server knows about one version of a document.
+pub struct Analysis {
+    document: Document,
+    tree: SyntaxNode,
+    program: Program,
+    name_errors: Vec<ResolveError>,
+    type_errors: Vec<TypeError>,
+    types: Vec<(Identifier, Type)>,
+    definitions: Vec<Definition>,
+    occurrences: Vec<Occurrence>,
+}
+
+fn is_before(a: BufferPosition, b: BufferPosition) -> bool {
+    (a.line, a.column) <= (b.line, b.column)
+}
+
+impl Analysis {
+    pub fn new(document: Document) -> Analysis {
+        let mut program = Parser::new(Lexer::new(document.text())).parse();
+
+        // Whatever parsed is still resolved and checked so that hover and
+        // completion work while the document is being edited
+        let name_errors = Resolver::new().resolve_program(&mut program).err().unwrap_or_default();
+        let mut checker = Checker::new();
+        let type_errors = checker.check_program(&mut program).err().unwrap_or_default();
+        let types = checker.name_types().to_vec();
+
+        let mut indexer = Indexer::new(&types, None);
+        indexer.stmts(&program.0);
+
+        Analysis {
+            tree: document.syntax(),
+            document,
+            name_errors,
+            type_errors,
+            definitions: indexer.definitions,
+            occurrences: indexer.occurrences,
+            program,
+            types,
+        }
+    }
+
+    /// Gives the document back, to be edited.
+    pub fn into_document(self) -> Document {
+        self.document
+    }
+
+    fn index(&self) -> LineIndex<'_> {
+        LineIndex::new(&self.document)
+    }
+
+    fn name_range(&self, position: BufferPosition, len: usize) -> std::ops::Range<usize> {
+        let start = self.index().buffer_offset(position);
+        start..start + len
+    }
+
+    /// The range of the token an error points at.
+    fn error_range(&self, position: BufferPosition) -> Range {
+        let offset = self.index().buffer_offset(position);
+
+        let range = match self.tree.token_at_offset(offset) {
+            Some(token) if token.text_range().start == offset && *token.kind() != TokenKind::Newline => {
+                token.text_range()
+            }
+            _ => offset..offset,
+        };
+
+        self.index().range(range)
+    }
+
+    /// The errors of the first stage that has any: syntax errors, then name
+    /// errors, then type errors. The syntax errors are the ones the document
+    /// keeps up to date as it is edited.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let syntax_errors = self.document.errors();
+
+        let errors: Vec<(String, BufferPosition)> = if !syntax_errors.is_empty() {
+            syntax_errors.into_iter().map(|error| (error.message, error.position)).collect()
+        } else if !self.name_errors.is_empty() {
+            self.name_errors.iter().map(|error| (error.message.clone(), error.position)).collect()
+        } else {
+            self.type_errors.iter().map(|error| (error.message.clone(), error.position)).collect()
+        };
+
+        errors
+            .into_iter()
+            .map(|(message, position)| Diagnostic {
+                range: self.error_range(position),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("k".to_owned()),
+                message,
+                ..Diagnostic::default()
+            })
+            .collect()
+    }
+
+    fn occurrence_at(&self, position: Position) -> Option<&Occurrence> {
+        let offset = self.index().offset(position);
+
+        self.occurrences.iter().find(|occurrence| {
+            let range = self.name_range(occurrence.position, occurrence.len);
+            range.start <= offset && offset <= range.end
+        })
+    }
+
+    fn type_at(&self, position: BufferPosition) -> Option<&Type> {
+        self.types.iter().find(|(name, _)| name.position == position).map(|(_, ty)| ty)
+    }
+
+    pub fn hover(&self, position: Position) -> Option<(String, Range)> {
+        let offset = self.index().offset(position);
+
+        let (name, ty) = self.types.iter().find(|(name, _)| {
+            let range = self.name_range(name.position, name.name.as_str().len());
+            range.start <= offset && offset <= range.end
+        })?;
+
+        let kind = self
+            .occurrences
+            .iter()
+            .find(|occurrence| occurrence.position == name.position)
+            .map(|occurrence| self.definitions[occurrence.def].kind);
+
+        let signature = match (kind, ty) {
+            (Some(DefKind::Function), Type::Fn(params, ret)) => {
+                let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                format!("fn {}({}): {}", name.name, params.join(", "), ret)
+            }
+            (Some(DefKind::Variant), Type::Enum(enum_name)) => format!("{}.{}", enum_name, name.name),
+            (Some(DefKind::Variant), Type::Fn(fields, ret)) => {
+                let fields: Vec<String> = fields.iter().map(Type::to_string).collect();
+                format!("{}.{}({})", ret, name.name, fields.join(", "))
+            }
+            (Some(DefKind::Variable), _) => format!("let {}: {}", name.name, ty),
+            (_, Type::StructType(_)) | (_, Type::EnumType(_)) | (_, Type::Module(_)) | (_, Type::Builtin(_)) => {
+                ty.to_string()
+            }
+            _ => format!("{}: {}", name.name, ty),
+        };
+
+        let range = self.index().range(self.name_range(name.position, name.name.as_str().len()));
+
+        Some((format!("```k\n{}\n```", signature), range))
+    }
+
+    pub fn definition(&self, position: Position) -> Option<Range> {
+        let def = &self.definitions[self.occurrence_at(position)?.def];
+        Some(self.index().range(self.name_range(def.position, def.name.len())))
+    }
+
+    pub fn references(&self, position: Position, include_declaration: bool) -> Vec<Range> {
+        let def = match self.occurrence_at(position) {
+            Some(occurrence) => occurrence.def,
+            None => return Vec::new(),
+        };
+
+        self.occurrences
+            .iter()
+            .filter(|occurrence| occurrence.def == def && (include_declaration || !occurrence.declaration))
+            .map(|occurrence| self.index().range(self.name_range(occurrence.position, occurrence.len)))
+            .collect()
+    }
+
+    /// The range of the whole declaration of a definition, not counting
+    /// trivia around it.
+    fn declaration_range(&self, def: &Definition) -> std::ops::Range<usize> {
+        let name = self.name_range(def.position, def.name.len());
+
+        let node = self.tree.token_at_offset(name.start).and_then(|token| {
+            token.parent().ancestors().find(|node| {
+                matches!(
+                    node.kind(),
+                    NodeKind::FnDecl
+                        | NodeKind::StructDecl
+                        | NodeKind::EnumDecl
+                        | NodeKind::LetStmt
+                        | NodeKind::ImportStmt
+                        | NodeKind::Field
+                        | NodeKind::Variant
+                        | NodeKind::Param
+                )
+            })
+        });
+
+        let tokens = match node {
+            Some(node) => node.descendant_tokens(),
+            None => return name,
+        };
+
+        let mut tokens = tokens.iter().filter(|token| *token.kind() != TokenKind::Newline);
+
+        match (tokens.next(), tokens.next_back()) {
+            (Some(first), Some(last)) => first.text_range().start..last.text_range().end,
+            (Some(first), None) => first.text_range(),
+            _ => name,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn document_symbol(&self, def: &Definition) -> DocumentSymbol {
+        let kind = match def.kind {
+            DefKind::Function => SymbolKind::FUNCTION,
+            DefKind::Variable | DefKind::Parameter => SymbolKind::VARIABLE,
+            DefKind::Struct => SymbolKind::STRUCT,
+            DefKind::Field => SymbolKind::FIELD,
+            DefKind::Enum => SymbolKind::ENUM,
+            DefKind::Variant => SymbolKind::ENUM_MEMBER,
+            DefKind::Module => SymbolKind::MODULE,
+        };
+
+        let children: Vec<DocumentSymbol> = def
+            .members
+            .iter()
+            .map(|member| self.document_symbol(&self.definitions[*member]))
+            .collect();
+
+        DocumentSymbol {
+            name: def.name.clone(),
+            detail: self.type_at(def.position).map(Type::to_string),
+            kind,
+            tags: None,
+            deprecated: None,
+            range: self.index().range(self.declaration_range(def)),
+            selection_range: self.index().range(self.name_range(def.position, def.name.len())),
+            children: if children.is_empty() { None } else { Some(children) },
+        }
+    }
+
+    /// The declarations at the top of the document, with the fields and
+    /// variants of structs and enums below them.
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol> {
+        self.definitions
+            .iter()
+            .filter(|def| def.top_level)
+            .map(|def| self.document_symbol(def))
+            .collect()
+    }
+
+    fn completion_item(&self, def: &Definition) -> CompletionItem {
+        let kind = match def.kind {
+            DefKind::Function => CompletionItemKind::FUNCTION,
+            DefKind::Variable | DefKind::Parameter => CompletionItemKind::VARIABLE,
+            DefKind::Struct => CompletionItemKind::STRUCT,
+            DefKind::Field => CompletionItemKind::FIELD,
+            DefKind::Enum => CompletionItemKind::ENUM,
+            DefKind::Variant => CompletionItemKind::ENUM_MEMBER,
+            DefKind::Module => CompletionItemKind::MODULE,
+        };
+
+        CompletionItem {
+            label: def.name.clone(),
+            kind: Some(kind),
+            detail: self.type_at(def.position).map(Type::to_string),
+            ..CompletionItem::default()
+        }
+    }
+
+    /// The names reachable with a `.` after `receiver`.
+    fn members(&self, receiver: &HashMap<Symbol, usize>, name: &str) -> Vec<CompletionItem> {
+        let def = match receiver.get(&Symbol::intern(name)) {
+            Some(def) => &self.definitions[*def],
+            None if builtins::module(name).is_some() => return std_members(),
+            None => return Vec::new(),
+        };
+
+        let owner = match (def.kind, self.type_at(def.position)) {
+            (DefKind::Enum, _) => Some(def),
+            (_, Some(Type::Module(_))) => return std_members(),
+            (_, Some(Type::Struct(struct_name))) => receiver
+                .get(struct_name)
+                .map(|def| &self.definitions[*def])
+                .filter(|def| def.kind == DefKind::Struct),
+            _ => None,
+        };
+
+        owner
+            .map(|owner| {
+                owner
+                    .members
+                    .iter()
+                    .map(|member| self.completion_item(&self.definitions[*member]))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn completions(&self, position: Position) -> Vec<CompletionItem> {
+        let text = self.document.text();
+        let offset = self.index().offset(position);
+        let line_start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let line = &text[line_start..offset];
+
+        let word_start = line
+            .char_indices()
+            .rev()
+            .take_while(|(_, ch)| unicode_ident::is_xid_continue(*ch))
+            .last()
+            .map_or(line.len(), |(index, _)| index);
+
+        let prefix = &line[word_start..];
+
+        // The program is walked again to find the names in scope at the
+        // cursor
+        let cursor = BufferPosition::new(position.line as usize + 1, line[..word_start].chars().count() + 1);
+        let mut indexer = Indexer::new(&self.types, Some(cursor));
+        indexer.stmts(&self.program.0);
+
+        let visible = indexer.visible;
+
+        let mut items = if line[..word_start].ends_with('.') {
+            let receiver = &line[..word_start - 1];
+            let receiver_start = receiver
+                .char_indices()
+                .rev()
+                .take_while(|(_, ch)| unicode_ident::is_xid_continue(*ch))
+                .last()
+                .map_or(receiver.len(), |(index, _)| index);
+
+            self.members(&visible, &receiver[receiver_start..])
+        } else {
+            let mut items: Vec<CompletionItem> = Lexer::keywords()
+                .map(|keyword| CompletionItem {
+                    label: keyword.to_owned(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    ..CompletionItem::default()
+                })
+                .collect();
+
+            items.extend(visible.values().map(|def| self.completion_item(&self.definitions[*def])));
+
+            if !visible.contains_key(&Symbol::intern("std")) {
+                items.push(CompletionItem {
+                    label: "std".to_owned(),
+                    kind: Some(CompletionItemKind::MODULE),
+                    ..CompletionItem::default()
+                });
+            }
+
+            items
+        };
+
+        items.retain(|item| item.label.starts_with(prefix));
+        items.sort_by(|a, b| a.label.cmp(&b.label));
+        items
+    }
+
+    /// An edit replacing the whole document with its formatted text, if it
+    /// parses and isn't formatted already.
+    pub fn format(&self) -> Option<Vec<TextEdit>> {
+        let text = self.document.text();
+        let formatted = formatter::format_source(text, &FormatConfig::default()).ok()?;
+
+        if formatted == text {
+            return Some(Vec::new());
+        }
+
+        Some(vec![TextEdit::new(self.index().range(0..text.len()), formatted)])
+    }
+}
+
+fn std_members() -> Vec<CompletionItem> {
+    builtins::STD_BUILTINS
+        .iter()
+        .map(|builtin| CompletionItem {
+            label: builtin.name.to_owned(),
+            kind: Some(CompletionItemKind::FUNCTION),
+            detail: Some(Type::Builtin(Symbol::intern(builtin.name)).to_string()),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Collects the definitions of a resolved program and every occurrence of
+/// them, following the bindings the resolver recorded. Fields and variants,
+/// which the resolver leaves alone, are found through their struct or enum.
+struct Indexer<'a> {
+    types: &'a [(Identifier, Type)],
+    // The definitions in scope, by name, for completion and for finding
+    // the struct a type names
+    scopes: Vec<HashMap<Symbol, usize>>,
+    definitions: Vec<Definition>,
+    by_binding: HashMap<BindingId, usize>,
+    occurrences: Vec<Occurrence>,
+    // With a cursor, the names in scope at the last statement starting
+    // before it are kept in `visible`
+    cursor: Option<BufferPosition>,
+    reached_cursor: bool,
+    visible: HashMap<Symbol, usize>,
+}
+
+impl<'a> Indexer<'a> {
+    fn new(types: &'a [(Identifier, Type)], cursor: Option<BufferPosition>) -> Indexer<'a> {
+        Indexer {
+            types,
+            scopes: vec![HashMap::new()],
+            definitions: Vec::new(),
+            by_binding: HashMap::new(),
+            occurrences: Vec::new(),
+            cursor,
+            reached_cursor: false,
+            visible: HashMap::new(),
+        }
+    }
+
+    fn snapshot(&mut self) {
+        if self.reached_cursor {
+            self.visible = self.scopes.iter().flatten().map(|(name, def)| (name.clone(), *def)).collect();
+        }
+    }
+
+    fn define(&mut self, name: &Identifier, kind: DefKind) -> usize {
+        let def = self.definitions.len();
+
+        self.definitions.push(Definition {
+            name: name.name.to_string(),
+            kind,
+            position: name.position,
+            members: Vec::new(),
+            // Fields and variants are listed under their struct or enum
+            top_level: self.scopes.len() == 1 && kind != DefKind::Field && kind != DefKind::Variant,
+        });
+
+        self.occurrences.push(Occurrence {
+            position: name.position,
+            len: name.name.as_str().len(),
+            def,
+            declaration: true,
+        });
+
+        if let Some(id) = name.binding {
+            self.by_binding.insert(id, def);
+        }
+
+        def
+    }
+
+    fn bind(&mut self, name: &Identifier, kind: DefKind) -> usize {
+        let def = self.define(name, kind);
+        self.scopes.last_mut().expect("the global scope is never popped").insert(name.name.clone(), def);
+        def
+    }
+
+    fn lookup(&self, name: &Symbol) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn refer(&mut self, name: &Identifier, def: usize) {
+        self.occurrences.push(Occurrence {
+            position: name.position,
+            len: name.name.as_str().len(),
+            def,
+            declaration: false,
+        });
+    }
+
+    /// The definition a name was resolved to.
+    fn binding(&self, name: &Identifier) -> Option<usize> {
+        name.binding.and_then(|id| self.by_binding.get(&id).copied())
+    }
+
+    fn use_name(&mut self, name: &Identifier) -> Option<usize> {
+        let def = self.binding(name)?;
+        self.refer(name, def);
+        Some(def)
+    }
+
+    fn member(&mut self, owner: usize, name: &Identifier) {
+        let member = self.definitions[owner]
+            .members
+            .iter()
+            .copied()
+            .find(|member| name.name == *self.definitions[*member].name);
+
+        if let Some(member) = member {
+            self.refer(name, member);
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare_items(&mut self, block: &[Stmt]) {
+        for stmt in block {
+            match &stmt.kind {
+                StmtKind::Struct(decl) => {
+                    let def = self.bind(&decl.name, DefKind::Struct);
+                    let members = decl.fields.iter().map(|field| self.define(&field.name, DefKind::Field)).collect();
+                    self.definitions[def].members = members;
+                }
+                StmtKind::Enum(decl) => {
+                    let def = self.bind(&decl.name, DefKind::Enum);
+                    let members = decl
+                        .variants
+                        .iter()
+                        .map(|variant| self.define(&variant.name, DefKind::Variant))
+                        .collect();
+                    self.definitions[def].members = members;
+                }
+                StmtKind::Fn(decl) => {
+                    self.bind(&decl.name, DefKind::Function);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn stmts(&mut self, block: &[Stmt]) {
+        self.declare_items(block);
+
+        for stmt in block {
+            let reached_cursor = self.cursor.is_some_and(|cursor| is_before(stmt.position, cursor));
+            let outer = mem::replace(&mut self.reached_cursor, reached_cursor);
+
+            self.snapshot();
+            self.stmt(stmt);
+
+            self.reached_cursor = outer;
+        }
+    }
+
+    fn block(&mut self, block: &[Stmt]) {
+        self.push_scope();
+        self.stmts(block);
+        self.pop_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Blank | StmtKind::Break | StmtKind::Continue => {}
+            StmtKind::Let(name, ty, value) => {
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+
+                self.expr(value);
+                self.bind(name, DefKind::Variable);
+            }
+            StmtKind::Assign(target, _, value) => {
+                self.expr(target);
+                self.expr(value);
+            }
+            StmtKind::Increment(target) | StmtKind::Decrement(target) => self.expr(target),
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            StmtKind::While(condition, body) => {
+                self.expr(condition);
+                self.block(body);
+            }
+            StmtKind::For(init, condition, step, body) => {
+                self.push_scope();
+
+                self.stmt(init);
+                self.expr(condition);
+                self.block(body);
+                self.stmt(step);
+
+                self.pop_scope();
+            }
+            StmtKind::Fn(decl) => {
+                self.push_scope();
+
+                for param in &decl.params {
+                    self.ty(&param.ty);
+                    self.bind(&param.name, DefKind::Parameter);
+                }
+
+                if let Some(ty) = &decl.return_type {
+                    self.ty(ty);
+                }
+
+                self.snapshot();
+                self.stmts(&decl.body);
+
+                self.pop_scope();
+            }
+            StmtKind::Struct(decl) => {
+                for field in &decl.fields {
+                    self.ty(&field.ty);
+                }
+            }
+            StmtKind::Enum(decl) => {
+                for ty in decl.variants.iter().flat_map(|variant| &variant.fields) {
+                    self.ty(ty);
+                }
+            }
+            StmtKind::Import(module) => {
+                self.bind(module, DefKind::Module);
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+        }
+    }
+
+    fn ty(&mut self, ty: &TypeExpr) {
+        match ty {
+            TypeExpr::Named(name) => {
+                self.use_name(name);
+            }
+            TypeExpr::Array(element, _) => self.ty(element),
+        }
+    }
+
+    /// The type the checker gave an expression, where it can be worked out
+    /// from the names in it.
+    fn expr_type(&self, expr: &Expr) -> Option<Type> {
+        let name_type = |name: &Identifier| {
+            self.types
+                .iter()
+                .find(|(typed, _)| typed.position == name.position)
+                .map(|(_, ty)| ty.clone())
+        };
+
+        match &expr.kind {
+            ExprKind::Identifier(name) | ExprKind::Field(_, name) => name_type(name),
+            ExprKind::StructLiteral(name, _) => Some(Type::Struct(name.name.clone())),
+            ExprKind::Call(function, _) => match self.expr_type(function)? {
+                Type::Fn(_, ret) => Some(*ret),
+                _ => None,
+            },
+            ExprKind::Index(object, _) => match self.expr_type(object)? {
+                Type::Array(element) => Some(*element),
+                Type::Str => Some(Type::Char),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(name) => {
+                self.use_name(name);
+            }
+            ExprKind::IntegerLiteral(_)
+            | ExprKind::FloatLiteral(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::CharLiteral(_)
+            | ExprKind::BooleanLiteral(_) => {}
+            ExprKind::ArrayLiteral(elements) => elements.iter().for_each(|element| self.expr(element)),
+            ExprKind::Interpolated(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            ExprKind::StructLiteral(name, fields) => {
+                let def = self.use_name(name);
+
+                for (field, value) in fields {
+                    if let Some(def) = def {
+                        self.member(def, field);
+                    }
+
+                    self.expr(value);
+                }
+            }
+            ExprKind::Prefix(_, right) => self.expr(right),
+            ExprKind::Infix(_, left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Call(function, args) => {
+                self.expr(function);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::Index(object, index) => {
+                self.expr(object);
+                self.expr(index);
+            }
+            ExprKind::Field(object, field) => {
+                self.expr(object);
+
+                let owner = match &object.kind {
+                    ExprKind::Identifier(name) => self
+                        .binding(name)
+                        .filter(|def| self.definitions[*def].kind == DefKind::Enum),
+                    _ => None,
+                };
+
+                let owner = owner.or_else(|| match self.expr_type(object) {
+                    Some(Type::Struct(name)) => self
+                        .lookup(&name)
+                        .filter(|def| self.definitions[*def].kind == DefKind::Struct),
+                    _ => None,
+                });
+
+                if let Some(owner) = owner {
+                    self.member(owner, field);
+                }
+            }
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::If(condition, consequence, alternative) => {
+                self.expr(condition);
+                self.block(consequence);
+
+                if let Some(alternative) = alternative {
+                    self.expr(alternative);
+                }
+            }
+            ExprKind::Match(subject, arms) => {
+                self.expr(subject);
+
+                for arm in arms {
+                    self.push_scope();
+                    self.pattern(&arm.pattern);
+
+                    if self.cursor.is_some_and(|cursor| is_before(arm.body.position, cursor)) {
+                        self.snapshot();
+                    }
+
+                    self.expr(&arm.body);
+                    self.pop_scope();
+                }
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard(_) | Pattern::Literal(_) => {}
+            Pattern::Binding(name) => {
+                self.bind(name, DefKind::Variable);
+            }
+            Pattern::Variant(enum_name, variant, fields, _) => {
+                if let Some(def) = self.use_name(enum_name) {
+                    self.member(def, variant);
+                }
+
+                fields.iter().for_each(|field| self.pattern(field));
+            }
+        }
+    }
+}
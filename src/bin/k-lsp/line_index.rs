@@ -0,0 +1,98 @@
+use k::incremental::Document;
+use k::token::BufferPosition;
+use lsp_types::{Position, Range};
+
+/// Converts between byte offsets, the lexer's positions (1-based, counted
+/// in characters) and LSP positions (0-based, counted in UTF-16 code units)
+/// in a document, using the line starts it keeps up to date.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: &'a [usize],
+}
+
+impl LineIndex<'_> {
+    pub fn new(document: &Document) -> LineIndex<'_> {
+        LineIndex {
+            text: document.text(),
+            line_starts: document.line_starts(),
+        }
+    }
+
+    fn line_end(&self, line: usize) -> usize {
+        match self.line_starts.get(line + 1) {
+            Some(next) => next - 1,
+            None => self.text.len(),
+        }
+    }
+
+    /// The byte offset of an LSP position, clamped to the end of its line.
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line as usize;
+
+        let start = match self.line_starts.get(line) {
+            Some(start) => *start,
+            None => return self.text.len(),
+        };
+
+        let mut units = 0;
+
+        for (index, ch) in self.text[start..self.line_end(line)].char_indices() {
+            if units >= position.character as usize {
+                return start + index;
+            }
+
+            units += ch.len_utf16();
+        }
+
+        self.line_end(line)
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset].encode_utf16().count();
+
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, range: std::ops::Range<usize>) -> Range {
+        Range::new(self.position(range.start), self.position(range.end))
+    }
+
+    /// The byte offset of a position reported by the lexer, parser or checker.
+    pub fn buffer_offset(&self, position: BufferPosition) -> usize {
+        let start = match self.line_starts.get(position.line.saturating_sub(1)) {
+            Some(start) => *start,
+            None => return self.text.len(),
+        };
+
+        self.text[start..]
+            .char_indices()
+            .nth(position.column.saturating_sub(1))
+            .map_or(self.text.len(), |(index, _)| start + index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let document = Document::new("let é = 1\n  \"𝄞\" x\n");
+        let index = LineIndex::new(&document);
+
+        assert_eq!(index.offset(Position::new(0, 5)), 6);
+        assert_eq!(index.position(6), Position::new(0, 5));
+        assert_eq!(index.offset(Position::new(0, 99)), 10);
+
+        // The clef is two UTF-16 code units but one character
+        assert_eq!(index.offset(Position::new(1, 6)), 19);
+        assert_eq!(index.position(19), Position::new(1, 6));
+        assert_eq!(index.buffer_offset(BufferPosition::new(2, 4)), 14);
+        assert_eq!(index.buffer_offset(BufferPosition::new(2, 6)), 19);
+
+        assert_eq!(index.position(22), Position::new(2, 0));
+        assert_eq!(index.offset(Position::new(5, 0)), 22);
+    }
+}
@@ -0,0 +1,23 @@
+extern crate k;
+
+mod analysis;
+mod line_index;
+mod server;
+
+use lsp_server::Connection;
+use std::process;
+
+fn main() {
+    // Messages are exchanged over stdin and stdout
+    let (connection, io_threads) = Connection::stdio();
+
+    let result = server::run(&connection).and_then(|()| {
+        drop(connection);
+        io_threads.join().map_err(Into::into)
+    });
+
+    if let Err(err) = result {
+        eprintln!("k-lsp: {}", err);
+        process::exit(1);
+    }
+}
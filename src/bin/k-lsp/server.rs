@@ -0,0 +1,540 @@
+use crate::analysis::Analysis;
+use crate::line_index::LineIndex;
+use k::incremental::{Document, Edit};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, LogMessage, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, References, Request as _,
+};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DocumentFormattingParams, DocumentSymbolParams,
+    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, LogMessageParams, MarkupContent, MarkupKind, MessageType, OneOf,
+    PublishDiagnosticsParams, ReferenceParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::error::Error;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::INCREMENTAL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_owned()]),
+            ..CompletionOptions::default()
+        }),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// The open documents by URI, each with the analysis of its current
+/// version.
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<Url, Analysis>,
+}
+
+/// Performs the initialization handshake and then answers messages until the
+/// client asks the server to shut down.
+pub fn run(connection: &Connection) -> Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server::default();
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+
+                connection.sender.send(Message::Response(server.handle_request(request)))?;
+            }
+            Message::Notification(notification) => {
+                let method = notification.method.clone();
+
+                match server.handle_notification(notification) {
+                    Ok(Some(diagnostics)) => connection.sender.send(Message::Notification(diagnostics))?,
+                    Ok(None) => {}
+                    // Notifications can't be answered, so a bad one is only
+                    // logged and the rest are still handled
+                    Err(error) => {
+                        let params = LogMessageParams {
+                            typ: MessageType::ERROR,
+                            message: format!("invalid `{}` notification: {}", method, error),
+                        };
+
+                        let log = Notification::new(LogMessage::METHOD.to_owned(), params);
+                        connection.sender.send(Message::Notification(log))?;
+                    }
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn params<P: DeserializeOwned>(value: serde_json::Value) -> Result<P> {
+    Ok(serde_json::from_value(value)?)
+}
+
+impl Server {
+    fn analysis(&self, uri: &Url) -> Option<&Analysis> {
+        self.documents.get(uri)
+    }
+
+    /// Updates the open documents, returning the new diagnostics of the one
+    /// that changed.
+    fn handle_notification(&mut self, notification: Notification) -> Result<Option<Notification>> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams = params(notification.params)?;
+                let uri = params.text_document.uri;
+
+                let document = Document::new(&params.text_document.text);
+                self.documents.insert(uri.clone(), Analysis::new(document));
+                uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams = params(notification.params)?;
+                let uri = params.text_document.uri;
+
+                if let Some(analysis) = self.documents.remove(&uri) {
+                    let mut document = analysis.into_document();
+
+                    for change in params.content_changes {
+                        // A change without a range replaces the whole text
+                        match change.range {
+                            Some(range) => {
+                                // Positions past the end of a line or of the
+                                // document are clamped to it, and the ends of a
+                                // reversed range are swapped
+                                let index = LineIndex::new(&document);
+                                let (start, end) = (index.offset(range.start), index.offset(range.end));
+
+                                document.apply(&Edit::new(start.min(end)..start.max(end), &change.text));
+                            }
+                            None => document = Document::new(&change.text),
+                        }
+                    }
+
+                    // All of the changes are analyzed together
+                    self.documents.insert(uri.clone(), Analysis::new(document));
+                }
+
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams = params(notification.params)?;
+                let uri = params.text_document.uri;
+
+                self.documents.remove(&uri);
+                uri
+            }
+            _ => return Ok(None),
+        };
+
+        let diagnostics = self.analysis(&uri).map(Analysis::diagnostics).unwrap_or_default();
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+
+        Ok(Some(Notification::new(PublishDiagnostics::METHOD.to_owned(), params)))
+    }
+
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => params(request.params).map(|params| self.hover(params)).and_then(to_value),
+            GotoDefinition::METHOD => params(request.params).map(|params| self.definition(params)).and_then(to_value),
+            References::METHOD => params(request.params).map(|params| self.references(params)).and_then(to_value),
+            DocumentSymbolRequest::METHOD => {
+                params(request.params).map(|params| self.document_symbols(params)).and_then(to_value)
+            }
+            Completion::METHOD => params(request.params).map(|params| self.completion(params)).and_then(to_value),
+            Formatting::METHOD => params(request.params).map(|params| self.formatting(params)).and_then(to_value),
+            method => {
+                let message = format!("unknown method `{}`", method);
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+
+        match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (signature, range) = self.analysis(&position.text_document.uri)?.hover(position.position)?;
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: signature,
+            }),
+            range: Some(range),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let range = self.analysis(&uri)?.definition(position.position)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri;
+        let ranges = self
+            .analysis(&uri)?
+            .references(position.position, params.context.include_declaration);
+
+        Some(ranges.into_iter().map(|range| Location::new(uri.clone(), range)).collect())
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let symbols = self.analysis(&params.text_document.uri)?.document_symbols();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let items = self.analysis(&position.text_document.uri)?.completions(position.position);
+
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn formatting(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        self.analysis(&params.text_document.uri)?.format()
+    }
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::thread::{self, JoinHandle};
+
+    const URI: &str = "file:///tmp/main.k";
+
+    /// Talks to a server running on another thread, the way an editor would.
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<()>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Client {
+            let (server, connection) = Connection::memory();
+            let server = thread::spawn(move || run(&server).unwrap());
+
+            let mut client = Client {
+                connection,
+                server,
+                next_id: 0,
+            };
+
+            let result = client.request("initialize", json!({ "capabilities": {} }));
+            assert_eq!(result["capabilities"]["hoverProvider"], true);
+
+            client.notify("initialized", json!({}));
+            client
+        }
+
+        fn send(&self, message: Message) {
+            self.connection.sender.send(message).unwrap();
+        }
+
+        fn receive(&self) -> Message {
+            self.connection.receiver.recv().unwrap()
+        }
+
+        fn try_request(&mut self, method: &str, params: Value) -> Response {
+            self.next_id += 1;
+            self.send(Message::Request(Request::new(self.next_id.into(), method.to_owned(), params)));
+
+            match self.receive() {
+                Message::Response(response) => {
+                    assert_eq!(response.id, self.next_id.into());
+                    response
+                }
+                message => panic!("expected a response to `{}`, got {:?}", method, message),
+            }
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> Value {
+            let response = self.try_request(method, params);
+            assert!(response.error.is_none(), "{:?}", response.error);
+            response.result.unwrap_or(Value::Null)
+        }
+
+        fn notify(&self, method: &str, params: Value) {
+            self.send(Message::Notification(Notification::new(method.to_owned(), params)));
+        }
+
+        fn diagnostics(&self) -> Value {
+            match self.receive() {
+                Message::Notification(notification) => {
+                    assert_eq!(notification.method, "textDocument/publishDiagnostics");
+                    assert_eq!(notification.params["uri"], URI);
+                    notification.params["diagnostics"].clone()
+                }
+                message => panic!("expected diagnostics, got {:?}", message),
+            }
+        }
+
+        fn open(&self, text: &str) -> Value {
+            let document = json!({ "uri": URI, "languageId": "k", "version": 1, "text": text });
+            self.notify("textDocument/didOpen", json!({ "textDocument": document }));
+            self.diagnostics()
+        }
+
+        fn change(&self, version: i32, text: &str) -> Value {
+            self.notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": URI, "version": version },
+                    "contentChanges": [{ "text": text }],
+                }),
+            );
+            self.diagnostics()
+        }
+
+        fn edit(&self, version: i32, start: (u32, u32), end: (u32, u32), text: &str) -> Value {
+            let range = json!({
+                "start": { "line": start.0, "character": start.1 },
+                "end": { "line": end.0, "character": end.1 },
+            });
+
+            self.notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": URI, "version": version },
+                    "contentChanges": [{ "range": range, "text": text }],
+                }),
+            );
+            self.diagnostics()
+        }
+
+        fn at(&mut self, method: &str, line: u32, character: u32) -> Value {
+            let mut params = json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            });
+
+            if method == "textDocument/references" {
+                params["context"] = json!({ "includeDeclaration": true });
+            }
+
+            self.request(method, params)
+        }
+
+        fn shutdown(mut self) {
+            assert_eq!(self.request("shutdown", Value::Null), Value::Null);
+            self.notify("exit", Value::Null);
+            self.server.join().unwrap();
+        }
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> Value {
+        json!({ "start": { "line": line, "character": start }, "end": { "line": line, "character": end } })
+    }
+
+    const PROGRAM: &str = "struct Point { x: f64, y: f64 }
+
+fn norm(p: Point): f64 {
+    p.x * p.x + p.y * p.y
+}
+
+let origin = Point { x: 0.0, y: 0.0 }
+let n = norm(origin)
+";
+
+    #[test]
+    fn test_diagnostics() {
+        let client = Client::start();
+
+        let diagnostics = client.open("let x = ;\n");
+        assert_eq!(diagnostics[0]["message"], "expected expression, found `;`");
+        assert_eq!(diagnostics[0]["range"], range(0, 8, 9));
+        assert_eq!(diagnostics[0]["severity"], 1);
+
+        let diagnostics = client.change(2, "let x: i32 = \"five\"\n");
+        assert_eq!(diagnostics[0]["message"], "mismatched types: expected i32, found str");
+        assert_eq!(diagnostics[0]["range"], range(0, 13, 19));
+
+        let diagnostics = client.change(3, "let x = 1 +\n");
+        assert_eq!(diagnostics[0]["message"], "expected expression, found end of input");
+        assert_eq!(diagnostics[0]["range"], range(1, 0, 0));
+
+        assert_eq!(client.change(4, "let x: i32 = 5\n"), json!([]));
+
+        let diagnostics = client.change(5, "fn f() {}\nfn f() {}\n");
+        assert_eq!(diagnostics[0]["message"], "the name `f` is defined multiple times, first at 1:4");
+        assert_eq!(diagnostics[0]["range"], range(1, 3, 4));
+
+        client.notify("textDocument/didClose", json!({ "textDocument": { "uri": URI } }));
+        assert_eq!(client.diagnostics(), json!([]));
+
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let mut client = Client::start();
+        assert_eq!(client.open("fn f() {\n    let s = \"é\"\n}\n"), json!([]));
+
+        // Positions count UTF-16 code units, so `é` is one of them
+        let diagnostics = client.edit(2, (1, 15), (1, 15), "; let t: i32 = s");
+        assert_eq!(diagnostics[0]["message"], "mismatched types: expected i32, found str");
+        assert_eq!(diagnostics[0]["range"], range(1, 30, 31));
+
+        let diagnostics = client.edit(3, (1, 30), (1, 31), "1\n    s");
+        assert_eq!(diagnostics, json!([]));
+
+        let hover = client.at("textDocument/hover", 2, 4);
+        assert_eq!(hover["contents"]["value"], "```k\nlet s: str\n```");
+
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_bad_ranges() {
+        let mut client = Client::start();
+        assert_eq!(client.open("let x = 1\n"), json!([]));
+
+        assert_eq!(client.edit(2, (0, 9), (0, 8), "2"), json!([]));
+        assert_eq!(client.edit(3, (7, 0), (9, 4), "let y = x\n"), json!([]));
+        assert_eq!(client.edit(4, (0, 99), (0, 99), " + 1"), json!([]));
+
+        let hover = client.at("textDocument/hover", 1, 4);
+        assert_eq!(hover["contents"]["value"], "```k\nlet y: i32\n```");
+
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let mut client = Client::start();
+
+        let document = json!({ "uri": URI, "version": 1, "text": "let x = 1\n" });
+        client.notify("textDocument/didOpen", json!({ "textDocument": document }));
+
+        match client.receive() {
+            Message::Notification(notification) => {
+                assert_eq!(notification.method, "window/logMessage");
+                assert_eq!(notification.params["type"], 1);
+                assert!(notification.params["message"].as_str().unwrap().contains("languageId"));
+            }
+            message => panic!("expected a log message, got {:?}", message),
+        }
+
+        let response = client.try_request("textDocument/hover", json!({ "position": null }));
+        assert_eq!(response.error.map(|error| error.code), Some(ErrorCode::InvalidParams as i32));
+
+        assert_eq!(client.open("let x = 1\n"), json!([]));
+
+        let hover = client.at("textDocument/hover", 0, 4);
+        assert_eq!(hover["contents"]["value"], "```k\nlet x: i32\n```");
+
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut client = Client::start();
+        assert_eq!(client.open(PROGRAM), json!([]));
+
+        let hover = client.at("textDocument/hover", 7, 9);
+        assert_eq!(hover["contents"]["value"], "```k\nfn norm(Point): f64\n```");
+        assert_eq!(hover["range"], range(7, 8, 12));
+
+        let hover = client.at("textDocument/hover", 6, 5);
+        assert_eq!(hover["contents"]["value"], "```k\nlet origin: Point\n```");
+
+        let hover = client.at("textDocument/hover", 3, 6);
+        assert_eq!(hover["contents"]["value"], "```k\nx: f64\n```");
+
+        let definition = client.at("textDocument/definition", 7, 14);
+        assert_eq!(definition, json!({ "uri": URI, "range": range(6, 4, 10) }));
+
+        let definition = client.at("textDocument/definition", 3, 18);
+        assert_eq!(definition["range"], range(0, 23, 24));
+
+        let references = client.at("textDocument/references", 0, 15);
+        let ranges: Vec<&Value> = references.as_array().unwrap().iter().map(|location| &location["range"]).collect();
+        assert_eq!(ranges, vec![&range(0, 15, 16), &range(3, 6, 7), &range(3, 12, 13), &range(6, 21, 22)]);
+
+        let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } }));
+        let names: Vec<&Value> = symbols.as_array().unwrap().iter().map(|symbol| &symbol["name"]).collect();
+        assert_eq!(names, vec!["Point", "norm", "origin", "n"]);
+
+        assert_eq!(symbols[0]["kind"], 23);
+        assert_eq!(symbols[0]["children"][1]["name"], "y");
+        assert_eq!(symbols[1]["range"]["end"], json!({ "line": 4, "character": 1 }));
+        assert_eq!(symbols[1]["detail"], "fn(Point): f64");
+
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_completion_and_formatting() {
+        let mut client = Client::start();
+        client.open(&format!("{}let m = o\norigin.\nimport std\nstd.pr", PROGRAM));
+
+        let labels = |items: Value| -> Vec<String> {
+            items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_owned())
+                .collect()
+        };
+
+        assert_eq!(labels(client.at("textDocument/completion", 8, 9)), vec!["origin"]);
+        assert_eq!(labels(client.at("textDocument/completion", 9, 7)), vec!["x", "y"]);
+        assert_eq!(labels(client.at("textDocument/completion", 11, 6)), vec!["print", "println"]);
+
+        let items = client.at("textDocument/completion", 4, 4);
+        assert!(labels(items.clone()).contains(&"p".to_owned()));
+        assert!(labels(items).contains(&"return".to_owned()));
+
+        client.change(2, "let  x=1");
+
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "options": { "tabSize": 4, "insertSpaces": true },
+        });
+
+        let edits = client.request("textDocument/formatting", params.clone());
+        assert_eq!(edits, json!([{ "range": range(0, 0, 8), "newText": "let x = 1\n" }]));
+
+        client.change(3, "let x = 1\n");
+        assert_eq!(client.request("textDocument/formatting", params), json!([]));
+
+        let response = client.try_request("textDocument/rename", json!({}));
+        assert_eq!(response.error.map(|error| error.code), Some(ErrorCode::MethodNotFound as i32));
+
+        client.shutdown();
+    }
+}
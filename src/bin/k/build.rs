@@ -0,0 +1,72 @@
+use crate::dump::read_source;
+use crate::print_error;
+use clap::ArgMatches;
+use k::compile::{self, CompileError, Options};
+use k::ir::Module;
+use k::{backend_c, backend_wasm, backend_x86};
+use std::path::Path;
+
+const SUCCESS: i32 = 0;
+const FAILURE: i32 = 1;
+
+/// Runs `k build`. Fails when the program has any errors, or the backend
+/// can't compile it.
+pub fn run(matches: &ArgMatches) -> i32 {
+    let (name, source) = match read_source(matches) {
+        Some(source) => source,
+        None => return FAILURE,
+    };
+
+    let module = match lower(&name, &source, Options { fold: !matches.is_present("no-fold"), optimize: true }) {
+        Some(module) => module,
+        None => return FAILURE,
+    };
+
+    let target = matches.value_of("target").unwrap_or("c");
+
+    let output = match matches.value_of("output") {
+        Some(output) => Path::new(output).to_owned(),
+        None => {
+            let stem = Path::new(Path::new(&name).file_stem().unwrap_or_else(|| "a.out".as_ref())).to_owned();
+
+            match target {
+                "wat" | "wasm" => stem.with_extension(target),
+                _ => stem,
+            }
+        }
+    };
+
+    let result = match target {
+        "c" => backend_c::build(&module, &output),
+        "wat" => backend_wasm::build_text(&module, &output),
+        "wasm" => backend_wasm::build_binary(&module, &output),
+        "x86-64" => backend_x86::build(&module, &output),
+        target => unreachable!("clap only allows known targets, not `{}`", target),
+    };
+
+    match result {
+        Ok(()) => SUCCESS,
+        Err(error) => {
+            print_error(&format!("{}: {}", name, error));
+            FAILURE
+        }
+    }
+}
+
+/// Takes a program through every stage up to a verified IR module, printing
+/// the errors of the first stage that fails.
+pub fn lower(name: &str, source: &str, options: Options) -> Option<Module> {
+    match compile::compile(source, options) {
+        Ok(module) => Some(module),
+        Err(error) => {
+            // Errors in the IR name a function rather than a position
+            let separator = if let CompileError::Verify(_) = error { ": " } else { ":" };
+
+            for message in error.messages() {
+                print_error(&format!("{}{}{}", name, separator, message));
+            }
+
+            None
+        }
+    }
+}
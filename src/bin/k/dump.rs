@@ -0,0 +1,106 @@
+use crate::build;
+use crate::print_error;
+use clap::ArgMatches;
+use k::compile::Options;
+use k::dump::{program_to_json, program_to_sexp, tokens_to_json, tokens_to_sexp};
+use k::lexer::Lexer;
+use k::parser::Parser;
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Read, Write};
+
+const SUCCESS: i32 = 0;
+const FAILURE: i32 = 1;
+
+/// Runs `k ast`. Fails when the source has syntax errors.
+pub fn ast(matches: &ArgMatches) -> i32 {
+    let (name, source) = match read_source(matches) {
+        Some(source) => source,
+        None => return FAILURE,
+    };
+
+    let mut parser = Parser::new(Lexer::new(&source));
+    let program = parser.parse();
+
+    if !parser.errors().is_empty() {
+        for error in parser.errors() {
+            print_error(&format!("{}:{}", name, error));
+        }
+
+        return FAILURE;
+    }
+
+    match matches.value_of("format") {
+        Some("json") => write(&json(&program_to_json(&program))),
+        _ => write(&program_to_sexp(&program)),
+    }
+}
+
+/// Runs `k ir`. Fails when the program has any errors, or can't be lowered.
+pub fn ir(matches: &ArgMatches) -> i32 {
+    let (name, source) = match read_source(matches) {
+        Some(source) => source,
+        None => return FAILURE,
+    };
+
+    let options = Options {
+        fold: !matches.is_present("no-fold"),
+        optimize: !matches.is_present("no-optimize"),
+    };
+
+    let module = match build::lower(&name, &source, options) {
+        Some(module) => module,
+        None => return FAILURE,
+    };
+
+    write(&module.to_string())
+}
+
+/// Runs `k tokens`. Illegal tokens are printed like any other.
+pub fn tokens(matches: &ArgMatches) -> i32 {
+    let source = match read_source(matches) {
+        Some((_, source)) => source,
+        None => return FAILURE,
+    };
+
+    match matches.value_of("format") {
+        Some("json") => write(&json(&tokens_to_json(&source))),
+        _ => write(&tokens_to_sexp(&source)),
+    }
+}
+
+fn json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).expect("values always serialize") + "\n"
+}
+
+fn write(output: &str) -> i32 {
+    match io::stdout().write_all(output.as_bytes()) {
+        Ok(()) => SUCCESS,
+        Err(_) => FAILURE,
+    }
+}
+
+/// Reads the `FILE` argument, or standard input without one, along with a
+/// name to report errors under.
+pub fn read_source(matches: &ArgMatches) -> Option<(String, String)> {
+    match matches.value_of("FILE") {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(source) => Some((path.to_owned(), source)),
+            Err(err) => {
+                print_error(&format!("could not read `{}`: {}", path, err));
+                None
+            }
+        },
+        None => {
+            let mut source = String::new();
+
+            match io::stdin().read_to_string(&mut source) {
+                Ok(_) => Some(("<stdin>".to_owned(), source)),
+                Err(err) => {
+                    print_error(&format!("could not read standard input: {}", err));
+                    None
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::print_error;
+use clap::ArgMatches;
+use k::formatter::{format_source, FormatConfig, DEFAULT_WIDTH};
+use std::fs;
+use std::io::{self, Read, Write};
+
+const SUCCESS: i32 = 0;
+const FAILURE: i32 = 1;
+
+/// Runs `k fmt`. Returns a failing status when a file has syntax errors or,
+/// with `--check`, when a file isn't already formatted.
+pub fn run(matches: &ArgMatches) -> i32 {
+    let width = match matches.value_of("width") {
+        None => DEFAULT_WIDTH,
+        Some(width) => match width.parse::<usize>() {
+            Ok(width) if width > 0 => width,
+            _ => {
+                print_error(&format!("invalid width `{}`, expected a positive number", width));
+                return FAILURE;
+            }
+        },
+    };
+
+    let config = FormatConfig { width };
+    let check = matches.is_present("check");
+
+    match matches.values_of("FILES") {
+        Some(paths) => paths
+            .map(|path| format_file(path, &config, check))
+            .fold(SUCCESS, i32::max),
+        None => format_stdin(&config, check),
+    }
+}
+
+/// Formats source read from `name`, printing syntax errors if it can't be.
+fn format(name: &str, source: &str, config: &FormatConfig) -> Option<String> {
+    match format_source(source, config) {
+        Ok(formatted) => Some(formatted),
+        Err(errors) => {
+            for error in errors {
+                print_error(&format!("{}:{}", name, error));
+            }
+
+            None
+        }
+    }
+}
+
+fn format_file(path: &str, config: &FormatConfig, check: bool) -> i32 {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            print_error(&format!("could not read `{}`: {}", path, err));
+            return FAILURE;
+        }
+    };
+
+    let formatted = match format(path, &source, config) {
+        Some(formatted) => formatted,
+        None => return FAILURE,
+    };
+
+    if formatted == source {
+        return SUCCESS;
+    }
+
+    if check {
+        println!("{} is not formatted", path);
+        return FAILURE;
+    }
+
+    if let Err(err) = fs::write(path, formatted) {
+        print_error(&format!("could not write `{}`: {}", path, err));
+        return FAILURE;
+    }
+
+    SUCCESS
+}
+
+fn format_stdin(config: &FormatConfig, check: bool) -> i32 {
+    let mut source = String::new();
+
+    if let Err(err) = io::stdin().read_to_string(&mut source) {
+        print_error(&format!("could not read standard input: {}", err));
+        return FAILURE;
+    }
+
+    let formatted = match format("<stdin>", &source, config) {
+        Some(formatted) => formatted,
+        None => return FAILURE,
+    };
+
+    if check {
+        if formatted == source {
+            return SUCCESS;
+        }
+
+        println!("<stdin> is not formatted");
+        return FAILURE;
+    }
+
+    match io::stdout().write_all(formatted.as_bytes()) {
+        Ok(()) => SUCCESS,
+        Err(_) => FAILURE,
+    }
+}
@@ -0,0 +1,76 @@
+use crate::print_error;
+use clap::ArgMatches;
+use k::lint::{lint_source, Level, LintConfig};
+use std::fs;
+use std::io::{self, Read};
+
+const SUCCESS: i32 = 0;
+const FAILURE: i32 = 1;
+
+/// Runs `k lint`. Returns a failing status when a file has syntax errors or
+/// a denied lint fires.
+pub fn run(matches: &ArgMatches) -> i32 {
+    let config = match matches.value_of("config") {
+        None => LintConfig::default(),
+        Some(path) => match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| LintConfig::parse(&text))
+        {
+            Ok(config) => config,
+            Err(err) => {
+                print_error(&format!("invalid lint config `{}`: {}", path, err));
+                return FAILURE;
+            }
+        },
+    };
+
+    match matches.values_of("FILES") {
+        Some(paths) => paths.map(|path| lint_file(path, &config)).fold(SUCCESS, i32::max),
+        None => lint_stdin(&config),
+    }
+}
+
+fn lint(name: &str, source: &str, config: &LintConfig) -> i32 {
+    let diagnostics = match lint_source(source, config) {
+        Ok(diagnostics) => diagnostics,
+        Err(errors) => {
+            for error in errors {
+                print_error(&format!("{}:{}", name, error));
+            }
+
+            return FAILURE;
+        }
+    };
+
+    for diagnostic in &diagnostics {
+        println!("{}:{}", name, diagnostic);
+    }
+
+    if diagnostics.iter().any(|diagnostic| diagnostic.level == Level::Deny) {
+        FAILURE
+    } else {
+        SUCCESS
+    }
+}
+
+fn lint_file(path: &str, config: &LintConfig) -> i32 {
+    match fs::read_to_string(path) {
+        Ok(source) => lint(path, &source, config),
+        Err(err) => {
+            print_error(&format!("could not read `{}`: {}", path, err));
+            FAILURE
+        }
+    }
+}
+
+fn lint_stdin(config: &LintConfig) -> i32 {
+    let mut source = String::new();
+
+    match io::stdin().read_to_string(&mut source) {
+        Ok(_) => lint("<stdin>", &source, config),
+        Err(err) => {
+            print_error(&format!("could not read standard input: {}", err));
+            FAILURE
+        }
+    }
+}
@@ -0,0 +1,35 @@
+#[macro_use]
+extern crate clap;
+extern crate colored;
+extern crate k;
+
+mod build;
+mod dump;
+mod fmt;
+mod lint;
+
+use clap::App;
+use colored::*;
+use std::fmt::Display;
+use std::process;
+
+pub fn print_error(message: &dyn Display) {
+    eprintln!("{}: {}", "error".red().bold(), message);
+}
+
+fn main() {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml).version(crate_version!()).get_matches();
+
+    let status = match matches.subcommand() {
+        ("fmt", Some(matches)) => fmt::run(matches),
+        ("lint", Some(matches)) => lint::run(matches),
+        ("build", Some(matches)) => build::run(matches),
+        ("ast", Some(matches)) => dump::ast(matches),
+        ("ir", Some(matches)) => dump::ir(matches),
+        ("tokens", Some(matches)) => dump::tokens(matches),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+
+    process::exit(status);
+}
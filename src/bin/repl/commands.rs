@@ -0,0 +1,180 @@
+use crate::session::{colorize, print_error, Session};
+use colored::*;
+use k::dump::{program_to_sexp, tokens_to_sexp};
+use std::fs;
+use std::time::Instant;
+
+pub struct Command {
+    pub name: &'static str,
+    pub argument: &'static str,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command { name: ":help", argument: "", description: "show this message" },
+    Command { name: ":license", argument: "", description: "show license information" },
+    Command { name: ":quit", argument: "", description: "exit the REPL" },
+    Command { name: ":load", argument: "<file.k>", description: "evaluate a file in the current session" },
+    Command { name: ":reset", argument: "", description: "forget every definition" },
+    Command { name: ":tokens", argument: "<expr>", description: "show the tokens of an expression" },
+    Command { name: ":ast", argument: "<expr>", description: "show the syntax tree of an expression" },
+    Command { name: ":type", argument: "<expr>", description: "show the type of an expression without evaluating it" },
+    Command { name: ":time", argument: "<expr>", description: "evaluate an expression and report how long it took" },
+    Command { name: ":env", argument: "", description: "list the global bindings" },
+    Command { name: ":optimize", argument: "[on|off]", description: "turn constant folding and dead code removal on or off" },
+];
+
+static BINARY_VERSION: &str = env!("CARGO_PKG_VERSION");
+static AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+static LICENSE: &str = env!("CARGO_PKG_LICENSE");
+
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+/// Runs a `:command` line. Bare `help` and `license` are accepted too since
+/// the banner advertises them.
+pub fn run(session: &mut Session, line: &str) -> Action {
+    let line = line.trim();
+
+    let (name, argument) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+
+    match name {
+        ":help" | "help" => help(),
+        ":license" | "license" => license(),
+        ":quit" => return Action::Quit,
+        ":load" => load(session, argument),
+        ":reset" => {
+            *session = Session::new();
+            println!("{}", "Session reset.".dimmed());
+        }
+        ":tokens" => tokens(argument),
+        ":ast" => ast(session, argument),
+        ":type" => type_of(session, argument),
+        ":time" => time(session, argument),
+        ":env" => env(session),
+        ":optimize" => optimize(session, argument),
+        _ => print_error(
+            "error",
+            &format!("unknown command `{}`, type {} for a list of commands", name, ":help".yellow()),
+        ),
+    }
+
+    Action::Continue
+}
+
+pub fn is_command(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(':') || line == "help" || line == "license"
+}
+
+fn help() {
+    println!("Enter K statements or expressions to evaluate them. Commands:");
+
+    for command in COMMANDS {
+        let usage = format!("{} {}", command.name, command.argument);
+        println!("  {:<18} {}", usage.trim_end().yellow(), command.description);
+    }
+}
+
+fn license() {
+    let license = if LICENSE.is_empty() { "not specified" } else { LICENSE };
+
+    println!("K Programming Language {}", BINARY_VERSION);
+    println!("Written by {}", AUTHORS.replace(':', ", "));
+    println!("License: {}", license);
+}
+
+fn require_argument(command: &str, argument: &str) -> bool {
+    if argument.is_empty() {
+        print_error("error", &format!("`{}` expects an argument", command));
+        return false;
+    }
+
+    true
+}
+
+fn load(session: &mut Session, path: &str) {
+    if !require_argument(":load", path) {
+        return;
+    }
+
+    match fs::read_to_string(path) {
+        Ok(source) => {
+            if session.eval(&source).is_some() {
+                println!("{}", format!("Loaded {}", path).dimmed());
+            }
+        }
+        Err(err) => print_error("error", &format!("could not read `{}`: {}", path, err)),
+    }
+}
+
+fn tokens(source: &str) {
+    print!("{}", tokens_to_sexp(source));
+}
+
+fn ast(session: &Session, source: &str) {
+    if let Some(program) = session.parse(source) {
+        print!("{}", program_to_sexp(&program));
+    }
+}
+
+fn type_of(session: &mut Session, source: &str) {
+    if !require_argument(":type", source) {
+        return;
+    }
+
+    if let Some(mut program) = session.parse(source) {
+        match session.checker.type_of(&mut program) {
+            Ok(ty) => println!("{}", ty.to_string().cyan()),
+            Err(errors) => {
+                for error in errors {
+                    print_error("type error", &error);
+                }
+            }
+        }
+    }
+}
+
+fn time(session: &mut Session, source: &str) {
+    if !require_argument(":time", source) {
+        return;
+    }
+
+    let start = Instant::now();
+    session.execute(source);
+    let elapsed = start.elapsed();
+
+    println!("{}", format!("took {:?}", elapsed).dimmed());
+}
+
+fn env(session: &Session) {
+    let types = session.checker.globals();
+    let bindings = session.evaluator.env().borrow().bindings();
+
+    for (name, value) in bindings {
+        match types.iter().find(|(typed, _)| typed == &name) {
+            Some((_, ty)) => println!("{}: {} = {}", name, ty.to_string().cyan(), colorize(&value)),
+            None => println!("{} = {}", name, colorize(&value)),
+        }
+    }
+}
+
+fn optimize(session: &mut Session, argument: &str) {
+    match argument {
+        "on" => session.optimize = true,
+        "off" => session.optimize = false,
+        "" => {}
+        _ => {
+            print_error("error", &format!("expected `on` or `off`, found `{}`", argument));
+            return;
+        }
+    }
+
+    let state = if session.optimize { "on" } else { "off" };
+    println!("{}", format!("Optimizations are {}.", state).dimmed());
+}
@@ -0,0 +1,283 @@
+use crate::commands::{self, COMMANDS};
+use colored::*;
+use k::builtins;
+use k::lexer::Lexer;
+use k::object::Object;
+use k::symbol::Symbol;
+use k::token::{BufferPosition, KeywordKind, SymbolKind, TokenKind};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+
+/// Editor support for the REPL: highlighting, completion and hints.
+#[derive(Default)]
+pub struct ReplHelper {
+    /// The global bindings of the session, refreshed before every prompt.
+    pub bindings: Vec<(Symbol, Object)>,
+}
+
+/// A hint showing the rest of a completion, and for commands their usage.
+pub struct ReplHint {
+    display: String,
+    completion: usize,
+}
+
+impl Hint for ReplHint {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn completion(&self) -> Option<&str> {
+        Some(&self.display[..self.completion])
+    }
+}
+
+/// Converts a line and column from the lexer into a byte offset in `source`.
+fn byte_offset(source: &str, position: BufferPosition) -> usize {
+    let mut line_start = 0;
+
+    for _ in 1..position.line {
+        match source[line_start..].find('\n') {
+            Some(index) => line_start += index + 1,
+            None => return source.len(),
+        }
+    }
+
+    source[line_start..]
+        .char_indices()
+        .nth(position.column - 1)
+        .map_or(source.len(), |(index, _)| line_start + index)
+}
+
+fn paint(kind: &TokenKind, text: &str) -> ColoredString {
+    match kind {
+        TokenKind::Keyword(KeywordKind::True) | TokenKind::Keyword(KeywordKind::False) => text.magenta(),
+        TokenKind::Keyword(_) => text.blue().bold(),
+        TokenKind::IntegerLiteral(_) | TokenKind::FloatLiteral(_) => text.yellow(),
+        TokenKind::StringLiteral(_)
+        | TokenKind::StringStart(_)
+        | TokenKind::StringMiddle(_)
+        | TokenKind::StringEnd(_)
+        | TokenKind::CharLiteral(_) => text.green(),
+        TokenKind::Symbol(symbol) => match symbol {
+            SymbolKind::LeftParen
+            | SymbolKind::RightParen
+            | SymbolKind::LeftBrace
+            | SymbolKind::RightBrace
+            | SymbolKind::LeftBracket
+            | SymbolKind::RightBracket
+            | SymbolKind::Comma
+            | SymbolKind::Semicolon
+            | SymbolKind::Colon
+            | SymbolKind::Dot => text.normal(),
+            _ => text.cyan(),
+        },
+        TokenKind::Illegal => text.red(),
+        TokenKind::Identifier(_) | TokenKind::Newline | TokenKind::EOF => text.normal(),
+    }
+}
+
+/// Colors source code by token kind. Comments and anything else the lexer
+/// skips are dimmed.
+pub fn highlight(source: &str) -> String {
+    let mut lexer = Lexer::new(source);
+    let mut output = String::new();
+    let mut end = 0;
+
+    loop {
+        let token = lexer.next_token();
+
+        if token.kind == TokenKind::EOF {
+            break;
+        }
+
+        let start = byte_offset(source, token.position);
+        output.push_str(&skipped(&source[end..start]));
+
+        end = byte_offset(source, lexer.current_position());
+        output.push_str(&paint(&token.kind, &source[start..end]).to_string());
+    }
+
+    output.push_str(&skipped(&source[end..]));
+    output
+}
+
+fn skipped(text: &str) -> String {
+    if text.trim().is_empty() {
+        text.to_owned()
+    } else {
+        text.dimmed().to_string()
+    }
+}
+
+/// Finds the start of the identifier that ends at `pos`.
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| unicode_ident::is_xid_continue(*ch))
+        .last()
+        .map_or(pos, |(index, _)| index)
+}
+
+impl ReplHelper {
+    pub fn new() -> ReplHelper {
+        ReplHelper::default()
+    }
+
+    fn lookup(&self, name: &str) -> Option<Object> {
+        self.bindings
+            .iter()
+            .find(|(binding, _)| binding == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// The names reachable with a `.` after a value: module members, enum
+    /// variants and struct fields. `std` is offered even before it is
+    /// imported.
+    fn members(&self, name: &str) -> Vec<String> {
+        match self.lookup(name).or_else(|| builtins::module(name)) {
+            Some(Object::Module(module)) => module.members.iter().map(|(name, _)| name.to_string()).collect(),
+            Some(Object::EnumType(decl)) => decl.variants.iter().map(|variant| variant.name.name.to_string()).collect(),
+            Some(Object::Struct(_, fields)) => fields.iter().map(|(name, _)| name.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Candidates for the word ending at `pos`, along with where it starts.
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let trimmed = line[..pos].trim_start();
+
+        if trimmed.starts_with(':') && !trimmed.contains(char::is_whitespace) {
+            let names = COMMANDS
+                .iter()
+                .map(|command| command.name.to_owned())
+                .filter(|name| name.starts_with(trimmed))
+                .collect();
+
+            return (pos - trimmed.len(), names);
+        }
+
+        let start = word_start(line, pos);
+        let prefix = &line[start..pos];
+
+        let mut names: Vec<String> = if line[..start].ends_with('.') {
+            let receiver = word_start(line, start - 1);
+            self.members(&line[receiver..start - 1])
+        } else if prefix.is_empty() {
+            Vec::new()
+        } else {
+            Lexer::keywords()
+                .map(str::to_owned)
+                .chain(self.bindings.iter().map(|(name, _)| name.to_string()))
+                .chain(std::iter::once("std".to_owned()))
+                .collect()
+        };
+
+        names.retain(|name| name.starts_with(prefix));
+        names.sort();
+        names.dedup();
+
+        (start, names)
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = ReplHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ReplHint> {
+        if pos < line.len() {
+            return None;
+        }
+
+        let (start, names) = self.candidates(line, pos);
+        let prefix = &line[start..pos];
+
+        // Hinting every name after a bare `.` would be noise
+        if prefix.is_empty() {
+            return None;
+        }
+
+        let name = names.into_iter().find(|name| name.len() > prefix.len())?;
+        let mut display = name[prefix.len()..].to_owned();
+        let completion = display.len();
+
+        if let Some(command) = COMMANDS.iter().find(|command| command.name == name) {
+            if !command.argument.is_empty() {
+                display.push(' ');
+                display.push_str(command.argument);
+            }
+        }
+
+        Some(ReplHint { display, completion })
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if commands::is_command(line) {
+            return Cow::Borrowed(line);
+        }
+
+        Cow::Owned(highlight(line))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.dimmed().to_string())
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+// Every line is accepted as it is, since the REPL itself asks for the rest
+// of an unfinished entry with its continuation prompt
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_offset() {
+        let source = "let é = 1\n  x";
+
+        assert_eq!(byte_offset(source, BufferPosition::new(1, 5)), 4);
+        assert_eq!(byte_offset(source, BufferPosition::new(1, 7)), 7);
+        assert_eq!(byte_offset(source, BufferPosition::new(2, 3)), 13);
+        assert_eq!(byte_offset(source, BufferPosition::new(2, 4)), source.len());
+    }
+
+    #[test]
+    fn test_highlight_keeps_text() {
+        colored::control::set_override(false);
+
+        let source = "fn f(x: i32): i32 { /* twice */ x * 2 } // done\nlet s = \"abc";
+        assert_eq!(highlight(source), source);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let mut helper = ReplHelper::new();
+        helper.bindings.push((Symbol::intern("letter"), Object::Char('a')));
+
+        assert_eq!(helper.candidates("le", 2), (0, vec!["let".to_owned(), "letter".to_owned()]));
+        assert_eq!(helper.candidates("1 + std.pri", 11), (8, vec!["print".to_owned(), "println".to_owned()]));
+        assert_eq!(helper.candidates(":lo", 3), (0, vec![":load".to_owned()]));
+        assert_eq!(helper.candidates("1 + ", 4), (4, vec![]));
+    }
+}
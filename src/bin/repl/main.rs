@@ -0,0 +1,107 @@
+extern crate k;
+use k::evaluator;
+
+extern crate rustyline;
+use rustyline::error::ReadlineError;
+use rustyline::{CompletionType, Config, Editor};
+
+extern crate colored;
+use colored::*;
+
+mod commands;
+mod helper;
+mod session;
+
+use commands::Action;
+use helper::ReplHelper;
+use session::Session;
+use std::env;
+use std::path::PathBuf;
+
+static BINARY_VERSION: &str = env!("CARGO_PKG_VERSION");
+static PROMPT: &str = ">>> ";
+static CONTINUATION_PROMPT: &str = "... ";
+static HISTORY_FILE: &str = ".k_history";
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+fn repl() {
+    println!("K Programming Language {} (written by Kosi Nwabueze)", BINARY_VERSION.blue());
+    println!(r#"Type "{}" or "{}" for more information."#, "help".yellow(), "license".yellow());
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .history_ignore_dups(true)
+        .build();
+
+    let mut rl = Editor::<ReplHelper>::with_config(config);
+    rl.set_helper(Some(ReplHelper::new()));
+
+    let history = history_path();
+
+    if let Some(path) = &history {
+        // There is no history to load the first time the REPL runs
+        let _ = rl.load_history(path);
+    }
+
+    let mut session = Session::new();
+
+    // Lines of an entry that is still missing closing brackets or quotes
+    let mut entry = String::new();
+
+    loop {
+        if let Some(helper) = rl.helper_mut() {
+            helper.bindings = session.evaluator.env().borrow().bindings();
+        }
+
+        let prompt = if entry.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let readline = rl.readline(prompt);
+
+        match readline {
+            Ok(line) => {
+                // Input that isn't a terminal is read with its line ending
+                let line = line.trim_end_matches(&['\r', '\n'][..]);
+
+                if entry.is_empty() && commands::is_command(line) {
+                    rl.add_history_entry(line);
+
+                    if let Action::Quit = commands::run(&mut session, line) {
+                        break;
+                    }
+
+                    continue;
+                }
+
+                entry.push_str(line);
+                entry.push('\n');
+
+                if session::is_incomplete(&entry) {
+                    continue;
+                }
+
+                rl.add_history_entry(entry.trim_end());
+                session.execute(&entry);
+                entry.clear();
+            }
+            // Ctrl-C abandons the current entry, Ctrl-D leaves the REPL
+            Err(ReadlineError::Interrupted) => entry.clear(),
+            Err(_) => break
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(err) = rl.save_history(path) {
+            session::print_error("error", &format!("could not save history to `{}`: {}", path.display(), err));
+        }
+    }
+}
+
+fn main() {
+    let repl = std::thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(repl)
+        .expect("failed to spawn the REPL thread");
+
+    repl.join().expect("the REPL thread panicked");
+}
@@ -0,0 +1,155 @@
+use colored::*;
+use k::ast::Program;
+use k::checker::Checker;
+use k::compile::{self, CompileError};
+use k::evaluator::Evaluator;
+use k::lexer::Lexer;
+use k::object::Object;
+use k::parser::Parser;
+use k::resolver::Resolver;
+use std::fmt::Display;
+
+/// The state that persists between REPL entries.
+pub struct Session {
+    pub evaluator: Evaluator,
+    pub checker: Checker,
+    pub resolver: Resolver,
+    // Whether programs are optimized before they run
+    pub optimize: bool,
+}
+
+pub fn colorize(value: &Object) -> ColoredString {
+    let text = value.inspect();
+
+    match value {
+        Object::Integer(_) | Object::Float(_) => text.yellow(),
+        Object::Boolean(_) => text.magenta(),
+        Object::Char(_) | Object::Str(_) => text.green(),
+        Object::Array(_) | Object::Struct(..) | Object::Variant(..) => text.normal(),
+        Object::Function(_)
+        | Object::Builtin(_)
+        | Object::VariantConstructor(..)
+        | Object::StructType(_)
+        | Object::EnumType(_)
+        | Object::Module(_) => text.cyan(),
+        Object::Void => text.dimmed(),
+    }
+}
+
+pub fn print_error(kind: &str, message: &dyn Display) {
+    eprintln!("{}: {}", kind.red().bold(), message);
+}
+
+/// Prints the errors of the stage an entry failed in.
+fn report(error: CompileError) {
+    for message in error.messages() {
+        print_error(&format!("{} error", error.stage()), &message);
+    }
+}
+
+/// Whether an entry needs more lines before it can be evaluated, such as an
+/// unclosed brace, a trailing operator or an unterminated string.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut parser = Parser::new(Lexer::new(source));
+    parser.parse();
+    parser.is_incomplete()
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            evaluator: Evaluator::new(),
+            checker: Checker::new(),
+            resolver: Resolver::new(),
+            optimize: true,
+        }
+    }
+
+    /// Parses a source string, printing any syntax errors.
+    pub fn parse(&self, source: &str) -> Option<Program> {
+        compile::parse(source).map_err(report).ok()
+    }
+
+    /// Resolves, checks and optimizes a program, printing what is wrong with
+    /// it if any of those fail.
+    fn compile(&mut self, program: &mut Program) -> bool {
+        let result = compile::check(&mut self.resolver, &mut self.checker, program).and_then(|_| {
+            if self.optimize {
+                compile::fold(program)
+            } else {
+                Ok(())
+            }
+        });
+
+        result.map_err(report).is_ok()
+    }
+
+    /// Parses, compiles and evaluates a source string in the global
+    /// environment, returning the resulting value.
+    pub fn eval(&mut self, source: &str) -> Option<Object> {
+        let mut program = self.parse(source)?;
+        let (resolver, checker) = (self.resolver.clone(), self.checker.clone());
+        let globals = self.evaluator.env().borrow().ids();
+
+        let result = if self.compile(&mut program) {
+            self.evaluator.eval_program(&program).map_err(|error| print_error("runtime error", &error))
+        } else {
+            Err(())
+        };
+
+        if result.is_err() {
+            // Forget the entry's globals everywhere, so the passes stay in step
+            self.resolver = resolver;
+            self.checker = checker;
+            self.evaluator.env().borrow_mut().retain(&globals);
+        }
+
+        result.ok()
+    }
+
+    /// Evaluates an entry and echoes its value.
+    pub fn execute(&mut self, source: &str) {
+        match self.eval(source) {
+            Some(Object::Void) | None => {}
+            Some(value) => println!("{}", colorize(&value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("fn f() { [1, (2)] }"));
+        assert!(is_incomplete("fn f() {\n  [1,"));
+        assert!(is_incomplete("\"abc"));
+        assert!(!is_incomplete("\"\\{(\" // ("));
+        assert!(is_incomplete("\"{[1]} {f(\n"));
+        assert!(is_incomplete("1 +"));
+
+        // Mismatched brackets are left for the parser to report
+        assert!(!is_incomplete("(1]"));
+    }
+
+    #[test]
+    fn test_runtime_errors_forget_globals() {
+        let mut session = Session::new();
+        session.optimize = false;
+
+        assert!(session.eval("let y = 2").is_some());
+        assert!(session.eval("let x = 1\nlet z = x / 0").is_none());
+
+        let mut program = session.parse("x + z").unwrap();
+        let result = compile::check(&mut session.resolver, &mut session.checker, &mut program);
+        assert!(matches!(result, Err(CompileError::Name(_))));
+
+        let names: Vec<String> = session.checker.globals().iter().map(|(name, _)| name.to_string()).collect();
+        assert_eq!(names, ["y"]);
+
+        let bindings = session.evaluator.env().borrow().bindings();
+        assert_eq!(bindings.len(), 1);
+        assert!(session.eval("y + 1").is_some_and(|value| value.equals(&Object::Integer(3))));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::object::{Builtin, Module, Object};
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Members of the `std` module in declaration order.
+pub const STD_BUILTINS: &[Builtin] = &[
+    Builtin { name: "print", function: print },
+    Builtin { name: "println", function: println },
+    Builtin { name: "len", function: len },
+    Builtin { name: "to_string", function: to_string },
+    Builtin { name: "abs", function: abs },
+    Builtin { name: "min", function: min },
+    Builtin { name: "max", function: max },
+    Builtin { name: "sqrt", function: sqrt },
+];
+
+/// Looks up a module that can be brought into scope with `import`.
+pub fn module(name: &str) -> Option<Object> {
+    match name {
+        "std" => Some(Object::Module(Rc::new(Module {
+            name: Symbol::intern("std"),
+            members: STD_BUILTINS
+                .iter()
+                .map(|builtin| (Symbol::intern(builtin.name), Object::Builtin(*builtin)))
+                .collect(),
+        }))),
+        _ => None,
+    }
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, collecting what `std.print` and `std.println` write on this
+/// thread instead of printing it.
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let outer = CAPTURED.with(|captured| captured.replace(Some(String::new())));
+    let result = f();
+    let output = CAPTURED.with(|captured| captured.replace(outer)).unwrap_or_default();
+
+    (result, output)
+}
+
+fn write_output(text: &str) {
+    CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(output) => output.push_str(text),
+        None => print!("{}", text),
+    })
+}
+
+fn join(args: &[Object]) -> String {
+    let args: Vec<String> = args.iter().map(Object::to_string).collect();
+    args.join(" ")
+}
+
+fn expect_args(name: &str, args: &[Object], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!(
+            "`std.{}` takes {} argument(s) but {} were supplied",
+            name,
+            count,
+            args.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn print(args: Vec<Object>) -> Result<Object, String> {
+    write_output(&join(&args));
+    Ok(Object::Void)
+}
+
+fn println(args: Vec<Object>) -> Result<Object, String> {
+    write_output(&(join(&args) + "\n"));
+    Ok(Object::Void)
+}
+
+fn len(args: Vec<Object>) -> Result<Object, String> {
+    expect_args("len", &args, 1)?;
+
+    match &args[0] {
+        Object::Str(value) => Ok(Object::Integer(value.chars().count() as i64)),
+        Object::Array(elements) => Ok(Object::Integer(elements.len() as i64)),
+        other => Err(format!("`std.len` expects a str or array, found {}", other.type_name())),
+    }
+}
+
+fn to_string(args: Vec<Object>) -> Result<Object, String> {
+    expect_args("to_string", &args, 1)?;
+
+    Ok(Object::Str(args[0].to_string()))
+}
+
+fn abs(args: Vec<Object>) -> Result<Object, String> {
+    expect_args("abs", &args, 1)?;
+
+    match &args[0] {
+        Object::Integer(value) => value
+            .checked_abs()
+            .map(Object::Integer)
+            .ok_or_else(|| "integer overflow".to_owned()),
+        Object::Float(value) => Ok(Object::Float(value.abs())),
+        other => Err(format!("`std.abs` expects a number, found {}", other.type_name())),
+    }
+}
+
+fn min(args: Vec<Object>) -> Result<Object, String> {
+    expect_args("min", &args, 2)?;
+
+    match (&args[0], &args[1]) {
+        (Object::Integer(a), Object::Integer(b)) => Ok(Object::Integer(*a.min(b))),
+        (Object::Float(a), Object::Float(b)) => Ok(Object::Float(a.min(*b))),
+        (a, b) => Err(format!(
+            "`std.min` expects two numbers of the same type, found {} and {}",
+            a.type_name(),
+            b.type_name()
+        )),
+    }
+}
+
+fn max(args: Vec<Object>) -> Result<Object, String> {
+    expect_args("max", &args, 2)?;
+
+    match (&args[0], &args[1]) {
+        (Object::Integer(a), Object::Integer(b)) => Ok(Object::Integer(*a.max(b))),
+        (Object::Float(a), Object::Float(b)) => Ok(Object::Float(a.max(*b))),
+        (a, b) => Err(format!(
+            "`std.max` expects two numbers of the same type, found {} and {}",
+            a.type_name(),
+            b.type_name()
+        )),
+    }
+}
+
+fn sqrt(args: Vec<Object>) -> Result<Object, String> {
+    expect_args("sqrt", &args, 1)?;
+
+    match &args[0] {
+        Object::Float(value) => Ok(Object::Float(value.sqrt())),
+        other => Err(format!("`std.sqrt` expects a float, found {}", other.type_name())),
+    }
+}
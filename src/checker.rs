@@ -0,0 +1,1166 @@
+use crate::ast::*;
+use crate::builtins;
+use crate::symbol::Symbol;
+use crate::token::BufferPosition;
+use crate::types::Type;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeError {
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl TypeError {
+    pub fn new(message: String, position: BufferPosition) -> TypeError {
+        TypeError { message, position }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+type Scope = HashMap<Symbol, Type>;
+
+/// Everything the checker knows about the declarations it has seen. This is
+/// what persists between calls to `check_program`.
+#[derive(Clone, Default)]
+struct Definitions {
+    scopes: Vec<Scope>,
+    structs: HashMap<Symbol, Vec<(Symbol, Type)>>,
+    enums: HashMap<Symbol, Vec<(Symbol, Vec<Type>)>>,
+}
+
+#[derive(Clone)]
+pub struct Checker {
+    defs: Definitions,
+    return_types: Vec<Type>,
+    loop_depth: usize,
+    errors: Vec<TypeError>,
+    names: Vec<(Identifier, Type)>,
+    bindings: HashMap<BindingId, Type>,
+}
+
+impl Default for Checker {
+    fn default() -> Checker {
+        Checker::new()
+    }
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        Checker {
+            defs: Definitions {
+                scopes: vec![Scope::new()],
+                ..Definitions::default()
+            },
+            return_types: Vec::new(),
+            loop_depth: 0,
+            errors: Vec::new(),
+            names: Vec::new(),
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Checks a program against the declarations seen so far and returns the
+    /// type of its final statement, filling in the type of every expression.
+    /// The program's own declarations are only kept if it has no type errors.
+    pub fn check_program(&mut self, program: &mut Program) -> Result<Type, Vec<TypeError>> {
+        let saved = self.defs.clone();
+
+        let result = self.check_top_level(program);
+
+        if result.is_err() {
+            self.defs = saved;
+        }
+
+        result
+    }
+
+    /// Like `check_program`, but always forgets the program's declarations.
+    pub fn type_of(&mut self, program: &mut Program) -> Result<Type, Vec<TypeError>> {
+        let saved = self.defs.clone();
+
+        let result = self.check_top_level(program);
+
+        self.defs = saved;
+
+        result
+    }
+
+    /// The types of the global bindings, sorted by name.
+    pub fn globals(&self) -> Vec<(Symbol, Type)> {
+        let mut globals: Vec<(Symbol, Type)> = self.defs.scopes[0]
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.clone()))
+            .collect();
+
+        globals.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        globals
+    }
+
+    /// Every name in the last program checked, declarations and uses alike,
+    /// along with its type. Names that couldn't be typed are `Unknown`.
+    pub fn name_types(&self) -> &[(Identifier, Type)] {
+        &self.names
+    }
+
+    /// The type of every resolved name declared by the last program checked.
+    pub fn binding_types(&self) -> &HashMap<BindingId, Type> {
+        &self.bindings
+    }
+
+    fn check_top_level(&mut self, program: &mut Program) -> Result<Type, Vec<TypeError>> {
+        self.errors.clear();
+        self.names.clear();
+        self.bindings.clear();
+        self.return_types.clear();
+        self.loop_depth = 0;
+
+        let ty = self.check_block(&mut program.0, None);
+
+        if self.errors.is_empty() {
+            Ok(ty)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn error(&mut self, message: String, position: BufferPosition) {
+        self.errors.push(TypeError::new(message, position));
+    }
+
+    fn expect(&mut self, expected: &Type, actual: &Type, position: BufferPosition) {
+        if !expected.accepts(actual) {
+            self.error(
+                format!("mismatched types: expected {}, found {}", expected, actual),
+                position,
+            );
+        }
+    }
+
+    fn lookup(&self, name: &Symbol) -> Option<&Type> {
+        self.defs.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn bind(&mut self, name: Symbol, ty: Type) {
+        let ty = if ty == Type::Never { Type::Unknown } else { ty };
+
+        self.defs
+            .scopes
+            .last_mut()
+            .expect("the global scope is never popped")
+            .insert(name, ty);
+    }
+
+    fn record(&mut self, name: &Identifier, ty: &Type) {
+        self.names.push((name.clone(), ty.clone()));
+    }
+
+    /// Binds a name from the source, remembering its type.
+    fn bind_name(&mut self, name: &Identifier, ty: Type) {
+        self.record(name, &ty);
+
+        if let Some(binding) = name.binding {
+            let ty = if ty == Type::Never { Type::Unknown } else { ty.clone() };
+            self.bindings.insert(binding, ty);
+        }
+
+        self.bind(name.name.clone(), ty);
+    }
+
+    fn push_scope(&mut self) {
+        self.defs.scopes.push(Scope::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.defs.scopes.pop();
+    }
+
+    fn resolve_type(&mut self, ty: &TypeExpr) -> Type {
+        match ty {
+            TypeExpr::Named(name) => {
+                if let Some(ty) = Type::primitive(name.name.as_str()) {
+                    return ty;
+                }
+
+                match self.lookup(&name.name) {
+                    Some(Type::StructType(name)) => Type::Struct(name.clone()),
+                    Some(Type::EnumType(name)) => Type::Enum(name.clone()),
+                    _ => {
+                        self.error(format!("cannot find type `{}` in this scope", name.name), name.position);
+                        Type::Unknown
+                    }
+                }
+            }
+            TypeExpr::Array(element, _) => Type::Array(Box::new(self.resolve_type(element))),
+        }
+    }
+
+    fn fn_type(&mut self, decl: &FnDecl) -> Type {
+        let params = decl.params.iter().map(|param| self.resolve_type(&param.ty)).collect();
+
+        let ret = match &decl.return_type {
+            Some(ty) => self.resolve_type(ty),
+            None => Type::Void,
+        };
+
+        Type::Fn(params, Box::new(ret))
+    }
+
+    /// Declares the structs, enums and functions of a block up front, the
+    /// same way the evaluator hoists them.
+    fn declare_items(&mut self, block: &[Stmt]) {
+        for stmt in block {
+            match &stmt.kind {
+                StmtKind::Struct(decl) => self.bind_name(&decl.name, Type::StructType(decl.name.name.clone())),
+                StmtKind::Enum(decl) => self.bind_name(&decl.name, Type::EnumType(decl.name.name.clone())),
+                _ => {}
+            }
+        }
+
+        for stmt in block {
+            match &stmt.kind {
+                StmtKind::Struct(decl) => {
+                    let fields = decl
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            let ty = self.resolve_type(&field.ty);
+                            self.record(&field.name, &ty);
+                            (field.name.name.clone(), ty)
+                        })
+                        .collect();
+
+                    self.defs.structs.insert(decl.name.name.clone(), fields);
+                }
+                StmtKind::Enum(decl) => {
+                    let variants = decl
+                        .variants
+                        .iter()
+                        .map(|variant| {
+                            let fields: Vec<Type> = variant.fields.iter().map(|ty| self.resolve_type(ty)).collect();
+                            self.record(&variant.name, &variant_type(decl.name.name.clone(), &fields));
+                            (variant.name.name.clone(), fields)
+                        })
+                        .collect();
+
+                    self.defs.enums.insert(decl.name.name.clone(), variants);
+                }
+                _ => {}
+            }
+        }
+
+        for stmt in block {
+            if let StmtKind::Fn(decl) = &stmt.kind {
+                let ty = self.fn_type(decl);
+                self.bind_name(&decl.name, ty);
+            }
+        }
+    }
+
+    fn check_block(&mut self, block: &mut [Stmt], expected: Option<&Type>) -> Type {
+        self.declare_items(block);
+
+        let mut ty = Type::Void;
+        let len = block.len();
+
+        for (i, stmt) in block.iter_mut().enumerate() {
+            let hint = if i + 1 == len { expected } else { None };
+            ty = self.check_stmt(stmt, hint);
+        }
+
+        ty
+    }
+
+    fn check_scoped_block(&mut self, block: &mut [Stmt], expected: Option<&Type>) -> Type {
+        self.push_scope();
+        let ty = self.check_block(block, expected);
+        self.pop_scope();
+        ty
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt, expected: Option<&Type>) -> Type {
+        match &mut stmt.kind {
+            StmtKind::Blank => {}
+            StmtKind::Let(name, ty, value) => {
+                let declared = ty.as_ref().map(|ty| self.resolve_type(ty));
+                let what = format!("the value of `{}`", name.name);
+                let actual = self.check_value(value, declared.as_ref(), &what);
+
+                if let Some(declared) = &declared {
+                    self.expect(declared, &actual, value.position);
+                }
+
+                self.bind_name(name, declared.unwrap_or(actual));
+            }
+            StmtKind::Assign(target, op, value) => {
+                let target_ty = self.check_expr(target, None);
+                let value_ty = self.check_expr(value, Some(&target_ty));
+
+                let result = match op.infix_op() {
+                    Some(infix_op) => self.infix_type(infix_op, &target_ty, &value_ty, stmt.position),
+                    None => value_ty,
+                };
+
+                self.expect(&target_ty, &result, value.position);
+            }
+            StmtKind::Increment(target) | StmtKind::Decrement(target) => {
+                let ty = self.check_expr(target, None);
+
+                if !ty.is_integer() && ty != Type::Unknown {
+                    self.error(format!("cannot increment or decrement a value of type {}", ty), stmt.position);
+                }
+            }
+            StmtKind::Return(value) => {
+                let ret = match self.return_types.last() {
+                    Some(ret) => ret.clone(),
+                    None => {
+                        self.error("`return` outside of a function".to_owned(), stmt.position);
+                        Type::Unknown
+                    }
+                };
+
+                match value {
+                    Some(value) => {
+                        let actual = self.check_expr(value, Some(&ret));
+                        self.expect(&ret, &actual, value.position);
+                    }
+                    None => self.expect(&ret, &Type::Void, stmt.position),
+                }
+
+                return Type::Never;
+            }
+            kind @ (StmtKind::Break | StmtKind::Continue) => {
+                if self.loop_depth == 0 {
+                    let keyword = if *kind == StmtKind::Break { "break" } else { "continue" };
+                    self.error(format!("`{}` outside of a loop", keyword), stmt.position);
+                }
+
+                return Type::Never;
+            }
+            StmtKind::While(condition, body) => {
+                self.check_condition(condition);
+
+                self.loop_depth += 1;
+                self.check_scoped_block(body, None);
+                self.loop_depth -= 1;
+            }
+            StmtKind::For(init, condition, step, body) => {
+                self.push_scope();
+
+                self.check_stmt(init, None);
+                self.check_condition(condition);
+
+                self.loop_depth += 1;
+                self.check_scoped_block(body, None);
+                self.loop_depth -= 1;
+
+                self.check_stmt(step, None);
+
+                self.pop_scope();
+            }
+            StmtKind::Fn(decl) => self.check_fn_body(decl),
+            StmtKind::Struct(_) | StmtKind::Enum(_) => {}
+            StmtKind::Import(module) => {
+                if builtins::module(module.name.as_str()).is_some() {
+                    self.bind_name(module, Type::Module(module.name.clone()));
+                } else {
+                    self.error(format!("unknown module `{}`", module.name), module.position);
+                }
+            }
+            StmtKind::Expr(expr) => return self.check_expr(expr, expected),
+        }
+
+        Type::Void
+    }
+
+    fn check_fn_body(&mut self, decl: &mut FnDecl) {
+        let (params, ret) = match self.fn_type(decl) {
+            Type::Fn(params, ret) => (params, *ret),
+            _ => unreachable!(),
+        };
+
+        self.push_scope();
+
+        for (param, ty) in decl.params.iter().zip(params) {
+            self.bind_name(&param.name, ty);
+        }
+
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.return_types.push(ret.clone());
+
+        let body_ty = self.check_block(&mut decl.body, Some(&ret));
+
+        self.return_types.pop();
+        self.loop_depth = loop_depth;
+
+        self.pop_scope();
+
+        if ret != Type::Void && !ret.accepts(&body_ty) {
+            let position = decl.body.last().map_or(decl.name.position, |stmt| stmt.position);
+
+            self.error(
+                format!(
+                    "mismatched types: function `{}` returns {} but its body evaluates to {}",
+                    decl.name.name, ret, body_ty
+                ),
+                position,
+            );
+        }
+    }
+
+    fn check_condition(&mut self, condition: &mut Expr) {
+        let ty = self.check_expr(condition, Some(&Type::Bool));
+        self.expect(&Type::Bool, &ty, condition.position);
+    }
+
+    /// Checks an expression whose value is used, which can't be void.
+    fn check_value(&mut self, expr: &mut Expr, expected: Option<&Type>, what: &str) -> Type {
+        // An unknown hint marks the value as used without constraining it
+        let ty = self.check_expr(expr, Some(expected.unwrap_or(&Type::Unknown)));
+
+        if ty == Type::Void {
+            self.error(format!("cannot use a value of type void as {}", what), expr.position);
+            return Type::Unknown;
+        }
+
+        ty
+    }
+
+    /// Works out the type of an expression and records it on the expression.
+    fn check_expr(&mut self, expr: &mut Expr, expected: Option<&Type>) -> Type {
+        let ty = self.check_expr_kind(&mut expr.kind, expr.position, expected);
+        expr.ty = Some(ty.clone());
+        ty
+    }
+
+    fn check_expr_kind(
+        &mut self,
+        kind: &mut ExprKind,
+        position: BufferPosition,
+        expected: Option<&Type>,
+    ) -> Type {
+        match kind {
+            ExprKind::Identifier(ident) => {
+                let ty = match self.lookup(&ident.name) {
+                    Some(ty) => ty.clone(),
+                    None => {
+                        self.error(format!("cannot find value `{}` in this scope", ident.name), ident.position);
+                        Type::Unknown
+                    }
+                };
+
+                self.record(ident, &ty);
+                ty
+            }
+            ExprKind::IntegerLiteral(value) => self.integer_literal_type(*value, expected, position),
+            ExprKind::FloatLiteral(_) => Type::F64,
+            ExprKind::StringLiteral(_) => Type::Str,
+            ExprKind::Interpolated(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        if self.check_expr(expr, Some(&Type::Unknown)) == Type::Void {
+                            self.error("cannot put a value of type void in a string".to_owned(), expr.position);
+                        }
+                    }
+                }
+
+                Type::Str
+            }
+            ExprKind::CharLiteral(_) => Type::Char,
+            ExprKind::BooleanLiteral(_) => Type::Bool,
+            ExprKind::ArrayLiteral(elements) => {
+                let mut element_ty = match expected {
+                    Some(Type::Array(element)) => Some((**element).clone()),
+                    _ => None,
+                };
+
+                for element in elements {
+                    let ty = self.check_value(element, element_ty.as_ref(), "an array element");
+
+                    element_ty = match &element_ty {
+                        None => Some(ty),
+                        Some(expected) => match expected.join(&ty) {
+                            Some(joined) => Some(joined),
+                            None => {
+                                self.expect(expected, &ty, element.position);
+                                Some(expected.clone())
+                            }
+                        },
+                    };
+                }
+
+                Type::Array(Box::new(element_ty.unwrap_or(Type::Unknown)))
+            }
+            ExprKind::StructLiteral(name, fields) => self.check_struct_literal(name, fields),
+            ExprKind::Prefix(PrefixOp::Neg, right) => {
+                let ty = self.check_expr(right, expected);
+
+                if !ty.is_numeric() && ty != Type::Unknown {
+                    self.error(format!("cannot apply unary operator `-` to {}", ty), position);
+                    return Type::Unknown;
+                }
+
+                ty
+            }
+            ExprKind::Prefix(PrefixOp::Not, right) => {
+                let ty = self.check_expr(right, Some(&Type::Bool));
+
+                if ty != Type::Bool && ty != Type::Unknown {
+                    self.error(format!("cannot apply unary operator `!` to {}", ty), position);
+                }
+
+                Type::Bool
+            }
+            ExprKind::Infix(op, left, right) => {
+                let hint = match op {
+                    InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod => {
+                        expected.or(Some(&Type::Unknown))
+                    }
+                    InfixOp::And | InfixOp::Or => Some(&Type::Bool),
+                    _ => Some(&Type::Unknown),
+                };
+
+                // An untyped integer literal takes its type from the other
+                // operand, so `1 + x` works for any integer type of `x`.
+                let (left_ty, right_ty) = if is_untyped_integer(left) && !is_untyped_integer(right) {
+                    let right_ty = self.check_expr(right, hint);
+                    (self.check_expr(left, Some(&right_ty)), right_ty)
+                } else {
+                    let left_ty = self.check_expr(left, hint);
+                    (left_ty.clone(), self.check_expr(right, Some(&left_ty)))
+                };
+
+                self.infix_type(*op, &left_ty, &right_ty, position)
+            }
+            ExprKind::Call(function, args) => self.check_call(function, args, position),
+            ExprKind::Index(object, index) => {
+                let object_ty = self.check_expr(object, None);
+                let index_ty = self.check_expr(index, None);
+
+                if !index_ty.is_integer() && index_ty != Type::Unknown {
+                    self.error(format!("index must be an integer, found {}", index_ty), index.position);
+                }
+
+                match object_ty {
+                    Type::Array(element) => *element,
+                    Type::Str => Type::Char,
+                    Type::Unknown => Type::Unknown,
+                    other => {
+                        self.error(format!("cannot index into a value of type {}", other), position);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::Field(object, field) => {
+                let object_ty = self.check_expr(object, None);
+                let ty = self.field_type(&object_ty, field);
+
+                self.record(field, &ty);
+                ty
+            }
+            ExprKind::Block(block) => self.check_scoped_block(block, expected),
+            ExprKind::If(condition, consequence, alternative) => {
+                self.check_condition(condition);
+
+                let consequence_ty = self.check_scoped_block(consequence, expected);
+
+                match alternative {
+                    Some(alternative) => {
+                        let alternative_ty = self.check_expr(alternative, expected);
+
+                        match consequence_ty.join(&alternative_ty) {
+                            Some(ty) => ty,
+                            None if is_used(expected) => {
+                                let message = format!(
+                                    "mismatched types: `if` and `else` have incompatible types {} and {}",
+                                    consequence_ty, alternative_ty
+                                );
+                                self.error(message, alternative.position);
+                                Type::Unknown
+                            }
+                            // Branches of different types are fine as long as
+                            // the value of the `if` is not used.
+                            None => Type::Void,
+                        }
+                    }
+                    None => Type::Void,
+                }
+            }
+            ExprKind::Match(subject, arms) => {
+                let subject_ty = self.check_expr(subject, None);
+
+                let mut ty: Option<Type> = None;
+
+                for arm in arms {
+                    self.push_scope();
+
+                    self.check_pattern(&mut arm.pattern, &subject_ty);
+                    let arm_ty = self.check_expr(&mut arm.body, expected);
+
+                    self.pop_scope();
+
+                    ty = match ty {
+                        None => Some(arm_ty),
+                        Some(ty) => match ty.join(&arm_ty) {
+                            Some(joined) => Some(joined),
+                            None if is_used(expected) => {
+                                let message = format!(
+                                    "mismatched types: `match` arms have incompatible types {} and {}",
+                                    ty, arm_ty
+                                );
+                                self.error(message, arm.body.position);
+                                Some(Type::Unknown)
+                            }
+                            None => Some(Type::Void),
+                        },
+                    };
+                }
+
+                ty.unwrap_or(Type::Void)
+            }
+        }
+    }
+
+    fn integer_literal_type(&mut self, value: i64, expected: Option<&Type>, position: BufferPosition) -> Type {
+        let fits_i32 = value >= i64::from(i32::MIN) && value <= i64::from(i32::MAX);
+
+        match expected {
+            Some(Type::I64) => Type::I64,
+            Some(Type::I32) if !fits_i32 => {
+                self.error("literal out of range for `i32`".to_owned(), position);
+                Type::I32
+            }
+            _ if fits_i32 => Type::I32,
+            _ => Type::I64,
+        }
+    }
+
+    fn check_struct_literal(&mut self, name: &Identifier, fields: &mut [(Identifier, Expr)]) -> Type {
+        let declared = match self.lookup(&name.name) {
+            Some(Type::StructType(struct_name)) => self.defs.structs.get(struct_name).cloned().unwrap_or_default(),
+            Some(other) => {
+                let message = format!("`{}` is a {}, not a struct", name.name, other);
+                self.error(message, name.position);
+                return Type::Unknown;
+            }
+            None => {
+                self.error(format!("cannot find struct `{}` in this scope", name.name), name.position);
+                return Type::Unknown;
+            }
+        };
+
+        self.record(name, &Type::Struct(name.name.clone()));
+
+        for (field, value) in fields.iter_mut() {
+            match declared.iter().find(|(declared, _)| *declared == field.name) {
+                Some((_, field_ty)) => {
+                    self.record(field, field_ty);
+
+                    let ty = self.check_expr(value, Some(field_ty));
+                    self.expect(field_ty, &ty, value.position);
+                }
+                None => {
+                    self.error(
+                        format!("struct `{}` has no field named `{}`", name.name, field.name),
+                        field.position,
+                    );
+                    self.check_expr(value, None);
+                }
+            }
+        }
+
+        for (field, _) in &declared {
+            if !fields.iter().any(|(given, _)| &given.name == field) {
+                self.error(
+                    format!("missing field `{}` in initializer of `{}`", field, name.name),
+                    name.position,
+                );
+            }
+        }
+
+        Type::Struct(name.name.clone())
+    }
+
+    fn infix_type(&mut self, op: InfixOp, left: &Type, right: &Type, position: BufferPosition) -> Type {
+        let is_comparison = matches!(
+            op,
+            InfixOp::Eq | InfixOp::NotEq | InfixOp::Lt | InfixOp::Gt | InfixOp::LtEq | InfixOp::GtEq
+        );
+
+        if *left == Type::Unknown || *right == Type::Unknown {
+            return if is_comparison { Type::Bool } else { Type::Unknown };
+        }
+
+        let ty = match op {
+            InfixOp::Add if *left == Type::Str || *right == Type::Str => Some(Type::Str),
+            InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod => {
+                if left.is_numeric() && left == right {
+                    Some(left.clone())
+                } else {
+                    None
+                }
+            }
+            InfixOp::Lt | InfixOp::Gt | InfixOp::LtEq | InfixOp::GtEq => {
+                let ordered = left.is_numeric() || *left == Type::Char || *left == Type::Str;
+
+                if ordered && left == right {
+                    Some(Type::Bool)
+                } else {
+                    None
+                }
+            }
+            InfixOp::Eq | InfixOp::NotEq => left.join(right).map(|_| Type::Bool),
+            InfixOp::And | InfixOp::Or => {
+                if *left == Type::Bool && *right == Type::Bool {
+                    Some(Type::Bool)
+                } else {
+                    None
+                }
+            }
+        };
+
+        match ty {
+            Some(ty) => ty,
+            None => {
+                self.error(
+                    format!("cannot apply binary operator `{}` to {} and {}", op.as_str(), left, right),
+                    position,
+                );
+
+                if is_comparison { Type::Bool } else { Type::Unknown }
+            }
+        }
+    }
+
+    fn check_call(&mut self, function: &mut Expr, args: &mut [Expr], position: BufferPosition) -> Type {
+        match self.check_expr(function, None) {
+            Type::Fn(params, ret) => {
+                if params.len() != args.len() {
+                    self.error(
+                        format!(
+                            "this function takes {} argument(s) but {} were supplied",
+                            params.len(),
+                            args.len()
+                        ),
+                        position,
+                    );
+                }
+
+                for (arg, param) in args.iter_mut().zip(params.iter()) {
+                    let ty = self.check_value(arg, Some(param), "an argument");
+                    self.expect(param, &ty, arg.position);
+                }
+
+                for arg in args.iter_mut().skip(params.len()) {
+                    self.check_value(arg, None, "an argument");
+                }
+
+                *ret
+            }
+            Type::Builtin(name) => self.check_builtin_call(name.as_str(), args, position),
+            Type::Unknown => {
+                for arg in args {
+                    self.check_value(arg, None, "an argument");
+                }
+
+                Type::Unknown
+            }
+            other => {
+                self.error(format!("a value of type {} is not callable", other), position);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_builtin_call(&mut self, name: &str, args: &mut [Expr], position: BufferPosition) -> Type {
+        let mut arg_types: Vec<Type> = Vec::with_capacity(args.len());
+
+        for arg in args.iter_mut() {
+            let hint = arg_types.first().cloned();
+            arg_types.push(self.check_value(arg, hint.as_ref(), "an argument"));
+        }
+
+        let arity = match name {
+            "print" | "println" => return Type::Void,
+            "min" | "max" => 2,
+            _ => 1,
+        };
+
+        if args.len() != arity {
+            self.error(
+                format!(
+                    "`std.{}` takes {} argument(s) but {} were supplied",
+                    name,
+                    arity,
+                    args.len()
+                ),
+                position,
+            );
+
+            return Type::Unknown;
+        }
+
+        let first = arg_types[0].clone();
+
+        if arg_types.contains(&Type::Unknown) {
+            return match name {
+                "len" => Type::I32,
+                "to_string" => Type::Str,
+                _ => Type::Unknown,
+            };
+        }
+
+        let (ok, ty) = match name {
+            "len" => (matches!(first, Type::Str | Type::Array(_)), Type::I32),
+            "to_string" => (true, Type::Str),
+            "abs" => (first.is_numeric(), first.clone()),
+            "min" | "max" => (first.is_numeric() && arg_types[1] == first, first.clone()),
+            "sqrt" => (first == Type::F64, Type::F64),
+            _ => (false, Type::Unknown),
+        };
+
+        if !ok {
+            let types: Vec<String> = arg_types.iter().map(Type::to_string).collect();
+
+            self.error(
+                format!("`std.{}` cannot be called with ({})", name, types.join(", ")),
+                position,
+            );
+        }
+
+        ty
+    }
+
+    fn field_type(&mut self, object: &Type, field: &Identifier) -> Type {
+        match object {
+            Type::Struct(name) => {
+                let field_ty = self
+                    .defs
+                    .structs
+                    .get(name)
+                    .and_then(|fields| fields.iter().find(|(declared, _)| *declared == field.name))
+                    .map(|(_, ty)| ty.clone());
+
+                match field_ty {
+                    Some(ty) => ty,
+                    None => {
+                        self.error(format!("no field `{}` on type {}", field.name, name), field.position);
+                        Type::Unknown
+                    }
+                }
+            }
+            Type::Module(module) => {
+                if builtins::STD_BUILTINS.iter().any(|builtin| field.name == builtin.name) {
+                    Type::Builtin(field.name.clone())
+                } else {
+                    self.error(
+                        format!("cannot find `{}` in module `{}`", field.name, module),
+                        field.position,
+                    );
+                    Type::Unknown
+                }
+            }
+            Type::EnumType(name) => {
+                let variant = self
+                    .defs
+                    .enums
+                    .get(name)
+                    .and_then(|variants| variants.iter().find(|(variant, _)| *variant == field.name))
+                    .map(|(_, fields)| fields.clone());
+
+                match variant {
+                    Some(fields) => variant_type(name.clone(), &fields),
+                    None => {
+                        self.error(
+                            format!("no variant named `{}` in enum `{}`", field.name, name),
+                            field.position,
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            Type::Unknown => Type::Unknown,
+            other => {
+                self.error(format!("no field `{}` on type {}", field.name, other), field.position);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_pattern(&mut self, pattern: &mut Pattern, ty: &Type) {
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Binding(name) => self.bind_name(name, ty.clone()),
+            Pattern::Literal(literal) => {
+                let literal_ty = self.check_expr(literal, Some(ty));
+                self.expect(ty, &literal_ty, literal.position);
+            }
+            Pattern::Variant(enum_name, variant, fields, _) => {
+                let name = match self.lookup(&enum_name.name) {
+                    Some(Type::EnumType(name)) => name.clone(),
+                    _ => {
+                        self.error(
+                            format!("cannot find enum `{}` in this scope", enum_name.name),
+                            enum_name.position,
+                        );
+                        return;
+                    }
+                };
+
+                self.record(enum_name, &Type::EnumType(name.clone()));
+                self.expect(ty, &Type::Enum(name.clone()), enum_name.position);
+
+                let declared = self
+                    .defs
+                    .enums
+                    .get(&name)
+                    .and_then(|variants| variants.iter().find(|(declared, _)| *declared == variant.name))
+                    .map(|(_, fields)| fields.clone());
+
+                let declared = match declared {
+                    Some(declared) => declared,
+                    None => {
+                        self.error(
+                            format!("no variant named `{}` in enum `{}`", variant.name, name),
+                            variant.position,
+                        );
+                        return;
+                    }
+                };
+
+                self.record(variant, &variant_type(name.clone(), &declared));
+
+                if declared.len() != fields.len() {
+                    self.error(
+                        format!(
+                            "variant `{}.{}` has {} field(s) but the pattern has {}",
+                            name,
+                            variant.name,
+                            declared.len(),
+                            fields.len()
+                        ),
+                        variant.position,
+                    );
+                }
+
+                for (field, field_ty) in fields.iter_mut().zip(declared.iter()) {
+                    self.check_pattern(field, field_ty);
+                }
+            }
+        }
+    }
+}
+
+/// The type of `Enum.Variant` as a value: the enum itself, or a function
+/// building it when the variant has fields.
+fn variant_type(enum_name: Symbol, fields: &[Type]) -> Type {
+    if fields.is_empty() {
+        Type::Enum(enum_name)
+    } else {
+        Type::Fn(fields.to_vec(), Box::new(Type::Enum(enum_name)))
+    }
+}
+
+/// Whether the value of an expression checked against `expected` is used.
+/// The tail of a block that returns nothing is not.
+fn is_used(expected: Option<&Type>) -> bool {
+    expected.is_some_and(|ty| *ty != Type::Void)
+}
+
+/// Whether an expression is built only from integer literals, in which case
+/// its type is decided by the context it is used in.
+fn is_untyped_integer(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::IntegerLiteral(_) => true,
+        ExprKind::Prefix(PrefixOp::Neg, right) => is_untyped_integer(right),
+        ExprKind::Infix(InfixOp::Add, left, right)
+        | ExprKind::Infix(InfixOp::Sub, left, right)
+        | ExprKind::Infix(InfixOp::Mul, left, right)
+        | ExprKind::Infix(InfixOp::Div, left, right)
+        | ExprKind::Infix(InfixOp::Mod, left, right) => is_untyped_integer(left) && is_untyped_integer(right),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn check_with(checker: &mut Checker, input: &str) -> Result<Type, Vec<TypeError>> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let mut program = parser.parse();
+
+        assert!(
+            parser.errors().is_empty(),
+            "Parser reported errors: {:?}",
+            parser.errors()
+        );
+
+        checker.check_program(&mut program)
+    }
+
+    fn type_of(input: &str) -> String {
+        match check_with(&mut Checker::new(), input) {
+            Ok(ty) => ty.to_string(),
+            Err(errors) => panic!("checking {:?} failed: {:?}", input, errors),
+        }
+    }
+
+    fn type_errors(input: &str) -> Vec<String> {
+        match check_with(&mut Checker::new(), input) {
+            Ok(ty) => panic!("checking {:?} succeeded with {}", input, ty),
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    #[test]
+    fn test_expression_types() {
+        let tests = vec![
+            ("5", "i32"),
+            ("5000000000", "i64"),
+            ("let x: i64 = 5\n1 + x * 2", "i64"),
+            ("1.5 / 2.0", "f64"),
+            ("\"fib is \" + 5", "str"),
+            ("\"fib is {1 + 2}\"", "str"),
+            ("'a' < 'b' && !false", "bool"),
+            ("[1, 2, 3]", "[i32]"),
+            ("[1, 2][0]", "i32"),
+            ("\"abc\"[1]", "char"),
+            ("import std\nstd.len(\"abc\")", "i32"),
+            ("import std\nstd.println", "builtin fn std.println"),
+            ("fn add(a: i32, b: i32): i32 { a + b }\nadd", "fn(i32, i32): i32"),
+            ("if true { 1 } else { 2 }", "i32"),
+            ("if true { 1 } else { \"two\" }", "void"),
+            ("struct Point { x: f64, y: f64 }\nPoint { x: 1.0, y: 2.0 }.y", "f64"),
+            ("enum Shape { Circle(f64), Empty }\nShape.Circle", "fn(f64): Shape"),
+            ("enum Shape { Circle(f64), Empty }\nShape.Empty", "Shape"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(type_of(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_samples_type_check() {
+        let samples = [
+            include_str!("../samples/arithmetic.k"),
+            include_str!("../samples/fib.k"),
+            include_str!("../samples/hello.k"),
+        ];
+
+        for sample in samples.iter() {
+            assert_eq!(type_of(sample), "void");
+        }
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let tests = vec![
+            ("let x: i32 = \"five\"", "mismatched types: expected i32, found str"),
+            ("let x: i32 = 5000000000", "literal out of range for `i32`"),
+            ("1 + true", "cannot apply binary operator `+` to i32 and bool"),
+            ("let x: i64 = 1\nlet y: i32 = 2\nx + y", "cannot apply binary operator `+` to i64 and i32"),
+            ("if 1 { 2 }", "mismatched types: expected bool, found i32"),
+            ("missing", "cannot find value `missing` in this scope"),
+            ("fn f(): i32 { }", "mismatched types: function `f` returns i32 but its body evaluates to void"),
+            ("fn f(n: i32): i32 { n }\nf(true)", "mismatched types: expected i32, found bool"),
+            ("fn f(n: i32): i32 { n }\nf()", "this function takes 1 argument(s) but 0 were supplied"),
+            ("break", "`break` outside of a loop"),
+            ("return 5", "`return` outside of a function"),
+            ("struct P { x: i32 }\nP { y: 1 }", "struct `P` has no field named `y`"),
+            ("import std\nstd.sqrt(4)", "`std.sqrt` cannot be called with (i32)"),
+            ("let x: Point = 5", "cannot find type `Point` in this scope"),
+            ("fn f() { }\n\"{f()}\"", "cannot put a value of type void in a string"),
+            ("\"{1 + true}\"", "cannot apply binary operator `+` to i32 and bool"),
+        ];
+
+        for (input, expected) in tests {
+            let errors = type_errors(input);
+            assert_eq!(errors[0], expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_name_types() {
+        let mut checker = Checker::new();
+        let input = "struct P { x: f64 }\nfn norm(p: P): f64 { p.x }\nlet n = norm(P { x: 2.0 })\nmissing";
+
+        check_with(&mut checker, input).unwrap_err();
+
+        let names: Vec<String> = checker
+            .name_types()
+            .iter()
+            .map(|(name, ty)| format!("{}@{}: {}", name.name, name.position, ty))
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "P@1:8: struct P",
+                "x@1:12: f64",
+                "norm@2:4: fn(P): f64",
+                "p@2:9: P",
+                "p@2:22: P",
+                "x@2:24: f64",
+                "norm@3:9: fn(P): f64",
+                "P@3:14: P",
+                "x@3:18: f64",
+                "n@3:5: f64",
+                "missing@4:1: {unknown}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recorded_types() {
+        let mut parser = Parser::new(Lexer::new("let x: i64 = 1 + 2\nlet ys: [i64] = [x, -3]"));
+        let mut program = parser.parse();
+        let mut checker = Checker::new();
+
+        Resolver::new().resolve_program(&mut program).unwrap();
+        checker.check_program(&mut program).unwrap();
+
+        let (name, value) = match &program.0[0].kind {
+            StmtKind::Let(name, _, value) => (name, value),
+            _ => unreachable!(),
+        };
+
+        // The literals take their type from the declared one
+        match &value.kind {
+            ExprKind::Infix(_, left, right) => {
+                assert_eq!(left.ty, Some(Type::I64));
+                assert_eq!(right.ty, Some(Type::I64));
+            }
+            _ => unreachable!(),
+        }
+
+        assert_eq!(value.ty, Some(Type::I64));
+        assert_eq!(checker.binding_types()[&name.binding.unwrap()], Type::I64);
+
+        match &program.0[1].kind {
+            StmtKind::Let(_, _, value) => assert_eq!(value.ty, Some(Type::Array(Box::new(Type::I64)))),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_failed_programs_are_rolled_back() {
+        let mut checker = Checker::new();
+
+        check_with(&mut checker, "let x = 5").unwrap();
+        check_with(&mut checker, "let y = 5\nlet z: bool = y").unwrap_err();
+
+        assert_eq!(check_with(&mut checker, "x").unwrap(), Type::I32);
+        assert!(check_with(&mut checker, "y").is_err());
+    }
+}
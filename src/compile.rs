@@ -0,0 +1,149 @@
+//! The stages a program goes through on its way from source to a verified IR
+//! module, for the tools that run more than one of them.
+
+use crate::ast::{BindingId, Program};
+use crate::checker::{Checker, TypeError};
+use crate::ir::Module;
+use crate::ir_lower::{self, LowerError};
+use crate::ir_passes;
+use crate::ir_verify::{self, VerifyError};
+use crate::lexer::Lexer;
+use crate::optimizer::{self, OptimizeError};
+use crate::parser::{ParseError, Parser};
+use crate::resolver::{ResolveError, Resolver};
+use crate::types::Type;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// The errors of the stage a program failed in.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CompileError {
+    Syntax(Vec<ParseError>),
+    Name(Vec<ResolveError>),
+    Type(Vec<TypeError>),
+    Constant(Vec<OptimizeError>),
+    Lower(Vec<LowerError>),
+    Verify(Vec<VerifyError>),
+}
+
+impl CompileError {
+    /// The name of the stage that failed, like `syntax` or `type`.
+    pub fn stage(&self) -> &'static str {
+        match self {
+            CompileError::Syntax(_) => "syntax",
+            CompileError::Name(_) => "name",
+            CompileError::Type(_) => "type",
+            CompileError::Constant(_) => "constant",
+            CompileError::Lower(_) => "lowering",
+            CompileError::Verify(_) => "verification",
+        }
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        fn strings<T: Display>(errors: &[T]) -> Vec<String> {
+            errors.iter().map(ToString::to_string).collect()
+        }
+
+        match self {
+            CompileError::Syntax(errors) => strings(errors),
+            CompileError::Name(errors) => strings(errors),
+            CompileError::Type(errors) => strings(errors),
+            CompileError::Constant(errors) => strings(errors),
+            CompileError::Lower(errors) => strings(errors),
+            CompileError::Verify(errors) => strings(errors),
+        }
+    }
+}
+
+/// Which of the optional stages to run.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // Whether constant expressions are folded before lowering
+    pub fold: bool,
+    // Whether the IR passes run after lowering
+    pub optimize: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { fold: true, optimize: true }
+    }
+}
+
+/// A program that has been resolved and type checked.
+#[derive(Debug, Clone)]
+pub struct Checked {
+    pub program: Program,
+    // The type of the program's last expression
+    pub ty: Type,
+    pub types: HashMap<BindingId, Type>,
+}
+
+pub fn parse(source: &str) -> Result<Program, CompileError> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+
+    if parser.errors().is_empty() {
+        Ok(program)
+    } else {
+        Err(CompileError::Syntax(parser.errors().to_vec()))
+    }
+}
+
+/// Resolves and checks a program with passes that may already know about
+/// earlier programs, like the REPL's.
+pub fn check(resolver: &mut Resolver, checker: &mut Checker, program: &mut Program) -> Result<Type, CompileError> {
+    resolver.resolve_program(program).map_err(CompileError::Name)?;
+    checker.check_program(program).map_err(CompileError::Type)
+}
+
+/// Folds a checked program's constant expressions.
+pub fn fold(program: &mut Program) -> Result<(), CompileError> {
+    optimizer::optimize(program).map_err(CompileError::Constant)
+}
+
+/// Parses, resolves and checks a program, folding its constants if `fold`
+/// is set.
+pub fn front_end(source: &str, fold: bool) -> Result<Checked, CompileError> {
+    let mut program = parse(source)?;
+    let mut checker = Checker::new();
+    let ty = check(&mut Resolver::new(), &mut checker, &mut program)?;
+
+    if fold {
+        self::fold(&mut program)?;
+    }
+
+    Ok(Checked { program, ty, types: checker.binding_types().clone() })
+}
+
+/// Lowers a checked program to a verified module, running the IR passes
+/// over it if `optimize` is set.
+pub fn lower(checked: &Checked, optimize: bool) -> Result<Module, CompileError> {
+    let mut module = ir_lower::lower(&checked.program, &checked.types).map_err(CompileError::Lower)?;
+
+    if optimize {
+        ir_passes::optimize(&mut module);
+    }
+
+    ir_verify::verify(&module).map_err(CompileError::Verify)?;
+    Ok(module)
+}
+
+/// Takes a program through every stage, stopping at the first that fails.
+pub fn compile(source: &str, options: Options) -> Result<Module, CompileError> {
+    lower(&front_end(source, options.fold)?, options.optimize)
+}
+
+/// Resolves and checks a program that a test expects to have no errors.
+#[cfg(test)]
+pub(crate) fn checked(source: &str) -> Checked {
+    front_end(source, false).unwrap_or_else(|error| panic!("{:?} should check, but: {:?}", source, error.messages()))
+}
+
+/// Lowers a program that a test expects to have no errors, without folding
+/// its constants, running the IR passes or verifying the module.
+#[cfg(test)]
+pub(crate) fn lowered(source: &str) -> Module {
+    let checked = checked(source);
+    ir_lower::lower(&checked.program, &checked.types).expect("the program should lower")
+}
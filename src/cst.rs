@@ -0,0 +1,587 @@
+//! A lossless concrete syntax tree. Green nodes are immutable and only know
+//! their kind, length and children, so they can be shared between trees.
+//! `SyntaxNode`s are built on top of them as the tree is walked and add
+//! parents and absolute offsets. Tokens keep their trivia, so the text of a
+//! tree is always exactly the source it was parsed from.
+
+use crate::token::{LosslessToken, TokenKind, Trivia};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum NodeKind {
+    SourceFile,
+    // Tokens that couldn't be parsed
+    Error,
+
+    ImportStmt,
+    LetStmt,
+    AssignStmt,
+    IncrementStmt,
+    DecrementStmt,
+    ReturnStmt,
+    BreakStmt,
+    ContinueStmt,
+    WhileStmt,
+    ForStmt,
+    ExprStmt,
+
+    FnDecl,
+    ParamList,
+    Param,
+    StructDecl,
+    FieldList,
+    Field,
+    EnumDecl,
+    VariantList,
+    Variant,
+    TypeList,
+
+    NamedType,
+    ArrayType,
+
+    // The name being declared, and a name being used
+    Name,
+    NameRef,
+
+    Literal,
+    // A string with embedded expressions
+    InterpolatedString,
+    ArrayExpr,
+    StructExpr,
+    FieldInitList,
+    FieldInit,
+    PrefixExpr,
+    InfixExpr,
+    ParenExpr,
+    CallExpr,
+    ArgList,
+    IndexExpr,
+    FieldExpr,
+    Block,
+    IfExpr,
+    MatchExpr,
+    MatchArmList,
+    MatchArm,
+
+    WildcardPat,
+    LiteralPat,
+    BindingPat,
+    VariantPat,
+    PatList,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GreenToken {
+    kind: TokenKind,
+    text: String,
+    leading: Vec<Trivia>,
+    trailing: Vec<Trivia>,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenKind, text: String, leading: Vec<Trivia>, trailing: Vec<Trivia>) -> GreenToken {
+        GreenToken {
+            kind,
+            text,
+            leading,
+            trailing,
+        }
+    }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    /// The text of the token itself, without its trivia.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn leading(&self) -> &[Trivia] {
+        &self.leading
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        &self.trailing
+    }
+
+    fn leading_len(&self) -> usize {
+        self.leading.iter().map(|trivia| trivia.text.len()).sum()
+    }
+
+    /// The length in bytes of the token along with its trivia.
+    pub fn full_len(&self) -> usize {
+        self.leading_len() + self.text.len() + self.trailing.iter().map(|trivia| trivia.text.len()).sum::<usize>()
+    }
+
+    fn write_full_text(&self, output: &mut String) {
+        for trivia in &self.leading {
+            output.push_str(&trivia.text);
+        }
+
+        output.push_str(&self.text);
+
+        for trivia in &self.trailing {
+            output.push_str(&trivia.text);
+        }
+    }
+}
+
+impl From<LosslessToken> for GreenToken {
+    fn from(token: LosslessToken) -> GreenToken {
+        GreenToken::new(token.token.kind, token.text, token.leading, token.trailing)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn full_len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.len,
+            GreenElement::Token(token) => token.full_len(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GreenNode {
+    kind: NodeKind,
+    len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> GreenNode {
+        let len = children.iter().map(GreenElement::full_len).sum();
+        GreenNode { kind, len, children }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    /// The length in bytes of the text the node covers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    /// A copy of the node with one of its children swapped out. The other
+    /// children are shared with the original.
+    pub fn replace_child(&self, index: usize, child: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children[index] = child;
+        GreenNode::new(self.kind, children)
+    }
+
+    fn write_text(&self, output: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(output),
+                GreenElement::Token(token) => token.write_full_text(output),
+            }
+        }
+    }
+}
+
+/// Marks a place in the builder that a node can later be started at, so
+/// that a node can wrap children that were added before its kind was known,
+/// like the left operand of an infix expression.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize);
+
+/// Builds a green tree from the top down.
+#[derive(Default)]
+pub struct GreenNodeBuilder {
+    // The kind of each unfinished node, and where its children start
+    parents: Vec<(NodeKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNodeBuilder {
+    pub fn new() -> GreenNodeBuilder {
+        GreenNodeBuilder::default()
+    }
+
+    pub fn start_node(&mut self, kind: NodeKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    pub fn token(&mut self, token: GreenToken) {
+        self.children.push(GreenElement::Token(Rc::new(token)));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, first) = self.parents.pop().expect("no node to finish");
+        let children = self.children.split_off(first);
+
+        self.children.push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// Starts a node whose first child is whatever was added after the
+    /// checkpoint.
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: NodeKind) {
+        let Checkpoint(first) = checkpoint;
+
+        assert!(first <= self.children.len(), "checkpoint is past the current children");
+
+        if let Some(&(_, parent_first)) = self.parents.last() {
+            assert!(first >= parent_first, "checkpoint is outside the current node");
+        }
+
+        self.parents.push((kind, first));
+    }
+
+    /// Returns the root node. Every started node must have been finished.
+    pub fn finish(mut self) -> Rc<GreenNode> {
+        assert!(self.parents.is_empty(), "unfinished nodes");
+        assert_eq!(self.children.len(), 1, "a tree has exactly one root");
+
+        match self.children.pop() {
+            Some(GreenElement::Node(node)) => node,
+            _ => panic!("the root of a tree must be a node"),
+        }
+    }
+}
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    index: usize,
+    offset: usize,
+}
+
+/// A node of the tree along with its position in it.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            parent: None,
+            index: 0,
+            offset: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    /// The node itself followed by its parent, its parent's parent and so
+    /// on up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(Some(self.clone()), SyntaxNode::parent)
+    }
+
+    /// The position of the node among its parent's children.
+    pub fn index(&self) -> usize {
+        self.0.index
+    }
+
+    /// The byte range of the source the node covers, including the trivia
+    /// of its first and last tokens.
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len
+    }
+
+    /// The full source text of the node.
+    pub fn text(&self) -> String {
+        let mut output = String::new();
+        self.0.green.write_text(&mut output);
+        output
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+
+        self.0
+            .green
+            .children
+            .iter()
+            .enumerate()
+            .map(|(index, child)| {
+                let element = match child {
+                    GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                        green: green.clone(),
+                        parent: Some(self.clone()),
+                        index,
+                        offset,
+                    }))),
+                    GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                        green: green.clone(),
+                        parent: self.clone(),
+                        index,
+                        offset,
+                    }),
+                };
+
+                offset += child.full_len();
+                element
+            })
+            .collect()
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(SyntaxElement::into_node)
+            .collect()
+    }
+
+    /// The tokens that are direct children of the node.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(SyntaxElement::into_token)
+            .collect()
+    }
+
+    pub fn first_child(&self, kind: NodeKind) -> Option<SyntaxNode> {
+        self.children().into_iter().find(|child| child.kind() == kind)
+    }
+
+    /// The node and every node below it, in source order.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+
+        nodes
+    }
+
+    /// Every token below the node, in source order.
+    pub fn descendant_tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.descendant_tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+
+        tokens
+    }
+
+    /// The token whose text, not counting trivia, contains or ends at the
+    /// byte `offset`. When two tokens touch, the one starting there wins.
+    pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken> {
+        let tokens = self.descendant_tokens();
+
+        tokens
+            .iter()
+            .find(|token| token.text_range().contains(&offset))
+            .or_else(|| tokens.iter().find(|token| token.text_range().end == offset))
+            .cloned()
+    }
+
+    /// The first token of the node that isn't a newline.
+    pub fn first_token(&self) -> Option<SyntaxToken> {
+        self.descendant_tokens()
+            .into_iter()
+            .find(|token| *token.kind() != TokenKind::Newline)
+    }
+
+    fn fmt_tree(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let range = self.text_range();
+        writeln!(f, "{:indent$}{:?}@{}..{}", "", self.kind(), range.start, range.end, indent = depth * 2)?;
+
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.fmt_tree(f, depth + 1)?,
+                SyntaxElement::Token(token) => writeln!(f, "{:indent$}{:?}", "", token, indent = depth * 2 + 2)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &SyntaxNode) -> bool {
+        Rc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+/// Prints the tree below the node, one node or token per line.
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_tree(f, 0)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &TokenKind {
+        &self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn leading(&self) -> &[Trivia] {
+        &self.green.leading
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        &self.green.trailing
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The byte range of the token's own text, without its trivia.
+    pub fn text_range(&self) -> Range<usize> {
+        let start = self.offset + self.green.leading_len();
+        start..start + self.green.text.len()
+    }
+
+    /// The byte range of the token along with its trivia.
+    pub fn full_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.full_len()
+    }
+}
+
+impl PartialEq for SyntaxToken {
+    fn eq(&self, other: &SyntaxToken) -> bool {
+        Rc::ptr_eq(&self.green, &other.green) && self.offset == other.offset
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = self.text_range();
+        write!(f, "{:?}@{}..{} {:?}", self.kind(), range.start, range.end, self.text())
+    }
+}
+
+impl SyntaxElement {
+    pub fn into_node(self) -> Option<SyntaxNode> {
+        match self {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }
+    }
+
+    pub fn into_token(self) -> Option<SyntaxToken> {
+        match self {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::TriviaKind;
+
+    fn token(kind: TokenKind, text: &str, trailing: &str) -> GreenToken {
+        let trailing = if trailing.is_empty() {
+            Vec::new()
+        } else {
+            vec![Trivia {
+                kind: TriviaKind::Whitespace,
+                text: trailing.to_owned(),
+            }]
+        };
+
+        GreenToken::new(kind, text.to_owned(), Vec::new(), trailing)
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut builder = GreenNodeBuilder::new();
+
+        builder.start_node(NodeKind::SourceFile);
+        builder.start_node(NodeKind::ExprStmt);
+
+        let checkpoint = builder.checkpoint();
+        builder.start_node(NodeKind::Literal);
+        builder.token(token(TokenKind::IntegerLiteral(1), "1", " "));
+        builder.finish_node();
+
+        builder.start_node_at(checkpoint, NodeKind::InfixExpr);
+        builder.token(token(TokenKind::Symbol(crate::token::SymbolKind::Plus), "+", " "));
+        builder.start_node(NodeKind::Literal);
+        builder.token(token(TokenKind::IntegerLiteral(2), "2", ""));
+        builder.finish_node();
+        builder.finish_node();
+
+        builder.finish_node();
+        builder.finish_node();
+
+        let root = SyntaxNode::new_root(builder.finish());
+
+        assert_eq!(root.text(), "1 + 2");
+        assert_eq!(root.text_range(), 0..5);
+
+        let infix = root.descendants()[2].clone();
+        assert_eq!(infix.kind(), NodeKind::InfixExpr);
+        assert_eq!(infix.children().len(), 2);
+        assert_eq!(infix.parent().map(|parent| parent.kind()), Some(NodeKind::ExprStmt));
+
+        let plus = root.token_at_offset(2).unwrap();
+        assert_eq!(plus.text(), "+");
+        assert_eq!(plus.text_range(), 2..3);
+        assert_eq!(plus.full_range(), 2..4);
+        assert_eq!(plus.parent(), infix);
+
+        assert_eq!(root.token_at_offset(5).map(|token| token.text().to_owned()), Some("2".to_owned()));
+    }
+}
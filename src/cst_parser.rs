@@ -0,0 +1,998 @@
+//! Parses source into a lossless `cst` tree. It follows the same grammar as
+//! `Parser`, but keeps every token and never gives up on a statement:
+//! tokens it can't make sense of are wrapped in `Error` nodes, so the tree
+//! always covers the whole input.
+
+use crate::cst::{GreenNode, GreenNodeBuilder, NodeKind, SyntaxNode};
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser, Precedence, MAX_NESTING};
+use crate::token::{KeywordKind, LosslessToken, SymbolKind, TokenKind};
+use std::mem;
+use std::rc::Rc;
+
+/// The result of parsing: a tree, along with the errors found on the way.
+pub struct Parse {
+    green: Rc<GreenNode>,
+    errors: Vec<ParseError>,
+}
+
+impl Parse {
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.green
+    }
+
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+}
+
+pub fn parse(source: &str) -> Parse {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+
+    loop {
+        let token = lexer.next_lossless();
+        let done = token.token.kind == TokenKind::EOF;
+
+        tokens.push(token);
+
+        if done {
+            break;
+        }
+    }
+
+    let errors = lexer
+        .errors()
+        .iter()
+        .map(|error| ParseError::new(error.kind.to_string(), error.position))
+        .collect();
+
+    parse_tokens(&tokens, errors)
+}
+
+/// Parses tokens that have already been lexed, ending with `EOF`. `errors`
+/// are the lexer's errors, which are kept alongside the parser's own.
+pub fn parse_tokens(tokens: &[LosslessToken], mut errors: Vec<ParseError>) -> Parse {
+    let mut parser = CstParser::new(tokens);
+    parser.source_file();
+
+    errors.extend(parser.errors);
+    errors.sort_by_key(|error| (error.position.line, error.position.column));
+
+    Parse {
+        green: parser.builder.finish(),
+        errors,
+    }
+}
+
+/// Parses `tokens`, which end with `EOF`, as a block on its own. This only
+/// succeeds if the block ends right before `EOF`, in which case the tree is the same as the one
+/// the block would get when parsing the whole file.
+pub(crate) fn parse_block(tokens: &[LosslessToken]) -> Option<(Rc<GreenNode>, Vec<ParseError>)> {
+    let mut parser = CstParser::new(tokens);
+
+    if !parser.at_symbol(SymbolKind::LeftBrace) || !parser.block() || !parser.at(&TokenKind::EOF) {
+        return None;
+    }
+
+    Some((parser.builder.finish(), parser.errors))
+}
+
+struct CstParser<'a> {
+    tokens: &'a [LosslessToken],
+    // Whether each token is seen by the grammar. Newlines that don't end a
+    // statement are kept in the tree but otherwise skipped.
+    significant: Vec<bool>,
+    cursor: usize,
+    builder: GreenNodeBuilder,
+    errors: Vec<ParseError>,
+    no_struct_literal: bool,
+    // How many levels of `MAX_NESTING` are in use, counted like the parser
+    depth: usize,
+}
+
+impl<'a> CstParser<'a> {
+    fn new(tokens: &'a [LosslessToken]) -> CstParser<'a> {
+        let mut last_kind = TokenKind::Newline;
+
+        let significant = tokens
+            .iter()
+            .map(|token| {
+                let significant = token.token.kind != TokenKind::Newline || Parser::ends_stmt(&last_kind);
+
+                if significant {
+                    last_kind = token.token.kind.clone();
+                }
+
+                significant
+            })
+            .collect();
+
+        CstParser {
+            tokens,
+            significant,
+            cursor: 0,
+            builder: GreenNodeBuilder::new(),
+            errors: Vec::new(),
+            no_struct_literal: false,
+            depth: 0,
+        }
+    }
+
+    fn source_file(&mut self) {
+        self.builder.start_node(NodeKind::SourceFile);
+        self.skip_insignificant();
+
+        while !self.at(&TokenKind::EOF) {
+            if self.at_symbol(SymbolKind::RightBrace) {
+                self.error("expression");
+                self.bump_error();
+                continue;
+            }
+
+            self.stmt();
+        }
+
+        self.bump();
+        self.builder.finish_node();
+    }
+
+    fn stmt(&mut self) {
+        let start = self.cursor;
+
+        let parsed = match self.current() {
+            TokenKind::Newline | TokenKind::Symbol(SymbolKind::Semicolon) => {
+                self.bump();
+                return;
+            }
+            TokenKind::Keyword(KeywordKind::Let) => self.let_stmt(),
+            TokenKind::Keyword(KeywordKind::Return) => self.return_stmt(),
+            TokenKind::Keyword(KeywordKind::Break) => self.keyword_stmt(NodeKind::BreakStmt),
+            TokenKind::Keyword(KeywordKind::Continue) => self.keyword_stmt(NodeKind::ContinueStmt),
+            TokenKind::Keyword(KeywordKind::While) => self.while_stmt(),
+            TokenKind::Keyword(KeywordKind::For) => self.for_stmt(),
+            TokenKind::Keyword(KeywordKind::Fn) => self.fn_decl(),
+            TokenKind::Keyword(KeywordKind::Struct) => self.struct_decl(),
+            TokenKind::Keyword(KeywordKind::Enum) => self.enum_decl(),
+            TokenKind::Keyword(KeywordKind::Import) => self.import_stmt(),
+            _ => self.simple_stmt(),
+        };
+
+        if self.cursor == start {
+            self.bump_error();
+            return;
+        }
+
+        if parsed && !self.at_end_stmt() {
+            self.error("`;` or newline after statement");
+        }
+
+        self.synchronize();
+    }
+
+    fn keyword_stmt(&mut self, kind: NodeKind) -> bool {
+        self.builder.start_node(kind);
+        self.bump();
+        self.builder.finish_node();
+        true
+    }
+
+    fn let_stmt(&mut self) -> bool {
+        self.builder.start_node(NodeKind::LetStmt);
+        self.bump();
+
+        let parsed = self.name()
+            && (!self.eat(SymbolKind::Colon) || self.ty())
+            && self.expect(SymbolKind::Assign)
+            && self.expr();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn return_stmt(&mut self) -> bool {
+        self.builder.start_node(NodeKind::ReturnStmt);
+        self.bump();
+
+        let parsed = self.at_end_stmt() || self.expr();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn while_stmt(&mut self) -> bool {
+        self.builder.start_node(NodeKind::WhileStmt);
+        self.bump();
+
+        let parsed = self.restricted(CstParser::expr) && self.block();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn for_stmt(&mut self) -> bool {
+        self.builder.start_node(NodeKind::ForStmt);
+        self.bump();
+
+        let init = if self.at(&TokenKind::Keyword(KeywordKind::Let)) {
+            self.let_stmt()
+        } else {
+            self.simple_stmt()
+        };
+
+        let parsed = init
+            && self.expect(SymbolKind::Semicolon)
+            && self.restricted(CstParser::expr)
+            && self.expect(SymbolKind::Semicolon)
+            && self.restricted(CstParser::simple_stmt)
+            && self.block();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn fn_decl(&mut self) -> bool {
+        self.builder.start_node(NodeKind::FnDecl);
+        self.bump();
+
+        let parsed = self.name()
+            && self.list(NodeKind::ParamList, SymbolKind::LeftParen, SymbolKind::RightParen, |parser| {
+                parser.param(NodeKind::Param)
+            })
+            && (!self.eat(SymbolKind::Colon) || self.ty())
+            && self.block();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn param(&mut self, kind: NodeKind) -> bool {
+        self.builder.start_node(kind);
+
+        let parsed = self.name() && self.expect(SymbolKind::Colon) && self.ty();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn struct_decl(&mut self) -> bool {
+        self.builder.start_node(NodeKind::StructDecl);
+        self.bump();
+
+        let parsed = self.name()
+            && self.list(NodeKind::FieldList, SymbolKind::LeftBrace, SymbolKind::RightBrace, |parser| {
+                parser.param(NodeKind::Field)
+            });
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn enum_decl(&mut self) -> bool {
+        self.builder.start_node(NodeKind::EnumDecl);
+        self.bump();
+
+        let parsed = self.name()
+            && self.list(NodeKind::VariantList, SymbolKind::LeftBrace, SymbolKind::RightBrace, |parser| {
+                parser.variant()
+            });
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn variant(&mut self) -> bool {
+        self.builder.start_node(NodeKind::Variant);
+
+        let parsed = self.name()
+            && (!self.at_symbol(SymbolKind::LeftParen)
+                || self.list(NodeKind::TypeList, SymbolKind::LeftParen, SymbolKind::RightParen, |parser| {
+                    parser.ty()
+                }));
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn import_stmt(&mut self) -> bool {
+        self.builder.start_node(NodeKind::ImportStmt);
+        self.bump();
+
+        let parsed = self.name();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    /// An expression statement, an assignment or an increment.
+    fn simple_stmt(&mut self) -> bool {
+        let checkpoint = self.builder.checkpoint();
+
+        if !self.expr() {
+            return false;
+        }
+
+        let kind = match self.current() {
+            TokenKind::Symbol(SymbolKind::Assign)
+            | TokenKind::Symbol(SymbolKind::PlusAssign)
+            | TokenKind::Symbol(SymbolKind::MinusAssign)
+            | TokenKind::Symbol(SymbolKind::AsteriskAssign)
+            | TokenKind::Symbol(SymbolKind::SlashAssign)
+            | TokenKind::Symbol(SymbolKind::ModAssign) => NodeKind::AssignStmt,
+            TokenKind::Symbol(SymbolKind::Increment) => NodeKind::IncrementStmt,
+            TokenKind::Symbol(SymbolKind::Decrement) => NodeKind::DecrementStmt,
+            _ => {
+                self.builder.start_node_at(checkpoint, NodeKind::ExprStmt);
+                self.builder.finish_node();
+                return true;
+            }
+        };
+
+        self.builder.start_node_at(checkpoint, kind);
+        self.bump();
+
+        let parsed = kind != NodeKind::AssignStmt || self.expr();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn ty(&mut self) -> bool {
+        self.nested(|parser| {
+            match parser.current() {
+                TokenKind::Identifier(_) => {
+                    parser.builder.start_node(NodeKind::NamedType);
+                    parser.bump();
+                    parser.builder.finish_node();
+                    true
+                }
+                TokenKind::Symbol(SymbolKind::LeftBracket) => {
+                    parser.builder.start_node(NodeKind::ArrayType);
+                    parser.bump();
+
+                    let parsed = parser.ty() && parser.expect(SymbolKind::RightBracket);
+
+                    parser.builder.finish_node();
+                    parsed
+                }
+                _ => {
+                    parser.error("type");
+                    false
+                }
+            }
+        })
+    }
+
+    fn block(&mut self) -> bool {
+        if !self.at_symbol(SymbolKind::LeftBrace) {
+            self.error("`{`");
+            return false;
+        }
+
+        self.nested(|parser| {
+            parser.builder.start_node(NodeKind::Block);
+            parser.bump();
+
+            // The outer expression restriction does not reach into blocks
+            let no_struct_literal = mem::replace(&mut parser.no_struct_literal, false);
+
+            let parsed = loop {
+                match parser.current() {
+                    TokenKind::Symbol(SymbolKind::RightBrace) => {
+                        parser.bump();
+                        break true;
+                    }
+                    TokenKind::EOF => {
+                        parser.error("`}`");
+                        break false;
+                    }
+                    _ => parser.stmt(),
+                }
+            };
+
+            parser.no_struct_literal = no_struct_literal;
+            parser.builder.finish_node();
+            parsed
+        })
+    }
+
+    /// Parses a comma (or newline) separated list, starting at its opening
+    /// delimiter.
+    fn list<F>(&mut self, kind: NodeKind, open: SymbolKind, close: SymbolKind, mut item: F) -> bool
+    where
+        F: FnMut(&mut Self) -> bool,
+    {
+        if !self.at_symbol(open.clone()) {
+            self.error(&TokenKind::Symbol(open).to_string());
+            return false;
+        }
+
+        self.builder.start_node(kind);
+        self.bump();
+
+        let parsed = loop {
+            self.skip_newlines();
+
+            if self.eat(close.clone()) {
+                break true;
+            }
+
+            if !item(self) {
+                break false;
+            }
+
+            let separated = self.at(&TokenKind::Newline);
+
+            self.skip_newlines();
+
+            if !self.eat(SymbolKind::Comma) && !separated && !self.at_symbol(close.clone()) {
+                self.error(&format!("`,` or {}", TokenKind::Symbol(close.clone())));
+                break false;
+            }
+        };
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn restricted(&mut self, parse: fn(&mut Self) -> bool) -> bool {
+        let no_struct_literal = mem::replace(&mut self.no_struct_literal, true);
+        let parsed = parse(self);
+        self.no_struct_literal = no_struct_literal;
+        parsed
+    }
+
+    fn expr(&mut self) -> bool {
+        self.expr_bp(Precedence::Lowest)
+    }
+
+    fn expr_bp(&mut self, precedence: Precedence) -> bool {
+        self.nested(|parser| {
+            let checkpoint = parser.builder.checkpoint();
+
+            if !parser.prefix() {
+                return false;
+            }
+
+            loop {
+                let next = Parser::token_precedence(parser.current());
+
+                if parser.at_end_stmt() || precedence >= next {
+                    return true;
+                }
+
+                // Each operator wraps everything to its left in another node
+                if !parser.descend() {
+                    return false;
+                }
+
+                let parsed = match parser.current() {
+                    TokenKind::Symbol(SymbolKind::LeftParen) => {
+                        parser.builder.start_node_at(checkpoint, NodeKind::CallExpr);
+                        parser.list(
+                            NodeKind::ArgList,
+                            SymbolKind::LeftParen,
+                            SymbolKind::RightParen,
+                            CstParser::expr,
+                        )
+                    }
+                    TokenKind::Symbol(SymbolKind::LeftBracket) => {
+                        parser.builder.start_node_at(checkpoint, NodeKind::IndexExpr);
+                        parser.bump();
+                        parser.expr() && parser.expect(SymbolKind::RightBracket)
+                    }
+                    TokenKind::Symbol(SymbolKind::Dot) => {
+                        parser.builder.start_node_at(checkpoint, NodeKind::FieldExpr);
+                        parser.bump();
+                        parser.name_ref()
+                    }
+                    _ => {
+                        parser.builder.start_node_at(checkpoint, NodeKind::InfixExpr);
+                        parser.bump();
+                        parser.expr_bp(next)
+                    }
+                };
+
+                parser.builder.finish_node();
+
+                if !parsed {
+                    return false;
+                }
+            }
+        })
+    }
+
+    fn prefix(&mut self) -> bool {
+        match self.current() {
+            TokenKind::Identifier(_) => {
+                let checkpoint = self.builder.checkpoint();
+                self.name_ref();
+
+                if self.no_struct_literal || !self.at_symbol(SymbolKind::LeftBrace) {
+                    return true;
+                }
+
+                self.builder.start_node_at(checkpoint, NodeKind::StructExpr);
+                let parsed = self.list(
+                    NodeKind::FieldInitList,
+                    SymbolKind::LeftBrace,
+                    SymbolKind::RightBrace,
+                    CstParser::field_init,
+                );
+                self.builder.finish_node();
+                parsed
+            }
+            TokenKind::IntegerLiteral(_)
+            | TokenKind::FloatLiteral(_)
+            | TokenKind::StringLiteral(_)
+            | TokenKind::CharLiteral(_)
+            | TokenKind::Keyword(KeywordKind::True)
+            | TokenKind::Keyword(KeywordKind::False) => self.node(NodeKind::Literal),
+            TokenKind::StringStart(_) => {
+                self.builder.start_node(NodeKind::InterpolatedString);
+
+                let no_struct_literal = mem::replace(&mut self.no_struct_literal, false);
+                let parsed = self.string_parts();
+                self.no_struct_literal = no_struct_literal;
+
+                self.builder.finish_node();
+                parsed
+            }
+            TokenKind::Symbol(SymbolKind::Minus) | TokenKind::Symbol(SymbolKind::Bang) => {
+                self.builder.start_node(NodeKind::PrefixExpr);
+                self.bump();
+
+                let parsed = self.expr_bp(Precedence::Prefix);
+
+                self.builder.finish_node();
+                parsed
+            }
+            TokenKind::Symbol(SymbolKind::LeftParen) => {
+                self.builder.start_node(NodeKind::ParenExpr);
+                self.bump();
+
+                // Parentheses lift the struct literal restriction
+                let no_struct_literal = mem::replace(&mut self.no_struct_literal, false);
+                let parsed = self.expr();
+                self.no_struct_literal = no_struct_literal;
+
+                self.skip_newlines();
+
+                let parsed = parsed && self.expect(SymbolKind::RightParen);
+
+                self.builder.finish_node();
+                parsed
+            }
+            TokenKind::Symbol(SymbolKind::LeftBracket) => {
+                self.builder.start_node(NodeKind::ArrayExpr);
+                let parsed =
+                    self.list(NodeKind::ArgList, SymbolKind::LeftBracket, SymbolKind::RightBracket, CstParser::expr);
+                self.builder.finish_node();
+                parsed
+            }
+            TokenKind::Symbol(SymbolKind::LeftBrace) => self.block(),
+            TokenKind::Keyword(KeywordKind::If) => self.if_expr(),
+            TokenKind::Keyword(KeywordKind::Match) => self.match_expr(),
+            // The lexer already reported why the token is illegal
+            TokenKind::Illegal => false,
+            _ => {
+                self.error("expression");
+                false
+            }
+        }
+    }
+
+    fn string_parts(&mut self) -> bool {
+        loop {
+            self.bump();
+
+            if !self.expr() {
+                return false;
+            }
+
+            match self.current() {
+                TokenKind::StringMiddle(_) => {}
+                TokenKind::StringEnd(_) => {
+                    self.bump();
+                    return true;
+                }
+                _ => {
+                    self.error("`}` after the embedded expression");
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn field_init(&mut self) -> bool {
+        self.builder.start_node(NodeKind::FieldInit);
+
+        let parsed = self.name_ref() && self.expect(SymbolKind::Colon) && self.expr();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn if_expr(&mut self) -> bool {
+        self.builder.start_node(NodeKind::IfExpr);
+        self.bump();
+
+        let mut parsed = self.restricted(CstParser::expr) && self.block();
+
+        if parsed && self.at(&TokenKind::Keyword(KeywordKind::Else)) {
+            self.bump();
+
+            parsed = if self.at(&TokenKind::Keyword(KeywordKind::If)) {
+                self.nested(CstParser::if_expr)
+            } else {
+                self.block()
+            };
+        }
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn match_expr(&mut self) -> bool {
+        self.builder.start_node(NodeKind::MatchExpr);
+        self.bump();
+
+        let mut parsed = self.restricted(CstParser::expr);
+
+        if parsed {
+            let no_struct_literal = mem::replace(&mut self.no_struct_literal, false);
+
+            parsed = self.list(NodeKind::MatchArmList, SymbolKind::LeftBrace, SymbolKind::RightBrace, |parser| {
+                parser.match_arm()
+            });
+
+            self.no_struct_literal = no_struct_literal;
+        }
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn match_arm(&mut self) -> bool {
+        self.builder.start_node(NodeKind::MatchArm);
+
+        let parsed = self.pattern() && self.expect(SymbolKind::FatArrow) && self.expr();
+
+        self.builder.finish_node();
+        parsed
+    }
+
+    fn pattern(&mut self) -> bool {
+        self.nested(|parser| {
+            match parser.current() {
+                TokenKind::Identifier(name) if name == "_" => parser.node(NodeKind::WildcardPat),
+                TokenKind::Identifier(_) => {
+                    let checkpoint = parser.builder.checkpoint();
+                    parser.name_ref();
+
+                    if !parser.at_symbol(SymbolKind::Dot) {
+                        // A lone name binds the value, so it declares the name
+                        parser.builder.start_node_at(checkpoint, NodeKind::BindingPat);
+                        parser.builder.finish_node();
+                        return true;
+                    }
+
+                    parser.builder.start_node_at(checkpoint, NodeKind::VariantPat);
+                    parser.bump();
+
+                    let parsed = parser.name_ref()
+                        && (!parser.at_symbol(SymbolKind::LeftParen)
+                            || parser.list(
+                                NodeKind::PatList,
+                                SymbolKind::LeftParen,
+                                SymbolKind::RightParen,
+                                CstParser::pattern,
+                            ));
+
+                    parser.builder.finish_node();
+                    parsed
+                }
+                TokenKind::Symbol(SymbolKind::Minus) => {
+                    parser.builder.start_node(NodeKind::LiteralPat);
+                    parser.bump();
+
+                    let parsed = match parser.current() {
+                        TokenKind::IntegerLiteral(_) | TokenKind::FloatLiteral(_) => {
+                            parser.bump();
+                            true
+                        }
+                        _ => {
+                            parser.error("number literal");
+                            false
+                        }
+                    };
+
+                    parser.builder.finish_node();
+                    parsed
+                }
+                TokenKind::IntegerLiteral(_)
+                | TokenKind::FloatLiteral(_)
+                | TokenKind::StringLiteral(_)
+                | TokenKind::CharLiteral(_)
+                | TokenKind::Keyword(KeywordKind::True)
+                | TokenKind::Keyword(KeywordKind::False) => parser.node(NodeKind::LiteralPat),
+                _ => {
+                    parser.error("pattern");
+                    false
+                }
+            }
+        })
+    }
+
+    fn name(&mut self) -> bool {
+        self.identifier(NodeKind::Name)
+    }
+
+    fn name_ref(&mut self) -> bool {
+        self.identifier(NodeKind::NameRef)
+    }
+
+    fn identifier(&mut self, kind: NodeKind) -> bool {
+        if let TokenKind::Identifier(_) = self.current() {
+            self.node(kind)
+        } else {
+            self.error("identifier");
+            false
+        }
+    }
+
+    /// Wraps the current token in a node of its own.
+    fn node(&mut self, kind: NodeKind) -> bool {
+        self.builder.start_node(kind);
+        self.bump();
+        self.builder.finish_node();
+        true
+    }
+
+    /// Parses something one level deeper, giving up past `MAX_NESTING`.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> bool) -> bool {
+        let depth = self.depth;
+        let parsed = self.descend() && parse(self);
+        self.depth = depth;
+        parsed
+    }
+
+    fn descend(&mut self) -> bool {
+        if self.depth == MAX_NESTING {
+            let message = format!("nested more than {} levels deep", MAX_NESTING);
+            let position = self.tokens[self.cursor].token.position;
+            self.errors.push(ParseError::new(message, position));
+            return false;
+        }
+
+        self.depth += 1;
+        true
+    }
+
+    fn current(&self) -> &TokenKind {
+        &self.tokens[self.cursor].token.kind
+    }
+
+    fn at(&self, kind: &TokenKind) -> bool {
+        self.current() == kind
+    }
+
+    fn at_symbol(&self, symbol: SymbolKind) -> bool {
+        self.at(&TokenKind::Symbol(symbol))
+    }
+
+    fn at_end_stmt(&self) -> bool {
+        matches!(
+            self.current(),
+            TokenKind::Newline
+                | TokenKind::EOF
+                | TokenKind::Symbol(SymbolKind::Semicolon)
+                | TokenKind::Symbol(SymbolKind::RightBrace)
+        )
+    }
+
+    /// Adds the current token to the tree, along with any newlines after it
+    /// that the grammar doesn't see.
+    fn bump(&mut self) {
+        let token = self.tokens[self.cursor].clone();
+        self.builder.token(token.into());
+
+        if self.cursor + 1 < self.tokens.len() {
+            self.cursor += 1;
+        }
+
+        self.skip_insignificant();
+    }
+
+    fn skip_insignificant(&mut self) {
+        while !self.significant[self.cursor] {
+            self.bump();
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.at(&TokenKind::Newline) {
+            self.bump();
+        }
+    }
+
+    fn eat(&mut self, symbol: SymbolKind) -> bool {
+        if self.at_symbol(symbol) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: SymbolKind) -> bool {
+        if self.eat(symbol.clone()) {
+            return true;
+        }
+
+        self.error(&TokenKind::Symbol(symbol).to_string());
+        false
+    }
+
+    fn error(&mut self, expected: &str) {
+        let token = &self.tokens[self.cursor].token;
+        let message = format!("expected {}, found {}", expected, token.kind);
+
+        self.errors.push(ParseError::new(message, token.position));
+    }
+
+    fn bump_error(&mut self) {
+        self.builder.start_node(NodeKind::Error);
+        self.bump();
+        self.builder.finish_node();
+    }
+
+    /// Wraps everything up to the end of the statement in an error node.
+    fn synchronize(&mut self) {
+        if self.at_end_stmt() {
+            return;
+        }
+
+        self.builder.start_node(NodeKind::Error);
+
+        while !self.at_end_stmt() {
+            self.bump();
+        }
+
+        self.builder.finish_node();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(source: &str) -> Parse {
+        let parse = parse(source);
+        assert_eq!(parse.syntax().text(), source);
+        assert_eq!(parse.syntax().text_range(), 0..source.len());
+        parse
+    }
+
+    /// The kinds of the nodes in the tree, indented by depth.
+    fn shape(node: &SyntaxNode) -> String {
+        node.descendants()
+            .iter()
+            .map(|node| format!("{}{:?}\n", "  ".repeat(node.ancestors().count() - 1), node.kind()))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            include_str!("../samples/arithmetic.k"),
+            include_str!("../samples/fib.k"),
+            include_str!("../samples/hello.k"),
+            "",
+            "\n\n  // only a comment\n",
+            "let x = 1 // one\r\nlet y = /* two */ x +\r\n    2\r\n",
+            "struct Point {\n    x: i32, // across\n    y: i32,\n}\n\nfn f(p: Point): [i32] { [p.x, p.y] }",
+            "enum Shape { Circle(f64), Square(f64) }\nmatch s { Shape.Circle(r) => r * r, _ => -1.0 }",
+            "for let i = 0; i < 10; i++ { if i % 2 == 0 { continue } else if i > 5 { break } }",
+            "let s = \"é\\n\"; let c = 'ü'",
+            "let s = \"a{ f(x) }b{\"{ {c} }\"}\\{\"",
+        ];
+
+        for source in sources.iter() {
+            let parse = assert_round_trip(source);
+            assert_eq!(parse.errors(), &[], "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_errors_match_parser() {
+        let sources = [
+            "let = 5",
+            "let x = ;\nlet y = 2",
+            "fn f( { }",
+            "let s = \"abc",
+            "/* open",
+            "1 + }\n2",
+            "x = @ 1",
+            "struct S { a: i32 b: i32 }",
+            "}",
+            "if x { 1 }\nelse { 2 }",
+            "let s = \"a{}b\"",
+            "let s = \"a{x y}b\"",
+            "let s = \"a{x",
+        ];
+
+        for source in sources.iter() {
+            let parse = assert_round_trip(source);
+
+            let mut parser = Parser::new(Lexer::new(source));
+            parser.parse();
+
+            assert!(!parse.errors().is_empty(), "{}", source);
+            assert_eq!(parse.errors()[0], parser.errors()[0], "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit_matches_parser() {
+        for source in crate::parser::tests::deeply_nested(MAX_NESTING + 1) {
+            let parse = assert_round_trip(&source);
+
+            let mut parser = Parser::new(Lexer::new(&source));
+            parser.parse();
+
+            assert!(!parse.errors().is_empty(), "{}", source);
+            assert_eq!(parse.errors()[0], parser.errors()[0], "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_tree_shape() {
+        let parse = parse("let x: i32 = f(1, -y)\nx += 2 * 3 // done\n");
+
+        assert_eq!(
+            shape(&parse.syntax()),
+            "SourceFile
+  LetStmt
+    Name
+    NamedType
+    CallExpr
+      NameRef
+      ArgList
+        Literal
+        PrefixExpr
+          NameRef
+  AssignStmt
+    NameRef
+    InfixExpr
+      Literal
+      Literal
+"
+        );
+
+        let assign = parse.syntax().children()[1].clone();
+        assert_eq!(assign.text(), "x += 2 * 3 // done");
+
+        let tokens = assign.descendant_tokens();
+        assert_eq!(tokens.last().map(|token| token.text()), Some("3"));
+        assert_eq!(tokens.last().map(|token| token.trailing().len()), Some(2));
+
+        let offset = parse.syntax().text().find('y').unwrap();
+        let token = parse.syntax().token_at_offset(offset).unwrap();
+        assert_eq!(token.parent().kind(), NodeKind::NameRef);
+        assert_eq!(token.parent().parent().map(|node| node.kind()), Some(NodeKind::PrefixExpr));
+    }
+
+    #[test]
+    fn test_newlines_in_lists() {
+        let source = "let xs = [\n    1,\n    2\n]\nf(a,\n  b)\n";
+        let parse = assert_round_trip(source);
+
+        assert_eq!(parse.errors(), &[]);
+        assert_eq!(parse.syntax().children().len(), 2);
+    }
+}
@@ -0,0 +1,530 @@
+//! Stable dumps of tokens and syntax trees, as JSON for tools and as
+//! S-expressions for people.
+//!
+//! Every syntax tree node becomes an object with its `kind`, `position`,
+//! `end`, and named children in JSON, or `(kind children...)` in an
+//! S-expression where names and literals are printed bare. An expression
+//! statement is dumped as its expression. Like for tokens, `end` is just past
+//! the last character.
+
+use crate::ast::*;
+use crate::formatter::DEFAULT_WIDTH;
+use crate::lexer::Lexer;
+use crate::pretty::{concat, group, join, nest, render, text, Doc};
+use crate::token::{BufferPosition, TokenKind};
+use serde_json::{json, Map, Value};
+
+enum Tree {
+    // The kind, start, end and named children
+    Node(&'static str, BufferPosition, BufferPosition, Vec<(&'static str, Tree)>),
+    // A name or literal
+    Leaf(&'static str, BufferPosition, BufferPosition, Atom),
+    List(Vec<Tree>),
+    // Something without a position of its own, like an operator
+    Atom(Atom),
+    Null,
+}
+
+enum Atom {
+    Name(String),
+    Str(String),
+    Char(char),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+pub fn program_to_json(program: &Program) -> Value {
+    let body: Vec<Value> = program.0.iter().map(|stmt| stmt_tree(stmt).to_json()).collect();
+    json!({ "kind": "program", "body": body })
+}
+
+/// One S-expression per top-level statement, each on its own line.
+pub fn program_to_sexp(program: &Program) -> String {
+    program.0.iter().map(|stmt| render(&stmt_tree(stmt).to_doc(), DEFAULT_WIDTH) + "\n").collect()
+}
+
+/// The tokens of `source` up to but not including `EOF`.
+pub fn tokens_to_json(source: &str) -> Value {
+    let tokens = spanned_tokens(source)
+        .into_iter()
+        .map(|(kind, value, start, end)| {
+            let mut token = Map::new();
+            token.insert("kind".to_owned(), json!(kind));
+
+            if let Some(value) = value {
+                token.insert("value".to_owned(), value.to_json());
+            }
+
+            token.insert("position".to_owned(), position_to_json(start));
+            token.insert("end".to_owned(), position_to_json(end));
+            Value::Object(token)
+        })
+        .collect();
+
+    Value::Array(tokens)
+}
+
+/// One token per line, as `(kind value start end)`.
+pub fn tokens_to_sexp(source: &str) -> String {
+    let mut output = String::new();
+
+    for (kind, value, start, end) in spanned_tokens(source) {
+        let value = value.map(|value| format!(" {}", value.to_sexp())).unwrap_or_default();
+        output.push_str(&format!("({}{} {} {})\n", kind, value, start, end));
+    }
+
+    output
+}
+
+type SpannedToken = (&'static str, Option<Atom>, BufferPosition, BufferPosition);
+
+fn spanned_tokens(source: &str) -> Vec<SpannedToken> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+
+    while let Some((token, text)) = lexer.next_spanned() {
+        let (kind, value) = match token.kind {
+            TokenKind::Identifier(name) => ("identifier", Some(Atom::Name(name.to_string()))),
+            TokenKind::Keyword(keyword) => ("keyword", Some(Atom::Name(keyword.as_str().to_owned()))),
+            TokenKind::IntegerLiteral(value) => ("integer", Some(Atom::Int(value))),
+            TokenKind::FloatLiteral(value) => ("float", Some(Atom::Float(value))),
+            TokenKind::StringLiteral(value) => ("string", Some(Atom::Str(value))),
+            TokenKind::StringStart(value) => ("string_start", Some(Atom::Str(value))),
+            TokenKind::StringMiddle(value) => ("string_middle", Some(Atom::Str(value))),
+            TokenKind::StringEnd(value) => ("string_end", Some(Atom::Str(value))),
+            TokenKind::CharLiteral(value) => ("char", Some(Atom::Char(value))),
+            TokenKind::Symbol(symbol) => ("symbol", Some(Atom::Name(symbol.as_str().to_owned()))),
+            TokenKind::Newline => ("newline", None),
+            TokenKind::Illegal => ("illegal", Some(Atom::Str(text.to_owned()))),
+            TokenKind::EOF => ("eof", None),
+        };
+
+        tokens.push((kind, value, token.position, end_position(token.position, text)));
+    }
+
+    tokens
+}
+
+fn end_position(start: BufferPosition, text: &str) -> BufferPosition {
+    text.chars().fold(start, |position, ch| match ch {
+        '\n' => BufferPosition::new(position.line + 1, 1),
+        _ => BufferPosition::new(position.line, position.column + 1),
+    })
+}
+
+fn position_to_json(position: BufferPosition) -> Value {
+    json!({ "line": position.line, "column": position.column })
+}
+
+fn name(identifier: &Identifier) -> Tree {
+    let end = end_position(identifier.position, identifier.name.as_str());
+    Tree::Leaf("identifier", identifier.position, end, Atom::Name(identifier.name.to_string()))
+}
+
+fn list<T>(items: &[T], tree: impl Fn(&T) -> Tree) -> Tree {
+    Tree::List(items.iter().map(tree).collect())
+}
+
+fn optional<T>(item: &Option<T>, tree: impl Fn(&T) -> Tree) -> Tree {
+    item.as_ref().map_or(Tree::Null, tree)
+}
+
+fn block(body: &[Stmt]) -> Tree {
+    list(body, stmt_tree)
+}
+
+fn stmt_tree(stmt: &Stmt) -> Tree {
+    let node = |kind, fields| Tree::Node(kind, stmt.position, stmt.end, fields);
+
+    match &stmt.kind {
+        StmtKind::Blank => node("blank", vec![]),
+        StmtKind::Let(identifier, ty, value) => node(
+            "let",
+            vec![("name", name(identifier)), ("type", optional(ty, type_tree)), ("value", expr_tree(value))],
+        ),
+        StmtKind::Assign(target, op, value) => node(
+            "assign",
+            vec![
+                ("target", expr_tree(target)),
+                ("op", Tree::Atom(Atom::Name(op.as_str().to_owned()))),
+                ("value", expr_tree(value)),
+            ],
+        ),
+        StmtKind::Increment(target) => node("increment", vec![("target", expr_tree(target))]),
+        StmtKind::Decrement(target) => node("decrement", vec![("target", expr_tree(target))]),
+        StmtKind::Return(value) => node("return", vec![("value", optional(value, expr_tree))]),
+        StmtKind::Break => node("break", vec![]),
+        StmtKind::Continue => node("continue", vec![]),
+        StmtKind::While(condition, body) => {
+            node("while", vec![("condition", expr_tree(condition)), ("body", block(body))])
+        }
+        StmtKind::For(init, condition, post, body) => node(
+            "for",
+            vec![
+                ("init", stmt_tree(init)),
+                ("condition", expr_tree(condition)),
+                ("post", stmt_tree(post)),
+                ("body", block(body)),
+            ],
+        ),
+        StmtKind::Fn(decl) => node(
+            "fn",
+            vec![
+                ("name", name(&decl.name)),
+                ("params", list(&decl.params, param_tree)),
+                ("return_type", optional(&decl.return_type, type_tree)),
+                ("body", block(&decl.body)),
+            ],
+        ),
+        StmtKind::Struct(decl) => {
+            node("struct", vec![("name", name(&decl.name)), ("fields", list(&decl.fields, param_tree))])
+        }
+        StmtKind::Enum(decl) => node(
+            "enum",
+            vec![
+                ("name", name(&decl.name)),
+                (
+                    "variants",
+                    list(&decl.variants, |variant| {
+                        Tree::Node(
+                            "variant",
+                            variant.name.position,
+                            variant.end,
+                            vec![("name", name(&variant.name)), ("fields", list(&variant.fields, type_tree))],
+                        )
+                    }),
+                ),
+            ],
+        ),
+        StmtKind::Import(module) => node("import", vec![("name", name(module))]),
+        StmtKind::Expr(expr) => expr_tree(expr),
+    }
+}
+
+fn param_tree(param: &Param) -> Tree {
+    let fields = vec![("name", name(&param.name)), ("type", type_tree(&param.ty))];
+    Tree::Node("param", param.name.position, type_end(&param.ty), fields)
+}
+
+fn type_tree(ty: &TypeExpr) -> Tree {
+    match ty {
+        TypeExpr::Named(type_name) => name(type_name),
+        TypeExpr::Array(element, end) => {
+            Tree::Node("array_type", ty.position(), *end, vec![("element", type_tree(element))])
+        }
+    }
+}
+
+fn type_end(ty: &TypeExpr) -> BufferPosition {
+    match ty {
+        TypeExpr::Named(type_name) => end_position(type_name.position, type_name.name.as_str()),
+        TypeExpr::Array(_, end) => *end,
+    }
+}
+
+fn expr_tree(expr: &Expr) -> Tree {
+    let node = |kind, fields| Tree::Node(kind, expr.position, expr.end, fields);
+    let leaf = |kind, atom| Tree::Leaf(kind, expr.position, expr.end, atom);
+
+    match &expr.kind {
+        ExprKind::Identifier(identifier) => name(identifier),
+        ExprKind::IntegerLiteral(value) => leaf("integer", Atom::Int(*value)),
+        ExprKind::FloatLiteral(value) => leaf("float", Atom::Float(*value)),
+        ExprKind::StringLiteral(value) => leaf("string", Atom::Str(value.clone())),
+        ExprKind::Interpolated(parts) => node(
+            "interpolated",
+            vec![(
+                "parts",
+                list(parts, |part| match part {
+                    StringPart::Text(value) => Tree::Atom(Atom::Str(value.clone())),
+                    StringPart::Expr(expr) => expr_tree(expr),
+                }),
+            )],
+        ),
+        ExprKind::CharLiteral(value) => leaf("char", Atom::Char(*value)),
+        ExprKind::BooleanLiteral(value) => leaf("boolean", Atom::Bool(*value)),
+        ExprKind::ArrayLiteral(elements) => node("array", vec![("elements", list(elements, expr_tree))]),
+        ExprKind::StructLiteral(struct_name, fields) => node(
+            "struct_literal",
+            vec![
+                ("name", name(struct_name)),
+                (
+                    "fields",
+                    list(fields, |(field, value)| {
+                        let fields = vec![("name", name(field)), ("value", expr_tree(value))];
+                        Tree::Node("field_init", field.position, value.end, fields)
+                    }),
+                ),
+            ],
+        ),
+        ExprKind::Prefix(op, right) => node(
+            "prefix",
+            vec![("op", Tree::Atom(Atom::Name(op.as_str().to_owned()))), ("right", expr_tree(right))],
+        ),
+        ExprKind::Infix(op, left, right) => node(
+            "infix",
+            vec![
+                ("op", Tree::Atom(Atom::Name(op.as_str().to_owned()))),
+                ("left", expr_tree(left)),
+                ("right", expr_tree(right)),
+            ],
+        ),
+        ExprKind::Call(function, args) => {
+            node("call", vec![("function", expr_tree(function)), ("args", list(args, expr_tree))])
+        }
+        ExprKind::Index(object, index) => {
+            node("index", vec![("object", expr_tree(object)), ("index", expr_tree(index))])
+        }
+        ExprKind::Field(object, field) => {
+            node("field", vec![("object", expr_tree(object)), ("field", name(field))])
+        }
+        ExprKind::Block(body) => node("block", vec![("body", block(body))]),
+        ExprKind::If(condition, consequence, alternative) => node(
+            "if",
+            vec![
+                ("condition", expr_tree(condition)),
+                ("consequence", block(consequence)),
+                ("alternative", optional(alternative, |alternative| expr_tree(alternative))),
+            ],
+        ),
+        ExprKind::Match(scrutinee, arms) => node(
+            "match",
+            vec![
+                ("scrutinee", expr_tree(scrutinee)),
+                (
+                    "arms",
+                    list(arms, |arm| {
+                        Tree::Node(
+                            "arm",
+                            arm.pattern.position(),
+                            arm.body.end,
+                            vec![("pattern", pattern_tree(&arm.pattern)), ("body", expr_tree(&arm.body))],
+                        )
+                    }),
+                ),
+            ],
+        ),
+    }
+}
+
+fn pattern_tree(pattern: &Pattern) -> Tree {
+    match pattern {
+        Pattern::Wildcard(position) => {
+            Tree::Node("wildcard", *position, end_position(*position, "_"), vec![])
+        }
+        Pattern::Literal(expr) => expr_tree(expr),
+        Pattern::Binding(binding) => name(binding),
+        Pattern::Variant(enum_name, variant, fields, end) => Tree::Node(
+            "variant_pattern",
+            enum_name.position,
+            *end,
+            vec![
+                ("enum", name(enum_name)),
+                ("variant", name(variant)),
+                ("fields", list(fields, pattern_tree)),
+            ],
+        ),
+    }
+}
+
+impl Tree {
+    fn to_json(&self) -> Value {
+        match self {
+            Tree::Node(kind, position, end, fields) => {
+                let mut object = Map::new();
+                object.insert("kind".to_owned(), json!(kind));
+                object.insert("position".to_owned(), position_to_json(*position));
+                object.insert("end".to_owned(), position_to_json(*end));
+
+                for (name, field) in fields {
+                    object.insert((*name).to_owned(), field.to_json());
+                }
+
+                Value::Object(object)
+            }
+            Tree::Leaf(kind, position, end, atom) => json!({
+                "kind": kind,
+                "position": position_to_json(*position),
+                "end": position_to_json(*end),
+                "value": atom.to_json(),
+            }),
+            Tree::List(items) => Value::Array(items.iter().map(Tree::to_json).collect()),
+            Tree::Atom(atom) => atom.to_json(),
+            Tree::Null => Value::Null,
+        }
+    }
+
+    fn to_doc(&self) -> Doc {
+        match self {
+            Tree::Node(kind, _, _, fields) => {
+                let mut children = vec![text(*kind)];
+                children.extend(fields.iter().map(|(_, field)| field.to_doc()));
+                group(concat(vec![text("("), nest(join(children, Doc::Line)), text(")")]))
+            }
+            Tree::Leaf(_, _, _, atom) | Tree::Atom(atom) => text(atom.to_sexp()),
+            Tree::List(items) => {
+                // Broken lists line up with the node they are in
+                let items = items.iter().map(Tree::to_doc).collect();
+                group(concat(vec![text("["), join(items, Doc::Line), text("]")]))
+            }
+            Tree::Null => text("nil"),
+        }
+    }
+}
+
+impl Atom {
+    fn to_json(&self) -> Value {
+        match self {
+            Atom::Name(value) | Atom::Str(value) => json!(value),
+            Atom::Char(value) => json!(value.to_string()),
+            Atom::Int(value) => json!(value),
+            Atom::Float(value) => json!(value),
+            Atom::Bool(value) => json!(value),
+        }
+    }
+
+    fn to_sexp(&self) -> String {
+        match self {
+            Atom::Name(value) => value.clone(),
+            Atom::Str(value) => format!("{:?}", value),
+            Atom::Char(value) => format!("{:?}", value),
+            Atom::Int(value) => value.to_string(),
+            Atom::Float(value) => format!("{:?}", value),
+            Atom::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        assert!(parser.errors().is_empty(), "Parser reported errors: {:?}", parser.errors());
+
+        program
+    }
+
+    #[test]
+    fn test_program_to_sexp() {
+        let tests = vec![
+            ("let x: [i32] = [1, -y]", "(let x (array_type i32) (array [1 (prefix - y)]))\n"),
+            ("f(a.b, \"s\\n\", 'c', 1.5, true)", "(call f [(field a b) \"s\\n\" 'c' 1.5 true])\n"),
+            ("x += 1; i++\nreturn", "(assign x += 1)\n(increment i)\n(return nil)\n"),
+            ("\"a{b}\"", "(interpolated [\"a\" b])\n"),
+            (
+                "match s { S.A(r, _) => r, 0 => { 1 } }",
+                "(match s [(arm (variant_pattern S A [r (wildcard)]) r) (arm 0 (block [1]))])\n",
+            ),
+            ("struct P { x: i32 }", "(struct P [(param x i32)])\n"),
+            ("enum E { A(i32), B }", "(enum E [(variant A [i32]) (variant B [])])\n"),
+            ("P { x: 1 }", "(struct_literal P [(field_init x 1)])\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(program_to_sexp(&parse(input)), expected, "{}", input);
+        }
+
+        // Long expressions are broken over lines
+        let program = parse("fn f(n: i32): i32 { if n < 2 { return n } else { return f(n - 1) + f(n - 2) } }");
+        let expected = "(fn
+    f
+    [(param n i32)]
+    i32
+    [(if
+        (infix < n 2)
+        [(return n)]
+        (block [(return (infix + (call f [(infix - n 1)]) (call f [(infix - n 2)])))]))])
+";
+
+        assert_eq!(program_to_sexp(&program), expected);
+    }
+
+    #[test]
+    fn test_program_to_json() {
+        let json = program_to_json(&parse("let x = a + 1"));
+
+        let expected = json!({
+            "kind": "program",
+            "body": [{
+                "kind": "let",
+                "position": { "line": 1, "column": 1 },
+                "end": { "line": 1, "column": 14 },
+                "name": {
+                    "kind": "identifier",
+                    "position": { "line": 1, "column": 5 },
+                    "end": { "line": 1, "column": 6 },
+                    "value": "x",
+                },
+                "type": null,
+                "value": {
+                    "kind": "infix",
+                    "position": { "line": 1, "column": 11 },
+                    "end": { "line": 1, "column": 14 },
+                    "op": "+",
+                    "left": {
+                        "kind": "identifier",
+                        "position": { "line": 1, "column": 9 },
+                        "end": { "line": 1, "column": 10 },
+                        "value": "a",
+                    },
+                    "right": {
+                        "kind": "integer",
+                        "position": { "line": 1, "column": 13 },
+                        "end": { "line": 1, "column": 14 },
+                        "value": 1,
+                    },
+                },
+            }],
+        });
+
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_node_ends() {
+        let json = program_to_json(&parse("fn f(a: [i32]) {\n  match a { E.A(x) => g(x) }\n}"));
+        let end = |line, column| json!({ "line": line, "column": column });
+
+        let decl = &json["body"][0];
+        assert_eq!(decl["end"], end(3, 2));
+        assert_eq!(decl["params"][0]["end"], end(1, 14));
+        assert_eq!(decl["params"][0]["type"]["end"], end(1, 14));
+
+        let arm = &decl["body"][0]["arms"][0];
+        assert_eq!(decl["body"][0]["end"], end(2, 29));
+        assert_eq!(arm["end"], end(2, 27));
+        assert_eq!(arm["pattern"]["end"], end(2, 19));
+    }
+
+    #[test]
+    fn test_tokens() {
+        let source = "let s = \"é\nx\" $\n";
+        let expected = "(keyword let 1:1 1:4)
+(identifier s 1:5 1:6)
+(symbol = 1:7 1:8)
+(string \"é\\nx\" 1:9 2:3)
+(illegal \"$\" 2:4 2:5)
+(newline 2:5 3:1)
+";
+
+        assert_eq!(tokens_to_sexp(source), expected);
+
+        let json = tokens_to_json("f(1)");
+        assert_eq!(json.as_array().unwrap().len(), 4);
+        assert_eq!(
+            json[2],
+            json!({
+                "kind": "integer",
+                "value": 1,
+                "position": { "line": 1, "column": 3 },
+                "end": { "line": 1, "column": 4 },
+            })
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::ast::{BindingId, Identifier};
+use crate::object::Object;
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// The values of the bindings in a scope, by the `BindingId` the resolver
+/// gave them. Names are only kept to list the bindings.
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<BindingId, (Symbol, Object)>,
+    outer: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment {
+            store: HashMap::new(),
+            outer: None,
+        }
+    }
+
+    pub fn new_enclosed(outer: Rc<RefCell<Environment>>) -> Environment {
+        Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }
+    }
+
+    pub fn get(&self, id: BindingId) -> Option<Object> {
+        match self.store.get(&id) {
+            Some((_, value)) => Some(value.clone()),
+            None => self.outer.as_ref().and_then(|outer| outer.borrow().get(id)),
+        }
+    }
+
+    /// The value of the latest binding of a name in this scope, for finding
+    /// a binding without a resolved name for it.
+    pub fn get_by_name(&self, name: &Symbol) -> Option<Object> {
+        self.store
+            .iter()
+            .filter(|(_, (bound, _))| bound == name)
+            .max_by_key(|(id, _)| id.0)
+            .map(|(_, (_, value))| value.clone())
+    }
+
+    /// Introduces the binding a name declares in this scope.
+    ///
+    /// Panics if the name hasn't been resolved.
+    pub fn define(&mut self, name: &Identifier, value: Object) {
+        let id = name.binding.expect("names are resolved before they are evaluated");
+        self.store.insert(id, (name.name.clone(), value));
+    }
+
+    /// Updates an existing binding. Returns false if it is not bound in any
+    /// enclosing scope.
+    pub fn assign(&mut self, id: BindingId, value: Object) -> bool {
+        if let Some((_, slot)) = self.store.get_mut(&id) {
+            *slot = value;
+            return true;
+        }
+
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(id, value),
+            None => false,
+        }
+    }
+
+    /// The bindings introduced in this scope itself.
+    pub fn ids(&self) -> HashSet<BindingId> {
+        self.store.keys().copied().collect()
+    }
+
+    /// Forgets the bindings introduced in this scope that aren't in `ids`.
+    pub fn retain(&mut self, ids: &HashSet<BindingId>) {
+        self.store.retain(|id, _| ids.contains(id));
+    }
+
+    /// The latest binding of each name in this scope, sorted by name.
+    pub fn bindings(&self) -> Vec<(Symbol, Object)> {
+        let mut latest: HashMap<Symbol, (BindingId, &Object)> = HashMap::new();
+
+        for (id, (name, value)) in &self.store {
+            let entry = latest.entry(name.clone()).or_insert((*id, value));
+
+            if entry.0 .0 < id.0 {
+                *entry = (*id, value);
+            }
+        }
+
+        let mut bindings: Vec<(Symbol, Object)> =
+            latest.into_iter().map(|(name, (_, value))| (name, value.clone())).collect();
+
+        bindings.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        bindings
+    }
+}
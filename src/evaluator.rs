@@ -0,0 +1,1022 @@
+use crate::ast::*;
+use crate::builtins;
+use crate::environment::Environment;
+use crate::object::{Function, Object};
+use crate::symbol::Symbol;
+use crate::token::BufferPosition;
+use crate::types::Type;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// Deep K recursion is turned into a runtime error long before it would
+// overflow the interpreter's own stack.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Stack size for threads running the evaluator. Every K call nests several
+/// interpreter frames, so this leaves room for `MAX_CALL_DEPTH` calls even
+/// in debug builds.
+pub const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl RuntimeError {
+    pub fn new(message: String, position: BufferPosition) -> RuntimeError {
+        RuntimeError { message, position }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+/// Anything that interrupts the normal flow of evaluation.
+enum Flow {
+    Return(Object, BufferPosition),
+    Break(BufferPosition),
+    Continue(BufferPosition),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Flow {
+    fn from(error: RuntimeError) -> Flow {
+        Flow::Error(error)
+    }
+}
+
+impl Flow {
+    /// Converts control flow that escaped its enclosing construct into an
+    /// error.
+    fn into_error(self) -> RuntimeError {
+        match self {
+            Flow::Return(_, position) => {
+                RuntimeError::new("`return` outside of a function".to_owned(), position)
+            }
+            Flow::Break(position) => RuntimeError::new("`break` outside of a loop".to_owned(), position),
+            Flow::Continue(position) => {
+                RuntimeError::new("`continue` outside of a loop".to_owned(), position)
+            }
+            Flow::Error(error) => error,
+        }
+    }
+}
+
+type EvalResult = Result<Object, Flow>;
+
+type Env = Rc<RefCell<Environment>>;
+
+pub struct Evaluator {
+    env: Env,
+    call_depth: usize,
+    // How many more expressions may be evaluated, if there is a limit
+    fuel: Option<usize>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Evaluator {
+        Evaluator::new()
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Evaluator {
+        Evaluator {
+            env: Rc::new(RefCell::new(Environment::new())),
+            call_depth: 0,
+            fuel: None,
+        }
+    }
+
+    /// An evaluator that gives up with a runtime error after evaluating
+    /// `fuel` expressions, so even programs that loop forever finish.
+    pub fn with_fuel(fuel: usize) -> Evaluator {
+        Evaluator {
+            fuel: Some(fuel),
+            ..Evaluator::new()
+        }
+    }
+
+    /// The global environment that persists across calls to `eval_program`.
+    pub fn env(&self) -> Env {
+        self.env.clone()
+    }
+
+    /// Evaluates the top level statements of a program in the global
+    /// environment and returns the value of the final statement.
+    pub fn eval_program(&mut self, program: &Program) -> Result<Object, RuntimeError> {
+        let env = self.env.clone();
+
+        self.eval_block(&program.0, &env).map_err(Flow::into_error)
+    }
+
+    /// Evaluates a program and then calls its `main` function, if any.
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.eval_program(program)?;
+
+        let main = self.env.borrow().get_by_name(&Symbol::intern("main"));
+
+        if let Some(main) = main {
+            let position = match &main {
+                Object::Function(function) => function.decl.name.position,
+                _ => BufferPosition::new(0, 0),
+            };
+
+            self.apply_function(main, Vec::new(), position)
+                .map_err(Flow::into_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn eval_block(&mut self, block: &[Stmt], env: &Env) -> EvalResult {
+        // Declarations are hoisted so functions can refer to each other
+        // regardless of the order they are written in.
+        for stmt in block.iter().filter(|stmt| is_declaration(stmt)) {
+            self.eval_stmt(stmt, env)?;
+        }
+
+        let mut value = Object::Void;
+
+        for stmt in block {
+            value = if is_declaration(stmt) {
+                Object::Void
+            } else {
+                self.eval_stmt(stmt, env)?
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn eval_scoped_block(&mut self, block: &[Stmt], env: &Env) -> EvalResult {
+        let scope = Rc::new(RefCell::new(Environment::new_enclosed(env.clone())));
+
+        self.eval_block(block, &scope)
+    }
+
+    fn eval_stmt(&mut self, stmt: &Stmt, env: &Env) -> EvalResult {
+        match &stmt.kind {
+            StmtKind::Blank => {}
+            StmtKind::Let(name, _, value) => {
+                let value = self.eval_expr(value, env)?;
+                env.borrow_mut().define(name, value);
+            }
+            StmtKind::Assign(target, op, value) => {
+                let value = self.eval_expr(value, env)?;
+
+                let value = match op.infix_op() {
+                    Some(infix_op) => {
+                        let current = self.eval_expr(target, env)?;
+                        let value = eval_infix(infix_op, current, value, stmt.position)?;
+                        fit(value, &target.ty, stmt.position)?
+                    }
+                    None => value,
+                };
+
+                self.assign(target, value, env)?;
+            }
+            StmtKind::Increment(target) | StmtKind::Decrement(target) => {
+                let op = match &stmt.kind {
+                    StmtKind::Increment(_) => InfixOp::Add,
+                    _ => InfixOp::Sub,
+                };
+
+                let current = self.eval_expr(target, env)?;
+                let value = eval_infix(op, current, Object::Integer(1), stmt.position)?;
+                let value = fit(value, &target.ty, stmt.position)?;
+
+                self.assign(target, value, env)?;
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value, env)?,
+                    None => Object::Void,
+                };
+
+                return Err(Flow::Return(value, stmt.position));
+            }
+            StmtKind::Break => return Err(Flow::Break(stmt.position)),
+            StmtKind::Continue => return Err(Flow::Continue(stmt.position)),
+            StmtKind::While(condition, body) => {
+                while self.eval_condition(condition, env)? {
+                    match self.eval_scoped_block(body, env) {
+                        Err(Flow::Break(_)) => break,
+                        Ok(_) | Err(Flow::Continue(_)) => {}
+                        Err(flow) => return Err(flow),
+                    }
+                }
+            }
+            StmtKind::For(init, condition, step, body) => {
+                let scope = Rc::new(RefCell::new(Environment::new_enclosed(env.clone())));
+
+                self.eval_stmt(init, &scope)?;
+
+                while self.eval_condition(condition, &scope)? {
+                    match self.eval_scoped_block(body, &scope) {
+                        Err(Flow::Break(_)) => break,
+                        Ok(_) | Err(Flow::Continue(_)) => {}
+                        Err(flow) => return Err(flow),
+                    }
+
+                    self.eval_stmt(step, &scope)?;
+                }
+            }
+            StmtKind::Fn(decl) => {
+                let function = Object::Function(Rc::new(Function {
+                    decl: decl.clone(),
+                    env: env.clone(),
+                }));
+
+                env.borrow_mut().define(&decl.name, function);
+            }
+            StmtKind::Struct(decl) => {
+                env.borrow_mut().define(&decl.name, Object::StructType(Rc::new(decl.clone())));
+            }
+            StmtKind::Enum(decl) => {
+                env.borrow_mut().define(&decl.name, Object::EnumType(Rc::new(decl.clone())));
+            }
+            StmtKind::Import(module) => match builtins::module(module.name.as_str()) {
+                Some(value) => env.borrow_mut().define(module, value),
+                None => {
+                    return Err(error(format!("unknown module `{}`", module.name), module.position));
+                }
+            },
+            StmtKind::Expr(expr) => return self.eval_expr(expr, env),
+        }
+
+        Ok(Object::Void)
+    }
+
+    fn eval_condition(&mut self, condition: &Expr, env: &Env) -> Result<bool, Flow> {
+        match self.eval_expr(condition, env)? {
+            Object::Boolean(value) => Ok(value),
+            other => Err(error(
+                format!("expected a bool condition, found {}", other.type_name()),
+                condition.position,
+            )),
+        }
+    }
+
+    /// Stores a value into an assignable place. Values are copied, so
+    /// assigning to a field or element rebuilds the enclosing value and
+    /// assigns that in turn.
+    fn assign(&mut self, target: &Expr, value: Object, env: &Env) -> Result<(), Flow> {
+        match &target.kind {
+            ExprKind::Identifier(ident) => {
+                if ident.binding.is_some_and(|id| env.borrow_mut().assign(id, value)) {
+                    Ok(())
+                } else {
+                    Err(error(format!("cannot find value `{}` in this scope", ident.name), ident.position))
+                }
+            }
+            ExprKind::Field(object, field) => match self.eval_expr(object, env)? {
+                Object::Struct(name, mut fields) => {
+                    match fields.iter_mut().find(|(field_name, _)| field_name == &field.name) {
+                        Some((_, slot)) => *slot = value,
+                        None => {
+                            return Err(error(
+                                format!("no field `{}` on struct `{}`", field.name, name),
+                                field.position,
+                            ));
+                        }
+                    }
+
+                    self.assign(object, Object::Struct(name, fields), env)
+                }
+                other => Err(error(
+                    format!("cannot assign to field `{}` of {}", field.name, other.type_name()),
+                    field.position,
+                )),
+            },
+            ExprKind::Index(object, index) => {
+                let index_value = self.eval_expr(index, env)?;
+
+                match self.eval_expr(object, env)? {
+                    Object::Array(mut elements) => {
+                        let i = array_index(&index_value, elements.len(), index.position)?;
+                        elements[i] = value;
+
+                        self.assign(object, Object::Array(elements), env)
+                    }
+                    other => Err(error(
+                        format!("cannot assign to an index of {}", other.type_name()),
+                        target.position,
+                    )),
+                }
+            }
+            _ => Err(error("invalid left-hand side of assignment".to_owned(), target.position)),
+        }
+    }
+
+    fn eval_expr(&mut self, expr: &Expr, env: &Env) -> EvalResult {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(error("ran out of fuel".to_owned(), expr.position));
+            }
+
+            *fuel -= 1;
+        }
+
+        let value = match &expr.kind {
+            ExprKind::Identifier(ident) => match lookup(ident, env) {
+                Some(value) => value,
+                None => {
+                    return Err(error(
+                        format!("cannot find value `{}` in this scope", ident.name),
+                        ident.position,
+                    ));
+                }
+            },
+            ExprKind::IntegerLiteral(value) => Object::Integer(*value),
+            ExprKind::FloatLiteral(value) => Object::Float(*value),
+            ExprKind::StringLiteral(value) => Object::Str(value.clone()),
+            ExprKind::Interpolated(parts) => {
+                let mut value = String::new();
+
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => value.push_str(text),
+                        // Values are shown the same way as by `std.to_string`
+                        StringPart::Expr(expr) => value.push_str(&self.eval_expr(expr, env)?.to_string()),
+                    }
+                }
+
+                Object::Str(value)
+            }
+            ExprKind::CharLiteral(value) => Object::Char(*value),
+            ExprKind::BooleanLiteral(value) => Object::Boolean(*value),
+            ExprKind::ArrayLiteral(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+
+                for element in elements {
+                    values.push(self.eval_expr(element, env)?);
+                }
+
+                Object::Array(values)
+            }
+            ExprKind::StructLiteral(name, fields) => self.eval_struct_literal(name, fields, env)?,
+            ExprKind::Prefix(op, right) => {
+                let right = self.eval_expr(right, env)?;
+                fit(eval_prefix(*op, right, expr.position)?, &expr.ty, expr.position)?
+            }
+            ExprKind::Infix(InfixOp::And, left, right) => {
+                Object::Boolean(self.eval_condition(left, env)? && self.eval_condition(right, env)?)
+            }
+            ExprKind::Infix(InfixOp::Or, left, right) => {
+                Object::Boolean(self.eval_condition(left, env)? || self.eval_condition(right, env)?)
+            }
+            ExprKind::Infix(op, left, right) => {
+                let left = self.eval_expr(left, env)?;
+                let right = self.eval_expr(right, env)?;
+                fit(eval_infix(*op, left, right, expr.position)?, &expr.ty, expr.position)?
+            }
+            ExprKind::Call(function, args) => {
+                let function = self.eval_expr(function, env)?;
+
+                let mut values = Vec::with_capacity(args.len());
+
+                for arg in args {
+                    values.push(self.eval_expr(arg, env)?);
+                }
+
+                let value = self.apply_function(function, values, expr.position)?;
+                fit(value, &expr.ty, expr.position)?
+            }
+            ExprKind::Index(object, index) => {
+                let object = self.eval_expr(object, env)?;
+                let index_value = self.eval_expr(index, env)?;
+
+                match object {
+                    Object::Array(elements) => {
+                        let i = array_index(&index_value, elements.len(), index.position)?;
+                        elements[i].clone()
+                    }
+                    Object::Str(value) => {
+                        let chars: Vec<char> = value.chars().collect();
+                        let i = array_index(&index_value, chars.len(), index.position)?;
+                        Object::Char(chars[i])
+                    }
+                    other => {
+                        return Err(error(format!("cannot index into {}", other.type_name()), expr.position));
+                    }
+                }
+            }
+            ExprKind::Field(object, field) => {
+                let object = self.eval_expr(object, env)?;
+                eval_field(object, field)?
+            }
+            ExprKind::Block(block) => self.eval_scoped_block(block, env)?,
+            ExprKind::If(condition, consequence, alternative) => {
+                if self.eval_condition(condition, env)? {
+                    self.eval_scoped_block(consequence, env)?
+                } else if let Some(alternative) = alternative {
+                    self.eval_expr(alternative, env)?
+                } else {
+                    Object::Void
+                }
+            }
+            ExprKind::Match(subject, arms) => {
+                let value = self.eval_expr(subject, env)?;
+
+                for arm in arms {
+                    let mut bindings = Vec::new();
+
+                    if self.match_pattern(&arm.pattern, &value, &mut bindings, env)? {
+                        let scope = Rc::new(RefCell::new(Environment::new_enclosed(env.clone())));
+
+                        for (name, value) in bindings {
+                            scope.borrow_mut().define(name, value);
+                        }
+
+                        return self.eval_expr(&arm.body, &scope);
+                    }
+                }
+
+                return Err(error(
+                    format!("no match arm matches the value {}", value.inspect()),
+                    expr.position,
+                ));
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn eval_struct_literal(
+        &mut self,
+        name: &Identifier,
+        fields: &[(Identifier, Expr)],
+        env: &Env,
+    ) -> Result<Object, Flow> {
+        let decl = match lookup(name, env) {
+            Some(Object::StructType(decl)) => decl,
+            Some(other) => {
+                return Err(error(
+                    format!("`{}` is a {}, not a struct", name.name, other.type_name()),
+                    name.position,
+                ));
+            }
+            None => {
+                return Err(error(format!("cannot find struct `{}` in this scope", name.name), name.position));
+            }
+        };
+
+        for (field, _) in fields {
+            if !decl.fields.iter().any(|param| param.name.name == field.name) {
+                return Err(error(
+                    format!("struct `{}` has no field named `{}`", name.name, field.name),
+                    field.position,
+                ));
+            }
+        }
+
+        let mut values = Vec::with_capacity(decl.fields.len());
+
+        for param in &decl.fields {
+            let value = match fields.iter().find(|(field, _)| field.name == param.name.name) {
+                Some((_, value)) => self.eval_expr(value, env)?,
+                None => {
+                    return Err(error(
+                        format!("missing field `{}` in initializer of `{}`", param.name.name, name.name),
+                        name.position,
+                    ));
+                }
+            };
+
+            values.push((param.name.name.clone(), value));
+        }
+
+        Ok(Object::Struct(name.name.clone(), values))
+    }
+
+    fn match_pattern<'p>(
+        &mut self,
+        pattern: &'p Pattern,
+        value: &Object,
+        bindings: &mut Vec<(&'p Identifier, Object)>,
+        env: &Env,
+    ) -> Result<bool, Flow> {
+        match pattern {
+            Pattern::Wildcard(_) => Ok(true),
+            Pattern::Binding(name) => {
+                bindings.push((name, value.clone()));
+                Ok(true)
+            }
+            Pattern::Literal(literal) => {
+                let literal = self.eval_expr(literal, env)?;
+                Ok(literal.equals(value))
+            }
+            Pattern::Variant(enum_name, variant, fields, _) => {
+                let decl = match lookup(enum_name, env) {
+                    Some(Object::EnumType(decl)) => decl,
+                    _ => {
+                        return Err(error(
+                            format!("cannot find enum `{}` in this scope", enum_name.name),
+                            enum_name.position,
+                        ));
+                    }
+                };
+
+                let declared = match decl.variants.iter().find(|v| v.name.name == variant.name) {
+                    Some(declared) => declared,
+                    None => {
+                        return Err(error(
+                            format!("no variant named `{}` in enum `{}`", variant.name, enum_name.name),
+                            variant.position,
+                        ));
+                    }
+                };
+
+                if declared.fields.len() != fields.len() {
+                    return Err(error(
+                        format!(
+                            "variant `{}.{}` has {} field(s) but the pattern has {}",
+                            enum_name.name,
+                            variant.name,
+                            declared.fields.len(),
+                            fields.len()
+                        ),
+                        variant.position,
+                    ));
+                }
+
+                match value {
+                    Object::Variant(value_enum, value_variant, values)
+                        if value_enum == &enum_name.name && value_variant == &variant.name =>
+                    {
+                        for (field, value) in fields.iter().zip(values) {
+                            if !self.match_pattern(field, value, bindings, env)? {
+                                return Ok(false);
+                            }
+                        }
+
+                        Ok(true)
+                    }
+                    _ => Ok(false),
+                }
+            }
+        }
+    }
+
+    fn apply_function(&mut self, function: Object, args: Vec<Object>, position: BufferPosition) -> EvalResult {
+        match function {
+            Object::Function(function) => {
+                let decl = &function.decl;
+
+                if decl.params.len() != args.len() {
+                    return Err(error(
+                        format!(
+                            "function `{}` takes {} argument(s) but {} were supplied",
+                            decl.name.name,
+                            decl.params.len(),
+                            args.len()
+                        ),
+                        position,
+                    ));
+                }
+
+                if self.call_depth >= MAX_CALL_DEPTH {
+                    return Err(error(
+                        format!("stack overflow while calling `{}`", decl.name.name),
+                        position,
+                    ));
+                }
+
+                let scope = Rc::new(RefCell::new(Environment::new_enclosed(function.env.clone())));
+
+                for (param, arg) in decl.params.iter().zip(args) {
+                    scope.borrow_mut().define(&param.name, arg);
+                }
+
+                self.call_depth += 1;
+                let result = self.eval_block(&decl.body, &scope);
+                self.call_depth -= 1;
+
+                let value = match result {
+                    Ok(value) | Err(Flow::Return(value, _)) => value,
+                    Err(flow) => return Err(Flow::Error(flow.into_error())),
+                };
+
+                match &decl.return_type {
+                    Some(TypeExpr::Named(name)) if name.name != "void" => Ok(value),
+                    Some(TypeExpr::Array(..)) => Ok(value),
+                    _ => Ok(Object::Void),
+                }
+            }
+            Object::Builtin(builtin) => (builtin.function)(args).map_err(|message| error(message, position)),
+            Object::VariantConstructor(decl, index) => {
+                let variant = &decl.variants[index];
+
+                if variant.fields.len() != args.len() {
+                    return Err(error(
+                        format!(
+                            "variant `{}.{}` takes {} argument(s) but {} were supplied",
+                            decl.name.name,
+                            variant.name.name,
+                            variant.fields.len(),
+                            args.len()
+                        ),
+                        position,
+                    ));
+                }
+
+                Ok(Object::Variant(decl.name.name.clone(), variant.name.name.clone(), args))
+            }
+            other => Err(error(format!("{} is not callable", other.type_name()), position)),
+        }
+    }
+}
+
+fn is_declaration(stmt: &Stmt) -> bool {
+    matches!(stmt.kind, StmtKind::Fn(_) | StmtKind::Struct(_) | StmtKind::Enum(_))
+}
+
+/// The value a name refers to. Names the resolver couldn't find are unbound.
+fn lookup(name: &Identifier, env: &Env) -> Option<Object> {
+    name.binding.and_then(|id| env.borrow().get(id))
+}
+
+fn error(message: String, position: BufferPosition) -> Flow {
+    Flow::Error(RuntimeError::new(message, position))
+}
+
+/// Checks that an integer fits the type the checker gave the expression
+/// producing it. Integers of every type are `i64`s here, so this is what
+/// makes `i32` arithmetic overflow where compiled code does.
+fn fit(value: Object, ty: &Option<Type>, position: BufferPosition) -> Result<Object, Flow> {
+    match (&value, ty) {
+        (Object::Integer(n), Some(Type::I32)) if i64::from(*n as i32) != *n => {
+            Err(error("integer overflow".to_owned(), position))
+        }
+        _ => Ok(value),
+    }
+}
+
+fn array_index(index: &Object, len: usize, position: BufferPosition) -> Result<usize, Flow> {
+    match index {
+        Object::Integer(i) if *i >= 0 && (*i as usize) < len => Ok(*i as usize),
+        Object::Integer(i) => Err(error(
+            format!("index out of bounds: the length is {} but the index is {}", len, i),
+            position,
+        )),
+        other => Err(error(format!("index must be an integer, found {}", other.type_name()), position)),
+    }
+}
+
+fn eval_field(object: Object, field: &Identifier) -> Result<Object, Flow> {
+    let missing = |object: &Object| {
+        error(
+            format!("no field `{}` on {}", field.name, object.type_name()),
+            field.position,
+        )
+    };
+
+    match &object {
+        Object::Struct(_, fields) => fields
+            .iter()
+            .find(|(name, _)| name == &field.name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| missing(&object)),
+        Object::Module(module) => module
+            .members
+            .iter()
+            .find(|(name, _)| name == &field.name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| {
+                error(
+                    format!("cannot find `{}` in module `{}`", field.name, module.name),
+                    field.position,
+                )
+            }),
+        Object::EnumType(decl) => match decl.variants.iter().position(|v| v.name.name == field.name) {
+            Some(index) if decl.variants[index].fields.is_empty() => Ok(Object::Variant(
+                decl.name.name.clone(),
+                field.name.clone(),
+                Vec::new(),
+            )),
+            Some(index) => Ok(Object::VariantConstructor(decl.clone(), index)),
+            None => Err(error(
+                format!("no variant named `{}` in enum `{}`", field.name, decl.name.name),
+                field.position,
+            )),
+        },
+        _ => Err(missing(&object)),
+    }
+}
+
+/// Applies a prefix operator the way a running program would, for a result
+/// of the type `ty` the checker gave it, so constants can be folded ahead of
+/// time.
+pub(crate) fn apply_prefix(
+    op: PrefixOp,
+    right: Object,
+    ty: &Option<Type>,
+    position: BufferPosition,
+) -> Result<Object, RuntimeError> {
+    eval_prefix(op, right, position)
+        .and_then(|value| fit(value, ty, position))
+        .map_err(Flow::into_error)
+}
+
+/// Like `apply_prefix`, for the operators other than `&&` and `||`.
+pub(crate) fn apply_infix(
+    op: InfixOp,
+    left: Object,
+    right: Object,
+    ty: &Option<Type>,
+    position: BufferPosition,
+) -> Result<Object, RuntimeError> {
+    eval_infix(op, left, right, position)
+        .and_then(|value| fit(value, ty, position))
+        .map_err(Flow::into_error)
+}
+
+fn eval_prefix(op: PrefixOp, right: Object, position: BufferPosition) -> Result<Object, Flow> {
+    match (op, right) {
+        (PrefixOp::Neg, Object::Integer(value)) => value
+            .checked_neg()
+            .map(Object::Integer)
+            .ok_or_else(|| error("integer overflow".to_owned(), position)),
+        (PrefixOp::Neg, Object::Float(value)) => Ok(Object::Float(-value)),
+        (PrefixOp::Not, Object::Boolean(value)) => Ok(Object::Boolean(!value)),
+        (op, right) => Err(error(
+            format!("cannot apply unary operator `{}` to {}", op.as_str(), right.type_name()),
+            position,
+        )),
+    }
+}
+
+fn eval_infix(op: InfixOp, left: Object, right: Object, position: BufferPosition) -> Result<Object, Flow> {
+    let value = match (&left, &right) {
+        (Object::Integer(a), Object::Integer(b)) => return eval_integer_infix(op, *a, *b, position),
+        (Object::Float(a), Object::Float(b)) => match op {
+            InfixOp::Add => Object::Float(a + b),
+            InfixOp::Sub => Object::Float(a - b),
+            InfixOp::Mul => Object::Float(a * b),
+            InfixOp::Div => Object::Float(a / b),
+            InfixOp::Mod => Object::Float(a % b),
+            InfixOp::Lt => Object::Boolean(a < b),
+            InfixOp::Gt => Object::Boolean(a > b),
+            InfixOp::LtEq => Object::Boolean(a <= b),
+            InfixOp::GtEq => Object::Boolean(a >= b),
+            InfixOp::Eq => Object::Boolean(a == b),
+            InfixOp::NotEq => Object::Boolean(a != b),
+            InfixOp::And | InfixOp::Or => return Err(unsupported(op, &left, &right, position)),
+        },
+        (Object::Str(_), _) | (_, Object::Str(_)) if op == InfixOp::Add => {
+            Object::Str(format!("{}{}", left, right))
+        }
+        (Object::Str(a), Object::Str(b)) => match compare(op, a, b) {
+            Some(value) => Object::Boolean(value),
+            None => return Err(unsupported(op, &left, &right, position)),
+        },
+        (Object::Char(a), Object::Char(b)) => match compare(op, a, b) {
+            Some(value) => Object::Boolean(value),
+            None => return Err(unsupported(op, &left, &right, position)),
+        },
+        _ => match op {
+            InfixOp::Eq => Object::Boolean(left.equals(&right)),
+            InfixOp::NotEq => Object::Boolean(!left.equals(&right)),
+            _ => return Err(unsupported(op, &left, &right, position)),
+        },
+    };
+
+    Ok(value)
+}
+
+fn eval_integer_infix(op: InfixOp, a: i64, b: i64, position: BufferPosition) -> Result<Object, Flow> {
+    let overflow = || error("integer overflow".to_owned(), position);
+
+    let value = match op {
+        InfixOp::Add => Object::Integer(a.checked_add(b).ok_or_else(overflow)?),
+        InfixOp::Sub => Object::Integer(a.checked_sub(b).ok_or_else(overflow)?),
+        InfixOp::Mul => Object::Integer(a.checked_mul(b).ok_or_else(overflow)?),
+        InfixOp::Div | InfixOp::Mod if b == 0 => {
+            return Err(error("attempt to divide by zero".to_owned(), position));
+        }
+        InfixOp::Div => Object::Integer(a.checked_div(b).ok_or_else(overflow)?),
+        InfixOp::Mod => Object::Integer(a.checked_rem(b).ok_or_else(overflow)?),
+        InfixOp::Lt => Object::Boolean(a < b),
+        InfixOp::Gt => Object::Boolean(a > b),
+        InfixOp::LtEq => Object::Boolean(a <= b),
+        InfixOp::GtEq => Object::Boolean(a >= b),
+        InfixOp::Eq => Object::Boolean(a == b),
+        InfixOp::NotEq => Object::Boolean(a != b),
+        InfixOp::And | InfixOp::Or => {
+            return Err(unsupported(op, &Object::Integer(a), &Object::Integer(b), position));
+        }
+    };
+
+    Ok(value)
+}
+
+fn compare<T: PartialOrd + ?Sized>(op: InfixOp, a: &T, b: &T) -> Option<bool> {
+    match op {
+        InfixOp::Lt => Some(a < b),
+        InfixOp::Gt => Some(a > b),
+        InfixOp::LtEq => Some(a <= b),
+        InfixOp::GtEq => Some(a >= b),
+        InfixOp::Eq => Some(a == b),
+        InfixOp::NotEq => Some(a != b),
+        _ => None,
+    }
+}
+
+fn unsupported(op: InfixOp, left: &Object, right: &Object, position: BufferPosition) -> Flow {
+    error(
+        format!(
+            "cannot apply binary operator `{}` to {} and {}",
+            op.as_str(),
+            left.type_name(),
+            right.type_name()
+        ),
+        position,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn eval_with(
+        resolver: &mut Resolver,
+        evaluator: &mut Evaluator,
+        input: &str,
+    ) -> Result<Object, RuntimeError> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let mut program = parser.parse();
+
+        assert!(
+            parser.errors().is_empty(),
+            "Parser reported errors: {:?}",
+            parser.errors()
+        );
+
+        // Names that don't resolve are left for the evaluator to report
+        let _ = resolver.resolve_program(&mut program);
+
+        evaluator.eval_program(&program)
+    }
+
+    fn eval(input: &str) -> Object {
+        match eval_with(&mut Resolver::new(), &mut Evaluator::new(), input) {
+            Ok(value) => value,
+            Err(err) => panic!("evaluation of {:?} failed: {}", input, err),
+        }
+    }
+
+    fn eval_error(input: &'static str) -> String {
+        // Runs on its own thread since the test harness' default stack is
+        // too small to reach the call depth limit.
+        let handle = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || match eval_with(&mut Resolver::new(), &mut Evaluator::new(), input) {
+                Ok(value) => panic!("evaluation of {:?} succeeded with {}", input, value.inspect()),
+                Err(err) => err.message,
+            })
+            .unwrap();
+
+        handle.join().unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let tests = vec![
+            ("5", "5"),
+            ("-20 - 15", "-35"),
+            ("15 * 88", "1320"),
+            ("15 / 2", "7"),
+            ("15 % 4", "3"),
+            ("1.5 * 2.0", "3.0"),
+            ("(1 + 2) * 3 == 9", "true"),
+            ("!(1 < 2) || 'a' < 'b'", "true"),
+            ("\"fib is \" + 5", "\"fib is 5\""),
+            ("let x = 2\n\"{x} * 3 / 2 = {x * 3 / 2}\"", "\"2 * 3 / 2 = 3\""),
+            ("\"{[1, 2]} {'c'} {\"{true}\"}\"", "\"[1, 2] c true\""),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval(input).inspect(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_functions_and_control_flow() {
+        let input = "
+        fn fib(n: i32): i32 {
+            if n < 2 {
+                return n
+            }
+
+            fib(n - 1) + fib(n - 2)
+        }
+
+        let total = 0
+        for let i = 0; i < 10; i++ {
+            if i % 2 == 0 { continue }
+            total += fib(i)
+        }
+
+        total
+        ";
+
+        // fib(1) + fib(3) + fib(5) + fib(7) + fib(9)
+        assert_eq!(eval(input).inspect(), "55");
+    }
+
+    #[test]
+    fn test_structs_enums_and_match() {
+        let input = "
+        struct Point { x: i32, y: i32 }
+        enum Shape { Circle(i32), Rect(Point), Empty }
+
+        fn area(shape: Shape): i32 {
+            match shape {
+                Shape.Circle(r) => 3 * r * r
+                Shape.Rect(p) => p.x * p.y
+                Shape.Empty => 0
+            }
+        }
+
+        let p = Point { x: 2, y: 3 }
+        p.x = 4
+        [area(Shape.Circle(2)), area(Shape.Rect(p)), area(Shape.Empty)]
+        ";
+
+        assert_eq!(eval(input).inspect(), "[12, 12, 0]");
+    }
+
+    #[test]
+    fn test_global_environment_persists() {
+        let (mut resolver, mut evaluator) = (Resolver::new(), Evaluator::new());
+
+        eval_with(&mut resolver, &mut evaluator, "let x = 5").unwrap();
+        eval_with(&mut resolver, &mut evaluator, "fn double(n: i32): i32 { n * 2 }").unwrap();
+
+        let value = eval_with(&mut resolver, &mut evaluator, "double(x)").unwrap();
+
+        assert_eq!(value.inspect(), "10");
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let tests = vec![
+            ("missing + 1", "cannot find value `missing` in this scope"),
+            ("1 / 0", "attempt to divide by zero"),
+            ("1 + true", "cannot apply binary operator `+` to integer and bool"),
+            ("break", "`break` outside of a loop"),
+            ("[1, 2][2]", "index out of bounds: the length is 2 but the index is 2"),
+            ("fn f(): i32 { f() }\nf()", "stack overflow while calling `f`"),
+            ("9223372036854775807 + 1", "integer overflow"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval_error(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_i32_overflow() {
+        let tests = vec![
+            ("let x: i32 = 2147483647\nx + 1", Err("integer overflow")),
+            ("let x: i32 = -2147483647 - 1\n-x", Err("integer overflow")),
+            ("let x: i32 = 2147483647\nx++", Err("integer overflow")),
+            ("import std\nlet x: i32 = -2147483647 - 1\nstd.abs(x)", Err("integer overflow")),
+            ("let x: i64 = 2147483647\nx + 1", Ok("2147483648")),
+        ];
+
+        for (input, expected) in tests {
+            // Integer types are only known once the program is checked
+            let mut parser = Parser::new(Lexer::new(input));
+            let mut program = parser.parse();
+            Resolver::new().resolve_program(&mut program).unwrap();
+            Checker::new().check_program(&mut program).unwrap();
+
+            let result = Evaluator::new().eval_program(&program);
+            let result = result.as_ref().map(Object::inspect).map_err(|error| error.message.as_str());
+
+            assert_eq!(result, expected.map(str::to_owned), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_fuel() {
+        let mut evaluator = Evaluator::with_fuel(1000);
+        let error = eval_with(&mut Resolver::new(), &mut evaluator, "while true {}").unwrap_err();
+
+        assert_eq!(error.message, "ran out of fuel");
+
+        let mut evaluator = Evaluator::with_fuel(1000);
+        assert_eq!(eval_with(&mut Resolver::new(), &mut evaluator, "1 + 2").unwrap().inspect(), "3");
+    }
+}
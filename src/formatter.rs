@@ -0,0 +1,886 @@
+//! Prints a program back out as canonically formatted K source. Comments
+//! aren't part of the syntax tree, so they are taken from the lexer and put
+//! back between the statements, fields and match arms they sit next to.
+
+use crate::ast::*;
+use crate::lexer::{Comment, Lexer};
+use crate::parser::{self, ParseError, Parser, Precedence};
+use crate::pretty::{self, concat, group, if_break, join, nest, text, Doc};
+use crate::token::{BufferPosition, SymbolKind, Token, TokenKind};
+use icu_properties::props::GeneralCategory;
+use icu_properties::CodePointMapData;
+
+pub const DEFAULT_WIDTH: usize = 100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FormatConfig {
+    /// The column lines are wrapped at when possible.
+    pub width: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> FormatConfig {
+        FormatConfig { width: DEFAULT_WIDTH }
+    }
+}
+
+/// Formats a source file, failing if it doesn't parse.
+pub fn format_source(source: &str, config: &FormatConfig) -> Result<String, Vec<ParseError>> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse();
+
+    if !parser.errors().is_empty() {
+        return Err(parser.errors().to_vec());
+    }
+
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+    let mut verbatim = Vec::new();
+
+    while let Some((token, text)) = lexer.next_spanned() {
+        match token.kind {
+            TokenKind::Newline => continue,
+            TokenKind::StringLiteral(_) if text.starts_with('r') || text.starts_with("\"\"\"") => {
+                verbatim.push((token.position, text))
+            }
+            _ => {}
+        }
+
+        tokens.push(token);
+    }
+
+    let mut formatter = Formatter {
+        lines: source.lines().collect(),
+        comments: lexer.comments().to_vec(),
+        next_comment: 0,
+        tokens,
+        verbatim,
+    };
+
+    let items = formatter.items(&program.0, None, Formatter::stmt_position, Formatter::stmt);
+
+    if items.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut output = pretty::render(&items.doc, config.width);
+    output.push('\n');
+
+    Ok(output)
+}
+
+// Binding strength of an expression, used to decide where parentheses are
+// needed. Taken from the parser's precedence table.
+const PREFIX: u8 = Precedence::Prefix as u8;
+const POSTFIX: u8 = Precedence::Call as u8;
+const PRIMARY: u8 = POSTFIX + 1;
+
+fn infix_precedence(op: InfixOp) -> u8 {
+    parser::infix_precedence(op) as u8
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Infix(op, _, _) => infix_precedence(*op),
+        ExprKind::Prefix(..) => PREFIX,
+        ExprKind::Call(..) | ExprKind::Index(..) | ExprKind::Field(..) => POSTFIX,
+        _ => PRIMARY,
+    }
+}
+
+fn before(a: BufferPosition, b: BufferPosition) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
+
+/// Whether a character is better written as an escape, since it is hard or
+/// impossible to see: control and format characters like zero-width spaces
+/// and bidirectional overrides, line and paragraph separators, and combining
+/// marks, which would otherwise join the character before them.
+fn is_invisible(ch: char) -> bool {
+    matches!(
+        CodePointMapData::<GeneralCategory>::new().get(ch),
+        GeneralCategory::Control
+            | GeneralCategory::Format
+            | GeneralCategory::LineSeparator
+            | GeneralCategory::ParagraphSeparator
+            | GeneralCategory::NonspacingMark
+            | GeneralCategory::SpacingMark
+            | GeneralCategory::EnclosingMark
+    )
+}
+
+fn escape(ch: char, quote: char) -> String {
+    match ch {
+        '\\' => "\\\\".to_owned(),
+        '\n' => "\\n".to_owned(),
+        '\t' => "\\t".to_owned(),
+        '\r' => "\\r".to_owned(),
+        '\x0C' => "\\f".to_owned(),
+        '\x0B' => "\\v".to_owned(),
+        ch if ch == quote => format!("\\{}", quote),
+        '{' if quote == '"' => "\\{".to_owned(),
+        ch if ch.is_ascii_control() => format!("\\x{:02X}", ch as u32),
+        ch if is_invisible(ch) => format!("\\u{{{:X}}}", ch as u32),
+        ch => ch.to_string(),
+    }
+}
+
+fn float_literal(value: f64) -> String {
+    let literal = value.to_string();
+
+    // Keep the fractional part so the literal is still read as a float
+    if literal.contains('.') {
+        literal
+    } else {
+        format!("{}.0", literal)
+    }
+}
+
+/// Items laid out one per line, along with the comments between them.
+struct Items {
+    doc: Doc,
+    count: usize,
+    has_comments: bool,
+    // A comment following the opening brace on the same line
+    opening_comment: Option<String>,
+}
+
+impl Items {
+    fn is_empty(&self) -> bool {
+        self.count == 0 && !self.has_comments
+    }
+}
+
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    tokens: Vec<Token>,
+    // Raw and multi-line string literals, which are printed as written
+    verbatim: Vec<(BufferPosition, &'a str)>,
+}
+
+impl<'a> Formatter<'a> {
+    /// Takes the comments that come before `position`, or every comment
+    /// that's left.
+    fn take_comments(&mut self, position: Option<BufferPosition>) -> Vec<Comment> {
+        let start = self.next_comment;
+
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if position.is_some_and(|position| !before(comment.position, position)) {
+                break;
+            }
+
+            self.next_comment += 1;
+        }
+
+        self.comments[start..self.next_comment].to_vec()
+    }
+
+    fn blank_line_before(&self, position: BufferPosition) -> bool {
+        position.line >= 2
+            && self
+                .lines
+                .get(position.line - 2)
+                .is_some_and(|line| line.trim().is_empty())
+    }
+
+    /// Finds the brace closing a block. `last` is the position of the last
+    /// item in the block; for an empty block the search starts at `from`
+    /// and first skips over the opening brace.
+    fn closing_brace(&self, from: BufferPosition, last: Option<BufferPosition>) -> Option<BufferPosition> {
+        let start = last.unwrap_or(from);
+        let index = self.tokens.partition_point(|token| before(token.position, start));
+        let mut opened = last.is_some();
+        let mut depth = 0;
+
+        for token in &self.tokens[index..] {
+            match &token.kind {
+                TokenKind::Symbol(SymbolKind::LeftBrace) if !opened && depth == 0 => opened = true,
+                TokenKind::Symbol(SymbolKind::LeftBrace)
+                | TokenKind::Symbol(SymbolKind::LeftParen)
+                | TokenKind::Symbol(SymbolKind::LeftBracket) => depth += 1,
+                TokenKind::Symbol(SymbolKind::RightBrace)
+                | TokenKind::Symbol(SymbolKind::RightParen)
+                | TokenKind::Symbol(SymbolKind::RightBracket) => {
+                    if depth == 0 {
+                        return Some(token.position);
+                    }
+
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Lays out items one per line, keeping single blank lines from the
+    /// source and the comments before each item and before `end`.
+    fn items<T, P, F>(&mut self, items: &[T], end: Option<BufferPosition>, position: P, mut format: F) -> Items
+    where
+        P: Fn(&T) -> Option<BufferPosition>,
+        F: FnMut(&mut Self, &T) -> Doc,
+    {
+        let mut lines: Vec<Doc> = Vec::new();
+        let mut count = 0;
+        let mut has_comments = false;
+        let mut opening_comment = None;
+        // The line the previous item or comment started on
+        let mut last_line = None;
+
+        let positions: Vec<Option<BufferPosition>> = items.iter().map(&position).collect();
+        let anchors = positions.into_iter().map(Some).chain(std::iter::once(None));
+
+        for (i, anchor) in anchors.enumerate() {
+            let anchor = match anchor {
+                Some(Some(position)) => Some(position),
+                // Items without a position, like blank statements, are skipped
+                Some(None) => continue,
+                None => end,
+            };
+
+            for comment in self.take_comments(anchor) {
+                has_comments = true;
+
+                if comment.trailing {
+                    match lines.last_mut() {
+                        Some(line) => {
+                            *line = concat(vec![line.clone(), Doc::Suffix(format!(" {}", comment.text))])
+                        }
+                        None => {
+                            opening_comment = match opening_comment.take() {
+                                Some(previous) => Some(format!("{} {}", previous, comment.text)),
+                                None => Some(comment.text),
+                            }
+                        }
+                    }
+
+                    continue;
+                }
+
+                if !lines.is_empty() && self.blank_line_before(comment.position) {
+                    lines.push(Doc::Nil);
+                }
+
+                last_line = Some(comment.position.line);
+                lines.push(text(comment.text));
+            }
+
+            if let (Some(item), Some(position)) = (items.get(i), anchor) {
+                // Items that shared a line in the source are kept together
+                if !lines.is_empty() && self.blank_line_before(position) && last_line != Some(position.line) {
+                    lines.push(Doc::Nil);
+                }
+
+                last_line = Some(position.line);
+                lines.push(format(self, item));
+                count += 1;
+            }
+        }
+
+        Items {
+            doc: join(lines, Doc::HardLine),
+            count,
+            has_comments,
+            opening_comment,
+        }
+    }
+
+    /// Wraps items in braces. Unless `always_break` is set, a single item
+    /// without comments may stay on the same line as the braces.
+    fn braced(&self, items: Items, always_break: bool) -> Doc {
+        if items.is_empty() {
+            return text("{}");
+        }
+
+        let opening = match items.opening_comment {
+            Some(comment) => concat(vec![text("{"), Doc::Suffix(format!(" {}", comment))]),
+            None => text("{"),
+        };
+
+        let single_line = !always_break && items.count == 1 && !items.has_comments;
+        let line = if single_line { Doc::Line } else { Doc::HardLine };
+
+        let doc = concat(vec![opening, nest(concat(vec![line.clone(), items.doc])), line, text("}")]);
+
+        if single_line {
+            group(doc)
+        } else {
+            doc
+        }
+    }
+
+    fn block(&mut self, block: &BlockStmt, from: BufferPosition, always_break: bool) -> Doc {
+        let end = self.closing_brace(from, block.last().map(|stmt| stmt.position));
+        let items = self.items(block, end, Formatter::stmt_position, Formatter::stmt);
+
+        self.braced(items, always_break)
+    }
+
+    fn stmt_position(stmt: &Stmt) -> Option<BufferPosition> {
+        match stmt.kind {
+            StmtKind::Blank => None,
+            _ => Some(stmt.position),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Doc {
+        match &stmt.kind {
+            StmtKind::Blank => Doc::Nil,
+            StmtKind::Let(name, ty, value) => {
+                let ty = match ty {
+                    Some(ty) => concat(vec![text(": "), self.ty(ty)]),
+                    None => Doc::Nil,
+                };
+
+                concat(vec![text("let "), text(name.name.as_str()), ty, text(" = "), self.expr(value, false)])
+            }
+            StmtKind::Assign(target, op, value) => concat(vec![
+                self.expr(target, false),
+                text(format!(" {} ", op.as_str())),
+                self.expr(value, false),
+            ]),
+            StmtKind::Increment(target) => concat(vec![self.expr(target, false), text("++")]),
+            StmtKind::Decrement(target) => concat(vec![self.expr(target, false), text("--")]),
+            StmtKind::Return(Some(value)) => concat(vec![text("return "), self.expr(value, false)]),
+            StmtKind::Return(None) => text("return"),
+            StmtKind::Break => text("break"),
+            StmtKind::Continue => text("continue"),
+            StmtKind::While(condition, body) => concat(vec![
+                text("while "),
+                self.expr(condition, true),
+                text(" "),
+                self.block(body, condition.position, true),
+            ]),
+            StmtKind::For(init, condition, step, body) => concat(vec![
+                text("for "),
+                self.stmt(init),
+                text("; "),
+                self.expr(condition, true),
+                text("; "),
+                self.simple_stmt(step),
+                text(" "),
+                self.block(body, step.position, true),
+            ]),
+            StmtKind::Fn(decl) => self.fn_decl(decl),
+            StmtKind::Struct(decl) => self.struct_decl(decl),
+            StmtKind::Enum(decl) => self.enum_decl(decl),
+            StmtKind::Import(module) => text(format!("import {}", module.name)),
+            StmtKind::Expr(expr) => self.expr(expr, false),
+        }
+    }
+
+    /// The step of a `for` loop, which is parsed with struct literals
+    /// restricted like the condition.
+    fn simple_stmt(&mut self, stmt: &Stmt) -> Doc {
+        match &stmt.kind {
+            StmtKind::Assign(target, op, value) => concat(vec![
+                self.expr(target, true),
+                text(format!(" {} ", op.as_str())),
+                self.expr(value, true),
+            ]),
+            StmtKind::Expr(expr) => self.expr(expr, true),
+            _ => self.stmt(stmt),
+        }
+    }
+
+    fn fn_decl(&mut self, decl: &FnDecl) -> Doc {
+        let params = decl.params.iter().map(|param| self.param(param)).collect();
+
+        let return_type = match &decl.return_type {
+            Some(ty) => concat(vec![text(": "), self.ty(ty)]),
+            None => Doc::Nil,
+        };
+
+        concat(vec![
+            text("fn "),
+            text(decl.name.name.as_str()),
+            self.list("(", params, ")"),
+            return_type,
+            text(" "),
+            self.block(&decl.body, decl.name.position, true),
+        ])
+    }
+
+    fn struct_decl(&mut self, decl: &StructDecl) -> Doc {
+        let last = decl.fields.last().map(|field| field.name.position);
+        let end = self.closing_brace(decl.name.position, last);
+
+        let fields = self.items(&decl.fields, end, |field| Some(field.name.position), |formatter, field| {
+            concat(vec![formatter.param(field), text(",")])
+        });
+
+        concat(vec![text(format!("struct {} ", decl.name.name)), self.braced(fields, true)])
+    }
+
+    fn enum_decl(&mut self, decl: &EnumDecl) -> Doc {
+        let last = decl.variants.last().map(|variant| variant.name.position);
+        let end = self.closing_brace(decl.name.position, last);
+
+        let variants = self.items(&decl.variants, end, |variant| Some(variant.name.position), |formatter, variant| {
+            concat(vec![formatter.variant(variant), text(",")])
+        });
+
+        concat(vec![text(format!("enum {} ", decl.name.name)), self.braced(variants, true)])
+    }
+
+    fn param(&mut self, param: &Param) -> Doc {
+        concat(vec![text(param.name.name.as_str()), text(": "), self.ty(&param.ty)])
+    }
+
+    fn variant(&mut self, variant: &Variant) -> Doc {
+        if variant.fields.is_empty() {
+            return text(variant.name.name.as_str());
+        }
+
+        let fields = variant.fields.iter().map(|ty| self.ty(ty)).collect();
+
+        concat(vec![text(variant.name.name.as_str()), self.list("(", fields, ")")])
+    }
+
+    fn ty(&mut self, ty: &TypeExpr) -> Doc {
+        match ty {
+            TypeExpr::Named(name) => text(name.name.as_str()),
+            TypeExpr::Array(element, _) => concat(vec![text("["), self.ty(element), text("]")]),
+        }
+    }
+
+    /// A delimited, comma separated list that puts each item on its own line
+    /// when it doesn't fit on one.
+    fn list(&self, open: &str, items: Vec<Doc>, close: &str) -> Doc {
+        if items.is_empty() {
+            return text(format!("{}{}", open, close));
+        }
+
+        group(concat(vec![
+            text(open),
+            nest(concat(vec![
+                Doc::SoftLine,
+                join(items, concat(vec![text(","), Doc::Line])),
+                if_break(text(",")),
+            ])),
+            Doc::SoftLine,
+            text(close),
+        ]))
+    }
+
+    /// Formats an expression. `restricted` is set in the heads of `if`,
+    /// `while`, `for` and `match`, where a struct literal needs parentheses
+    /// so that its brace isn't taken for the start of the body.
+    fn expr(&mut self, expr: &Expr, restricted: bool) -> Doc {
+        match &expr.kind {
+            ExprKind::Identifier(name) => text(name.name.as_str()),
+            ExprKind::IntegerLiteral(value) => text(value.to_string()),
+            ExprKind::FloatLiteral(value) => text(float_literal(*value)),
+            ExprKind::StringLiteral(value) => {
+                let verbatim = self.verbatim.iter().find(|(position, _)| *position == expr.position);
+
+                if let Some((_, literal)) = verbatim {
+                    return text(*literal);
+                }
+
+                let escaped: String = value.chars().map(|ch| escape(ch, '"')).collect();
+                text(format!("\"{}\"", escaped))
+            }
+            ExprKind::Interpolated(parts) => {
+                let mut docs = vec![text("\"")];
+
+                for part in parts {
+                    match part {
+                        StringPart::Text(value) => {
+                            docs.push(text(value.chars().map(|ch| escape(ch, '"')).collect::<String>()))
+                        }
+                        StringPart::Expr(expr) => docs.extend(vec![text("{"), self.expr(expr, false), text("}")]),
+                    }
+                }
+
+                docs.push(text("\""));
+                concat(docs)
+            }
+            ExprKind::CharLiteral(value) => text(format!("'{}'", escape(*value, '\''))),
+            ExprKind::BooleanLiteral(value) => text(value.to_string()),
+            ExprKind::ArrayLiteral(elements) => {
+                let elements = elements.iter().map(|element| self.expr(element, restricted)).collect();
+                self.list("[", elements, "]")
+            }
+            ExprKind::StructLiteral(name, fields) => {
+                let literal = self.struct_literal(name, fields);
+
+                if restricted {
+                    concat(vec![text("("), literal, text(")")])
+                } else {
+                    literal
+                }
+            }
+            ExprKind::Prefix(op, operand) => {
+                // `- -x` must not run together into `--x`
+                let operand_doc = match &operand.kind {
+                    ExprKind::Prefix(PrefixOp::Neg, _) if *op == PrefixOp::Neg => self.parenthesized(operand),
+                    _ => self.operand(operand, PREFIX, restricted),
+                };
+
+                concat(vec![text(op.as_str()), operand_doc])
+            }
+            ExprKind::Infix(op, _, _) => self.infix(expr, *op, restricted),
+            ExprKind::Call(callee, args) => {
+                let args = args.iter().map(|arg| self.expr(arg, restricted)).collect();
+                concat(vec![self.operand(callee, POSTFIX, restricted), self.list("(", args, ")")])
+            }
+            ExprKind::Index(target, index) => concat(vec![
+                self.operand(target, POSTFIX, restricted),
+                text("["),
+                self.expr(index, restricted),
+                text("]"),
+            ]),
+            ExprKind::Field(target, field) => concat(vec![
+                self.operand(target, POSTFIX, restricted),
+                text("."),
+                text(field.name.as_str()),
+            ]),
+            ExprKind::Block(block) => self.block(block, expr.position, false),
+            ExprKind::If(..) => self.if_expr(expr),
+            ExprKind::Match(subject, arms) => self.match_expr(subject, arms),
+        }
+    }
+
+    fn parenthesized(&mut self, expr: &Expr) -> Doc {
+        concat(vec![text("("), self.expr(expr, false), text(")")])
+    }
+
+    /// Formats an operand, adding parentheses if it binds more loosely than
+    /// `min_precedence`.
+    fn operand(&mut self, expr: &Expr, min_precedence: u8, restricted: bool) -> Doc {
+        if precedence(expr) < min_precedence {
+            self.parenthesized(expr)
+        } else {
+            self.expr(expr, restricted)
+        }
+    }
+
+    /// Formats a chain of operators with the same precedence as one group,
+    /// breaking after the operators so the lines continue the statement.
+    fn infix(&mut self, expr: &Expr, op: InfixOp, restricted: bool) -> Doc {
+        let level = infix_precedence(op);
+
+        let mut operands = Vec::new();
+        let mut current = expr;
+
+        while let ExprKind::Infix(op, left, right) = &current.kind {
+            if infix_precedence(*op) != level {
+                break;
+            }
+
+            operands.push((*op, right.as_ref()));
+            current = left;
+        }
+
+        let first = self.operand(current, level, restricted);
+        let mut rest = Vec::new();
+
+        for (op, right) in operands.into_iter().rev() {
+            rest.push(text(format!(" {}", op.as_str())));
+            rest.push(Doc::Line);
+            // Operators associate to the left, so an equal right operand
+            // needs parentheses
+            rest.push(self.operand(right, level + 1, restricted));
+        }
+
+        group(concat(vec![first, nest(concat(rest))]))
+    }
+
+    fn struct_literal(&mut self, name: &Identifier, fields: &[(Identifier, Expr)]) -> Doc {
+        if fields.is_empty() {
+            return text(format!("{} {{}}", name.name));
+        }
+
+        let fields = fields
+            .iter()
+            .map(|(field, value)| concat(vec![text(format!("{}: ", field.name)), self.expr(value, false)]))
+            .collect();
+
+        group(concat(vec![
+            text(format!("{} {{", name.name)),
+            nest(concat(vec![
+                Doc::Line,
+                join(fields, concat(vec![text(","), Doc::Line])),
+                if_break(text(",")),
+            ])),
+            Doc::Line,
+            text("}"),
+        ]))
+    }
+
+    fn if_expr(&mut self, expr: &Expr) -> Doc {
+        let mut parts = Vec::new();
+        let mut current = expr;
+
+        loop {
+            match &current.kind {
+                ExprKind::If(condition, consequence, alternative) => {
+                    parts.push(text("if "));
+                    parts.push(self.expr(condition, true));
+                    parts.push(text(" "));
+                    parts.push(self.block(consequence, condition.position, false));
+
+                    match alternative {
+                        Some(alternative) => {
+                            parts.push(text(" else "));
+                            current = alternative;
+                        }
+                        None => break,
+                    }
+                }
+                ExprKind::Block(block) => {
+                    parts.push(self.block(block, current.position, false));
+                    break;
+                }
+                _ => {
+                    parts.push(self.expr(current, false));
+                    break;
+                }
+            }
+        }
+
+        concat(parts)
+    }
+
+    fn match_expr(&mut self, subject: &Expr, arms: &[MatchArm]) -> Doc {
+        let subject_doc = self.expr(subject, true);
+
+        let end = self.closing_brace(subject.position, arms.last().map(|arm| arm.pattern.position()));
+
+        let arms = self.items(arms, end, |arm| Some(arm.pattern.position()), |formatter, arm| {
+            concat(vec![formatter.pattern(&arm.pattern), text(" => "), formatter.expr(&arm.body, false)])
+        });
+
+        concat(vec![text("match "), subject_doc, text(" "), self.braced(arms, true)])
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Doc {
+        match pattern {
+            Pattern::Wildcard(_) => text("_"),
+            Pattern::Literal(literal) => self.expr(literal, false),
+            Pattern::Binding(name) => text(name.name.as_str()),
+            Pattern::Variant(enum_name, variant, fields, _) => {
+                let name = text(format!("{}.{}", enum_name.name, variant.name));
+
+                if fields.is_empty() {
+                    return name;
+                }
+
+                let fields = fields.iter().map(|field| self.pattern(field)).collect();
+                concat(vec![name, self.list("(", fields, ")")])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn format(source: &str) -> String {
+        format_width(source, DEFAULT_WIDTH)
+    }
+
+    fn format_width(source: &str, width: usize) -> String {
+        let output = format_source(source, &FormatConfig { width }).expect("source should parse");
+        let again = format_source(&output, &FormatConfig { width }).expect("output should parse");
+
+        assert_eq!(output, again, "formatting is not idempotent");
+        assert_eq!(strip_positions(source), strip_positions(&output), "formatting changed the program");
+
+        output
+    }
+
+    /// The syntax tree of a program with every position removed.
+    fn strip_positions(source: &str) -> String {
+        let tree = format!("{:?}", Parser::new(Lexer::new(source)).parse());
+        let mut stripped = String::new();
+        let mut rest = tree.as_str();
+
+        while let Some(index) = rest.find("BufferPosition {") {
+            stripped.push_str(&rest[..index]);
+            rest = &rest[index..];
+            rest = &rest[rest.find('}').unwrap() + 1..];
+        }
+
+        stripped.push_str(rest);
+        stripped
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        let tests = vec![
+            ("let x=1+2*3;", "let x = 1 + 2 * 3\n"),
+            ("let a = 1; let b = 2;;", "let a = 1\nlet b = 2\n"),
+            ("x = (1 + 2) * -(3 - 4)", "x = (1 + 2) * -(3 - 4)\n"),
+            ("x = a - (b - c) - d", "x = a - (b - c) - d\n"),
+            ("x = - -y", "x = -(-y)\n"),
+            ("fn add(a:i32,b:i32):i32{return a+b}", "fn add(a: i32, b: i32): i32 {\n    return a + b\n}\n"),
+            ("fn main(): void {}", "fn main(): void {}\n"),
+            ("let y = if a {1} else if b {2} else {3}", "let y = if a { 1 } else if b { 2 } else { 3 }\n"),
+            ("if (Point {x: 1}).x > 0 { f() }", "if (Point { x: 1 }).x > 0 { f() }\n"),
+            ("struct P { x: i32, y: i32 }", "struct P {\n    x: i32,\n    y: i32,\n}\n"),
+            ("enum E { A(i32, f64), B }", "enum E {\n    A(i32, f64),\n    B,\n}\n"),
+            (
+                "match e { E.A(x, _) => x, -1 => { 0 }\n _ => 2 }",
+                "match e {\n    E.A(x, _) => x\n    -1 => { 0 }\n    _ => 2\n}\n",
+            ),
+            ("for let i = 0; i < 10; i++ { x += i; y-- }", "for let i = 0; i < 10; i++ {\n    x += i\n    y--\n}\n"),
+            ("let s = \"a\\tb\\\"c\\n\"; let c = '\\''", "let s = \"a\\tb\\\"c\\n\"\nlet c = '\\''\n"),
+            ("let e = \"\\x7F\\u{1F600}\\u{85}\"", "let e = \"\\x7F\u{1F600}\\u{85}\"\n"),
+            ("let z = \"a\u{200B}\u{202E}\\u{2028}\"", "let z = \"a\\u{200B}\\u{202E}\\u{2028}\"\n"),
+            ("let m = '\u{301}' + \"\u{20DD}{x}\u{903}\"", "let m = '\\u{301}' + \"\\u{20DD}{x}\\u{903}\"\n"),
+            ("let p = r#\"C:\\\"dir\"\"#", "let p = r#\"C:\\\"dir\"\"#\n"),
+            (
+                "fn f() {\nlet q = \"\"\"\n  SELECT \\\"x\"\n  \"\"\"}",
+                "fn f() {\n    let q = \"\"\"\n  SELECT \\\"x\"\n  \"\"\"\n}\n",
+            ),
+            ("let i = \"x={ x+1 }\\{{\"{y}\"}\"", "let i = \"x={x + 1}\\{{\"{y}\"}\"\n"),
+            ("let j = \"{{\n1\n}}\" + \"\\{\"", "let j = \"{{ 1 }}\" + \"\\{\"\n"),
+            ("let f = 2.0 * 1.5", "let f = 2.0 * 1.5\n"),
+            ("let xs: [[i32]] = [[1], []]", "let xs: [[i32]] = [[1], []]\n"),
+            ("", ""),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(format(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let input = "import std\n\n\n\nfn main(): void {\n\n    let a = 1\n\n    let b = 2\n\n}\n";
+        let expected = "import std\n\nfn main(): void {\n    let a = 1\n\n    let b = 2\n}\n";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// header
+
+import std // the standard library
+
+/*
+    block
+*/
+fn main(): void { // entry point
+    // leading
+    let a = [1, // one
+        2]
+
+    // at the end
+}
+
+struct Empty {
+    // nothing yet
+}
+
+match x {
+    // first
+    1 => 2 // one
+    _ => 3
+}
+// footer
+";
+
+        let expected = "// header
+
+import std // the standard library
+
+/*
+    block
+*/
+fn main(): void { // entry point
+    // leading
+    let a = [1, 2] // one
+
+    // at the end
+}
+
+struct Empty {
+    // nothing yet
+}
+
+match x {
+    // first
+    1 => 2 // one
+    _ => 3
+}
+// footer
+";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_line_wrapping() {
+        let input = "let total = first_value + second_value + third_value\nresult = compute(alpha, beta, gamma)";
+
+        assert_eq!(
+            format_width(input, 30),
+            "let total = first_value +
+    second_value +
+    third_value
+result = compute(
+    alpha,
+    beta,
+    gamma,
+)
+"
+        );
+
+        assert_eq!(format_width(input, 60), format(input));
+    }
+
+    #[test]
+    fn test_narrow_widths() {
+        let input = r#"import std
+struct Point { x: i32, y: i32 } // a point
+enum Shape { Circle(f64), Rect(f64, f64), Empty, }
+fn area(s: Shape): f64 {
+  match s { Shape.Circle(r) => 3.14 * r * r, Shape.Rect(w, h) => w * h
+    Shape.Empty => 0.0 }
+}
+fn very_long_function_name(first_argument: i32, second_argument: i32, third_argument: [i32]): i32 {
+  let total = first_argument * second_argument + third_argument[0] * first_argument - second_argument / 2 + 1000000
+  while total > 0 && (total % 2 == 0 || total % 3 == 0) { total -= 1 }
+  for let i = 0; i < len(third_argument); i++ { if third_argument[i] > total { continue } else { total += third_argument[i] } }
+  let p = Point { x: total, y: -total }
+  if !(p.x == p.y) { return p.x } /* trailing */
+  {
+    let inner = { 1 }
+    inner
+  }
+}
+let nested = [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12], [13, 14, 15], [16, 17, 18], [19, 20, 21]]
+"#;
+
+        // Whatever the width, the output must keep its meaning and be stable
+        for width in &[10, 20, 40, 60, DEFAULT_WIDTH] {
+            format_width(input, *width);
+        }
+    }
+
+    #[test]
+    fn test_samples() {
+        for entry in fs::read_dir("samples").unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_some_and(|extension| extension == "k") {
+                format(&fs::read_to_string(&path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let errors = format_source("let = 5", &FormatConfig::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,629 @@
+//! Keeps the tokens and syntax tree of a buffer up to date as it is edited.
+//! An edit only re-lexes the text from the token it touches up to the first
+//! token boundary after it that the old tokens share, and if the changed
+//! tokens all sit inside one block, only that block is parsed again. The
+//! rest of the tree is shared with the previous version.
+
+use crate::cst::{GreenElement, GreenNode, NodeKind, SyntaxNode};
+use crate::cst_parser;
+use crate::lexer::{LexError, Lexer};
+use crate::parser::ParseError;
+use crate::token::{BufferPosition, LosslessToken, SymbolKind, Token, TokenKind};
+use std::ops::Range;
+use std::rc::Rc;
+
+// How many bytes past a re-lexed token must be in the window for it to be
+// trusted, since the lexer looks a character or two ahead
+const LOOKAHEAD: usize = 8;
+
+// The amount of unchanged text re-lexed along with an edit at first
+const INITIAL_WINDOW: usize = 256;
+
+/// Replaces the bytes in `range` with `text`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Edit {
+    pub fn new(range: Range<usize>, text: &str) -> Edit {
+        Edit {
+            range,
+            text: text.to_owned(),
+        }
+    }
+}
+
+pub struct Document {
+    text: String,
+    line_starts: Vec<usize>,
+    tokens: Vec<LosslessToken>,
+    // Where each token starts, counting its leading trivia
+    offsets: Vec<usize>,
+    // Whether each token is inside an expression embedded in a string,
+    // where the lexer can't start from scratch
+    interpolated: Vec<bool>,
+    lex_errors: Vec<LexError>,
+    parse_errors: Vec<ParseError>,
+    green: Rc<GreenNode>,
+}
+
+// The nodes on the way down to a node, each with the index of the next one
+// among its children
+type Path = Vec<(Rc<GreenNode>, usize)>;
+
+/// The tokens re-lexed for an edit, and the old tokens they replace.
+struct Relex {
+    tokens: Vec<LosslessToken>,
+    interpolated: Vec<bool>,
+    errors: Vec<LexError>,
+    replaced: Range<usize>,
+    // Where the replaced tokens end, before and after the edit
+    old_end: (usize, BufferPosition),
+    new_end: (usize, BufferPosition),
+}
+
+fn full_len(token: &LosslessToken) -> usize {
+    let trivia = token.leading.iter().chain(&token.trailing);
+    token.text.len() + trivia.map(|trivia| trivia.text.len()).sum::<usize>()
+}
+
+/// Where each token starts, if the first one starts at `start`.
+fn token_offsets(tokens: &[LosslessToken], start: usize) -> Vec<usize> {
+    tokens
+        .iter()
+        .scan(start, |offset, token| {
+            let start = *offset;
+            *offset += full_len(token);
+            Some(start)
+        })
+        .collect()
+}
+
+fn is_before(a: BufferPosition, b: BufferPosition) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
+
+/// Moves a position that comes after an edit, given where the text after
+/// the edit started before and after it.
+fn shift(position: BufferPosition, old: BufferPosition, new: BufferPosition) -> BufferPosition {
+    if position.line == old.line {
+        BufferPosition::new(new.line, position.column - old.column + new.column)
+    } else {
+        BufferPosition::new(position.line - old.line + new.line, position.column)
+    }
+}
+
+/// Turns a position relative to `base` into an absolute one.
+fn rebase(position: BufferPosition, base: BufferPosition) -> BufferPosition {
+    shift(position, BufferPosition::new(1, 1), base)
+}
+
+impl Document {
+    pub fn new(text: &str) -> Document {
+        let mut lexer = Lexer::new(text);
+        let mut tokens = Vec::new();
+        let mut interpolated = Vec::new();
+
+        loop {
+            interpolated.push(lexer.is_interpolating());
+
+            let token = lexer.next_lossless();
+            let done = token.token.kind == TokenKind::EOF;
+
+            tokens.push(token);
+
+            if done {
+                break;
+            }
+        }
+
+        let offsets = token_offsets(&tokens, 0);
+
+        let parse = cst_parser::parse_tokens(&tokens, Vec::new());
+
+        Document {
+            text: text.to_owned(),
+            line_starts: std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+                .collect(),
+            tokens,
+            offsets,
+            interpolated,
+            lex_errors: lexer.errors().to_vec(),
+            parse_errors: parse.errors().to_vec(),
+            green: parse.green().clone(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Where each line starts.
+    pub fn line_starts(&self) -> &[usize] {
+        &self.line_starts
+    }
+
+    pub fn tokens(&self) -> &[LosslessToken] {
+        &self.tokens
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.green
+    }
+
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    /// The errors of both the lexer and the parser, in source order.
+    pub fn errors(&self) -> Vec<ParseError> {
+        let mut errors: Vec<ParseError> = self
+            .lex_errors
+            .iter()
+            .map(|error| ParseError::new(error.kind.to_string(), error.position))
+            .chain(self.parse_errors.iter().cloned())
+            .collect();
+
+        errors.sort_by_key(|error| (error.position.line, error.position.column));
+        errors
+    }
+
+    /// Applies an edit, leaving the document as if its new text had been
+    /// parsed from scratch.
+    ///
+    /// Panics if the range is out of bounds or splits a character, like
+    /// `String::replace_range`.
+    pub fn apply(&mut self, edit: &Edit) {
+        let Range { start, end } = edit.range;
+
+        assert!(start <= end && end <= self.text.len(), "edit out of bounds");
+        assert!(self.text.is_char_boundary(start) && self.text.is_char_boundary(end));
+
+        let relex = self.relex(edit);
+        let replaced = relex.replaced.clone();
+        let (old_end, old_position) = relex.old_end;
+        let (new_end, new_position) = relex.new_end;
+        let relex_start = self.offsets[replaced.start];
+
+        // Only the block around the edit needs parsing again, if there is
+        // one. The old tree is searched before the tokens change.
+        let block = self.enclosing_block(relex_start, old_end);
+
+        let moved = |position: BufferPosition| {
+            if is_before(position, old_position) {
+                position
+            } else {
+                shift(position, old_position, new_position)
+            }
+        };
+
+        // The errors of the re-lexed tokens are replaced
+        let relex_position = self.position(relex_start);
+
+        self.lex_errors
+            .retain(|error| is_before(error.position, relex_position) || !is_before(error.position, old_position));
+
+        for error in &mut self.lex_errors {
+            error.position = moved(error.position);
+        }
+
+        self.lex_errors.extend(relex.errors);
+        self.lex_errors.sort_by_key(|error| (error.position.line, error.position.column));
+
+        // So are the parser's errors inside the block, if it gets parsed
+        // again. If it doesn't, all of them are.
+        if let Some((_, open, close)) = &block {
+            let open = self.tokens[*open].token.position;
+            let close = self.tokens[*close].token.position;
+
+            self.parse_errors
+                .retain(|error| !is_before(open, error.position) || is_before(close, error.position));
+        }
+
+        for error in &mut self.parse_errors {
+            error.position = moved(error.position);
+        }
+
+        for token in &mut self.tokens[replaced.end..] {
+            // Without new or removed lines, only the rest of the line moves
+            if old_position.line == new_position.line && token.token.position.line != old_position.line {
+                break;
+            }
+
+            token.token.position = moved(token.token.position);
+        }
+
+        for offset in &mut self.offsets[replaced.end..] {
+            *offset = *offset - old_end + new_end;
+        }
+
+        let new_offsets = token_offsets(&relex.tokens, relex_start);
+
+        let count = relex.tokens.len();
+        self.offsets.splice(replaced.clone(), new_offsets);
+        self.interpolated.splice(replaced.clone(), relex.interpolated);
+        self.tokens.splice(replaced.clone(), relex.tokens);
+
+        self.edit_text(edit);
+
+        let reparsed = block.is_some_and(|(path, open, close)| {
+            // The closing brace was not re-lexed, so it has moved along
+            // with the tokens after the edit
+            let close = close - replaced.len() + count;
+            self.reparse_block(path, open..close + 1)
+        });
+
+        if !reparsed {
+            self.reparse();
+        }
+    }
+
+    /// The position of a byte offset in the current text.
+    fn position(&self, offset: usize) -> BufferPosition {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let column = self.text[self.line_starts[line]..offset].chars().count() + 1;
+
+        BufferPosition::new(line + 1, column)
+    }
+
+    /// Re-lexes the text around an edit, before it is made. Lexing starts
+    /// near the first token the edit touches, and stops once a token ends
+    /// where an old token after the edit starts: from there on the old
+    /// tokens are still right. Only a window of the text after the edit is
+    /// copied for the lexer, and the window grows until the tokens line up.
+    fn relex(&self, edit: &Edit) -> Relex {
+        let Range { start, end } = edit.range;
+
+        // Lexing starts a token before the first one whose trivia reaches
+        // the edit: text added right after a token may become part of it,
+        // and a comment added at the start of a token may trail the one
+        // before it. It can't start inside a string either.
+        let mut first = self.offsets[1..].partition_point(|offset| *offset < start).saturating_sub(1);
+
+        while self.interpolated[first] {
+            first -= 1;
+        }
+
+        let relex_start = self.offsets[first];
+        let base = self.position(relex_start);
+
+        let mut tail = INITIAL_WINDOW;
+
+        loop {
+            let mut window_end = (end + tail).min(self.text.len());
+
+            while !self.text.is_char_boundary(window_end) {
+                window_end += 1;
+            }
+
+            let complete = window_end == self.text.len();
+            let window = [&self.text[relex_start..start], &edit.text, &self.text[end..window_end]].concat();
+
+            let mut lexer = Lexer::new(&window);
+            let mut tokens = Vec::new();
+            let mut interpolated = Vec::new();
+            let mut errors = Vec::new();
+            let mut boundary = 0;
+
+            loop {
+                let error_count = lexer.errors().len();
+                let nested = lexer.is_interpolating();
+                let token = lexer.next_lossless();
+                let token_end = boundary + full_len(&token);
+
+                if !complete && token_end + LOOKAHEAD > window.len() {
+                    break;
+                }
+
+                let done = token.token.kind == TokenKind::EOF;
+
+                errors.extend(lexer.errors()[error_count..].iter().map(|error| LexError {
+                    kind: error.kind.clone(),
+                    position: rebase(error.position, base),
+                }));
+
+                tokens.push(LosslessToken {
+                    token: Token::new(token.token.kind, rebase(token.token.position, base)),
+                    ..token
+                });
+                interpolated.push(nested);
+
+                boundary = token_end;
+
+                let new_end = relex_start + boundary;
+                let new_position = rebase(lexer.current_position(), base);
+
+                if done {
+                    return Relex {
+                        tokens,
+                        interpolated,
+                        errors,
+                        replaced: first..self.tokens.len(),
+                        old_end: (self.text.len(), self.position(self.text.len())),
+                        new_end: (new_end, new_position),
+                    };
+                }
+
+                if new_end < start + edit.text.len() {
+                    continue;
+                }
+
+                let old_end = new_end - start - edit.text.len() + end;
+
+                // Where `EOF` ends up depends on how the input ended, so the
+                // lexer always goes on up to it
+                if old_end == self.text.len() {
+                    continue;
+                }
+
+                // The lexer has to be in the same state as it was there too
+                if lexer.is_interpolating() {
+                    continue;
+                }
+
+                let index = self.offsets.binary_search(&old_end).ok();
+
+                if let Some(index) = index.filter(|index| !self.interpolated[*index]) {
+                    return Relex {
+                        tokens,
+                        interpolated,
+                        errors,
+                        replaced: first..index,
+                        old_end: (old_end, self.position(old_end)),
+                        new_end: (new_end, new_position),
+                    };
+                }
+            }
+
+            tail *= 2;
+        }
+    }
+
+    /// The innermost block whose braces are both outside of `start..end`,
+    /// as the path to it from the root, and the indices of its braces.
+    fn enclosing_block(&self, start: usize, end: usize) -> Option<(Path, usize, usize)> {
+        let mut path = Vec::new();
+        let mut node = self.green.clone();
+        let mut node_offset = 0;
+        let mut block = None;
+
+        'descend: loop {
+            let mut offset = node_offset;
+
+            for (index, child) in node.children().iter().enumerate() {
+                let len = child.full_len();
+
+                if offset <= start && end <= offset + len {
+                    let child = match child {
+                        GreenElement::Node(child) => child.clone(),
+                        GreenElement::Token(_) => break,
+                    };
+
+                    path.push((node, index));
+
+                    if let Some((open_end, close)) = braces(&child) {
+                        if offset + open_end <= start && end <= offset + close {
+                            block = Some((path.clone(), offset, offset + close));
+                        }
+                    }
+
+                    node = child;
+                    node_offset = offset;
+                    continue 'descend;
+                }
+
+                offset += len;
+            }
+
+            break;
+        }
+
+        block.map(|(path, open, close)| {
+            let index = |offset| self.offsets.binary_search(&offset).expect("not a token boundary");
+            (path, index(open), index(close))
+        })
+    }
+
+    /// Parses the tokens in `range` as the block at the end of `path`, and
+    /// puts the result in the tree if it spans exactly those tokens.
+    fn reparse_block(&mut self, path: Path, range: Range<usize>) -> bool {
+        let close = self.tokens[range.end - 1].token.position;
+
+        let mut tokens = self.tokens[range].to_vec();
+
+        tokens.push(LosslessToken {
+            token: Token::new(TokenKind::EOF, close),
+            text: String::new(),
+            leading: Vec::new(),
+            trailing: Vec::new(),
+        });
+
+        let (block, errors) = match cst_parser::parse_block(&tokens) {
+            Some(parsed) => parsed,
+            None => return false,
+        };
+
+        self.parse_errors.extend(errors);
+        self.parse_errors.sort_by_key(|error| (error.position.line, error.position.column));
+
+        let mut element = GreenElement::Node(block);
+
+        for (parent, index) in path.into_iter().rev() {
+            element = GreenElement::Node(Rc::new(parent.replace_child(index, element)));
+        }
+
+        if let GreenElement::Node(root) = element {
+            self.green = root;
+        }
+
+        true
+    }
+
+    fn reparse(&mut self) {
+        let parse = cst_parser::parse_tokens(&self.tokens, Vec::new());
+
+        self.green = parse.green().clone();
+        self.parse_errors = parse.errors().to_vec();
+    }
+
+    fn edit_text(&mut self, edit: &Edit) {
+        let Range { start, end } = edit.range;
+
+        self.text.replace_range(start..end, &edit.text);
+
+        let first = self.line_starts.partition_point(|line_start| *line_start <= start);
+        let last = self.line_starts.partition_point(|line_start| *line_start <= end);
+
+        for line_start in &mut self.line_starts[last..] {
+            *line_start = *line_start - end + start + edit.text.len();
+        }
+
+        let inserted = edit.text.match_indices('\n').map(|(index, _)| start + index + 1);
+        self.line_starts.splice(first..last, inserted);
+    }
+}
+
+/// Where the opening brace of a block ends and where its closing brace
+/// starts, relative to the block, if it is a block that has both.
+fn braces(node: &GreenNode) -> Option<(usize, usize)> {
+    if node.kind() != NodeKind::Block {
+        return None;
+    }
+
+    let is_token = |element: Option<&GreenElement>, symbol: SymbolKind| match element {
+        Some(GreenElement::Token(token)) => *token.kind() == TokenKind::Symbol(symbol),
+        _ => false,
+    };
+
+    let children = node.children();
+
+    if !is_token(children.first(), SymbolKind::LeftBrace) || !is_token(children.last(), SymbolKind::RightBrace) {
+        return None;
+    }
+
+    let open_len = children[0].full_len();
+    let close_len = children[children.len() - 1].full_len();
+
+    Some((open_len, node.len() - close_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst_parser::parse;
+
+    fn assert_matches_parse(document: &Document) {
+        let parse = parse(document.text());
+
+        assert_eq!(document.syntax().text(), document.text());
+        assert_eq!(document.tokens(), &Lexer::tokenize_lossless(document.text())[..], "{}", document.text());
+        assert_eq!(document.green(), parse.green(), "{}", document.text());
+        assert_eq!(document.errors(), parse.errors(), "{}", document.text());
+    }
+
+    #[test]
+    fn test_edits() {
+        let source = "fn f(x: i32): i32 {\n    let y = x * 2 // double\n    return y + 1\n}\n\nlet s = \"abc\"\n";
+        let edits = [
+            // Inside a token, at its end and across tokens
+            (source.find("x * 2").unwrap(), 1, "xy"),
+            (source.find("double").unwrap() - 3, 0, "+ 1"),
+            (source.find("return").unwrap(), 6, ""),
+            (source.find("let s").unwrap(), 3, "/* open"),
+            (source.find("+ 1").unwrap(), 0, "}"),
+            (source.find("{\n").unwrap() + 1, 0, "\n\"multi\nline"),
+            (source.find("\"abc").unwrap() + 1, 0, "é"),
+            (0, 0, "\n"),
+            (source.len(), 0, "x"),
+            (0, source.len(), ""),
+        ];
+
+        for &(start, len, text) in edits.iter() {
+            let mut document = Document::new(source);
+            document.apply(&Edit::new(start..start + len, text));
+            assert_matches_parse(&document);
+        }
+    }
+
+    #[test]
+    fn test_typing() {
+        let source = include_str!("../samples/fib.k");
+        let typed = "let z = [1, 2]\nif z[0] > 1 { print(\"big\") } // note\n";
+        let offset = source.find('{').unwrap() + 1;
+
+        let mut document = Document::new(source);
+
+        for (index, ch) in typed.char_indices() {
+            document.apply(&Edit::new(offset + index..offset + index, &ch.to_string()));
+            assert_matches_parse(&document);
+        }
+
+        for _ in typed.chars() {
+            document.apply(&Edit::new(offset..offset + 1, ""));
+            assert_matches_parse(&document);
+        }
+
+        assert_eq!(document.text(), source);
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let source = "fn f() {\n    let s = \"a{x + 1}b{ {y}\n}c\"\n    let t = \"d\"\n}\nlet u = 1\n";
+        let edits = [
+            // Inside an embedded expression, across its braces and in the
+            // text between them
+            (source.find("x + 1").unwrap(), 1, "xs"),
+            (source.find("+ 1").unwrap(), 0, "\""),
+            (source.find("1}").unwrap() + 1, 1, ""),
+            (source.find("{y}").unwrap(), 0, "}"),
+            (source.find("{y}").unwrap() + 1, 1, "{z}"),
+            (source.find("b{").unwrap(), 1, "\\"),
+            (source.find("\"d").unwrap() + 1, 0, "{"),
+            (source.find("\"d").unwrap() + 1, 0, "{}"),
+            (source.find("a{").unwrap(), 0, "\""),
+        ];
+
+        for &(start, len, text) in edits.iter() {
+            let mut document = Document::new(source);
+            document.apply(&Edit::new(start..start + len, text));
+            assert_matches_parse(&document);
+        }
+
+        let typed = "\"v = {v {w}\n} {\"{v}\"}\"";
+        let offset = source.find("let u").unwrap();
+        let mut document = Document::new(source);
+
+        for (index, ch) in typed.char_indices() {
+            document.apply(&Edit::new(offset + index..offset + index, &ch.to_string()));
+            assert_matches_parse(&document);
+        }
+    }
+
+    #[test]
+    fn test_reuses_other_items() {
+        let source = "fn f() {\n    1\n}\n\nfn g() {\n    2\n}\n";
+        let mut document = Document::new(source);
+        let items = |document: &Document| {
+            let children = document.green().children().iter();
+            children.filter(|child| matches!(child, GreenElement::Node(_))).cloned().collect::<Vec<_>>()
+        };
+
+        let before = items(&document);
+
+        let offset = source.find('2').unwrap();
+        document.apply(&Edit::new(offset..offset + 1, "3 + 4"));
+        assert_matches_parse(&document);
+
+        let after = items(&document);
+
+        match (&before[0], &after[0]) {
+            (GreenElement::Node(before), GreenElement::Node(after)) => assert!(Rc::ptr_eq(before, after)),
+            _ => panic!("expected a function"),
+        }
+
+        assert_ne!(before[1], after[1]);
+    }
+}
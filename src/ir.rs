@@ -0,0 +1,668 @@
+//! A typed SSA intermediate representation that backends are generated from.
+//!
+//! A module holds the program's structs, enums, globals and functions. Each
+//! function is a list of basic blocks, the first being its entry, whose
+//! parameters are the function's parameters. Every value is defined exactly
+//! once, either as a block parameter or by an instruction, and has a type.
+//! Values flow between blocks as arguments to the jumps that end them, in
+//! place of phi nodes.
+//!
+//! Aggregates are values, like in the evaluator: `set_index` and `set_field`
+//! build a copy with one element replaced. Integer arithmetic is checked at
+//! the width of its type, and indexing is bounds checked, so those
+//! instructions can stop the program with a runtime error.
+
+use crate::ast::{InfixOp, PrefixOp};
+use crate::symbol::Symbol;
+use crate::types::Type;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default)]
+pub struct FuncId(pub usize);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct GlobalId(pub usize);
+
+/// The variants of an enum, each with the types of its payload.
+pub type Variants = Vec<(Symbol, Vec<Type>)>;
+
+#[derive(Clone, Debug, Default)]
+pub struct Module {
+    pub structs: Vec<(Symbol, Vec<(Symbol, Type)>)>,
+    pub enums: Vec<(Symbol, Variants)>,
+    pub globals: Vec<(Symbol, Type)>,
+    pub functions: Vec<Function>,
+    // Runs the top level statements of the program and then calls `main`.
+    pub entry: FuncId,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub ret: Type,
+    pub blocks: Vec<Block>,
+    // The type of every value, indexed by the value's number.
+    pub types: Vec<Type>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub params: Vec<Value>,
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug)]
+pub struct Inst {
+    pub result: Option<Value>,
+    pub kind: InstKind,
+}
+
+/// A constant value. Floats are equal when their bits are, so `0.0` and
+/// `-0.0` differ while a NaN equals itself, which makes constants usable as
+/// keys.
+#[derive(Clone, Debug)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Callee {
+    Function(FuncId),
+    Builtin(Symbol),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InstKind {
+    Const(Constant),
+    Copy(Value),
+    Unary(PrefixOp, Value),
+    // Never `&&` or `||`, which are lowered to branches.
+    Binary(InfixOp, Value, Value),
+    ToString(Value),
+    Call(Callee, Vec<Value>),
+    Array(Vec<Value>),
+    Index(Value, Value),
+    SetIndex(Value, Value, Value),
+    // Fields are given in declaration order.
+    Struct(Symbol, Vec<Value>),
+    Field(Value, Symbol),
+    SetField(Value, Symbol, Value),
+    Variant(Symbol, Symbol, Vec<Value>),
+    IsVariant(Value, Symbol),
+    Payload(Value, Symbol, usize),
+    Load(GlobalId),
+    Store(GlobalId, Value),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub block: BlockId,
+    pub args: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+    Jump(Target),
+    Branch(Value, Target, Target),
+    Return(Option<Value>),
+    // Stops the program with a runtime error.
+    Trap(String),
+    // Ends blocks that can never run.
+    Unreachable,
+}
+
+impl Module {
+    pub fn function(&self, id: FuncId) -> &Function {
+        &self.functions[id.0]
+    }
+
+    pub fn struct_fields(&self, name: &Symbol) -> Option<&[(Symbol, Type)]> {
+        self.structs
+            .iter()
+            .find(|(declared, _)| declared == name)
+            .map(|(_, fields)| fields.as_slice())
+    }
+
+    pub fn enum_variants(&self, name: &Symbol) -> Option<&[(Symbol, Vec<Type>)]> {
+        self.enums
+            .iter()
+            .find(|(declared, _)| declared == name)
+            .map(|(_, variants)| variants.as_slice())
+    }
+}
+
+impl Function {
+    pub fn new(name: String, ret: Type) -> Function {
+        Function {
+            name,
+            ret,
+            blocks: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+
+    pub fn params(&self) -> &[Value] {
+        &self.blocks[0].params
+    }
+
+    pub fn param_types(&self) -> Vec<Type> {
+        self.params().iter().map(|param| self.value_type(*param).clone()).collect()
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    pub fn value_type(&self, value: Value) -> &Type {
+        &self.types[value.0]
+    }
+
+    pub fn new_value(&mut self, ty: Type) -> Value {
+        self.types.push(ty);
+        Value(self.types.len() - 1)
+    }
+
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.block(block).terminator.targets().iter().map(|target| target.block).collect()
+    }
+
+    /// The predecessors of every block. A block that branches to another
+    /// twice is listed twice.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            for target in block.terminator.targets() {
+                preds[target.block.0].push(BlockId(i));
+            }
+        }
+
+        preds
+    }
+
+    /// The blocks reachable from the entry in reverse postorder.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        // Each entry is a block and the number of its successors visited.
+        let mut stack = vec![(BlockId(0), 0)];
+        visited[0] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.successors(block);
+
+            match successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+
+                    if !visited[successor.0] {
+                        visited[successor.0] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    pub fn dominators(&self) -> Dominators {
+        Dominators::new(self)
+    }
+}
+
+/// The dominator tree of a function, found with the algorithm of Cooper,
+/// Harvey and Kennedy.
+pub struct Dominators {
+    idom: Vec<Option<BlockId>>,
+    order: Vec<BlockId>,
+    // The position of each reachable block in `order`.
+    rank: Vec<usize>,
+}
+
+impl Dominators {
+    fn new(function: &Function) -> Dominators {
+        let order = function.reverse_postorder();
+        let preds = function.predecessors();
+
+        let mut rank = vec![usize::MAX; function.blocks.len()];
+        for (i, block) in order.iter().enumerate() {
+            rank[block.0] = i;
+        }
+
+        let mut idom = vec![None; function.blocks.len()];
+        idom[0] = Some(BlockId(0));
+
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for &block in order.iter().skip(1) {
+                let mut new_idom: Option<BlockId> = None;
+
+                for &pred in &preds[block.0] {
+                    if idom[pred.0].is_none() {
+                        continue;
+                    }
+
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, &rank, pred, other),
+                    });
+                }
+
+                if new_idom.is_some() && idom[block.0] != new_idom {
+                    idom[block.0] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        Dominators { idom, order, rank }
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.rank[block.0] != usize::MAX
+    }
+
+    /// The immediate dominator of a reachable block other than the entry.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        match self.idom[block.0] {
+            Some(idom) if idom != block => Some(idom),
+            _ => None,
+        }
+    }
+
+    /// Whether every path from the entry to `b` passes through `a`.
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+
+        loop {
+            if a == b {
+                return true;
+            }
+
+            match self.idom(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// The reachable blocks in reverse postorder, so each block comes after
+    /// its dominators.
+    pub fn order(&self) -> &[BlockId] {
+        &self.order
+    }
+
+    /// The blocks immediately dominated by each block.
+    pub fn children(&self) -> Vec<Vec<BlockId>> {
+        let mut children = vec![Vec::new(); self.idom.len()];
+
+        for &block in &self.order {
+            if let Some(idom) = self.idom(block) {
+                children[idom.0].push(block);
+            }
+        }
+
+        children
+    }
+}
+
+fn intersect(idom: &[Option<BlockId>], rank: &[usize], mut a: BlockId, mut b: BlockId) -> BlockId {
+    while a != b {
+        while rank[a.0] > rank[b.0] {
+            a = idom[a.0].expect("processed blocks have a dominator");
+        }
+
+        while rank[b.0] > rank[a.0] {
+            b = idom[b.0].expect("processed blocks have a dominator");
+        }
+    }
+
+    a
+}
+
+impl Constant {
+    /// What the constant is compared and hashed by.
+    fn key(&self) -> (u8, u64, Option<&str>) {
+        match self {
+            Constant::Int(value) => (0, *value as u64, None),
+            Constant::Float(value) => (1, value.to_bits(), None),
+            Constant::Bool(value) => (2, u64::from(*value), None),
+            Constant::Char(value) => (3, u64::from(*value), None),
+            Constant::Str(value) => (4, 0, Some(value)),
+        }
+    }
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, other: &Constant) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Constant {}
+
+impl Hash for Constant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl InstKind {
+    pub fn operands(&self) -> Vec<Value> {
+        let mut operands = Vec::new();
+
+        match self {
+            InstKind::Const(_) | InstKind::Load(_) => {}
+            InstKind::Copy(value)
+            | InstKind::Unary(_, value)
+            | InstKind::ToString(value)
+            | InstKind::Field(value, _)
+            | InstKind::IsVariant(value, _)
+            | InstKind::Payload(value, _, _)
+            | InstKind::Store(_, value) => operands.push(*value),
+            InstKind::Binary(_, a, b) | InstKind::Index(a, b) | InstKind::SetField(a, _, b) => {
+                operands.extend([*a, *b].iter())
+            }
+            InstKind::SetIndex(a, b, c) => operands.extend([*a, *b, *c].iter()),
+            InstKind::Call(_, values)
+            | InstKind::Array(values)
+            | InstKind::Struct(_, values)
+            | InstKind::Variant(_, _, values) => operands.extend(values.iter()),
+        }
+
+        operands
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            InstKind::Const(_) | InstKind::Load(_) => Vec::new(),
+            InstKind::Copy(value)
+            | InstKind::Unary(_, value)
+            | InstKind::ToString(value)
+            | InstKind::Field(value, _)
+            | InstKind::IsVariant(value, _)
+            | InstKind::Payload(value, _, _)
+            | InstKind::Store(_, value) => vec![value],
+            InstKind::Binary(_, a, b) | InstKind::Index(a, b) | InstKind::SetField(a, _, b) => vec![a, b],
+            InstKind::SetIndex(a, b, c) => vec![a, b, c],
+            InstKind::Call(_, values)
+            | InstKind::Array(values)
+            | InstKind::Struct(_, values)
+            | InstKind::Variant(_, _, values) => values.iter_mut().collect(),
+        }
+    }
+
+    /// Whether the result depends only on the operands, so two copies of
+    /// the instruction with the same operands give the same value.
+    pub fn is_pure(&self) -> bool {
+        !matches!(self, InstKind::Call(..) | InstKind::Load(_) | InstKind::Store(..))
+    }
+
+    /// Whether the instruction can be dropped when its result is unused.
+    /// Instructions that may stop the program with an error are kept.
+    pub fn is_removable(&self, types: &[Type]) -> bool {
+        match self {
+            InstKind::Unary(PrefixOp::Neg, value) => !types[value.0].is_integer(),
+            InstKind::Binary(op, left, _) => {
+                let arithmetic = matches!(
+                    op,
+                    InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod
+                );
+
+                !(arithmetic && types[left.0].is_integer())
+            }
+            InstKind::Index(..) | InstKind::SetIndex(..) => false,
+            kind => kind.is_pure(),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn targets(&self) -> Vec<&Target> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Trap(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut Target> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Trap(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    /// Every value used by the terminator, including the arguments passed to
+    /// other blocks.
+    pub fn operands(&self) -> Vec<Value> {
+        let mut operands = match self {
+            Terminator::Branch(condition, _, _) => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            _ => Vec::new(),
+        };
+
+        for target in self.targets() {
+            operands.extend(target.args.iter());
+        }
+
+        operands
+    }
+
+    /// The values used by the terminator itself, not counting the arguments
+    /// passed to other blocks.
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Branch(condition, _, _) => vec![condition],
+            Terminator::Return(Some(value)) => vec![value],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, fields) in &self.structs {
+            let fields: Vec<String> = fields.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+            writeln!(f, "struct {} {{ {} }}", name, fields.join(", "))?;
+        }
+
+        for (name, variants) in &self.enums {
+            let variants: Vec<String> = variants
+                .iter()
+                .map(|(name, fields)| {
+                    if fields.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}({})", name, join(fields))
+                    }
+                })
+                .collect();
+
+            writeln!(f, "enum {} {{ {} }}", name, variants.join(", "))?;
+        }
+
+        for (i, (name, ty)) in self.globals.iter().enumerate() {
+            writeln!(f, "global ${} {}: {}", i, name, ty)?;
+        }
+
+        for (i, function) in self.functions.iter().enumerate() {
+            let items = self.structs.len() + self.enums.len() + self.globals.len();
+
+            if i > 0 || items > 0 {
+                writeln!(f)?;
+            }
+
+            write_function(f, self, function)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_function(f: &mut fmt::Formatter, module: &Module, function: &Function) -> fmt::Result {
+    writeln!(f, "fn @{}({}): {} {{", function.name, join(&function.param_types()), function.ret)?;
+
+    for (i, block) in function.blocks.iter().enumerate() {
+        write!(f, "bb{}", i)?;
+
+        if !block.params.is_empty() {
+            let params: Vec<String> = block
+                .params
+                .iter()
+                .map(|param| format!("%{}: {}", param.0, function.value_type(*param)))
+                .collect();
+
+            write!(f, "({})", params.join(", "))?;
+        }
+
+        writeln!(f, ":")?;
+
+        for inst in &block.insts {
+            write!(f, "    ")?;
+
+            if let Some(result) = inst.result {
+                write!(f, "%{}: {} = ", result.0, function.value_type(result))?;
+            }
+
+            write_inst(f, module, &inst.kind)?;
+            writeln!(f)?;
+        }
+
+        writeln!(f, "    {}", block.terminator)?;
+    }
+
+    writeln!(f, "}}")
+}
+
+fn write_inst(f: &mut fmt::Formatter, module: &Module, kind: &InstKind) -> fmt::Result {
+    match kind {
+        InstKind::Const(constant) => write!(f, "const {}", constant),
+        InstKind::Copy(value) => write!(f, "copy {}", value),
+        InstKind::Unary(op, value) => {
+            let name = match op {
+                PrefixOp::Neg => "neg",
+                PrefixOp::Not => "not",
+            };
+
+            write!(f, "{} {}", name, value)
+        }
+        InstKind::Binary(op, left, right) => write!(f, "{} {}, {}", binary_name(*op), left, right),
+        InstKind::ToString(value) => write!(f, "to_string {}", value),
+        InstKind::Call(Callee::Function(id), args) => {
+            write!(f, "call @{}({})", module.function(*id).name, join(args))
+        }
+        InstKind::Call(Callee::Builtin(name), args) => write!(f, "call std.{}({})", name, join(args)),
+        InstKind::Array(elements) => write!(f, "array [{}]", join(elements)),
+        InstKind::Index(array, index) => write!(f, "index {}, {}", array, index),
+        InstKind::SetIndex(array, index, value) => write!(f, "set_index {}, {}, {}", array, index, value),
+        InstKind::Struct(name, fields) => write!(f, "struct {} {{ {} }}", name, join(fields)),
+        InstKind::Field(value, field) => write!(f, "field {}, {}", value, field),
+        InstKind::SetField(value, field, new) => write!(f, "set_field {}, {}, {}", value, field, new),
+        InstKind::Variant(name, variant, fields) => {
+            if fields.is_empty() {
+                write!(f, "variant {}.{}", name, variant)
+            } else {
+                write!(f, "variant {}.{}({})", name, variant, join(fields))
+            }
+        }
+        InstKind::IsVariant(value, variant) => write!(f, "is_variant {}, {}", value, variant),
+        InstKind::Payload(value, variant, i) => write!(f, "payload {}, {}.{}", value, variant, i),
+        InstKind::Load(global) => write!(f, "load ${}", global.0),
+        InstKind::Store(global, value) => write!(f, "store ${}, {}", global.0, value),
+    }
+}
+
+/// The mnemonic of a binary instruction.
+pub fn binary_name(op: InfixOp) -> &'static str {
+    match op {
+        InfixOp::Add => "add",
+        InfixOp::Sub => "sub",
+        InfixOp::Mul => "mul",
+        InfixOp::Div => "div",
+        InfixOp::Mod => "rem",
+        InfixOp::Eq => "eq",
+        InfixOp::NotEq => "ne",
+        InfixOp::Lt => "lt",
+        InfixOp::Gt => "gt",
+        InfixOp::LtEq => "le",
+        InfixOp::GtEq => "ge",
+        InfixOp::And => "and",
+        InfixOp::Or => "or",
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    let items: Vec<String> = items.iter().map(T::to_string).collect();
+    items.join(", ")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constant::Int(value) => write!(f, "{}", value),
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::Bool(value) => write!(f, "{}", value),
+            Constant::Char(value) => write!(f, "{:?}", value),
+            Constant::Str(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.args.is_empty() {
+            write!(f, "{}", self.block)
+        } else {
+            write!(f, "{}({})", self.block, join(&self.args))
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch(condition, then, otherwise) => {
+                write!(f, "branch {}, {}, {}", condition, then, otherwise)
+            }
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Trap(message) => write!(f, "trap {:?}", message),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
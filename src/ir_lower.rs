@@ -0,0 +1,1470 @@
+//! Lowers a program to the SSA IR.
+//!
+//! The program should have been resolved, since variables are told apart by
+//! their `BindingId`s, and type checked, since the types of values are the
+//! ones the checker gave their expressions and bindings. SSA form is built
+//! directly while lowering, following Braun et al., "Simple and Efficient
+//! Construction of Static Single Assignment Form".
+//!
+//! Top level variables that functions use become globals. Functions can't
+//! yet use the variables of an enclosing function, or be used as values
+//! other than by calling them.
+
+use crate::ast::*;
+use crate::ir::*;
+use crate::symbol::Symbol;
+use crate::token::BufferPosition;
+use crate::types::Type;
+use crate::visit::{self, Visitor};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+
+/// The name of the function running the top level statements.
+pub const ENTRY: &str = "$entry";
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LowerError {
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl LowerError {
+    pub fn new(message: String, position: BufferPosition) -> LowerError {
+        LowerError { message, position }
+    }
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+/// Lowers a whole program, given the types the checker found for its
+/// bindings. Its entry function runs the top level statements and then
+/// calls `main`, like `Evaluator::run`.
+pub fn lower(program: &Program, types: &HashMap<BindingId, Type>) -> Result<Module, Vec<LowerError>> {
+    let mut scan = Scan {
+        decls: HashMap::new(),
+        functions: vec![(ENTRY.to_owned(), None)],
+        structs: Vec::new(),
+        enums: Vec::new(),
+        current: FuncId(0),
+        uses: Vec::new(),
+    };
+
+    scan.visit_program(program);
+
+    let mut cx = Context {
+        decls: scan.decls,
+        captured: HashSet::new(),
+        globals: HashMap::new(),
+        statics: HashMap::new(),
+        types,
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        signatures: Vec::new(),
+        module: Module::default(),
+        errors: Vec::new(),
+    };
+
+    for (ident, user) in scan.uses {
+        if let Some(Decl::Variable(owner)) = ident.binding.and_then(|binding| cx.decls.get(&binding)) {
+            if *owner == user {
+                continue;
+            }
+
+            if *owner == FuncId(0) {
+                cx.captured.insert(ident.binding.expect("only resolved names have a declaration"));
+            } else {
+                let message = format!("cannot capture `{}` from an enclosing function", ident.name);
+                cx.error(message, ident.position);
+            }
+        }
+    }
+
+    cx.declare_types(&scan.structs, &scan.enums);
+
+    cx.signatures.push((Vec::new(), Type::Void));
+
+    for (_, decl) in scan.functions.iter().skip(1) {
+        let decl = decl.expect("only the entry has no declaration");
+        let params = decl.params.iter().map(|param| cx.resolve_type(&param.ty)).collect();
+        let ret = decl.return_type.as_ref().map_or(Type::Void, |ty| cx.resolve_type(ty));
+
+        cx.signatures.push((params, ret));
+    }
+
+    // The entry is lowered first so the globals it declares are known by the
+    // time functions use them.
+    for (i, (name, decl)) in scan.functions.into_iter().enumerate() {
+        let function = Lowerer::new(&mut cx, FuncId(i), name).lower(program, decl);
+        cx.module.functions.push(function);
+    }
+
+    if cx.errors.is_empty() {
+        Ok(cx.module)
+    } else {
+        cx.errors.sort_by_key(|error| (error.position.line, error.position.column));
+        Err(cx.errors)
+    }
+}
+
+/// What a name refers to.
+#[derive(Clone, PartialEq, Debug)]
+enum Decl {
+    // A variable, along with the function it belongs to.
+    Variable(FuncId),
+    Function(FuncId),
+    Struct(Symbol),
+    Enum(Symbol),
+    Module,
+}
+
+/// Finds every declaration in the program ahead of lowering, and which
+/// function each variable is used in.
+struct Scan<'ast> {
+    decls: HashMap<BindingId, Decl>,
+    functions: Vec<(String, Option<&'ast FnDecl>)>,
+    structs: Vec<&'ast StructDecl>,
+    enums: Vec<&'ast EnumDecl>,
+    current: FuncId,
+    uses: Vec<(&'ast Identifier, FuncId)>,
+}
+
+impl<'ast> Scan<'ast> {
+    fn declare(&mut self, name: &Identifier, decl: Decl) {
+        if let Some(binding) = name.binding {
+            self.decls.insert(binding, decl);
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for Scan<'ast> {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(name, _, _) => self.declare(name, Decl::Variable(self.current)),
+            StmtKind::Struct(decl) => {
+                self.declare(&decl.name, Decl::Struct(decl.name.name.clone()));
+                self.structs.push(decl);
+            }
+            StmtKind::Enum(decl) => {
+                self.declare(&decl.name, Decl::Enum(decl.name.name.clone()));
+                self.enums.push(decl);
+            }
+            StmtKind::Import(module) => self.declare(module, Decl::Module),
+            _ => {}
+        }
+
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_fn_decl(&mut self, decl: &'ast FnDecl) {
+        let id = FuncId(self.functions.len());
+
+        // Nested functions are named after the functions they are in.
+        let mut name = match self.current {
+            FuncId(0) => decl.name.name.to_string(),
+            parent => format!("{}.{}", self.functions[parent.0].0, decl.name.name),
+        };
+
+        let base = name.clone();
+        let mut suffix = 1;
+
+        while self.functions.iter().any(|(taken, _)| *taken == name) {
+            suffix += 1;
+            name = format!("{}.{}", base, suffix);
+        }
+
+        self.declare(&decl.name, Decl::Function(id));
+        self.functions.push((name, Some(decl)));
+
+        let parent = mem::replace(&mut self.current, id);
+
+        for param in &decl.params {
+            self.declare(&param.name, Decl::Variable(id));
+        }
+
+        visit::walk_fn_decl(self, decl);
+
+        self.current = parent;
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        if let Pattern::Binding(name) = pattern {
+            self.declare(name, Decl::Variable(self.current));
+        }
+
+        visit::walk_pattern(self, pattern);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if let ExprKind::Identifier(ident) = &expr.kind {
+            self.uses.push((ident, self.current));
+        }
+
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// What an expression lowers to. Only values are computed at runtime; the
+/// rest are known ahead of time.
+#[derive(Clone, PartialEq, Debug)]
+enum Operand {
+    Value(Value),
+    Void,
+    Function(FuncId),
+    Builtin(Symbol),
+    Module,
+    Struct(Symbol),
+    Enum(Symbol),
+    Constructor(Symbol, Symbol),
+}
+
+/// State shared between the functions being lowered.
+struct Context<'t> {
+    decls: HashMap<BindingId, Decl>,
+    // Top level variables used by functions, which become globals.
+    captured: HashSet<BindingId>,
+    globals: HashMap<BindingId, GlobalId>,
+    // Variables holding something other than a runtime value.
+    statics: HashMap<BindingId, Operand>,
+    types: &'t HashMap<BindingId, Type>,
+    structs: HashMap<Symbol, Vec<(Symbol, Type)>>,
+    enums: HashMap<Symbol, Variants>,
+    signatures: Vec<(Vec<Type>, Type)>,
+    module: Module,
+    errors: Vec<LowerError>,
+}
+
+impl Context<'_> {
+    fn error(&mut self, message: String, position: BufferPosition) {
+        self.errors.push(LowerError::new(message, position));
+    }
+
+    fn resolve_type(&self, ty: &TypeExpr) -> Type {
+        match ty {
+            TypeExpr::Named(name) => {
+                if let Some(ty) = Type::primitive(name.name.as_str()) {
+                    return ty;
+                }
+
+                match name.binding.and_then(|binding| self.decls.get(&binding)) {
+                    Some(Decl::Struct(name)) => Type::Struct(name.clone()),
+                    Some(Decl::Enum(name)) => Type::Enum(name.clone()),
+                    _ => Type::Unknown,
+                }
+            }
+            TypeExpr::Array(element, _) => Type::Array(Box::new(self.resolve_type(element))),
+        }
+    }
+
+    /// Adds the structs and enums to the module. Types are only known by
+    /// name, so two different types with the same name are refused.
+    fn declare_types(&mut self, structs: &[&StructDecl], enums: &[&EnumDecl]) {
+        for decl in structs {
+            let fields: Vec<(Symbol, Type)> = decl
+                .fields
+                .iter()
+                .map(|field| (field.name.name.clone(), self.resolve_type(&field.ty)))
+                .collect();
+
+            match self.structs.get(&decl.name.name) {
+                Some(declared) if *declared == fields => {}
+                Some(_) => {
+                    let message = format!("cannot lower two different types named `{}`", decl.name.name);
+                    self.error(message, decl.name.position);
+                }
+                None => {
+                    self.structs.insert(decl.name.name.clone(), fields.clone());
+                    self.module.structs.push((decl.name.name.clone(), fields));
+                }
+            }
+        }
+
+        for decl in enums {
+            let variants: Variants = decl
+                .variants
+                .iter()
+                .map(|variant| {
+                    let fields = variant.fields.iter().map(|ty| self.resolve_type(ty)).collect();
+                    (variant.name.name.clone(), fields)
+                })
+                .collect();
+
+            match self.enums.get(&decl.name.name) {
+                Some(declared) if *declared == variants => {}
+                Some(_) => {
+                    let message = format!("cannot lower two different types named `{}`", decl.name.name);
+                    self.error(message, decl.name.position);
+                }
+                None => {
+                    self.enums.insert(decl.name.name.clone(), variants.clone());
+                    self.module.enums.push((decl.name.name.clone(), variants));
+                }
+            }
+        }
+    }
+}
+
+/// Lowers the body of one function.
+struct Lowerer<'a, 't> {
+    cx: &'a mut Context<'t>,
+    id: FuncId,
+    function: Function,
+    // Filled in as blocks are finished, and moved into them at the end.
+    terminators: Vec<Option<Terminator>>,
+    preds: Vec<Vec<BlockId>>,
+    // Sealed blocks have all their predecessors.
+    sealed: Vec<bool>,
+    // The value of each variable at the end of each block.
+    defs: Vec<HashMap<BindingId, Value>>,
+    // Parameters added to unsealed blocks, given arguments once sealed.
+    incomplete: Vec<Vec<(BindingId, Value)>>,
+    current: BlockId,
+    // The blocks `continue` and `break` jump to.
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a, 't> Lowerer<'a, 't> {
+    fn new(cx: &'a mut Context<'t>, id: FuncId, name: String) -> Lowerer<'a, 't> {
+        let ret = cx.signatures[id.0].1.clone();
+
+        let mut lowerer = Lowerer {
+            cx,
+            id,
+            function: Function::new(name, ret),
+            terminators: Vec::new(),
+            preds: Vec::new(),
+            sealed: Vec::new(),
+            defs: Vec::new(),
+            incomplete: Vec::new(),
+            current: BlockId(0),
+            loops: Vec::new(),
+        };
+
+        let entry = lowerer.new_block();
+        lowerer.seal(entry);
+
+        lowerer
+    }
+
+    fn lower(mut self, program: &Program, decl: Option<&FnDecl>) -> Function {
+        match decl {
+            Some(decl) => self.lower_body(decl),
+            None => self.lower_entry(program),
+        }
+
+        for (block, terminator) in self.function.blocks.iter_mut().zip(self.terminators) {
+            block.terminator = terminator.unwrap_or(Terminator::Unreachable);
+        }
+
+        self.function
+    }
+
+    fn lower_entry(&mut self, program: &Program) {
+        self.lower_block(&program.0);
+
+        let main = program.0.iter().find_map(|stmt| match &stmt.kind {
+            StmtKind::Fn(decl) if decl.name.name == "main" => Some(decl),
+            _ => None,
+        });
+
+        if let Some(decl) = main {
+            if !decl.params.is_empty() {
+                let message = format!(
+                    "function `main` takes {} argument(s) but 0 were supplied",
+                    decl.params.len()
+                );
+
+                return self.terminate(Terminator::Trap(message));
+            }
+
+            let decl = decl.name.binding.and_then(|binding| self.cx.decls.get(&binding));
+
+            if let Some(Decl::Function(id)) = decl {
+                let id = *id;
+                let ret = self.cx.signatures[id.0].1.clone();
+                self.emit(InstKind::Call(Callee::Function(id), Vec::new()), ret);
+            }
+        }
+
+        self.terminate(Terminator::Return(None));
+    }
+
+    fn lower_body(&mut self, decl: &FnDecl) {
+        let (params, ret) = self.cx.signatures[self.id.0].clone();
+
+        for (param, ty) in decl.params.iter().zip(params) {
+            let value = self.function.new_value(ty);
+            self.function.blocks[0].params.push(value);
+            self.bind(&param.name, Operand::Value(value));
+        }
+
+        let body = self.lower_block(&decl.body);
+
+        let terminator = match body {
+            _ if ret == Type::Void => Terminator::Return(None),
+            Operand::Value(value) => Terminator::Return(Some(value)),
+            _ => Terminator::Trap(format!("function `{}` did not return a value", decl.name.name)),
+        };
+
+        self.terminate(terminator);
+    }
+
+    fn error(&mut self, message: String, position: BufferPosition) {
+        self.cx.error(message, position);
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block {
+            params: Vec::new(),
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+
+        self.terminators.push(None);
+        self.preds.push(Vec::new());
+        self.sealed.push(false);
+        self.defs.push(HashMap::new());
+        self.incomplete.push(Vec::new());
+
+        BlockId(self.function.blocks.len() - 1)
+    }
+
+    /// Whether a block can never run, which is the case after a `return`,
+    /// `break` or `continue`.
+    fn is_dead(&self, block: BlockId) -> bool {
+        block != BlockId(0) && self.sealed[block.0] && self.preds[block.0].is_empty()
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.terminate_block(self.current, terminator);
+    }
+
+    fn terminate_block(&mut self, block: BlockId, terminator: Terminator) {
+        let terminator = if self.is_dead(block) {
+            Terminator::Unreachable
+        } else {
+            terminator
+        };
+
+        for target in terminator.targets() {
+            debug_assert!(!self.sealed[target.block.0], "sealed blocks get no new predecessors");
+            self.preds[target.block.0].push(block);
+        }
+
+        self.terminators[block.0] = Some(terminator);
+    }
+
+    fn jump(&mut self, block: BlockId) {
+        self.terminate(Terminator::Jump(Target { block, args: Vec::new() }));
+    }
+
+    fn branch(&mut self, condition: Value, then: BlockId, otherwise: BlockId) {
+        let then = Target { block: then, args: Vec::new() };
+        let otherwise = Target { block: otherwise, args: Vec::new() };
+
+        self.terminate(Terminator::Branch(condition, then, otherwise));
+    }
+
+    /// Continues in a block with no predecessors after control has left the
+    /// current one.
+    fn start_dead_block(&mut self) {
+        let block = self.new_block();
+        self.seal(block);
+        self.current = block;
+    }
+
+    fn emit(&mut self, kind: InstKind, ty: Type) -> Option<Value> {
+        let result = match ty {
+            Type::Void => None,
+            ty => Some(self.function.new_value(ty)),
+        };
+
+        self.function.blocks[self.current.0].insts.push(Inst { result, kind });
+        result
+    }
+
+    fn value(&mut self, kind: InstKind, ty: Type) -> Value {
+        self.emit(kind, ty).expect("only void instructions have no result")
+    }
+
+    fn constant(&mut self, constant: Constant, ty: Type) -> Value {
+        self.value(InstKind::Const(constant), ty)
+    }
+
+    fn type_of(&self, value: Value) -> Type {
+        self.function.value_type(value).clone()
+    }
+
+    /// A value standing in for one that couldn't be lowered, once the error
+    /// has been reported.
+    fn poison(&mut self) -> Value {
+        self.function.new_value(Type::Unknown)
+    }
+
+    fn add_param(&mut self, block: BlockId, ty: Type) -> Value {
+        let value = self.function.new_value(ty);
+        self.function.blocks[block.0].params.push(value);
+        value
+    }
+
+    fn write_variable(&mut self, binding: BindingId, value: Value) {
+        self.defs[self.current.0].insert(binding, value);
+    }
+
+    fn read_variable(&mut self, binding: BindingId, block: BlockId) -> Value {
+        if let Some(value) = self.defs[block.0].get(&binding) {
+            return *value;
+        }
+
+        let ty = self.cx.types.get(&binding).cloned().unwrap_or(Type::Unknown);
+
+        let value = if !self.sealed[block.0] {
+            let param = self.add_param(block, ty);
+            self.incomplete[block.0].push((binding, param));
+            param
+        } else if self.preds[block.0].len() == 1 {
+            let pred = self.preds[block.0][0];
+            self.read_variable(binding, pred)
+        } else {
+            // The entry block's parameters are the function's, so it must
+            // define everything it reads.
+            assert!(block != BlockId(0), "variable read in the entry block before it is written");
+
+            let param = self.add_param(block, ty);
+            self.defs[block.0].insert(binding, param);
+            self.add_param_args(binding, block);
+            param
+        };
+
+        self.defs[block.0].insert(binding, value);
+        value
+    }
+
+    /// Passes the value of a variable from each predecessor to the parameter
+    /// just added to a block for it.
+    fn add_param_args(&mut self, binding: BindingId, block: BlockId) {
+        let mut preds = self.preds[block.0].clone();
+        preds.dedup();
+
+        for pred in preds {
+            let arg = self.read_variable(binding, pred);
+
+            let terminator = self.terminators[pred.0]
+                .as_mut()
+                .expect("predecessors have been terminated");
+
+            for target in terminator.targets_mut() {
+                if target.block == block {
+                    target.args.push(arg);
+                }
+            }
+        }
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        for (binding, _) in mem::take(&mut self.incomplete[block.0]) {
+            self.add_param_args(binding, block);
+        }
+
+        self.sealed[block.0] = true;
+    }
+
+    fn binding(&mut self, name: &Identifier) -> Option<BindingId> {
+        if name.binding.is_none() {
+            self.error(format!("`{}` has not been resolved", name.name), name.position);
+        }
+
+        name.binding
+    }
+
+    /// Declares a variable holding an operand.
+    fn bind(&mut self, name: &Identifier, operand: Operand) {
+        let binding = match self.binding(name) {
+            Some(binding) => binding,
+            None => return,
+        };
+
+        let value = match operand {
+            Operand::Value(value) => value,
+            operand => {
+                self.cx.statics.insert(binding, operand);
+                return;
+            }
+        };
+
+        if self.cx.captured.contains(&binding) {
+            let global = GlobalId(self.cx.module.globals.len());
+            let ty = self.cx.types.get(&binding).cloned().unwrap_or_else(|| self.type_of(value));
+
+            self.cx.module.globals.push((name.name.clone(), ty));
+            self.cx.globals.insert(binding, global);
+            self.emit(InstKind::Store(global, value), Type::Void);
+        } else {
+            self.write_variable(binding, value);
+        }
+    }
+
+    fn lower_block(&mut self, block: &[Stmt]) -> Operand {
+        let mut operand = Operand::Void;
+
+        for stmt in block {
+            operand = self.lower_stmt(stmt);
+        }
+
+        operand
+    }
+
+    fn lower_stmt(&mut self, stmt: &Stmt) -> Operand {
+        match &stmt.kind {
+            StmtKind::Blank | StmtKind::Fn(_) | StmtKind::Struct(_) | StmtKind::Enum(_) => {}
+            StmtKind::Import(module) => self.bind(module, Operand::Module),
+            StmtKind::Let(name, _, value) => {
+                let operand = self.lower_expr(value);
+                self.bind(name, operand);
+            }
+            StmtKind::Assign(target, op, value) => {
+                if let ExprKind::Identifier(ident) = &target.kind {
+                    if ident.binding.is_some_and(|binding| self.cx.statics.contains_key(&binding)) {
+                        let message =
+                            format!("cannot lower an assignment to `{}`, which is not a value", ident.name);
+                        self.error(message, ident.position);
+                        return Operand::Void;
+                    }
+                }
+
+                let value = self.lower_value(value);
+
+                let value = match op.infix_op() {
+                    Some(op) => {
+                        let current = self.lower_value(target);
+                        self.binary(op, current, value)
+                    }
+                    None => value,
+                };
+
+                self.assign(target, value);
+            }
+            StmtKind::Increment(target) | StmtKind::Decrement(target) => {
+                let op = match &stmt.kind {
+                    StmtKind::Increment(_) => InfixOp::Add,
+                    _ => InfixOp::Sub,
+                };
+
+                let current = self.lower_value(target);
+                let one = self.constant(Constant::Int(1), self.type_of(current));
+                let value = self.binary(op, current, one);
+
+                self.assign(target, value);
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.lower_expr(value),
+                    None => Operand::Void,
+                };
+
+                let value = match value {
+                    Operand::Value(value) if self.function.ret != Type::Void => Some(value),
+                    _ => None,
+                };
+
+                self.terminate(Terminator::Return(value));
+                self.start_dead_block();
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                let (next, exit) = *self.loops.last().expect("the checker rejects jumps outside loops");
+                let target = if stmt.kind == StmtKind::Break { exit } else { next };
+
+                self.jump(target);
+                self.start_dead_block();
+            }
+            StmtKind::While(condition, body) => {
+                let header = self.new_block();
+                self.jump(header);
+                self.current = header;
+
+                let condition = self.lower_value(condition);
+                let body_block = self.new_block();
+                let exit = self.new_block();
+
+                self.branch(condition, body_block, exit);
+                self.seal(body_block);
+                self.current = body_block;
+
+                self.loops.push((header, exit));
+                self.lower_block(body);
+                self.loops.pop();
+
+                self.jump(header);
+                self.seal(header);
+                self.seal(exit);
+                self.current = exit;
+            }
+            StmtKind::For(init, condition, step, body) => {
+                self.lower_stmt(init);
+
+                let header = self.new_block();
+                self.jump(header);
+                self.current = header;
+
+                let condition = self.lower_value(condition);
+                let body_block = self.new_block();
+                let step_block = self.new_block();
+                let exit = self.new_block();
+
+                self.branch(condition, body_block, exit);
+                self.seal(body_block);
+                self.current = body_block;
+
+                self.loops.push((step_block, exit));
+                self.lower_block(body);
+                self.loops.pop();
+
+                self.jump(step_block);
+                self.seal(step_block);
+                self.current = step_block;
+
+                self.lower_stmt(step);
+
+                self.jump(header);
+                self.seal(header);
+                self.seal(exit);
+                self.current = exit;
+            }
+            StmtKind::Expr(expr) => return self.lower_expr(expr),
+        }
+
+        Operand::Void
+    }
+
+    /// Stores a value into an assignable place. Assigning to a field or
+    /// element builds a new enclosing value and assigns that in turn.
+    fn assign(&mut self, target: &Expr, value: Value) {
+        match &target.kind {
+            ExprKind::Identifier(ident) => {
+                let binding = match self.binding(ident) {
+                    Some(binding) => binding,
+                    None => return,
+                };
+
+                if let Some(global) = self.cx.globals.get(&binding) {
+                    let global = *global;
+                    self.emit(InstKind::Store(global, value), Type::Void);
+                } else {
+                    self.write_variable(binding, value);
+                }
+            }
+            ExprKind::Field(object, field) => {
+                let current = self.lower_value(object);
+                let ty = self.type_of(current);
+                let new = self.value(InstKind::SetField(current, field.name.clone(), value), ty);
+
+                self.assign(object, new);
+            }
+            ExprKind::Index(object, index) => {
+                let index = self.lower_value(index);
+                let current = self.lower_value(object);
+                let ty = self.type_of(current);
+
+                if ty == Type::Str {
+                    self.terminate(Terminator::Trap("cannot assign to an index of str".to_owned()));
+                    return self.start_dead_block();
+                }
+
+                let new = self.value(InstKind::SetIndex(current, index, value), ty);
+
+                self.assign(object, new);
+            }
+            _ => self.error("invalid left-hand side of assignment".to_owned(), target.position),
+        }
+    }
+
+    /// Lowers an expression that should produce a runtime value.
+    fn lower_value(&mut self, expr: &Expr) -> Value {
+        match self.lower_expr(expr) {
+            Operand::Value(value) => value,
+            Operand::Void => {
+                self.error("cannot lower a use of a void value".to_owned(), expr.position);
+                self.poison()
+            }
+            _ => {
+                let message = "cannot lower a use of a function or type as a value".to_owned();
+                self.error(message, expr.position);
+                self.poison()
+            }
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> Operand {
+        let ty = expr.ty.clone().unwrap_or(Type::Unknown);
+
+        let value = match &expr.kind {
+            ExprKind::Identifier(ident) => return self.lower_identifier(ident),
+            ExprKind::IntegerLiteral(value) => self.constant(Constant::Int(*value), ty),
+            ExprKind::FloatLiteral(value) => self.constant(Constant::Float(*value), Type::F64),
+            ExprKind::StringLiteral(value) => self.constant(Constant::Str(value.clone()), Type::Str),
+            ExprKind::CharLiteral(value) => self.constant(Constant::Char(*value), Type::Char),
+            ExprKind::BooleanLiteral(value) => self.constant(Constant::Bool(*value), Type::Bool),
+            ExprKind::Interpolated(parts) => {
+                let mut result: Option<Value> = None;
+
+                for part in parts {
+                    let value = match part {
+                        StringPart::Text(text) => self.constant(Constant::Str(text.clone()), Type::Str),
+                        StringPart::Expr(expr) => {
+                            let value = self.lower_value(expr);
+                            self.stringify(value)
+                        }
+                    };
+
+                    result = Some(match result {
+                        Some(result) => self.value(InstKind::Binary(InfixOp::Add, result, value), Type::Str),
+                        None => value,
+                    });
+                }
+
+                match result {
+                    Some(result) => result,
+                    None => self.constant(Constant::Str(String::new()), Type::Str),
+                }
+            }
+            ExprKind::ArrayLiteral(elements) => {
+                if ty == Type::Array(Box::new(Type::Unknown)) {
+                    let message = "cannot infer the element type of an empty array".to_owned();
+                    self.error(message, expr.position);
+                }
+
+                let values = elements.iter().map(|element| self.lower_value(element)).collect();
+
+                self.value(InstKind::Array(values), ty)
+            }
+            ExprKind::StructLiteral(name, fields) => {
+                let declared = self.cx.structs.get(&name.name).cloned().unwrap_or_default();
+
+                // Fields are evaluated in the order they are written but
+                // stored in the order they are declared.
+                let mut values = HashMap::new();
+
+                for (field, value) in fields {
+                    let value = self.lower_value(value);
+                    values.insert(field.name.clone(), value);
+                }
+
+                let values = declared
+                    .iter()
+                    .map(|(field, _)| values.get(field).copied())
+                    .collect::<Option<Vec<Value>>>();
+
+                match values {
+                    Some(values) => self.value(InstKind::Struct(name.name.clone(), values), ty),
+                    None => {
+                        let message = format!("missing field in initializer of `{}`", name.name);
+                        self.error(message, name.position);
+                        self.poison()
+                    }
+                }
+            }
+            ExprKind::Prefix(op, right) => {
+                let right = self.lower_value(right);
+                self.value(InstKind::Unary(*op, right), ty)
+            }
+            ExprKind::Infix(op @ (InfixOp::And | InfixOp::Or), left, right) => {
+                let left = self.lower_value(left);
+
+                let right_block = self.new_block();
+                let join = self.new_block();
+                let result = self.add_param(join, Type::Bool);
+
+                // The left operand decides the result when it is false for
+                // `&&` or true for `||`.
+                let skip = Target { block: join, args: vec![left] };
+                let rest = Target { block: right_block, args: Vec::new() };
+
+                let terminator = match op {
+                    InfixOp::And => Terminator::Branch(left, rest, skip),
+                    _ => Terminator::Branch(left, skip, rest),
+                };
+
+                self.terminate(terminator);
+                self.seal(right_block);
+                self.current = right_block;
+
+                let right = self.lower_value(right);
+
+                self.terminate(Terminator::Jump(Target { block: join, args: vec![right] }));
+                self.seal(join);
+                self.current = join;
+
+                result
+            }
+            ExprKind::Infix(op, left, right) => {
+                let left = self.lower_value(left);
+                let right = self.lower_value(right);
+
+                self.binary(*op, left, right)
+            }
+            ExprKind::Call(function, args) => return self.lower_call(function, args, ty, expr.position),
+            ExprKind::Index(object, index) => {
+                let object = self.lower_value(object);
+                let index = self.lower_value(index);
+
+                self.value(InstKind::Index(object, index), ty)
+            }
+            ExprKind::Field(object, field) => return self.lower_field(object, field, ty),
+            ExprKind::Block(block) => return self.lower_block(block),
+            ExprKind::If(condition, consequence, alternative) => {
+                let condition = self.lower_value(condition);
+
+                let then_block = self.new_block();
+                let join = self.new_block();
+
+                let else_block = match alternative {
+                    Some(_) => self.new_block(),
+                    None => join,
+                };
+
+                self.branch(condition, then_block, else_block);
+                self.seal(then_block);
+                self.current = then_block;
+
+                let then = self.lower_block(consequence);
+                let mut branches = vec![(self.current, then)];
+
+                match alternative {
+                    Some(alternative) => {
+                        self.seal(else_block);
+                        self.current = else_block;
+
+                        let otherwise = self.lower_expr(alternative);
+                        branches.push((self.current, otherwise));
+                    }
+                    None => branches[0].1 = Operand::Void,
+                }
+
+                return self.join(branches, join);
+            }
+            ExprKind::Match(subject, arms) => {
+                let subject = self.lower_value(subject);
+                let join = self.new_block();
+                let mut branches = Vec::with_capacity(arms.len());
+
+                for arm in arms {
+                    let next = self.new_block();
+
+                    self.lower_pattern(&arm.pattern, subject, next);
+                    self.seal(next);
+
+                    let value = self.lower_expr(&arm.body);
+                    branches.push((self.current, value));
+
+                    self.current = next;
+                }
+
+                self.terminate(Terminator::Trap("no match arm matches the value".to_owned()));
+
+                return self.join(branches, join);
+            }
+        };
+
+        Operand::Value(value)
+    }
+
+    /// Jumps from the end of each branch to `join`, passing the branch's
+    /// value when they all produce one of the same type.
+    fn join(&mut self, branches: Vec<(BlockId, Operand)>, join: BlockId) -> Operand {
+        let live: Vec<Operand> = branches
+            .iter()
+            .filter(|(block, _)| !self.is_dead(*block))
+            .map(|(_, operand)| operand.clone())
+            .collect();
+
+        let ty = match live.first() {
+            Some(Operand::Value(first)) => {
+                let ty = self.type_of(*first);
+
+                let same = live.iter().all(|operand| match operand {
+                    Operand::Value(value) => *self.function.value_type(*value) == ty,
+                    _ => false,
+                });
+
+                if same { Some(ty) } else { None }
+            }
+            _ => None,
+        };
+
+        let result = ty.map(|ty| self.add_param(join, ty));
+
+        for (block, operand) in branches {
+            let args = match (result, operand) {
+                (Some(_), Operand::Value(value)) => vec![value],
+                _ => Vec::new(),
+            };
+
+            self.terminate_block(block, Terminator::Jump(Target { block: join, args }));
+        }
+
+        self.seal(join);
+        self.current = join;
+
+        result.map_or(Operand::Void, Operand::Value)
+    }
+
+    /// Tests a value against a pattern, binding its names and continuing in
+    /// the current block when it matches and jumping to `fail` otherwise.
+    fn lower_pattern(&mut self, pattern: &Pattern, value: Value, fail: BlockId) {
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Binding(name) => self.bind(name, Operand::Value(value)),
+            Pattern::Literal(literal) => {
+                let literal = self.lower_value(literal);
+                let matches = self.value(InstKind::Binary(InfixOp::Eq, value, literal), Type::Bool);
+
+                self.test(matches, fail);
+            }
+            Pattern::Variant(enum_name, variant, fields, _) => {
+                let matches = self.value(InstKind::IsVariant(value, variant.name.clone()), Type::Bool);
+                self.test(matches, fail);
+
+                let types = self
+                    .cx
+                    .enums
+                    .get(&enum_name.name)
+                    .and_then(|variants| variants.iter().find(|(name, _)| *name == variant.name))
+                    .map(|(_, types)| types.clone())
+                    .unwrap_or_default();
+
+                for (i, (field, ty)) in fields.iter().zip(types).enumerate() {
+                    if let Pattern::Wildcard(_) = field {
+                        continue;
+                    }
+
+                    let payload = self.value(InstKind::Payload(value, variant.name.clone(), i), ty);
+                    self.lower_pattern(field, payload, fail);
+                }
+            }
+        }
+    }
+
+    fn test(&mut self, condition: Value, fail: BlockId) {
+        let next = self.new_block();
+
+        self.branch(condition, next, fail);
+        self.seal(next);
+        self.current = next;
+    }
+
+    fn lower_identifier(&mut self, ident: &Identifier) -> Operand {
+        let binding = match self.binding(ident) {
+            Some(binding) => binding,
+            None => return Operand::Value(self.poison()),
+        };
+
+        match self.cx.decls.get(&binding).cloned() {
+            Some(Decl::Variable(owner)) => {
+                if let Some(operand) = self.cx.statics.get(&binding) {
+                    return operand.clone();
+                }
+
+                if let Some(global) = self.cx.globals.get(&binding) {
+                    let global = *global;
+                    let ty = self.cx.module.globals[global.0].1.clone();
+
+                    return Operand::Value(self.value(InstKind::Load(global), ty));
+                }
+
+                if owner == self.id {
+                    Operand::Value(self.read_variable(binding, self.current))
+                } else {
+                    // Already reported as a capture from an enclosing
+                    // function.
+                    Operand::Value(self.poison())
+                }
+            }
+            Some(Decl::Function(id)) => Operand::Function(id),
+            Some(Decl::Struct(name)) => Operand::Struct(name),
+            Some(Decl::Enum(name)) => Operand::Enum(name),
+            Some(Decl::Module) => Operand::Module,
+            None => {
+                self.error(format!("cannot find value `{}`", ident.name), ident.position);
+                Operand::Value(self.poison())
+            }
+        }
+    }
+
+    fn lower_field(&mut self, object: &Expr, field: &Identifier, ty: Type) -> Operand {
+        let object = match self.lower_expr(object) {
+            Operand::Module => return Operand::Builtin(field.name.clone()),
+            Operand::Enum(name) => {
+                let fields = self
+                    .cx
+                    .enums
+                    .get(&name)
+                    .and_then(|variants| variants.iter().find(|(variant, _)| *variant == field.name))
+                    .map(|(_, fields)| fields.len());
+
+                return match fields {
+                    Some(0) => {
+                        let kind = InstKind::Variant(name.clone(), field.name.clone(), Vec::new());
+                        Operand::Value(self.value(kind, Type::Enum(name)))
+                    }
+                    _ => Operand::Constructor(name, field.name.clone()),
+                };
+            }
+            Operand::Value(value) => value,
+            _ => {
+                let message = format!("cannot lower access to field `{}`", field.name);
+                self.error(message, field.position);
+                return Operand::Value(self.poison());
+            }
+        };
+
+        Operand::Value(self.value(InstKind::Field(object, field.name.clone()), ty))
+    }
+
+    fn lower_call(&mut self, function: &Expr, args: &[Expr], ty: Type, position: BufferPosition) -> Operand {
+        let callee = match self.lower_expr(function) {
+            Operand::Function(id) => Callee::Function(id),
+            Operand::Builtin(name) => Callee::Builtin(name),
+            Operand::Constructor(name, variant) => {
+                let args = self.lower_args(args);
+                let kind = InstKind::Variant(name, variant, args);
+
+                return Operand::Value(self.value(kind, ty));
+            }
+            _ => {
+                let message = "cannot lower a call to something other than a named function".to_owned();
+                self.error(message, position);
+                return Operand::Value(self.poison());
+            }
+        };
+
+        let args = self.lower_args(args);
+
+        self.emit(InstKind::Call(callee, args), ty).map_or(Operand::Void, Operand::Value)
+    }
+
+    fn lower_args(&mut self, args: &[Expr]) -> Vec<Value> {
+        args.iter().map(|arg| self.lower_value(arg)).collect()
+    }
+
+    fn binary(&mut self, op: InfixOp, left: Value, right: Value) -> Value {
+        let left_ty = self.type_of(left);
+        let right_ty = self.type_of(right);
+
+        if op == InfixOp::Add && (left_ty == Type::Str || right_ty == Type::Str) {
+            let left = self.stringify(left);
+            let right = self.stringify(right);
+
+            return self.value(InstKind::Binary(op, left, right), Type::Str);
+        }
+
+        let ty = match op {
+            InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod => left_ty,
+            _ => Type::Bool,
+        };
+
+        self.value(InstKind::Binary(op, left, right), ty)
+    }
+
+    /// Converts a value to a string the way `std.to_string` does.
+    fn stringify(&mut self, value: Value) -> Value {
+        if *self.function.value_type(value) == Type::Str {
+            value
+        } else {
+            self.value(InstKind::ToString(value), Type::Str)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+    use crate::ir_verify::verify;
+
+    pub fn run(input: &str) -> Result<Module, Vec<String>> {
+        let checked = compile::checked(input);
+
+        match lower(&checked.program, &checked.types) {
+            Ok(module) => Ok(module),
+            Err(errors) => Err(errors.iter().map(LowerError::to_string).collect()),
+        }
+    }
+
+    fn lowered(input: &str) -> String {
+        match run(input) {
+            Ok(module) => {
+                if let Err(errors) = verify(&module) {
+                    panic!("lowering {:?} gave an invalid module: {:?}\n{}", input, errors, module);
+                }
+
+                module.to_string()
+            }
+            Err(errors) => panic!("lowering {:?} failed: {:?}", input, errors),
+        }
+    }
+
+    #[test]
+    fn test_straight_line() {
+        let input = "
+            fn add(a: i32, b: i64): i64 {
+                let c = a * 2
+                b + 1
+            }
+        ";
+
+        let expected = "\
+fn @$entry(): void {
+bb0:
+    return
+}
+
+fn @add(i32, i64): i64 {
+bb0(%0: i32, %1: i64):
+    %2: i32 = const 2
+    %3: i32 = mul %0, %2
+    %4: i64 = const 1
+    %5: i64 = add %1, %4
+    return %5
+}
+";
+
+        assert_eq!(lowered(input), expected);
+    }
+
+    #[test]
+    fn test_variables_become_block_params() {
+        let input = "
+            fn count(n: i32): i32 {
+                let total = 0
+                let i = 0
+                while i < n {
+                    if i % 2 == 0 {
+                        total += i
+                    }
+                    i++
+                }
+                total
+            }
+        ";
+
+        let expected = "\
+fn @count(i32): i32 {
+bb0(%0: i32):
+    %1: i32 = const 0
+    %2: i32 = const 0
+    jump bb1(%2, %0, %1)
+bb1(%3: i32, %4: i32, %10: i32):
+    %5: bool = lt %3, %4
+    branch %5, bb2, bb3
+bb2:
+    %6: i32 = const 2
+    %7: i32 = rem %3, %6
+    %8: i32 = const 0
+    %9: bool = eq %7, %8
+    branch %9, bb4, bb5(%3, %4, %10)
+bb3:
+    return %10
+bb4:
+    %11: i32 = add %10, %3
+    jump bb5(%3, %4, %11)
+bb5(%12: i32, %15: i32, %16: i32):
+    %13: i32 = const 1
+    %14: i32 = add %12, %13
+    jump bb1(%14, %15, %16)
+}
+";
+
+        let module = lowered(input);
+        assert!(module.ends_with(expected), "{}", module);
+    }
+
+    #[test]
+    fn test_expressions() {
+        let tests = vec![
+            (
+                "let a = true && false",
+                "\
+bb0:
+    %0: bool = const true
+    branch %0, bb1, bb2(%0)
+bb1:
+    %2: bool = const false
+    jump bb2(%2)
+bb2(%1: bool):
+    return
+",
+            ),
+            (
+                "let s = \"a{1.5}\" + 2",
+                "\
+bb0:
+    %0: str = const \"a\"
+    %1: f64 = const 1.5
+    %2: str = to_string %1
+    %3: str = add %0, %2
+    %4: i32 = const 2
+    %5: str = to_string %4
+    %6: str = add %3, %5
+    return
+",
+            ),
+            (
+                "let x = if 1 < 2 { 3 } else { 4 }",
+                "\
+bb0:
+    %0: i32 = const 1
+    %1: i32 = const 2
+    %2: bool = lt %0, %1
+    branch %2, bb1, bb3
+bb1:
+    %3: i32 = const 3
+    jump bb2(%3)
+bb2(%5: i32):
+    return
+bb3:
+    %4: i32 = const 4
+    jump bb2(%4)
+",
+            ),
+            (
+                "let xs: [i64] = [1, 2]; xs[0] = 3",
+                "\
+bb0:
+    %0: i64 = const 1
+    %1: i64 = const 2
+    %2: [i64] = array [%0, %1]
+    %3: i64 = const 3
+    %4: i32 = const 0
+    %5: [i64] = set_index %2, %4, %3
+    return
+",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let expected = format!("fn @$entry(): void {{\n{}}}\n", expected);
+            assert_eq!(lowered(input), expected, "lowering {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_items() {
+        let input = "
+            import std
+            let print = std.println
+            struct P { x: i32, y: i32 }
+            enum E { A(P), B }
+            let limit = 3
+            fn f(e: E): i32 {
+                fn g(): i32 { limit }
+                match e {
+                    E.A(p) => p.y,
+                    E.B => g(),
+                }
+            }
+            fn main() {
+                print(f(E.A(P { y: 1, x: 2 })))
+            }
+        ";
+
+        let expected = "\
+struct P { x: i32, y: i32 }
+enum E { A(P), B }
+global $0 limit: i32
+
+fn @$entry(): void {
+bb0:
+    %0: i32 = const 3
+    store $0, %0
+    call @main()
+    return
+}
+
+fn @f(E): i32 {
+bb0(%0: E):
+    %1: bool = is_variant %0, A
+    branch %1, bb3, bb2
+bb1(%6: i32):
+    return %6
+bb2:
+    %4: bool = is_variant %0, B
+    branch %4, bb5, bb4
+bb3:
+    %2: P = payload %0, A.0
+    %3: i32 = field %2, y
+    jump bb1(%3)
+bb4:
+    trap \"no match arm matches the value\"
+bb5:
+    %5: i32 = call @f.g()
+    jump bb1(%5)
+}
+
+fn @f.g(): i32 {
+bb0:
+    %0: i32 = load $0
+    return %0
+}
+
+fn @main(): void {
+bb0:
+    %0: i32 = const 1
+    %1: i32 = const 2
+    %2: P = struct P { %1, %0 }
+    %3: E = variant E.A(%2)
+    %4: i32 = call @f(%3)
+    call std.println(%4)
+    return
+}
+";
+
+        assert_eq!(lowered(input), expected);
+    }
+
+    #[test]
+    fn test_dead_code_is_unreachable() {
+        let input = "
+            fn f(): i32 {
+                return 1
+                2
+            }
+        ";
+
+        let expected = "\
+fn @f(): i32 {
+bb0:
+    %0: i32 = const 1
+    return %0
+bb1:
+    %1: i32 = const 2
+    unreachable
+}
+";
+
+        let module = lowered(input);
+        assert!(module.ends_with(expected), "{}", module);
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            (
+                "fn f(x: i32) { fn g(): i32 { x } }",
+                "1:30: cannot capture `x` from an enclosing function",
+            ),
+            ("fn f() {}\nlet g = f; g = f", "2:12: cannot lower an assignment to `g`, which is not a value"),
+            ("let xs = []", "1:10: cannot infer the element type of an empty array"),
+        ];
+
+        for (input, expected) in tests {
+            match run(input) {
+                Ok(module) => panic!("lowering {:?} should fail, got:\n{}", input, module),
+                Err(errors) => assert_eq!(errors, vec![expected.to_owned()], "lowering {:?}", input),
+            }
+        }
+    }
+}
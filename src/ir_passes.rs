@@ -0,0 +1,545 @@
+//! Optimization passes over the SSA IR. Each pass leaves the module well
+//! formed, so they can be run in any order and any number of times.
+
+use crate::ir::*;
+use crate::types::Type;
+use std::collections::HashMap;
+use std::mem;
+
+/// Callees with at most this many instructions are inlined.
+const INLINE_LIMIT: usize = 8;
+
+/// Runs every pass over a module.
+pub fn optimize(module: &mut Module) {
+    inline_calls(module);
+
+    for function in &mut module.functions {
+        propagate_copies(function);
+        eliminate_common_subexpressions(function);
+        propagate_copies(function);
+        eliminate_dead_code(function);
+    }
+}
+
+/// Replaces uses of copied values with the originals, and block parameters
+/// that are always passed the same value with that value.
+pub fn propagate_copies(function: &mut Function) {
+    let mut replacements: HashMap<Value, Value> = HashMap::new();
+
+    for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+        if let (Some(result), InstKind::Copy(value)) = (inst.result, &inst.kind) {
+            replacements.insert(result, *value);
+        }
+    }
+
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for block in 1..function.blocks.len() {
+            let incoming = incoming_args(function, BlockId(block));
+
+            if incoming.is_empty() {
+                continue;
+            }
+
+            for i in (0..function.blocks[block].params.len()).rev() {
+                let param = function.blocks[block].params[i];
+
+                let mut args = incoming
+                    .iter()
+                    .map(|args| resolve(&replacements, args[i]))
+                    .filter(|arg| *arg != param);
+
+                let first = match args.next() {
+                    Some(first) => first,
+                    None => continue,
+                };
+
+                if args.all(|arg| arg == first) {
+                    replacements.insert(param, first);
+                    remove_param(function, BlockId(block), i);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| !matches!(inst.kind, InstKind::Copy(_)));
+
+        let insts = block.insts.iter_mut().flat_map(|inst| inst.kind.operands_mut());
+
+        for operand in insts.chain(block.terminator.operands_mut()) {
+            *operand = resolve(&replacements, *operand);
+        }
+
+        for target in block.terminator.targets_mut() {
+            for arg in &mut target.args {
+                *arg = resolve(&replacements, *arg);
+            }
+        }
+    }
+}
+
+fn resolve(replacements: &HashMap<Value, Value>, mut value: Value) -> Value {
+    while let Some(replacement) = replacements.get(&value) {
+        value = *replacement;
+    }
+
+    value
+}
+
+/// The arguments passed to a block along each edge into it.
+fn incoming_args(function: &Function, block: BlockId) -> Vec<Vec<Value>> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|pred| pred.terminator.targets())
+        .filter(|target| target.block == block)
+        .map(|target| target.args.clone())
+        .collect()
+}
+
+/// Removes a block parameter along with the arguments passed to it.
+fn remove_param(function: &mut Function, block: BlockId, index: usize) {
+    function.blocks[block.0].params.remove(index);
+
+    for pred in &mut function.blocks {
+        for target in pred.terminator.targets_mut() {
+            if target.block == block {
+                target.args.remove(index);
+            }
+        }
+    }
+}
+
+/// Replaces a pure instruction with a copy of an identical one that
+/// dominates it.
+pub fn eliminate_common_subexpressions(function: &mut Function) {
+    let dominators = function.dominators();
+    let children = dominators.children();
+    let mut available = HashMap::new();
+
+    cse_block(function, &children, BlockId(0), &mut available);
+}
+
+fn cse_block(
+    function: &mut Function,
+    children: &[Vec<BlockId>],
+    block: BlockId,
+    available: &mut HashMap<(InstKind, Type), Value>,
+) {
+    let mut added = Vec::new();
+
+    for i in 0..function.blocks[block.0].insts.len() {
+        let inst = &function.blocks[block.0].insts[i];
+
+        let result = match inst.result {
+            Some(result) if inst.kind.is_pure() => result,
+            _ => continue,
+        };
+
+        // Equal instructions that produce the same type compute the same
+        // value.
+        let key = (inst.kind.clone(), function.value_type(result).clone());
+
+        match available.get(&key) {
+            Some(value) => function.blocks[block.0].insts[i].kind = InstKind::Copy(*value),
+            None => {
+                available.insert(key.clone(), result);
+                added.push(key);
+            }
+        }
+    }
+
+    for child in &children[block.0] {
+        cse_block(function, children, *child, available);
+    }
+
+    for key in added {
+        available.remove(&key);
+    }
+}
+
+/// Where a value is defined.
+enum Def {
+    Param,
+    Inst(BlockId, usize),
+}
+
+/// Removes unreachable blocks, and the instructions and block parameters
+/// whose values are never used.
+pub fn eliminate_dead_code(function: &mut Function) {
+    remove_unreachable_blocks(function);
+
+    let mut defs: HashMap<Value, Def> = HashMap::new();
+    let mut param_args: HashMap<Value, Vec<Value>> = HashMap::new();
+
+    for (i, block) in function.blocks.iter().enumerate() {
+        for param in &block.params {
+            defs.insert(*param, Def::Param);
+        }
+
+        for (j, inst) in block.insts.iter().enumerate() {
+            if let Some(result) = inst.result {
+                defs.insert(result, Def::Inst(BlockId(i), j));
+            }
+        }
+
+        for target in block.terminator.targets() {
+            let params = &function.blocks[target.block.0].params;
+
+            for (param, arg) in params.iter().zip(&target.args) {
+                param_args.entry(*param).or_default().push(*arg);
+            }
+        }
+    }
+
+    let mut live = vec![false; function.types.len()];
+    let mut worklist: Vec<Value> = function.params().to_vec();
+
+    for block in &function.blocks {
+        for inst in &block.insts {
+            if !inst.kind.is_removable(&function.types) {
+                worklist.extend(inst.result);
+                worklist.extend(inst.kind.operands());
+            }
+        }
+
+        // Arguments passed to other blocks are only live if the parameter
+        // they are passed to is.
+        match &block.terminator {
+            Terminator::Branch(condition, _, _) => worklist.push(*condition),
+            Terminator::Return(Some(value)) => worklist.push(*value),
+            _ => {}
+        }
+    }
+
+    while let Some(value) = worklist.pop() {
+        if mem::replace(&mut live[value.0], true) {
+            continue;
+        }
+
+        match defs.get(&value) {
+            Some(Def::Inst(block, i)) => worklist.extend(function.blocks[block.0].insts[*i].kind.operands()),
+            Some(Def::Param) => worklist.extend(param_args.get(&value).into_iter().flatten()),
+            None => {}
+        }
+    }
+
+    for block in 0..function.blocks.len() {
+        let Function { blocks, types, .. } = &mut *function;
+
+        blocks[block]
+            .insts
+            .retain(|inst| inst.result.is_none_or(|result| live[result.0]) || !inst.kind.is_removable(types));
+
+        if block == 0 {
+            continue;
+        }
+
+        for i in (0..function.blocks[block].params.len()).rev() {
+            if !live[function.blocks[block].params[i].0] {
+                remove_param(function, BlockId(block), i);
+            }
+        }
+    }
+}
+
+fn remove_unreachable_blocks(function: &mut Function) {
+    let dominators = function.dominators();
+
+    if dominators.order().len() == function.blocks.len() {
+        return;
+    }
+
+    let mut renumbered = vec![None; function.blocks.len()];
+    let mut count = 0;
+
+    for (i, slot) in renumbered.iter_mut().enumerate() {
+        if dominators.is_reachable(BlockId(i)) {
+            *slot = Some(BlockId(count));
+            count += 1;
+        }
+    }
+
+    let blocks = mem::take(&mut function.blocks);
+
+    for (i, mut block) in blocks.into_iter().enumerate() {
+        if renumbered[i].is_none() {
+            continue;
+        }
+
+        for target in block.terminator.targets_mut() {
+            target.block = renumbered[target.block.0].expect("reachable blocks only jump to reachable ones");
+        }
+
+        function.blocks.push(block);
+    }
+}
+
+/// Inlines calls to small functions made of a single block. Functions that
+/// call themselves are never inlined.
+pub fn inline_calls(module: &mut Module) {
+    let callees: Vec<Option<Function>> = module
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, function)| {
+            if FuncId(i) != module.entry && is_inlinable(FuncId(i), function) {
+                Some(function.clone())
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    for function in &mut module.functions {
+        for block in 0..function.blocks.len() {
+            let insts = mem::take(&mut function.blocks[block].insts);
+            let mut inlined = Vec::with_capacity(insts.len());
+
+            for inst in insts {
+                let (callee, args) = match &inst.kind {
+                    InstKind::Call(Callee::Function(id), args) => match &callees[id.0] {
+                        Some(callee) => (callee, args),
+                        None => {
+                            inlined.push(inst);
+                            continue;
+                        }
+                    },
+                    _ => {
+                        inlined.push(inst);
+                        continue;
+                    }
+                };
+
+                let mut values: HashMap<Value, Value> =
+                    callee.params().iter().copied().zip(args.iter().copied()).collect();
+
+                for callee_inst in &callee.blocks[0].insts {
+                    let result = callee_inst.result.map(|result| {
+                        let value = function.new_value(callee.value_type(result).clone());
+                        values.insert(result, value);
+                        value
+                    });
+
+                    let mut kind = callee_inst.kind.clone();
+
+                    for operand in kind.operands_mut() {
+                        *operand = values[operand];
+                    }
+
+                    inlined.push(Inst { result, kind });
+                }
+
+                let returned = &callee.blocks[0].terminator;
+
+                if let (Some(result), Terminator::Return(Some(value))) = (inst.result, returned) {
+                    inlined.push(Inst {
+                        result: Some(result),
+                        kind: InstKind::Copy(values[value]),
+                    });
+                }
+            }
+
+            function.blocks[block].insts = inlined;
+        }
+    }
+}
+
+fn is_inlinable(id: FuncId, function: &Function) -> bool {
+    let block = &function.blocks[0];
+
+    let recursive = block
+        .insts
+        .iter()
+        .any(|inst| matches!(inst.kind, InstKind::Call(Callee::Function(callee), _) if callee == id));
+
+    function.blocks.len() == 1
+        && matches!(block.terminator, Terminator::Return(_))
+        && block.insts.len() <= INLINE_LIMIT
+        && !recursive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::lowered;
+    use crate::ir_verify::verify;
+
+    /// Runs a pass over every function in a program and dumps the result.
+    fn run(input: &str, pass: fn(&mut Function)) -> String {
+        let mut module = lowered(input);
+        module.functions.iter_mut().for_each(pass);
+
+        if let Err(errors) = verify(&module) {
+            panic!("the pass left {:?} invalid: {:?}\n{}", input, errors, module);
+        }
+
+        module.to_string()
+    }
+
+    #[test]
+    fn test_propagate_copies() {
+        let output = run(
+            "fn f(n: i32): i32 { let a = n\nlet b = 0\nwhile b < a { b += 1 }\nb }",
+            propagate_copies,
+        );
+
+        let expected = "\
+fn @$entry(): void {
+bb0:
+    return
+}
+
+fn @f(i32): i32 {
+bb0(%0: i32):
+    %1: i32 = const 0
+    jump bb1(%1)
+bb1(%2: i32):
+    %4: bool = lt %2, %0
+    branch %4, bb2, bb3
+bb2:
+    %5: i32 = const 1
+    %6: i32 = add %2, %5
+    jump bb1(%6)
+bb3:
+    return %2
+}
+";
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_eliminate_common_subexpressions() {
+        let output = run(
+            "fn f(a: i32, b: i32): i32 { let c = a * b\nif c > 0 { a * b + 1 } else { a * b } }",
+            eliminate_common_subexpressions,
+        );
+
+        let expected = "\
+fn @$entry(): void {
+bb0:
+    return
+}
+
+fn @f(i32, i32): i32 {
+bb0(%0: i32, %1: i32):
+    %2: i32 = mul %0, %1
+    %3: i32 = const 0
+    %4: bool = gt %2, %3
+    branch %4, bb1, bb3
+bb1:
+    %5: i32 = copy %2
+    %6: i32 = const 1
+    %7: i32 = add %5, %6
+    jump bb2(%7)
+bb2(%9: i32):
+    return %9
+bb3:
+    %8: i32 = copy %2
+    jump bb2(%8)
+}
+";
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_constants_are_keyed_by_bits() {
+        let mut function = Function::new("f".to_owned(), Type::Void);
+        let block = Block {
+            params: Vec::new(),
+            insts: Vec::new(),
+            terminator: Terminator::Return(None),
+        };
+
+        function.blocks.push(block);
+
+        for value in &[0.0, -0.0, 0.0, f64::NAN, f64::NAN] {
+            let result = function.new_value(Type::F64);
+            let kind = InstKind::Const(Constant::Float(*value));
+            function.blocks[0].insts.push(Inst { result: Some(result), kind });
+        }
+
+        eliminate_common_subexpressions(&mut function);
+
+        let kinds: Vec<String> = function.blocks[0]
+            .insts
+            .iter()
+            .map(|inst| format!("{:?}", inst.kind))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                "Const(Float(0.0))",
+                "Const(Float(-0.0))",
+                "Copy(Value(0))",
+                "Const(Float(NaN))",
+                "Copy(Value(3))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eliminate_dead_code() {
+        let output = run(
+            "fn f(a: i32, b: i32): i32 { let c = a + 1\nlet d = a / b\nlet e = a > b\nreturn a\nb }",
+            eliminate_dead_code,
+        );
+
+        let expected = "\
+fn @$entry(): void {
+bb0:
+    return
+}
+
+fn @f(i32, i32): i32 {
+bb0(%0: i32, %1: i32):
+    %2: i32 = const 1
+    %3: i32 = add %0, %2
+    %4: i32 = div %0, %1
+    return %0
+}
+";
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_inline_calls() {
+        let mut module = lowered("fn sq(x: i32): i32 { x * x }\nfn f(a: i32): i32 { sq(a) + sq(2) }");
+        optimize(&mut module);
+
+        assert_eq!(verify(&module), Ok(()));
+
+        let expected = "\
+fn @$entry(): void {
+bb0:
+    return
+}
+
+fn @sq(i32): i32 {
+bb0(%0: i32):
+    %1: i32 = mul %0, %0
+    return %1
+}
+
+fn @f(i32): i32 {
+bb0(%0: i32):
+    %5: i32 = mul %0, %0
+    %2: i32 = const 2
+    %6: i32 = mul %2, %2
+    %4: i32 = add %5, %6
+    return %4
+}
+";
+
+        assert_eq!(module.to_string(), expected);
+    }
+}
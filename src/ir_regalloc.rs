@@ -0,0 +1,279 @@
+//! Linear scan register allocation, giving every value of a function a
+//! register or a stack slot for its whole life.
+//!
+//! Blocks are laid out in order and every block start, instruction and
+//! terminator gets a position. A value lives from the first to the last
+//! position it is defined, used or live at, and values whose lives overlap
+//! never share a location. Values that live across a call only get the
+//! registers that calls preserve.
+//!
+//! A jump writes its target's parameters while other values may share their
+//! locations, but only values that are dead once the jump is taken, so
+//! backends have to read every argument before writing any parameter.
+
+use crate::ir::*;
+use std::collections::HashSet;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Location {
+    // An index into the registers given to `allocate`.
+    Register(usize),
+    Stack(usize),
+}
+
+#[derive(Debug)]
+pub struct Allocation {
+    // The location of every value, indexed by the value's number. Values
+    // that are never defined have none.
+    pub locations: Vec<Option<Location>>,
+    pub stack_slots: usize,
+}
+
+impl Allocation {
+    pub fn location(&self, value: Value) -> Location {
+        self.locations[value.0].expect("every defined value has a location")
+    }
+}
+
+#[derive(Debug)]
+struct Interval {
+    value: Value,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+/// Allocates locations for a function's values. `preserved` says for each
+/// register whether it survives calls, with registers tried in order, and
+/// `is_call` whether an instruction calls another function.
+pub fn allocate(function: &Function, preserved: &[bool], is_call: impl Fn(&Inst) -> bool) -> Allocation {
+    let mut allocation = Allocation {
+        locations: vec![None; function.types.len()],
+        stack_slots: 0,
+    };
+
+    // The value and register of every interval with a register, and the
+    // registers no interval has.
+    let mut active: Vec<(usize, usize)> = Vec::new();
+    let mut free: Vec<bool> = vec![true; preserved.len()];
+    let intervals = intervals(function, &is_call);
+
+    for (i, interval) in intervals.iter().enumerate() {
+        active.retain(|&(other, register)| {
+            let expired = intervals[other].end < interval.start;
+            free[register] |= expired;
+            !expired
+        });
+
+        let allowed = |register: usize| preserved[register] || !interval.crosses_call;
+
+        if let Some(register) = (0..preserved.len()).find(|&register| free[register] && allowed(register)) {
+            free[register] = false;
+            active.push((i, register));
+            allocation.locations[interval.value.0] = Some(Location::Register(register));
+            continue;
+        }
+
+        // Without a free register, the interval that lives the longest is
+        // spilled, so the most positions have a value in a register.
+        let longest = active
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, register))| allowed(*register))
+            .max_by_key(|(_, (other, _))| intervals[*other].end)
+            .map(|(j, &(other, register))| (j, other, register));
+
+        let slot = Location::Stack(allocation.stack_slots);
+        allocation.stack_slots += 1;
+
+        match longest {
+            Some((j, other, register)) if intervals[other].end > interval.end => {
+                active[j] = (i, register);
+                allocation.locations[intervals[other].value.0] = Some(slot);
+                allocation.locations[interval.value.0] = Some(Location::Register(register));
+            }
+            _ => allocation.locations[interval.value.0] = Some(slot),
+        }
+    }
+
+    allocation
+}
+
+/// The interval of every defined value, ordered by where they start.
+fn intervals(function: &Function, is_call: &impl Fn(&Inst) -> bool) -> Vec<Interval> {
+    let live_in = live_in(function);
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; function.types.len()];
+    let mut calls = Vec::new();
+    let mut position = 0;
+
+    let mut extend = |value: Value, position: usize| {
+        let range = ranges[value.0].get_or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+
+    for (i, block) in function.blocks.iter().enumerate() {
+        let start = position;
+
+        for value in block.params.iter().chain(&live_in[i]) {
+            extend(*value, start);
+        }
+
+        for inst in &block.insts {
+            position += 1;
+
+            for operand in inst.kind.operands() {
+                extend(operand, position);
+            }
+
+            if let Some(result) = inst.result {
+                extend(result, position);
+            }
+
+            if is_call(inst) {
+                calls.push(position);
+            }
+        }
+
+        position += 1;
+
+        for operand in block.terminator.operands() {
+            extend(operand, position);
+        }
+
+        for target in block.terminator.targets() {
+            for value in &live_in[target.block.0] {
+                extend(*value, position);
+            }
+        }
+
+        position += 1;
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .enumerate()
+        .filter_map(|(value, range)| {
+            let (start, end) = range?;
+
+            Some(Interval {
+                value: Value(value),
+                start,
+                end,
+                crosses_call: calls.iter().any(|call| start < *call && *call < end),
+            })
+        })
+        .collect();
+
+    intervals.sort_by_key(|interval| (interval.start, interval.value));
+    intervals
+}
+
+/// The values live at the start of every block, not counting its
+/// parameters.
+fn live_in(function: &Function) -> Vec<HashSet<Value>> {
+    let mut live_in = vec![HashSet::new(); function.blocks.len()];
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for (i, block) in function.blocks.iter().enumerate().rev() {
+            let mut live: HashSet<Value> = block.terminator.operands().into_iter().collect();
+
+            for target in block.terminator.targets() {
+                live.extend(live_in[target.block.0].iter().copied());
+            }
+
+            for inst in block.insts.iter().rev() {
+                if let Some(result) = inst.result {
+                    live.remove(&result);
+                }
+
+                live.extend(inst.kind.operands());
+            }
+
+            for param in &block.params {
+                live.remove(param);
+            }
+
+            if live != live_in[i] {
+                live_in[i] = live;
+                changed = true;
+            }
+        }
+    }
+
+    live_in
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+    use crate::ir_passes::optimize;
+
+    fn optimized(input: &str) -> Module {
+        let mut module = compile::lowered(input);
+        optimize(&mut module);
+        module
+    }
+
+    fn is_call(inst: &Inst) -> bool {
+        matches!(inst.kind, InstKind::Call(..))
+    }
+
+    /// Checks that no two values that are live at once share a location.
+    fn check(function: &Function, allocation: &Allocation) {
+        let intervals = intervals(function, &is_call);
+
+        for a in &intervals {
+            for b in intervals.iter().filter(|b| b.value != a.value && b.start <= a.end && a.start <= b.end) {
+                assert_ne!(
+                    allocation.location(a.value),
+                    allocation.location(b.value),
+                    "{:?} and {:?} share a location in\n{:?}",
+                    a,
+                    b,
+                    function
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_allocate() {
+        let input = "
+fn sum(n: i32): i32 {
+    let total = 0
+    let i = 0
+    while i < n {
+        total = total + i * i
+        i = i + 1
+    }
+    total
+}
+
+fn fib(n: i32): i32 {
+    if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+}
+";
+        let module = optimized(input);
+
+        for function in &module.functions {
+            for preserved in &[vec![false, false, true], vec![true, false], vec![false]] {
+                let allocation = allocate(function, preserved, is_call);
+                check(function, &allocation);
+            }
+        }
+
+        // With enough registers nothing is spilled. `n`, the constant 2 and
+        // the result of the first call to `fib` live across calls, so `n`
+        // gets the first preserved register.
+        let fib = module.functions.iter().find(|function| function.name == "fib").unwrap();
+        let allocation = allocate(fib, &[false, false, true, true, true], is_call);
+
+        assert_eq!(allocation.stack_slots, 0);
+        assert_eq!(allocation.location(fib.params()[0]), Location::Register(2));
+    }
+}
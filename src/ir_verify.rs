@@ -0,0 +1,551 @@
+//! Checks that a module is well formed: every value is defined once before
+//! it is used, blocks are passed the arguments they expect, and every
+//! instruction is applied to operands of the right types.
+
+use crate::ast::{InfixOp, PrefixOp};
+use crate::ir::*;
+use crate::symbol::Symbol;
+use crate::types::Type;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct VerifyError {
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in @{}: {}", self.function, self.message)
+    }
+}
+
+pub fn verify(module: &Module) -> Result<(), Vec<VerifyError>> {
+    let mut errors = Vec::new();
+
+    if module.entry.0 >= module.functions.len() {
+        errors.push(VerifyError {
+            function: String::new(),
+            message: "the entry function does not exist".to_owned(),
+        });
+    }
+
+    for function in &module.functions {
+        let mut verifier = Verifier {
+            module,
+            function,
+            errors: Vec::new(),
+        };
+
+        verifier.verify();
+
+        errors.extend(verifier.errors.into_iter().map(|message| VerifyError {
+            function: function.name.clone(),
+            message,
+        }));
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    errors: Vec<String>,
+}
+
+/// Where a value is defined: a block, and the index of the instruction in
+/// it, or `None` for a block parameter.
+type Definition = (BlockId, Option<usize>);
+
+impl<'a> Verifier<'a> {
+    fn verify(&mut self) {
+        let function = self.function;
+
+        if function.blocks.is_empty() {
+            return self.errors.push("the function has no blocks".to_owned());
+        }
+
+        let definitions = match self.definitions() {
+            Some(definitions) => definitions,
+            None => return,
+        };
+
+        if !function.predecessors()[0].is_empty() {
+            self.errors.push("the entry block has predecessors".to_owned());
+        }
+
+        let dominators = function.dominators();
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            let id = BlockId(i);
+
+            // Unreachable blocks never run, so only their shape matters.
+            let reachable = dominators.is_reachable(id);
+
+            for (j, inst) in block.insts.iter().enumerate() {
+                for operand in inst.kind.operands() {
+                    if reachable {
+                        self.check_dominates(&definitions, &dominators, operand, (id, Some(j)));
+                    }
+                }
+
+                self.check_inst(id, inst);
+            }
+
+            if reachable {
+                let end = (id, Some(block.insts.len()));
+
+                for operand in block.terminator.operands() {
+                    self.check_dominates(&definitions, &dominators, operand, end);
+                }
+            }
+
+            self.check_terminator(id, &block.terminator);
+        }
+    }
+
+    /// Finds where each value is defined, reporting values defined twice or
+    /// never given a type.
+    fn definitions(&mut self) -> Option<Vec<Option<Definition>>> {
+        let function = self.function;
+        let mut definitions: Vec<Option<Definition>> = vec![None; function.types.len()];
+        let mut ok = true;
+
+        let mut define = |value: Value, definition: Definition, errors: &mut Vec<String>| {
+            match definitions.get_mut(value.0) {
+                None => {
+                    errors.push(format!("{} has no type", value));
+                    ok = false;
+                }
+                Some(Some(_)) => {
+                    errors.push(format!("{} is defined more than once", value));
+                    ok = false;
+                }
+                Some(slot) => *slot = Some(definition),
+            }
+        };
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            for param in &block.params {
+                define(*param, (BlockId(i), None), &mut self.errors);
+            }
+
+            for (j, inst) in block.insts.iter().enumerate() {
+                if let Some(result) = inst.result {
+                    define(result, (BlockId(i), Some(j)), &mut self.errors);
+                }
+            }
+        }
+
+        if ok { Some(definitions) } else { None }
+    }
+
+    fn check_dominates(
+        &mut self,
+        definitions: &[Option<Definition>],
+        dominators: &Dominators,
+        value: Value,
+        (block, position): Definition,
+    ) {
+        let (def_block, def_position) = match definitions.get(value.0).copied().flatten() {
+            Some(definition) => definition,
+            None => return self.errors.push(format!("{} is used in {} but never defined", value, block)),
+        };
+
+        let dominates = if def_block == block {
+            // Parameters come before every instruction of their block.
+            match (def_position, position) {
+                (None, _) => true,
+                (Some(def), Some(at)) => def < at,
+                (Some(_), None) => false,
+            }
+        } else {
+            dominators.dominates(def_block, block)
+        };
+
+        if !dominates {
+            self.errors.push(format!("{} is used in {} where its definition may not have run", value, block));
+        }
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.function.types.get(value.0).cloned().unwrap_or(Type::Unknown)
+    }
+
+    fn check_inst(&mut self, block: BlockId, inst: &Inst) {
+        let expected = match self.inst_type(inst) {
+            Ok(ty) => ty,
+            Err(message) => return self.errors.push(format!("in {}: {}", block, message)),
+        };
+
+        let actual = inst.result.map(|result| self.ty(result));
+
+        match (expected, actual) {
+            (Some(expected), Some(actual)) if expected != actual => self.errors.push(format!(
+                "in {}: {} has type {} but its instruction produces {}",
+                block,
+                inst.result.expect("a type was found for the result"),
+                actual,
+                expected
+            )),
+            (None, Some(actual)) => self.errors.push(format!(
+                "in {}: {} has type {} but its instruction produces nothing",
+                block,
+                inst.result.expect("a type was found for the result"),
+                actual
+            )),
+            (Some(expected), None) => self.errors.push(format!(
+                "in {}: the result of an instruction producing {} is missing",
+                block, expected
+            )),
+            _ => {}
+        }
+    }
+
+    /// The type an instruction produces from its operands, or `None` if it
+    /// produces nothing.
+    fn inst_type(&self, inst: &Inst) -> Result<Option<Type>, String> {
+        let module = self.module;
+
+        let ty = match &inst.kind {
+            InstKind::Const(constant) => match constant {
+                Constant::Int(value) => match inst.result.map(|result| self.ty(result)) {
+                    Some(Type::I32) if i32::try_from(*value).is_err() => {
+                        return Err(format!("{} is out of range for i32", value));
+                    }
+                    Some(ty) if ty.is_integer() => ty,
+                    _ => return Err("integer constants must be i32 or i64".to_owned()),
+                },
+                Constant::Float(_) => Type::F64,
+                Constant::Bool(_) => Type::Bool,
+                Constant::Char(_) => Type::Char,
+                Constant::Str(_) => Type::Str,
+            },
+            InstKind::Copy(value) => self.ty(*value),
+            InstKind::Unary(op, value) => {
+                let ty = self.ty(*value);
+
+                match op {
+                    PrefixOp::Neg if ty.is_numeric() => ty,
+                    PrefixOp::Not if ty == Type::Bool => ty,
+                    _ => return Err(format!("cannot apply `{}` to {}", op.as_str(), ty)),
+                }
+            }
+            InstKind::Binary(op, left, right) => {
+                let (left, right) = (self.ty(*left), self.ty(*right));
+
+                if left != right {
+                    return Err(format!("`{}` is applied to {} and {}", binary_name(*op), left, right));
+                }
+
+                match op {
+                    InfixOp::Add if left == Type::Str => Type::Str,
+                    InfixOp::Add | InfixOp::Sub | InfixOp::Mul | InfixOp::Div | InfixOp::Mod
+                        if left.is_numeric() =>
+                    {
+                        left
+                    }
+                    InfixOp::Lt | InfixOp::Gt | InfixOp::LtEq | InfixOp::GtEq
+                        if left.is_numeric() || left == Type::Char || left == Type::Str =>
+                    {
+                        Type::Bool
+                    }
+                    InfixOp::Eq | InfixOp::NotEq => Type::Bool,
+                    _ => return Err(format!("cannot apply `{}` to {}", binary_name(*op), left)),
+                }
+            }
+            InstKind::ToString(_) => Type::Str,
+            InstKind::Call(Callee::Function(id), args) => {
+                let callee = match module.functions.get(id.0) {
+                    Some(callee) => callee,
+                    None => return Err(format!("function {} does not exist", id.0)),
+                };
+
+                self.check_args(&format!("@{}", callee.name), args, &callee.param_types())?;
+
+                callee.ret.clone()
+            }
+            InstKind::Call(Callee::Builtin(name), args) => {
+                let types: Vec<Type> = args.iter().map(|arg| self.ty(*arg)).collect();
+                let first = types.first().cloned().unwrap_or(Type::Unknown);
+
+                let (ok, ty) = match name.as_str() {
+                    "print" | "println" => (true, Type::Void),
+                    "len" => (types.len() == 1 && matches!(first, Type::Str | Type::Array(_)), Type::I32),
+                    "to_string" => (types.len() == 1, Type::Str),
+                    "abs" => (types.len() == 1 && first.is_numeric(), first),
+                    "min" | "max" => (types.len() == 2 && first.is_numeric() && types[1] == first, first),
+                    "sqrt" => (types.len() == 1 && first == Type::F64, Type::F64),
+                    _ => return Err(format!("there is no builtin named `std.{}`", name)),
+                };
+
+                if !ok {
+                    let types: Vec<String> = types.iter().map(Type::to_string).collect();
+                    return Err(format!("`std.{}` cannot be called with ({})", name, types.join(", ")));
+                }
+
+                ty
+            }
+            InstKind::Array(elements) => {
+                let ty = match inst.result.map(|result| self.ty(result)) {
+                    Some(Type::Array(element)) => *element,
+                    _ => return Err("arrays must have an array type".to_owned()),
+                };
+
+                for element in elements {
+                    self.expect(&ty, *element)?;
+                }
+
+                Type::Array(Box::new(ty))
+            }
+            InstKind::Index(array, index) => {
+                self.expect_integer(*index)?;
+
+                match self.ty(*array) {
+                    Type::Array(element) => *element,
+                    Type::Str => Type::Char,
+                    other => return Err(format!("cannot index into {}", other)),
+                }
+            }
+            InstKind::SetIndex(array, index, value) => {
+                self.expect_integer(*index)?;
+
+                match self.ty(*array) {
+                    Type::Array(element) => {
+                        self.expect(&element, *value)?;
+                        Type::Array(element)
+                    }
+                    other => return Err(format!("cannot set an element of {}", other)),
+                }
+            }
+            InstKind::Struct(name, values) => {
+                let fields = module
+                    .struct_fields(name)
+                    .ok_or_else(|| format!("there is no struct named `{}`", name))?;
+
+                let types: Vec<Type> = fields.iter().map(|(_, ty)| ty.clone()).collect();
+                self.check_args(&format!("struct {}", name), values, &types)?;
+
+                Type::Struct(name.clone())
+            }
+            InstKind::Field(value, field) => self.field_type(*value, field)?,
+            InstKind::SetField(value, field, new) => {
+                let ty = self.field_type(*value, field)?;
+                self.expect(&ty, *new)?;
+
+                self.ty(*value)
+            }
+            InstKind::Variant(name, variant, values) => {
+                let types = self.variant_fields(&Type::Enum(name.clone()), variant)?;
+                self.check_args(&format!("{}.{}", name, variant), values, &types)?;
+
+                Type::Enum(name.clone())
+            }
+            InstKind::IsVariant(value, variant) => {
+                self.variant_fields(&self.ty(*value), variant)?;
+                Type::Bool
+            }
+            InstKind::Payload(value, variant, i) => {
+                let types = self.variant_fields(&self.ty(*value), variant)?;
+
+                match types.get(*i) {
+                    Some(ty) => ty.clone(),
+                    None => return Err(format!("variant `{}` has no field {}", variant, i)),
+                }
+            }
+            InstKind::Load(global) => match module.globals.get(global.0) {
+                Some((_, ty)) => ty.clone(),
+                None => return Err(format!("global ${} does not exist", global.0)),
+            },
+            InstKind::Store(global, value) => match module.globals.get(global.0) {
+                Some((_, ty)) => {
+                    self.expect(ty, *value)?;
+                    Type::Void
+                }
+                None => return Err(format!("global ${} does not exist", global.0)),
+            },
+        };
+
+        Ok(if ty == Type::Void { None } else { Some(ty) })
+    }
+
+    fn expect(&self, expected: &Type, value: Value) -> Result<(), String> {
+        let actual = self.ty(value);
+
+        if actual == *expected {
+            Ok(())
+        } else {
+            Err(format!("expected {} but {} has type {}", expected, value, actual))
+        }
+    }
+
+    fn expect_integer(&self, value: Value) -> Result<(), String> {
+        let ty = self.ty(value);
+
+        if ty.is_integer() {
+            Ok(())
+        } else {
+            Err(format!("expected an integer but {} has type {}", value, ty))
+        }
+    }
+
+    fn check_args(&self, what: &str, args: &[Value], params: &[Type]) -> Result<(), String> {
+        if args.len() != params.len() {
+            return Err(format!("{} takes {} value(s) but is given {}", what, params.len(), args.len()));
+        }
+
+        for (arg, param) in args.iter().zip(params) {
+            self.expect(param, *arg)?;
+        }
+
+        Ok(())
+    }
+
+    fn field_type(&self, value: Value, field: &Symbol) -> Result<Type, String> {
+        let ty = self.ty(value);
+
+        let fields = match &ty {
+            Type::Struct(name) => self.module.struct_fields(name),
+            _ => None,
+        };
+
+        fields
+            .and_then(|fields| fields.iter().find(|(name, _)| name == field))
+            .map(|(_, ty)| ty.clone())
+            .ok_or_else(|| format!("there is no field `{}` on {}", field, ty))
+    }
+
+    fn variant_fields(&self, ty: &Type, variant: &Symbol) -> Result<Vec<Type>, String> {
+        let variants = match ty {
+            Type::Enum(name) => self.module.enum_variants(name),
+            _ => None,
+        };
+
+        variants
+            .and_then(|variants| variants.iter().find(|(name, _)| name == variant))
+            .map(|(_, fields)| fields.clone())
+            .ok_or_else(|| format!("there is no variant `{}` of {}", variant, ty))
+    }
+
+    fn check_terminator(&mut self, block: BlockId, terminator: &Terminator) {
+        match terminator {
+            Terminator::Branch(condition, _, _) if self.ty(*condition) != Type::Bool => {
+                let ty = self.ty(*condition);
+                self.errors.push(format!("in {}: branch on {} of type {}", block, condition, ty));
+            }
+            Terminator::Return(value) => {
+                let ty = value.map_or(Type::Void, |value| self.ty(value));
+
+                if ty != self.function.ret {
+                    let ret = &self.function.ret;
+                    let message = format!("in {}: returns {} from a function returning {}", block, ty, ret);
+                    self.errors.push(message);
+                }
+            }
+            _ => {}
+        }
+
+        for target in terminator.targets() {
+            let params = match self.function.blocks.get(target.block.0) {
+                Some(target) => &target.params,
+                None => {
+                    let message = format!("in {}: jumps to {}, which does not exist", block, target.block);
+                    self.errors.push(message);
+                    continue;
+                }
+            };
+
+            let types: Vec<Type> = params.iter().map(|param| self.ty(*param)).collect();
+
+            if let Err(message) = self.check_args(&target.block.to_string(), &target.args, &types) {
+                self.errors.push(format!("in {}: {}", block, message));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::lowered;
+
+    fn errors(module: &Module) -> Vec<String> {
+        match verify(module) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(VerifyError::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn test_accepts_lowered_programs() {
+        let tests = vec![
+            "fn f(n: i32): i32 {\nif n < 2 { return n }\nf(n - 1) + f(n - 2)\n}",
+            "let xs = [1, 2]\nfor let i = 0; i < 2; i++ { xs[i] *= 2 }",
+            "import std\nstruct P { x: f64 }\nlet p = P { x: 1.0 }\np.x = std.sqrt(p.x)",
+            "let i = 0\nwhile true { i++\nif i > 3 { break } else { continue } }",
+        ];
+
+        for input in tests {
+            let module = lowered(input);
+            assert_eq!(errors(&module), Vec::<String>::new(), "verifying:\n{}", module);
+        }
+    }
+
+    type Corruption = fn(&mut Function);
+
+    #[test]
+    fn test_errors() {
+        let input = "fn f(a: i32): i32 { let b = a + 1\nif b > 2 { b } else { 0 } }";
+
+        let tests: Vec<(Corruption, &[&str])> = vec![
+            (
+                |f| f.blocks[0].insts.swap(0, 1),
+                &["in @f: %1 is used in bb0 where its definition may not have run"],
+            ),
+            (
+                |f| f.blocks[3].insts[0].result = Some(Value(2)),
+                &["in @f: %2 is defined more than once"],
+            ),
+            (
+                |f| f.types[5] = Type::I64,
+                &["in @f: in bb3: expected i32 but %5 has type i64"],
+            ),
+            (
+                |f| f.types[1] = Type::Bool,
+                &[
+                    "in @f: in bb0: integer constants must be i32 or i64",
+                    "in @f: in bb0: `add` is applied to i32 and bool",
+                ],
+            ),
+            (
+                |f| f.blocks[1].terminator = Terminator::Jump(Target { block: BlockId(2), args: Vec::new() }),
+                &["in @f: in bb1: bb2 takes 1 value(s) but is given 0"],
+            ),
+            (
+                |f| f.blocks[2].terminator = Terminator::Return(None),
+                &["in @f: in bb2: returns void from a function returning i32"],
+            ),
+            (
+                |f| {
+                    if let Terminator::Branch(condition, _, _) = &mut f.blocks[0].terminator {
+                        *condition = Value(2);
+                    }
+                },
+                &["in @f: in bb0: branch on %2 of type i32"],
+            ),
+            (
+                |f| f.blocks[3].terminator.targets_mut()[0].args = vec![Value(6)],
+                &["in @f: %6 is used in bb3 where its definition may not have run"],
+            ),
+        ];
+
+        for (corrupt, expected) in tests {
+            let mut module = lowered(input);
+            corrupt(&mut module.functions[1]);
+
+            assert_eq!(errors(&module), expected, "verifying:\n{}", module);
+        }
+    }
+}
@@ -1,271 +1,882 @@
-use crate::token::{BufferPosition, KeywordKind, SymbolKind, Token, TokenKind};
-use std::collections::HashMap;
+use crate::symbol::Symbol;
+use crate::token::{BufferPosition, KeywordKind, LosslessToken, SymbolKind, Token, TokenKind, Trivia, TriviaKind};
+use icu_normalizer::ComposingNormalizerBorrowed;
+use std::fmt;
 use std::vec::Vec;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Lexer {
-    input: Vec<char>,
+pub enum LexErrorKind {
+    UnterminatedString,
+    UnterminatedChar,
+    UnterminatedBlockComment,
+    EmptyChar,
+    OverlongChar,
+    UnknownEscape(char),
+    MalformedHexEscape,
+    HexEscapeOutOfRange(u32),
+    MalformedUnicodeEscape,
+    InvalidCodePoint(u32),
+    SurrogateCodePoint(u32),
+    IntegerTooLarge,
+    UnexpectedChar(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub position: BufferPosition,
+}
+
+/// A comment skipped by the lexer, kept so tools like the formatter can put
+/// it back. `trailing` comments follow a token on the same line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub position: BufferPosition,
+    pub trailing: bool,
+}
+
+/// Reads tokens straight out of the source text, without copying it. The
+/// cursor is a byte offset into the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexer<'a> {
+    input: &'a str,
+    // Where the current character starts, and where the next one does
+    offset: usize,
     cursor: usize,
     current_char: Option<char>,
     current_line: usize,
     current_col: usize,
+    // The line the last token (other than a newline) ended on
+    last_token_line: usize,
+    errors: Vec<LexError>,
+    comments: Vec<Comment>,
+    // Trivia skipped since the last token, only kept once `next_lossless`
+    // has been called
+    trivia: Option<Vec<Trivia>>,
+    // The interpolated strings whose expressions are being lexed, innermost
+    // last, each with how many braces are open in it and where it started
+    interpolations: Vec<(usize, BufferPosition)>,
 }
 
-lazy_static! {
-    static ref KEYWORDS: HashMap<String, KeywordKind> = {
-        let mut m = HashMap::new();
-        m.insert("fn".to_owned(), KeywordKind::Fn);
-        m.insert("let".to_owned(), KeywordKind::Let);
-        m.insert("if".to_owned(), KeywordKind::If);
-        m.insert("else".to_owned(), KeywordKind::Else);
-        m.insert("for".to_owned(), KeywordKind::For);
-        m.insert("while".to_owned(), KeywordKind::While);
-        m.insert("enum".to_owned(), KeywordKind::Enum);
-        m.insert("struct".to_owned(), KeywordKind::Struct);
-        m.insert("break".to_owned(), KeywordKind::Break);
-        m.insert("continue".to_owned(), KeywordKind::Continue);
-        m.insert("true".to_owned(), KeywordKind::True);
-        m.insert("false".to_owned(), KeywordKind::False);
-        m.insert("match".to_owned(), KeywordKind::Match);
-        m.insert("return".to_owned(), KeywordKind::Return);
-        m
-    };
-}
+const KEYWORDS: [&str; 15] = [
+    "fn", "let", "if", "else", "for", "while", "enum", "struct", "break", "continue", "true", "false", "match",
+    "return", "import",
+];
 
-impl Lexer {
-    pub fn new(input: &str) -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
         let mut lexer = Lexer {
-            input: input.chars().collect(),
+            input,
+            offset: 0,
             cursor: 0,
             current_char: None,
             current_line: 1,
             current_col: 0,
+            last_token_line: 0,
+            errors: Vec::new(),
+            comments: Vec::new(),
+            trivia: None,
+            interpolations: Vec::new(),
         };
 
         lexer.read_char();
         lexer
     }
 
-    fn is_alpha(ch: char) -> bool {
-        ch.is_ascii_alphabetic() || ch == '_'
+    /// Errors found so far. Each of them corresponds to an `Illegal` token
+    /// or, for block comments, to skipped input.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
     }
 
-    fn is_digit(ch: char) -> bool {
-        ch.is_ascii_digit()
+    /// Comments skipped so far, in source order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
     }
 
-    fn is_alphanumeric(ch: char) -> bool {
-        Self::is_alpha(ch) || ch.is_ascii_digit()
+    /// Reads the next token along with its source text and trivia. Trivia
+    /// up to the end of a line trails the token before it, and the rest
+    /// leads the token after it. Once the input runs out this returns an
+    /// `EOF` token holding whatever trivia is left.
+    pub fn next_lossless(&mut self) -> LosslessToken {
+        if self.trivia.is_none() {
+            self.trivia = Some(Vec::new());
+        }
+
+        let (token, text) = match self.next_spanned() {
+            Some((token, text)) => (token, text.to_owned()),
+            None => (Token::new(TokenKind::EOF, self.current_position()), String::new()),
+        };
+
+        let leading = self.take_trivia();
+
+        let trailing = match token.kind {
+            TokenKind::Newline | TokenKind::EOF => Vec::new(),
+            _ => {
+                self.skip_whitespace();
+                self.take_trivia()
+            }
+        };
+
+        LosslessToken {
+            token,
+            text,
+            leading,
+            trailing,
+        }
     }
 
-    fn read_char(&mut self) -> Option<char> {
-        self.current_col += 1;
+    /// Reads the next token along with the source text it was read from.
+    pub fn next_spanned(&mut self) -> Option<(Token, &'a str)> {
+        self.skip_whitespace();
 
-        let r = if self.cursor >= self.input.len() {
-            None
+        let start = self.offset;
+        let token = self.next()?;
+
+        Some((token, &self.input[start..self.offset]))
+    }
+
+    /// Reads every token up to and including `EOF` with `next_lossless`.
+    pub fn tokenize_lossless(input: &str) -> Vec<LosslessToken> {
+        let mut lexer = Lexer::new(input);
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = lexer.next_lossless();
+            let done = token.token.kind == TokenKind::EOF;
+
+            tokens.push(token);
+
+            if done {
+                return tokens;
+            }
+        }
+    }
+
+    /// Whether the lexer is inside an expression embedded in a string, so
+    /// that lexing can't start afresh from where it is.
+    pub fn is_interpolating(&self) -> bool {
+        !self.interpolations.is_empty()
+    }
+
+    fn take_trivia(&mut self) -> Vec<Trivia> {
+        self.trivia.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn error(&mut self, kind: LexErrorKind, position: BufferPosition) {
+        self.errors.push(LexError { kind, position });
+    }
+
+    /// The position of the next character to be read. Right after `next`
+    /// returns, this is where the token it produced ends.
+    pub fn current_position(&self) -> BufferPosition {
+        BufferPosition::new(self.current_line, self.current_col)
+    }
+
+    /// Every reserved word, in no particular order.
+    pub fn keywords() -> impl Iterator<Item = &'static str> {
+        KEYWORDS.iter().copied()
+    }
+
+    fn is_ident_start(ch: char) -> bool {
+        if ch.is_ascii() {
+            ch.is_ascii_alphabetic() || ch == '_'
         } else {
-            Some(self.input[self.cursor])
-        };
+            unicode_ident::is_xid_start(ch)
+        }
+    }
 
-        self.current_char = r;
+    fn is_digit(ch: char) -> bool {
+        ch.is_ascii_digit()
+    }
 
-        self.cursor += 1;
+    fn is_ident_continue(ch: char) -> bool {
+        if ch.is_ascii() {
+            ch.is_ascii_alphanumeric() || ch == '_'
+        } else {
+            unicode_ident::is_xid_continue(ch)
+        }
+    }
 
-        if r == Some('\n') {
+    fn read_char(&mut self) -> Option<char> {
+        // The position of a newline belongs to the line it terminates, so
+        // only move to the next line once we step past it.
+        if self.current_char == Some('\n') {
             self.current_line += 1;
             self.current_col = 1;
+        } else {
+            self.current_col += 1;
         }
 
-        r
+        self.offset = self.cursor;
+        self.current_char = self.char_at(self.cursor);
+        self.cursor += self.current_char.map_or(0, char::len_utf8);
+
+        self.current_char
     }
 
     fn peek_char(&self) -> Option<char> {
-        if self.cursor >= self.input.len() {
-            None
-        } else {
-            Some(self.input[self.cursor])
+        self.char_at(self.cursor)
+    }
+
+    fn char_at(&self, offset: usize) -> Option<char> {
+        match self.input.as_bytes().get(offset) {
+            // Most source is ASCII, which doesn't need decoding
+            Some(byte) if byte.is_ascii() => Some(*byte as char),
+            Some(_) => self.input[offset..].chars().next(),
+            None => None,
         }
     }
 
-    fn read_identifier(&mut self) -> String {
-        let mut buffer = String::new();
+    /// Reads characters for as long as they match, returning the text read
+    /// starting at the current character.
+    fn read_while(&mut self, matches: fn(char) -> bool) -> &'a str {
+        let start = self.offset;
 
         loop {
-            buffer.push(self.current_char.unwrap());
-
             self.read_char();
 
-            if self.current_char == None || !Lexer::is_alphanumeric(self.current_char.unwrap()) {
+            if !self.current_char.is_some_and(matches) {
                 break;
             }
         }
 
-        buffer
+        &self.input[start..self.offset]
     }
 
-    // TODO(kosi): Come back and add universal/unicode characters
     fn read_escaped_char_literal(&mut self) -> Option<char> {
+        let position = self.current_position();
+
         self.read_char();
 
         match self.current_char {
-            Some('\'') | Some('\"') | Some('\\') => self.current_char,
+            Some('\'') | Some('\"') | Some('\\') | Some('{') | Some('}') => self.current_char,
             Some('t') => Some('\t'),
             Some('n') => Some('\n'),
             Some('f') => Some('\x0C'),
             Some('r') => Some('\r'),
             Some('v') => Some('\x0B'),
-            _ => None, // TODO(kosi): Add unknown escape character error here
+            Some('x') => self.read_hex_escape(position),
+            Some('u') => self.read_unicode_escape(position),
+            Some(ch) => {
+                self.error(LexErrorKind::UnknownEscape(ch), position);
+                None
+            }
+            // The caller reports the unterminated literal
+            None => None,
         }
     }
 
-    fn read_char_literal(&mut self) -> Option<char> {
+    /// Reads the two hex digits of a `\x7F` escape, which only reaches as
+    /// far as ASCII.
+    fn read_hex_escape(&mut self, position: BufferPosition) -> Option<char> {
+        let digits = self.read_hex_digits(2);
+
+        if digits.len() != 2 {
+            self.error(LexErrorKind::MalformedHexEscape, position);
+            return None;
+        }
+
+        let value = u32::from_str_radix(digits, 16).unwrap();
+
+        if value > 0x7F {
+            self.error(LexErrorKind::HexEscapeOutOfRange(value), position);
+            return None;
+        }
+
+        char::from_u32(value)
+    }
+
+    /// Reads the braced hex digits of a `\u{1F600}` escape.
+    fn read_unicode_escape(&mut self, position: BufferPosition) -> Option<char> {
+        if self.peek_char() != Some('{') {
+            self.error(LexErrorKind::MalformedUnicodeEscape, position);
+            return None;
+        }
+
         self.read_char();
 
-        let ret = if self.current_char == Some('\\') {
-            self.read_escaped_char_literal()
-        } else {
-            self.current_char
-        };
+        // Read one digit too many so overlong escapes can be told apart
+        let digits = self.read_hex_digits(7);
+
+        if digits.is_empty() || digits.len() > 6 || self.peek_char() != Some('}') {
+            self.error(LexErrorKind::MalformedUnicodeEscape, position);
+            return None;
+        }
 
         self.read_char();
 
-        // TODO(kosi): Generate some error message here
-        if self.current_char != Some('\'') {
-            None
+        let value = u32::from_str_radix(digits, 16).unwrap();
+
+        match char::from_u32(value) {
+            Some(ch) => Some(ch),
+            None if (0xD800..=0xDFFF).contains(&value) => {
+                self.error(LexErrorKind::SurrogateCodePoint(value), position);
+                None
+            }
+            None => {
+                self.error(LexErrorKind::InvalidCodePoint(value), position);
+                None
+            }
+        }
+    }
+
+    /// Reads up to `limit` hex digits following the current character.
+    fn read_hex_digits(&mut self, limit: usize) -> &'a str {
+        let start = self.cursor;
+
+        while self.cursor - start < limit && self.peek_char().is_some_and(|ch| ch.is_ascii_hexdigit()) {
+            self.read_char();
+        }
+
+        &self.input[start..self.cursor]
+    }
+
+    fn read_char_literal(&mut self) -> Option<char> {
+        let position = self.current_position();
+
+        let value = match self.peek_char() {
+            None | Some('\n') => {
+                self.error(LexErrorKind::UnterminatedChar, position);
+                return None;
+            }
+            Some('\'') => {
+                self.read_char();
+                self.error(LexErrorKind::EmptyChar, position);
+                return None;
+            }
+            Some(_) => {
+                self.read_char();
+
+                if self.current_char == Some('\\') {
+                    self.read_escaped_char_literal()
+                } else {
+                    self.current_char
+                }
+            }
+        };
+
+        if self.peek_char() == Some('\'') {
+            self.read_char();
+            return value;
+        }
+
+        // Skip the rest of a literal like 'ab' so lexing resumes after it
+        while !matches!(self.peek_char(), None | Some('\n') | Some('\'')) {
+            self.read_char();
+        }
+
+        if self.peek_char() == Some('\'') {
+            self.read_char();
+            self.error(LexErrorKind::OverlongChar, position);
         } else {
-            ret
+            self.error(LexErrorKind::UnterminatedChar, position);
+        }
+
+        None
+    }
+
+    fn read_string_literal(&mut self) -> TokenKind {
+        let position = self.current_position();
+        let error_count = self.errors.len();
+
+        match self.read_string_text(position) {
+            Some((text, true)) => {
+                self.interpolations.push((0, position));
+                TokenKind::StringStart(text)
+            }
+            Some((text, false)) if self.errors.len() == error_count => TokenKind::StringLiteral(text),
+            _ => TokenKind::Illegal,
         }
     }
 
-    fn read_string_literal(&mut self) -> Option<String> {
+    /// Carries on with an interpolated string after the `}` closing one of
+    /// its expressions.
+    fn read_string_continuation(&mut self) -> TokenKind {
+        let (_, position) = self.interpolations.pop().unwrap();
+
+        match self.read_string_text(position) {
+            Some((text, true)) => {
+                self.interpolations.push((0, position));
+                TokenKind::StringMiddle(text)
+            }
+            Some((text, false)) => TokenKind::StringEnd(text),
+            None => TokenKind::Illegal,
+        }
+    }
+
+    /// Reads the text of a string up to its closing quote, or up to the `{`
+    /// starting an embedded expression, saying whether it stopped at one.
+    /// `position` is where the string started, for when it is never closed.
+    fn read_string_text(&mut self, position: BufferPosition) -> Option<(String, bool)> {
         let mut buf = String::new();
-        
+
         loop {
             self.read_char();
 
             match self.current_char {
-                None => return None, // TODO(kosi): Add unterminated string error here
-                Some('"') => return Some(buf),
+                None => {
+                    self.error(LexErrorKind::UnterminatedString, position);
+                    return None;
+                }
+                Some('"') => return Some((buf, false)),
+                Some('{') => return Some((buf, true)),
                 Some('\\') => {
                     if let Some(ch) = self.read_escaped_char_literal() {
                         buf.push(ch);
                     }
-                },
+                }
                 Some(ch) => buf.push(ch)
             }
         }
     }
 
-    fn read_decimal_literal(&mut self) -> i64 {
-        let mut buffer = String::new();
+    /// Whether the current `r` starts a raw string like `r"..."` or
+    /// `r#"..."#` rather than a name.
+    fn at_raw_string(&self) -> bool {
+        let rest = &self.input[self.cursor..];
+        rest.trim_start_matches('#').starts_with('"')
+    }
 
-        loop {
-            buffer.push(self.current_char.unwrap());
+    /// Reads a raw string, which has no escapes and ends at the first quote
+    /// followed by as many `#`s as it started with.
+    fn read_raw_string_literal(&mut self) -> Option<String> {
+        let position = self.current_position();
+        let mut hashes = 0;
 
+        while self.peek_char() == Some('#') {
             self.read_char();
+            hashes += 1;
+        }
 
-            if self.current_char == None || !Lexer::is_digit(self.current_char.unwrap()) {
-                break;
+        self.read_char();
+        let start = self.cursor;
+
+        loop {
+            self.read_char();
+
+            match self.current_char {
+                None => {
+                    self.error(LexErrorKind::UnterminatedString, position);
+                    return None;
+                }
+                Some('"') if self.input[self.cursor..].starts_with(&"#".repeat(hashes)) => {
+                    let text = &self.input[start..self.offset];
+
+                    for _ in 0..hashes {
+                        self.read_char();
+                    }
+
+                    return Some(text.to_owned());
+                }
+                Some(_) => {}
             }
         }
+    }
 
-        buffer.parse::<i64>().unwrap()
+    fn at_multiline_string(&self) -> bool {
+        self.input[self.offset..].starts_with("\"\"\"")
     }
 
-    fn lookup_identifier(identifier: String) -> TokenKind {
-        if let Some(keyword) = KEYWORDS.get(&identifier) {
-            TokenKind::Keyword(*keyword)
+    /// Reads a string between triple quotes. When it spans several lines,
+    /// the indentation the lines have in common is stripped, as are the
+    /// first and last lines if they are blank, so the string can be indented
+    /// along with the code around it. Escapes work as in other strings.
+    fn read_multiline_string_literal(&mut self) -> Option<String> {
+        let position = self.current_position();
+
+        self.read_char();
+        self.read_char();
+
+        let start = self.cursor;
+
+        let end = match Lexer::multiline_string_end(&self.input[start..]) {
+            Some(length) => start + length,
+            None => {
+                while self.read_char().is_some() {}
+
+                self.error(LexErrorKind::UnterminatedString, position);
+                return None;
+            }
+        };
+
+        let body = &self.input[start..end];
+        let mut content = start..end;
+        let mut indent = 0;
+        let mut stripped = 0;
+
+        if let (Some(first), Some(last)) = (body.find('\n'), body.rfind('\n')) {
+            indent = body[first + 1..]
+                .split('\n')
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+                .min()
+                .unwrap_or(0);
+
+            if body[..first].trim().is_empty() {
+                content.start = start + first + 1;
+            } else {
+                // Text on the opening line is kept as it is
+                stripped = indent;
+            }
+
+            if body[last + 1..].trim().is_empty() {
+                content.end = (start + last).max(content.start);
+            }
+        }
+
+        while self.cursor < content.start {
+            self.read_char();
+        }
+
+        let mut buf = String::new();
+        let mut valid = true;
+
+        while self.cursor < content.end {
+            self.read_char();
+
+            match self.current_char {
+                Some(' ') | Some('\t') if stripped < indent => stripped += 1,
+                // Line endings are always `\n`, whatever the source uses
+                Some('\r') if self.peek_char() == Some('\n') => {}
+                Some('\n') => {
+                    buf.push('\n');
+                    stripped = 0;
+                }
+                Some('\\') => {
+                    match self.read_escaped_char_literal() {
+                        Some(ch) => buf.push(ch),
+                        None => valid = false,
+                    }
+
+                    stripped = indent;
+                }
+                Some(ch) => {
+                    buf.push(ch);
+                    stripped = indent;
+                }
+                None => break,
+            }
+        }
+
+        // Stop on the last of the closing quotes
+        while self.offset < end + 2 {
+            self.read_char();
+        }
+
+        if valid {
+            Some(buf)
         } else {
-            TokenKind::Identifier(identifier)
+            None
+        }
+    }
+
+    /// Finds the closing triple quote of a multi-line string, skipping over
+    /// escaped quotes.
+    fn multiline_string_end(body: &str) -> Option<usize> {
+        let bytes = body.as_bytes();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' if bytes[i..].starts_with(b"\"\"\"") => return Some(i),
+                _ => i += 1,
+            }
+        }
+
+        None
+    }
+
+    fn read_number_literal(&mut self) -> TokenKind {
+        let position = self.current_position();
+        let start = self.offset;
+        let integer_part = self.read_while(Lexer::is_digit);
+
+        // A dot only starts a fractional part when it is followed by a digit
+        if self.current_char != Some('.') || !self.peek_char().is_some_and(Lexer::is_digit) {
+            return match integer_part.parse::<i64>() {
+                Ok(value) => TokenKind::IntegerLiteral(value),
+                Err(_) => {
+                    self.error(LexErrorKind::IntegerTooLarge, position);
+                    TokenKind::Illegal
+                }
+            };
+        }
+
+        self.read_char();
+        self.read_while(Lexer::is_digit);
+
+        TokenKind::FloatLiteral(self.input[start..self.offset].parse::<f64>().unwrap())
+    }
+
+    fn lookup_identifier(identifier: &str) -> TokenKind {
+        let keyword = match identifier {
+            "fn" => KeywordKind::Fn,
+            "let" => KeywordKind::Let,
+            "if" => KeywordKind::If,
+            "else" => KeywordKind::Else,
+            "for" => KeywordKind::For,
+            "while" => KeywordKind::While,
+            "enum" => KeywordKind::Enum,
+            "struct" => KeywordKind::Struct,
+            "break" => KeywordKind::Break,
+            "continue" => KeywordKind::Continue,
+            "true" => KeywordKind::True,
+            "false" => KeywordKind::False,
+            "match" => KeywordKind::Match,
+            "return" => KeywordKind::Return,
+            "import" => KeywordKind::Import,
+            _ => return TokenKind::Identifier(Lexer::intern_identifier(identifier)),
+        };
+
+        TokenKind::Keyword(keyword)
+    }
+
+    /// Interns an identifier in NFC, so that differently composed spellings
+    /// of a name are the same name.
+    fn intern_identifier(identifier: &str) -> Symbol {
+        if identifier.is_ascii() {
+            return Symbol::intern(identifier);
         }
+
+        Symbol::intern(&ComposingNormalizerBorrowed::new_nfc().normalize(identifier))
     }
 
     fn skip_line(&mut self) {
         loop {
             let next_ch = self.read_char();
 
-            if next_ch == None || next_ch == Some('\n') {
+            if next_ch.is_none() || next_ch == Some('\n') {
                 break;
             }
         }
     }
 
-    // TODO(kosi): Fix this function so it doesn't look like this mess
-    fn skip_whitespace(&mut self) {
+    /// Skips a comment, remembering its text.
+    fn skip_comment(&mut self) {
+        let position = self.current_position();
+        let start = self.offset;
+
+        if self.peek_char() == Some('/') {
+            self.skip_line();
+        } else {
+            self.skip_block_comment();
+        }
+
+        // The newline ending a line comment is still a token
+        let end = self.offset;
+
+        self.comments.push(Comment {
+            text: self.input[start..end].to_owned(),
+            position,
+            trailing: self.last_token_line == position.line,
+        });
+    }
+
+    fn skip_block_comment(&mut self) {
+        let position = self.current_position();
+
+        // Step onto the '*' of the opening "/*"
+        self.read_char();
+
         loop {
-            if let Some(current_char) = self.current_char {
-                if current_char != '\n' && current_char.is_whitespace() {
+            match self.read_char() {
+                None => {
+                    self.error(LexErrorKind::UnterminatedBlockComment, position);
+                    break;
+                }
+                Some('*') if self.peek_char() == Some('/') => {
                     self.read_char();
-                    continue;
-                } else if current_char == '/' {
-                    if let Some(next_ch) = self.peek_char() {
-                        if next_ch == '/' {
-                            self.read_char();
-                            self.skip_line();
-                            break;
-                        }
-                    }
+                    self.read_char();
+                    break;
                 }
+                _ => {}
+            }
+        }
+    }
 
-                break;
+    fn skip_whitespace(&mut self) {
+        while let Some(current_char) = self.current_char {
+            let start = self.offset;
+
+            let kind = match (current_char, self.peek_char()) {
+                ('/', Some('/')) => TriviaKind::LineComment,
+                ('/', Some('*')) => TriviaKind::BlockComment,
+                // Newlines don't end statements inside embedded expressions
+                (ch, _) if ch.is_whitespace() && (ch != '\n' || self.is_interpolating()) => TriviaKind::Whitespace,
+                _ => break,
+            };
+
+            if kind == TriviaKind::Whitespace {
+                self.read_char();
+            } else {
+                self.skip_comment();
             }
 
-            break;
+            self.record_trivia(kind, start);
         }
     }
 
+    fn record_trivia(&mut self, kind: TriviaKind, start: usize) {
+        let text = &self.input[start..self.offset];
+
+        if let Some(trivia) = &mut self.trivia {
+            match trivia.last_mut() {
+                Some(last) if kind == TriviaKind::Whitespace && last.kind == TriviaKind::Whitespace => {
+                    last.text.push_str(text)
+                }
+                _ => trivia.push(Trivia {
+                    kind,
+                    text: text.to_owned(),
+                }),
+            }
+        }
+    }
+
+    /// Picks between a one character symbol and a two character symbol
+    /// depending on the character that follows the cursor.
+    fn read_compound_symbol(&mut self, single: SymbolKind, compounds: &[(char, SymbolKind)]) -> TokenKind {
+        for (next_ch, symbol) in compounds {
+            if self.peek_char() == Some(*next_ch) {
+                self.read_char();
+                return TokenKind::Symbol(symbol.clone());
+            }
+        }
+
+        TokenKind::Symbol(single)
+    }
+
     pub fn next_token(&mut self) -> Token {
         let token = self.next();
 
         match token {
-            None => Token::EOF,
+            None => Token::new(TokenKind::EOF, self.current_position()),
             Some(tok) => tok
         }
     }
 }
 
-impl Iterator for Lexer {
+impl LexErrorKind {
+    /// Whether the error is caused by the input ending in the middle of a
+    /// token, meaning more input could still make it valid.
+    pub fn is_unterminated(&self) -> bool {
+        matches!(
+            self,
+            LexErrorKind::UnterminatedString | LexErrorKind::UnterminatedBlockComment
+        )
+    }
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::UnterminatedChar => write!(f, "unterminated character literal"),
+            LexErrorKind::UnterminatedBlockComment => write!(f, "unterminated block comment"),
+            LexErrorKind::EmptyChar => write!(f, "empty character literal"),
+            LexErrorKind::OverlongChar => write!(f, "character literal may only contain one character"),
+            LexErrorKind::UnknownEscape(ch) => write!(f, "unknown character escape `\\{}`", ch.escape_debug()),
+            LexErrorKind::MalformedHexEscape => write!(f, "hex escape must be `\\x` followed by two hex digits"),
+            LexErrorKind::HexEscapeOutOfRange(value) => {
+                write!(f, "hex escape `\\x{:02X}` is out of range, it must be at most `\\x7F`", value)
+            }
+            LexErrorKind::MalformedUnicodeEscape => {
+                write!(f, "unicode escape must be `\\u{{...}}` with one to six hex digits")
+            }
+            LexErrorKind::InvalidCodePoint(value) => write!(f, "invalid unicode code point `\\u{{{:X}}}`", value),
+            LexErrorKind::SurrogateCodePoint(value) => {
+                write!(f, "unicode escape `\\u{{{:X}}}` is a surrogate, which is not a character", value)
+            }
+            LexErrorKind::IntegerTooLarge => {
+                write!(f, "integer literal is too large, the largest is {}", i64::MAX)
+            }
+            LexErrorKind::UnexpectedChar(ch) => write!(f, "unexpected character `{}`", ch.escape_debug()),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.kind)
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
     type Item = Token;
 
-    // TODO(kosi): Rewrite this to take errors into account
-    // maybe call an error method that accumulates errors in an
-    // internal buffer in Lexer struct?
-    //
     fn next(&mut self) -> Option<Self::Item> {
+        let token = self.read_token();
+
+        if let Some(token) = &token {
+            if token.kind != TokenKind::Newline {
+                self.last_token_line = self.current_line;
+            }
+        }
+
+        token
+    }
+}
+
+impl<'a> Lexer<'a> {
+    fn read_token(&mut self) -> Option<Token> {
         self.skip_whitespace();
 
         let current_line = self.current_line;
         let current_col = self.current_col;
 
         let token_kind = match self.current_char {
-            Some('=') => match self.peek_char() {
-                Some('=') => {
-                    self.read_char();
-                    TokenKind::Symbol(SymbolKind::Eq)
-                }
-                _ => TokenKind::Symbol(SymbolKind::Assign),
-            },
-            Some('+') => TokenKind::Symbol(SymbolKind::Plus),
+            Some('=') => self.read_compound_symbol(
+                SymbolKind::Assign,
+                &[('=', SymbolKind::Eq), ('>', SymbolKind::FatArrow)],
+            ),
+            Some('+') => self.read_compound_symbol(
+                SymbolKind::Plus,
+                &[('+', SymbolKind::Increment), ('=', SymbolKind::PlusAssign)],
+            ),
+            Some('-') => self.read_compound_symbol(
+                SymbolKind::Minus,
+                &[('-', SymbolKind::Decrement), ('=', SymbolKind::MinusAssign)],
+            ),
+            Some('*') => self.read_compound_symbol(SymbolKind::Asterisk, &[('=', SymbolKind::AsteriskAssign)]),
+            Some('/') => self.read_compound_symbol(SymbolKind::Slash, &[('=', SymbolKind::SlashAssign)]),
+            Some('%') => self.read_compound_symbol(SymbolKind::Mod, &[('=', SymbolKind::ModAssign)]),
+            Some('!') => self.read_compound_symbol(SymbolKind::Bang, &[('=', SymbolKind::NotEq)]),
+            Some('<') => self.read_compound_symbol(SymbolKind::Lt, &[('=', SymbolKind::LtEq)]),
+            Some('>') => self.read_compound_symbol(SymbolKind::Gt, &[('=', SymbolKind::GtEq)]),
+            Some('&') if self.peek_char() == Some('&') => {
+                self.read_char();
+                TokenKind::Symbol(SymbolKind::And)
+            }
+            Some('|') if self.peek_char() == Some('|') => {
+                self.read_char();
+                TokenKind::Symbol(SymbolKind::Or)
+            }
             Some('(') => TokenKind::Symbol(SymbolKind::LeftParen),
             Some(')') => TokenKind::Symbol(SymbolKind::RightParen),
-            Some('{') => TokenKind::Symbol(SymbolKind::LeftBrace),
-            Some('}') => TokenKind::Symbol(SymbolKind::RightBrace),
+            Some('{') => {
+                if let Some((braces, _)) = self.interpolations.last_mut() {
+                    *braces += 1;
+                }
+
+                TokenKind::Symbol(SymbolKind::LeftBrace)
+            }
+            Some('}') => match self.interpolations.last_mut() {
+                Some((0, _)) => self.read_string_continuation(),
+                Some((braces, _)) => {
+                    *braces -= 1;
+                    TokenKind::Symbol(SymbolKind::RightBrace)
+                }
+                None => TokenKind::Symbol(SymbolKind::RightBrace),
+            },
+            Some('[') => TokenKind::Symbol(SymbolKind::LeftBracket),
+            Some(']') => TokenKind::Symbol(SymbolKind::RightBracket),
             Some(',') => TokenKind::Symbol(SymbolKind::Comma),
             Some(';') => TokenKind::Symbol(SymbolKind::Semicolon),
             Some(':') => TokenKind::Symbol(SymbolKind::Colon),
-            Some('!') => match self.peek_char() {
-                Some('=') => {
-                    self.read_char();
-                    TokenKind::Symbol(SymbolKind::NotEq)
-                }
-                _ => TokenKind::Symbol(SymbolKind::Bang),
-            },
-            Some('-') => TokenKind::Symbol(SymbolKind::Minus),
-            Some('*') => TokenKind::Symbol(SymbolKind::Asterisk),
-            Some('<') => TokenKind::Symbol(SymbolKind::Lt),
-            Some('>') => TokenKind::Symbol(SymbolKind::Gt),
-            Some('/') => TokenKind::Symbol(SymbolKind::Slash),
-            Some('%') => TokenKind::Symbol(SymbolKind::Mod),
+            Some('.') => TokenKind::Symbol(SymbolKind::Dot),
+            Some('?') => TokenKind::Symbol(SymbolKind::Question),
             Some('\n') => TokenKind::Newline,
             Some('\'') => {
                 let lit = self.read_char_literal();
@@ -275,18 +886,18 @@ impl Iterator for Lexer {
                     TokenKind::Illegal
                 }
             }
-            Some('"') => {
-                let lit = self.read_string_literal();
-
-                if let Some(l) = lit {
-                    TokenKind::StringLiteral(l)
-                } else {
-                    TokenKind::Illegal
-                }
-            }
+            Some('r') if self.at_raw_string() => match self.read_raw_string_literal() {
+                Some(literal) => TokenKind::StringLiteral(literal),
+                None => TokenKind::Illegal,
+            },
+            Some('"') if self.at_multiline_string() => match self.read_multiline_string_literal() {
+                Some(literal) => TokenKind::StringLiteral(literal),
+                None => TokenKind::Illegal,
+            },
+            Some('"') => self.read_string_literal(),
             Some(ch) => {
-                if Lexer::is_alpha(ch) {
-                    let ident = self.read_identifier();
+                if Lexer::is_ident_start(ch) {
+                    let ident = self.read_while(Lexer::is_ident_continue);
                     let token_kind = Lexer::lookup_identifier(ident);
                     return Some(Token::new(
                         token_kind,
@@ -294,18 +905,29 @@ impl Iterator for Lexer {
                     ));
                 } else if Lexer::is_digit(ch) {
                     // TODO(kosi): Comeback and add hex, binary, and octal literals
-                    // TODO(kosi): Comeback and add floating point literals
-                    let literal = self.read_decimal_literal();
+                    let literal = self.read_number_literal();
 
                     return Some(Token::new(
-                        TokenKind::IntegerLiteral(literal),
+                        literal,
                         BufferPosition::new(current_line, current_col),
                     ));
                 } else {
+                    self.error(
+                        LexErrorKind::UnexpectedChar(ch),
+                        BufferPosition::new(current_line, current_col),
+                    );
                     TokenKind::Illegal
                 }
             }
-            None => return None,
+            None => {
+                // The input ended inside an embedded expression
+                if let Some((_, position)) = self.interpolations.first() {
+                    self.error(LexErrorKind::UnterminatedString, *position);
+                    self.interpolations.clear();
+                }
+
+                return None;
+            }
         };
 
         self.read_char();
@@ -342,6 +964,85 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_raw_strings() {
+        let input = r####"r"C:\path" r#"say "hi""# r##"a "# b"## r"" r
+"####;
+
+        let tests = vec![
+            TokenKind::StringLiteral(r"C:\path".to_owned()),
+            TokenKind::StringLiteral(r#"say "hi""#.to_owned()),
+            TokenKind::StringLiteral(r##"a "# b"##.to_owned()),
+            TokenKind::StringLiteral(String::new()),
+            TokenKind::Identifier(Symbol::intern("r")),
+            TokenKind::Newline,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for test in tests {
+            assert_eq!(test, lexer.next().unwrap().kind);
+        }
+
+        assert_eq!(lexer.next(), None);
+        assert!(lexer.errors().is_empty());
+    }
+
+    #[test]
+    fn test_multiline_strings() {
+        let tests = vec![
+            ("\"\"\"{\"a\": 1}\"\"\"", "{\"a\": 1}"),
+            ("\"\"\"\n    SELECT *\n      FROM t\n    \"\"\"", "SELECT *\n  FROM t"),
+            ("\"\"\"\n\tone\n\n\ttwo\\t\\u{21}\n\t\"\"\"", "one\n\ntwo\t!"),
+            ("\"\"\"first\n    second\"\"\"", "first\nsecond"),
+            ("\"\"\"\r\n  a\r\n  b\r\n  \"\"\"", "a\nb"),
+            ("\"\"\"quote \\\"\"\" inside\"\"\"", "quote \"\"\" inside"),
+            ("\"\"\"\n\"\"\"", ""),
+        ];
+
+        for (input, expected) in tests {
+            let mut lexer = Lexer::new(input);
+
+            assert_eq!(lexer.next().unwrap().kind, TokenKind::StringLiteral(expected.to_owned()), "{}", input);
+            assert_eq!(lexer.next(), None);
+            assert!(lexer.errors().is_empty());
+        }
+
+        let positions: Vec<BufferPosition> = Lexer::new("x = \"\"\"\n  a\n  \"\"\" + y")
+            .map(|token| token.position)
+            .collect();
+
+        assert_eq!(positions[3..], [BufferPosition::new(3, 7), BufferPosition::new(3, 9)]);
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let input = "\"a{x}b{ {y}\n}\\{c\" \"{\"s\"}\"";
+
+        let tests = vec![
+            TokenKind::StringStart("a".to_owned()),
+            TokenKind::Identifier(Symbol::intern("x")),
+            TokenKind::StringMiddle("b".to_owned()),
+            TokenKind::Symbol(SymbolKind::LeftBrace),
+            TokenKind::Identifier(Symbol::intern("y")),
+            TokenKind::Symbol(SymbolKind::RightBrace),
+            TokenKind::StringEnd("{c".to_owned()),
+            TokenKind::StringStart(String::new()),
+            TokenKind::StringLiteral("s".to_owned()),
+            TokenKind::StringEnd(String::new()),
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for test in tests {
+            assert_eq!(test, lexer.next().unwrap().kind);
+        }
+
+        assert_eq!(lexer.next(), None);
+        assert!(lexer.errors().is_empty());
+        assert!(!lexer.is_interpolating());
+    }
+
     #[test]
     fn test_comments() {
         let input = "// no tokens
@@ -350,7 +1051,7 @@ mod tests {
         let tests = vec![
             TokenKind::Newline,
             TokenKind::Keyword(KeywordKind::Let),
-            TokenKind::Identifier("token".to_owned()),
+            TokenKind::Identifier(Symbol::intern("token")),
             TokenKind::Symbol(SymbolKind::Semicolon)
         ];
 
@@ -363,6 +1064,93 @@ mod tests {
        }
     }
 
+    #[test]
+    fn test_comment_text() {
+        let input = "// first\nlet x = 1 // second\n/* third\n */ x";
+
+        let mut lexer = Lexer::new(input);
+        lexer.by_ref().for_each(drop);
+
+        assert_eq!(
+            lexer.comments(),
+            &[
+                Comment { text: "// first".to_owned(), position: BufferPosition::new(1, 1), trailing: false },
+                Comment { text: "// second".to_owned(), position: BufferPosition::new(2, 11), trailing: true },
+                Comment { text: "/* third\n */".to_owned(), position: BufferPosition::new(3, 1), trailing: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spanned_tokens() {
+        let input = "let ∂_s = \"ü\\n\" /* ∑ */ + 12.50\n";
+        let mut lexer = Lexer::new(input);
+        let mut spans = Vec::new();
+
+        while let Some((token, text)) = lexer.next_spanned() {
+            spans.push((text, token.position.column));
+        }
+
+        assert_eq!(
+            spans,
+            vec![
+                ("let", 1),
+                ("∂", 5),
+                ("_s", 6),
+                ("=", 9),
+                ("\"ü\\n\"", 11),
+                ("+", 25),
+                ("12.50", 27),
+                ("\n", 32)
+            ]
+        );
+
+        let error = LexError {
+            kind: LexErrorKind::UnexpectedChar('∂'),
+            position: BufferPosition::new(1, 5),
+        };
+        assert_eq!(lexer.errors(), &[error]);
+    }
+
+    #[test]
+    fn test_lossless_tokens() {
+        let input = "  let x = 1 // one\n\n/* two */ \"é\"";
+        let tokens = Lexer::tokenize_lossless(input);
+
+        let trivia = |kind, text: &str| Trivia { kind, text: text.to_owned() };
+
+        assert_eq!(tokens[0].text, "let");
+        assert_eq!(tokens[0].leading, vec![trivia(TriviaKind::Whitespace, "  ")]);
+        assert_eq!(tokens[0].trailing, vec![trivia(TriviaKind::Whitespace, " ")]);
+
+        assert_eq!(tokens[3].text, "1");
+        assert_eq!(
+            tokens[3].trailing,
+            vec![trivia(TriviaKind::Whitespace, " "), trivia(TriviaKind::LineComment, "// one")]
+        );
+
+        assert_eq!(tokens[5].token.kind, TokenKind::Newline);
+        assert_eq!(tokens[6].text, "\"é\"");
+        assert_eq!(
+            tokens[6].leading,
+            vec![trivia(TriviaKind::BlockComment, "/* two */"), trivia(TriviaKind::Whitespace, " ")]
+        );
+        assert_eq!(tokens[7].token.kind, TokenKind::EOF);
+
+        for input in &[input, "x\r\n  y /* open", "\"abc\n1", "  \n", ""] {
+            let text: String = Lexer::tokenize_lossless(input)
+                .iter()
+                .flat_map(|token| {
+                    let leading = token.leading.iter().map(|trivia| trivia.text.as_str());
+                    let trailing = token.trailing.iter().map(|trivia| trivia.text.as_str());
+                    leading.chain(std::iter::once(token.text.as_str())).chain(trailing).collect::<Vec<_>>()
+                })
+                .collect();
+
+            assert_eq!(&text, input);
+        }
+    }
+
     #[test]
     fn test_char_literals() {
         let input = r#"'a'
@@ -395,6 +1183,142 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unicode() {
+        let input = "let café = '\\u{1F600}' + \"\\x41\\u{e9}\" + λ_1 + cafe\u{301}";
+
+        let tests = vec![
+            TokenKind::Keyword(KeywordKind::Let),
+            TokenKind::Identifier(Symbol::intern("café")),
+            TokenKind::Symbol(SymbolKind::Assign),
+            TokenKind::CharLiteral('😀'),
+            TokenKind::Symbol(SymbolKind::Plus),
+            TokenKind::StringLiteral("Aé".to_owned()),
+            TokenKind::Symbol(SymbolKind::Plus),
+            TokenKind::Identifier(Symbol::intern("λ_1")),
+            TokenKind::Symbol(SymbolKind::Plus),
+            // Decomposed, but the same name once normalized
+            TokenKind::Identifier(Symbol::intern("café")),
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for test in tests {
+            assert_eq!(test, lexer.next().unwrap().kind);
+        }
+
+        assert_eq!(lexer.next(), None);
+        assert!(lexer.errors().is_empty());
+
+        // Emoji and other symbols still can't start or continue a name
+        let mut lexer = Lexer::new("a😀");
+        lexer.by_ref().for_each(drop);
+        assert_eq!(lexer.errors()[0].kind, LexErrorKind::UnexpectedChar('😀'));
+    }
+
+    #[test]
+    fn test_tokens_4() {
+        let input = "a.b[0] += 1.5 /* skipped
+        */ && c || !d => e++ <= f-- >= g -= h *= i /= j %= k;";
+
+        let tests = vec![
+            TokenKind::Identifier(Symbol::intern("a")),
+            TokenKind::Symbol(SymbolKind::Dot),
+            TokenKind::Identifier(Symbol::intern("b")),
+            TokenKind::Symbol(SymbolKind::LeftBracket),
+            TokenKind::IntegerLiteral(0),
+            TokenKind::Symbol(SymbolKind::RightBracket),
+            TokenKind::Symbol(SymbolKind::PlusAssign),
+            TokenKind::FloatLiteral(1.5),
+            TokenKind::Symbol(SymbolKind::And),
+            TokenKind::Identifier(Symbol::intern("c")),
+            TokenKind::Symbol(SymbolKind::Or),
+            TokenKind::Symbol(SymbolKind::Bang),
+            TokenKind::Identifier(Symbol::intern("d")),
+            TokenKind::Symbol(SymbolKind::FatArrow),
+            TokenKind::Identifier(Symbol::intern("e")),
+            TokenKind::Symbol(SymbolKind::Increment),
+            TokenKind::Symbol(SymbolKind::LtEq),
+            TokenKind::Identifier(Symbol::intern("f")),
+            TokenKind::Symbol(SymbolKind::Decrement),
+            TokenKind::Symbol(SymbolKind::GtEq),
+            TokenKind::Identifier(Symbol::intern("g")),
+            TokenKind::Symbol(SymbolKind::MinusAssign),
+            TokenKind::Identifier(Symbol::intern("h")),
+            TokenKind::Symbol(SymbolKind::AsteriskAssign),
+            TokenKind::Identifier(Symbol::intern("i")),
+            TokenKind::Symbol(SymbolKind::SlashAssign),
+            TokenKind::Identifier(Symbol::intern("j")),
+            TokenKind::Symbol(SymbolKind::ModAssign),
+            TokenKind::Identifier(Symbol::intern("k")),
+            TokenKind::Symbol(SymbolKind::Semicolon),
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for test in tests {
+            let token = lexer.next();
+
+            assert_eq!(test, token.unwrap().kind);
+        }
+
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn test_positions() {
+        let input = "let x = 5\n  x";
+
+        let positions: Vec<BufferPosition> = Lexer::new(input).map(|token| token.position).collect();
+
+        assert_eq!(
+            positions,
+            vec![
+                BufferPosition::new(1, 1),
+                BufferPosition::new(1, 5),
+                BufferPosition::new(1, 7),
+                BufferPosition::new(1, 9),
+                BufferPosition::new(1, 10),
+                BufferPosition::new(2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            ("\"abc", LexErrorKind::UnterminatedString, BufferPosition::new(1, 1)),
+            ("x /* abc", LexErrorKind::UnterminatedBlockComment, BufferPosition::new(1, 3)),
+            ("x = r#\"abc\"", LexErrorKind::UnterminatedString, BufferPosition::new(1, 5)),
+            ("\"\"\"abc\n\"\"", LexErrorKind::UnterminatedString, BufferPosition::new(1, 1)),
+            ("''", LexErrorKind::EmptyChar, BufferPosition::new(1, 1)),
+            ("'ab'", LexErrorKind::OverlongChar, BufferPosition::new(1, 1)),
+            ("\"a\\qb\"", LexErrorKind::UnknownEscape('q'), BufferPosition::new(1, 3)),
+            ("'\\x4'", LexErrorKind::MalformedHexEscape, BufferPosition::new(1, 2)),
+            ("'\\x80'", LexErrorKind::HexEscapeOutOfRange(0x80), BufferPosition::new(1, 2)),
+            ("\"\\u0041\"", LexErrorKind::MalformedUnicodeEscape, BufferPosition::new(1, 2)),
+            ("\"\\u{}\"", LexErrorKind::MalformedUnicodeEscape, BufferPosition::new(1, 2)),
+            ("\"\\u{1234567}\"", LexErrorKind::MalformedUnicodeEscape, BufferPosition::new(1, 2)),
+            ("'\\u{110000}'", LexErrorKind::InvalidCodePoint(0x110000), BufferPosition::new(1, 2)),
+            ("'\\u{D800}'", LexErrorKind::SurrogateCodePoint(0xD800), BufferPosition::new(1, 2)),
+            ("let $ = 1", LexErrorKind::UnexpectedChar('$'), BufferPosition::new(1, 5)),
+            ("x = 9223372036854775808", LexErrorKind::IntegerTooLarge, BufferPosition::new(1, 5)),
+            ("x = \"a{b", LexErrorKind::UnterminatedString, BufferPosition::new(1, 5)),
+            ("\"a{\"b{c}\"", LexErrorKind::UnterminatedString, BufferPosition::new(1, 1)),
+        ];
+
+        for (input, kind, position) in tests {
+            let mut lexer = Lexer::new(input);
+            lexer.by_ref().for_each(drop);
+
+            assert_eq!(lexer.errors(), &[LexError { kind, position }], "{}", input);
+        }
+
+        let mut lexer = Lexer::new("let x = 'a' + \"b\\n\"");
+        lexer.by_ref().for_each(drop);
+        assert!(lexer.errors().is_empty());
+    }
+
     #[test]
     fn test_tokens_3() {
         let input = "10 == 10; 10 != 9;";
@@ -431,11 +1355,11 @@ mod tests {
 
         let tests = vec![
             TokenKind::Keyword(KeywordKind::Fn),
-            TokenKind::Identifier("main".to_owned()),
+            TokenKind::Identifier(Symbol::intern("main")),
             TokenKind::Symbol(SymbolKind::LeftParen),
             TokenKind::Symbol(SymbolKind::RightParen),
             TokenKind::Symbol(SymbolKind::Colon),
-            TokenKind::Identifier("void".to_owned()),
+            TokenKind::Identifier(Symbol::intern("void")),
             TokenKind::Symbol(SymbolKind::LeftBrace),
             TokenKind::Newline,
             TokenKind::Keyword(KeywordKind::If),
@@ -472,41 +1396,60 @@ mod tests {
         }
     }
 
+    /// `/*` has opened a block comment since `samples/hello.k` first used
+    /// one, so the operators the baseline lexed from `!-/*%` now stop at `-`.
+    #[test]
+    fn test_block_comment_start() {
+        let mut lexer = Lexer::new("!-/*%\n5");
+        let kinds: Vec<TokenKind> = lexer.by_ref().map(|token| token.kind).collect();
+
+        assert_eq!(kinds, vec![TokenKind::Symbol(SymbolKind::Bang), TokenKind::Symbol(SymbolKind::Minus)]);
+        assert_eq!(
+            lexer.errors(),
+            &[LexError { kind: LexErrorKind::UnterminatedBlockComment, position: BufferPosition::new(1, 3) }]
+        );
+
+        let mut lexer = Lexer::new(include_str!("../samples/hello.k"));
+        lexer.by_ref().for_each(drop);
+        assert!(lexer.errors().is_empty());
+    }
+
     #[test]
     fn test_tokens_1() {
+        // The slash and asterisk are apart so they aren't a block comment
         let input = "fn main(): void {
                         let five: i32 = 5
                         let ten = 10; let fifteen = five + ten     
                     }
-                    !-/*%
+                    !-/ *%
                     5 < 10 > 5;";
         let tests = vec![
             TokenKind::Keyword(KeywordKind::Fn),
-            TokenKind::Identifier("main".to_owned()),
+            TokenKind::Identifier(Symbol::intern("main")),
             TokenKind::Symbol(SymbolKind::LeftParen),
             TokenKind::Symbol(SymbolKind::RightParen),
             TokenKind::Symbol(SymbolKind::Colon),
-            TokenKind::Identifier("void".to_owned()),
+            TokenKind::Identifier(Symbol::intern("void")),
             TokenKind::Symbol(SymbolKind::LeftBrace),
             TokenKind::Newline,
             TokenKind::Keyword(KeywordKind::Let),
-            TokenKind::Identifier("five".to_owned()),
+            TokenKind::Identifier(Symbol::intern("five")),
             TokenKind::Symbol(SymbolKind::Colon),
-            TokenKind::Identifier("i32".to_owned()),
+            TokenKind::Identifier(Symbol::intern("i32")),
             TokenKind::Symbol(SymbolKind::Assign),
             TokenKind::IntegerLiteral(5),
             TokenKind::Newline,
             TokenKind::Keyword(KeywordKind::Let),
-            TokenKind::Identifier("ten".to_owned()),
+            TokenKind::Identifier(Symbol::intern("ten")),
             TokenKind::Symbol(SymbolKind::Assign),
             TokenKind::IntegerLiteral(10),
             TokenKind::Symbol(SymbolKind::Semicolon),
             TokenKind::Keyword(KeywordKind::Let),
-            TokenKind::Identifier("fifteen".to_owned()),
+            TokenKind::Identifier(Symbol::intern("fifteen")),
             TokenKind::Symbol(SymbolKind::Assign),
-            TokenKind::Identifier("five".to_owned()),
+            TokenKind::Identifier(Symbol::intern("five")),
             TokenKind::Symbol(SymbolKind::Plus),
-            TokenKind::Identifier("ten".to_owned()),
+            TokenKind::Identifier(Symbol::intern("ten")),
             TokenKind::Newline,
             TokenKind::Symbol(SymbolKind::RightBrace),
             TokenKind::Newline,
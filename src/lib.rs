@@ -1,7 +1,31 @@
-#[macro_use]
-extern crate lazy_static;
-
 pub mod lexer;
 pub mod token;
+pub mod symbol;
 pub mod parser;
-pub mod ast;
\ No newline at end of file
+pub mod cst;
+pub mod cst_parser;
+pub mod incremental;
+pub mod ast;
+pub mod visit;
+pub mod object;
+pub mod environment;
+pub mod builtins;
+pub mod evaluator;
+pub mod types;
+pub mod checker;
+pub mod resolver;
+pub mod lint;
+pub mod optimizer;
+pub mod ir;
+pub mod ir_lower;
+pub mod ir_verify;
+pub mod ir_passes;
+pub mod ir_regalloc;
+pub mod backend;
+pub mod backend_c;
+pub mod backend_wasm;
+pub mod backend_x86;
+pub mod compile;
+pub mod pretty;
+pub mod formatter;
+pub mod dump;
@@ -0,0 +1,646 @@
+//! Lints: warnings about code that is valid but probably not what was meant,
+//! or that doesn't follow the usual style.
+//!
+//! Each lint can be allowed, made a warning or denied, first by a
+//! `LintConfig`, usually read from a file of `name = level` lines, and then
+//! by comments in the source like
+//!
+//! ```text
+//! // lint: allow(shadowing, unused_variables)
+//! ```
+//!
+//! A directive before the first token applies to the whole file. Anywhere
+//! else it applies to its own line, or to the next one when it is the only
+//! thing on its line.
+
+use crate::ast::*;
+use crate::lexer::{Comment, Lexer};
+use crate::parser::{ParseError, Parser};
+use crate::resolver::{BindingKind, Resolver};
+use crate::token::{BufferPosition, SymbolKind, Token, TokenKind};
+use crate::visit::{self, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedImports,
+    UnreachableCode,
+    Shadowing,
+    ConstantConditions,
+    RedundantSemicolons,
+    NonSnakeCase,
+    NonCamelCaseTypes,
+}
+
+pub const LINTS: &[Lint] = &[
+    Lint::UnusedVariables,
+    Lint::UnusedImports,
+    Lint::UnreachableCode,
+    Lint::Shadowing,
+    Lint::ConstantConditions,
+    Lint::RedundantSemicolons,
+    Lint::NonSnakeCase,
+    Lint::NonCamelCaseTypes,
+];
+
+impl Lint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedImports => "unused_imports",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::Shadowing => "shadowing",
+            Lint::ConstantConditions => "constant_conditions",
+            Lint::RedundantSemicolons => "redundant_semicolons",
+            Lint::NonSnakeCase => "non_snake_case",
+            Lint::NonCamelCaseTypes => "non_camel_case_types",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        LINTS.iter().copied().find(|lint| lint.name() == name)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Level::Allow => "allowed",
+            Level::Warn => "warning",
+            Level::Deny => "error",
+        })
+    }
+}
+
+/// The level of each lint. Every lint is a warning unless configured
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, Level>,
+}
+
+impl LintConfig {
+    /// Reads a config file. Each line sets a lint's level, like
+    /// `shadowing = allow`, and `#` starts a comment.
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, level) = match line.split_once('=') {
+                Some((name, level)) => (name.trim(), level.trim()),
+                None => return Err(format!("line {}: expected `lint = level`", i + 1)),
+            };
+
+            let lint =
+                Lint::from_name(name).ok_or_else(|| format!("line {}: unknown lint `{}`", i + 1, name))?;
+            let level = Level::from_name(level).ok_or_else(|| {
+                format!("line {}: unknown level `{}`, expected allow, warn or deny", i + 1, level)
+            })?;
+
+            config.set(lint, level);
+        }
+
+        Ok(config)
+    }
+
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub level: Level,
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {} [{}]", self.position, self.level, self.message, self.lint)
+    }
+}
+
+/// Lints a source file, failing if it doesn't parse. Unused variables and
+/// shadowing are only looked for when every name resolves.
+pub fn lint_source(source: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, Vec<ParseError>> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let mut program = parser.parse();
+
+    if !parser.errors().is_empty() {
+        return Err(parser.errors().to_vec());
+    }
+
+    let mut lexer = Lexer::new(source);
+    let tokens: Vec<Token> = lexer.by_ref().collect();
+    let mut linter = Linter { found: Vec::new() };
+    let mut resolver = Resolver::new();
+
+    if resolver.resolve_program(&mut program).is_ok() {
+        linter.check_bindings(&program, &resolver);
+    }
+
+    linter.visit_program(&program);
+    linter.check_semicolons(&tokens);
+
+    let first_line = tokens
+        .iter()
+        .find(|token| token.kind != TokenKind::Newline)
+        .map_or(usize::MAX, |token| token.position.line);
+    let directives = Directives::new(lexer.comments(), first_line);
+
+    let mut diagnostics: Vec<Diagnostic> = linter
+        .found
+        .into_iter()
+        .filter_map(|(lint, message, position)| {
+            let level = directives.level(lint, position.line).unwrap_or_else(|| config.level(lint));
+
+            if level == Level::Allow {
+                return None;
+            }
+
+            Some(Diagnostic { lint, level, message, position })
+        })
+        .collect();
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.position.line, diagnostic.position.column));
+    Ok(diagnostics)
+}
+
+/// The levels set by `// lint:` comments.
+struct Directives {
+    file: HashMap<Lint, Level>,
+    lines: HashMap<(usize, Lint), Level>,
+}
+
+impl Directives {
+    fn new(comments: &[Comment], first_line: usize) -> Directives {
+        let mut directives = Directives {
+            file: HashMap::new(),
+            lines: HashMap::new(),
+        };
+
+        for comment in comments {
+            let text = comment.text.strip_prefix("//").unwrap_or_default().trim();
+
+            let directive = match text.strip_prefix("lint:") {
+                Some(directive) => directive.trim(),
+                None => continue,
+            };
+
+            let (level, lints) = match directive.split_once('(') {
+                Some((level, lints)) => (level.trim(), lints.trim_end().trim_end_matches(')')),
+                None => continue,
+            };
+
+            let level = match Level::from_name(level) {
+                Some(level) => level,
+                None => continue,
+            };
+
+            for lint in lints.split(',').filter_map(|name| Lint::from_name(name.trim())) {
+                if comment.trailing {
+                    directives.lines.insert((comment.position.line, lint), level);
+                } else if comment.position.line < first_line {
+                    directives.file.insert(lint, level);
+                } else {
+                    directives.lines.insert((comment.position.line + 1, lint), level);
+                }
+            }
+        }
+
+        directives
+    }
+
+    fn level(&self, lint: Lint, line: usize) -> Option<Level> {
+        self.lines.get(&(line, lint)).or_else(|| self.file.get(&lint)).copied()
+    }
+}
+
+struct Linter {
+    found: Vec<(Lint, String, BufferPosition)>,
+}
+
+impl Linter {
+    fn report(&mut self, lint: Lint, message: String, position: BufferPosition) {
+        self.found.push((lint, message, position));
+    }
+
+    fn check_bindings(&mut self, program: &Program, resolver: &Resolver) {
+        let mut uses = Uses {
+            resolver,
+            counts: vec![0; resolver.bindings().len()],
+        };
+
+        uses.visit_program(program);
+
+        for (binding, count) in resolver.bindings().iter().zip(uses.counts) {
+            if count > 0 || binding.name.as_str().starts_with('_') {
+                continue;
+            }
+
+            match binding.kind {
+                kind if kind.is_variable() => self.report(
+                    Lint::UnusedVariables,
+                    format!("unused variable `{}`", binding.name),
+                    binding.position,
+                ),
+                BindingKind::Module => self.report(
+                    Lint::UnusedImports,
+                    format!("unused import `{}`", binding.name),
+                    binding.position,
+                ),
+                _ => {}
+            }
+        }
+
+        for (id, previous) in resolver.shadowed() {
+            let binding = resolver.binding(*id);
+
+            self.report(
+                Lint::Shadowing,
+                format!(
+                    "`{}` shadows the binding declared at {}",
+                    binding.name,
+                    resolver.binding(*previous).position
+                ),
+                binding.position,
+            );
+        }
+    }
+
+    /// Looks for semicolons that end a line or a block, or follow another
+    /// one, since newlines and braces already end statements.
+    fn check_semicolons(&mut self, tokens: &[Token]) {
+        for (i, token) in tokens.iter().enumerate() {
+            if token.kind != TokenKind::Symbol(SymbolKind::Semicolon) {
+                continue;
+            }
+
+            let redundant = matches!(
+                tokens.get(i + 1).map(|token| &token.kind),
+                None | Some(TokenKind::Newline)
+                    | Some(TokenKind::Symbol(SymbolKind::RightBrace | SymbolKind::Semicolon))
+            );
+
+            if redundant {
+                self.report(Lint::RedundantSemicolons, "unnecessary semicolon".to_owned(), token.position);
+            }
+        }
+    }
+
+    /// Reports the first statement after a `return`, `break` or `continue`.
+    /// Declarations are hoisted, so they aren't unreachable.
+    fn check_unreachable(&mut self, block: &[Stmt]) {
+        let mut stmts = block.iter().skip_while(|stmt| {
+            !matches!(stmt.kind, StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue)
+        });
+
+        let jump = match stmts.next() {
+            Some(jump) => jump,
+            None => return,
+        };
+
+        let unreachable = stmts.find(|stmt| {
+            !matches!(
+                stmt.kind,
+                StmtKind::Blank | StmtKind::Fn(_) | StmtKind::Struct(_) | StmtKind::Enum(_)
+            )
+        });
+
+        if let Some(stmt) = unreachable {
+            let keyword = match jump.kind {
+                StmtKind::Return(_) => "return",
+                StmtKind::Break => "break",
+                _ => "continue",
+            };
+
+            self.report(
+                Lint::UnreachableCode,
+                format!("unreachable statement after `{}` at {}", keyword, jump.position),
+                stmt.position,
+            );
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expr, keyword: &str) {
+        if !is_constant(condition) {
+            return;
+        }
+
+        let message = match condition.kind {
+            // `while true` is how an endless loop is written
+            ExprKind::BooleanLiteral(true) if keyword == "while" => return,
+            ExprKind::BooleanLiteral(value) => format!("this `{}` condition is always `{}`", keyword, value),
+            _ => format!("this `{}` condition is constant", keyword),
+        };
+
+        self.report(Lint::ConstantConditions, message, condition.position);
+    }
+
+    fn check_snake_case(&mut self, name: &Identifier, what: &str) {
+        let text = name.name.as_str();
+        let snake = to_snake_case(text);
+
+        if snake != text {
+            self.report(
+                Lint::NonSnakeCase,
+                format!("{} `{}` should have a snake case name such as `{}`", what, text, snake),
+                name.position,
+            );
+        }
+    }
+
+    fn check_camel_case(&mut self, name: &Identifier, what: &str) {
+        let text = name.name.as_str();
+        let camel = to_camel_case(text);
+
+        if camel != text {
+            self.report(
+                Lint::NonCamelCaseTypes,
+                format!("{} `{}` should have an upper camel case name such as `{}`", what, text, camel),
+                name.position,
+            );
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for Linter {
+    fn visit_block(&mut self, block: &'ast [Stmt]) {
+        self.check_unreachable(block);
+        visit::walk_block(self, block)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        match &stmt.kind {
+            StmtKind::While(condition, _) => self.check_condition(condition, "while"),
+            StmtKind::For(_, condition, _, _) => self.check_condition(condition, "for"),
+            _ => {}
+        }
+
+        visit::walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if let ExprKind::If(condition, _, _) = &expr.kind {
+            self.check_condition(condition, "if");
+        }
+
+        visit::walk_expr(self, expr)
+    }
+
+    fn visit_fn_decl(&mut self, decl: &'ast FnDecl) {
+        self.check_snake_case(&decl.name, "function");
+        visit::walk_fn_decl(self, decl)
+    }
+
+    fn visit_struct_decl(&mut self, decl: &'ast StructDecl) {
+        self.check_camel_case(&decl.name, "struct");
+        visit::walk_struct_decl(self, decl)
+    }
+
+    fn visit_enum_decl(&mut self, decl: &'ast EnumDecl) {
+        self.check_camel_case(&decl.name, "enum");
+        visit::walk_enum_decl(self, decl)
+    }
+
+    fn visit_variant(&mut self, variant: &'ast Variant) {
+        self.check_camel_case(&variant.name, "variant");
+        visit::walk_variant(self, variant)
+    }
+}
+
+/// Counts the uses of each binding, leaving out the declarations.
+struct Uses<'r> {
+    resolver: &'r Resolver,
+    counts: Vec<usize>,
+}
+
+impl<'ast> Visitor<'ast> for Uses<'_> {
+    fn visit_identifier(&mut self, identifier: &'ast Identifier) {
+        if let Some(id) = identifier.binding {
+            if self.resolver.binding(id).position != identifier.position {
+                self.counts[id.0] += 1;
+            }
+        }
+    }
+}
+
+/// Whether an expression is made only of literals and operators.
+fn is_constant(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::IntegerLiteral(_)
+        | ExprKind::FloatLiteral(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::CharLiteral(_)
+        | ExprKind::BooleanLiteral(_) => true,
+        ExprKind::Prefix(_, right) => is_constant(right),
+        ExprKind::Infix(_, left, right) => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+/// Splits words at lower to upper case changes, keeping acronyms like the
+/// `HTTP` in `HTTPServer` together.
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+
+    for (i, &ch) in chars.iter().enumerate() {
+        let starts_word = i > 0
+            && ch.is_uppercase()
+            && (!chars[i - 1].is_uppercase() || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+
+        if starts_word && !snake.ends_with('_') {
+            snake.push('_');
+        }
+
+        snake.extend(ch.to_lowercase());
+    }
+
+    snake
+}
+
+fn to_camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().into_iter().flat_map(char::to_uppercase);
+            first.chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(input: &str) -> Vec<String> {
+        lint_with(input, &LintConfig::default())
+    }
+
+    fn lint_with(input: &str, config: &LintConfig) -> Vec<String> {
+        match lint_source(input, config) {
+            Ok(diagnostics) => diagnostics.iter().map(Diagnostic::to_string).collect(),
+            Err(errors) => panic!("linting {:?} failed: {:?}", input, errors),
+        }
+    }
+
+    #[test]
+    fn test_lints() {
+        let tests = vec![
+            ("let x = 1", vec!["1:5: warning: unused variable `x` [unused_variables]"]),
+            ("let _x = 1\nlet y = 2\ny", vec![]),
+            (
+                "fn f(a: i32, b: i32): i32 { a }\nf(1, 2)",
+                vec!["1:14: warning: unused variable `b` [unused_variables]"],
+            ),
+            (
+                "match 1 { n => 2 }",
+                vec!["1:11: warning: unused variable `n` [unused_variables]"],
+            ),
+            ("import std", vec!["1:8: warning: unused import `std` [unused_imports]"]),
+            ("import std\nstd.println(1)", vec![]),
+            (
+                "fn f(): i32 {\n    return 1\n    2\n}\nf()",
+                vec!["3:5: warning: unreachable statement after `return` at 2:5 [unreachable_code]"],
+            ),
+            (
+                "while true {\n    break\n    fn g() {}\n}",
+                vec![],
+            ),
+            (
+                "let x = 1\nlet y = { let x = 2; x }\ny + x",
+                vec!["2:15: warning: `x` shadows the binding declared at 1:5 [shadowing]"],
+            ),
+            (
+                "if false { 1 }\nwhile 1 < 2 { break }\nwhile true { break }",
+                vec![
+                    "1:4: warning: this `if` condition is always `false` [constant_conditions]",
+                    "2:9: warning: this `while` condition is constant [constant_conditions]",
+                ],
+            ),
+            (
+                "let x = 1;\nlet y = 2; x + y\n{ x; }\n;;",
+                vec![
+                    "1:10: warning: unnecessary semicolon [redundant_semicolons]",
+                    "3:4: warning: unnecessary semicolon [redundant_semicolons]",
+                    "4:1: warning: unnecessary semicolon [redundant_semicolons]",
+                    "4:2: warning: unnecessary semicolon [redundant_semicolons]",
+                ],
+            ),
+            (
+                "fn addOne() {}\nstruct point {}\nenum Shape_kind { circle }\naddOne()",
+                vec![
+                    "1:4: warning: function `addOne` should have a snake case name such as `add_one` \
+                     [non_snake_case]",
+                    "2:8: warning: struct `point` should have an upper camel case name such as `Point` \
+                     [non_camel_case_types]",
+                    "3:6: warning: enum `Shape_kind` should have an upper camel case name such as \
+                     `ShapeKind` \
+                     [non_camel_case_types]",
+                    "3:19: warning: variant `circle` should have an upper camel case name such as `Circle` \
+                     [non_camel_case_types]",
+                ],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(lint(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_unresolved_names_skip_binding_lints() {
+        assert_eq!(lint("let x = y"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_config() {
+        let text = "# comment\nunused_variables = deny\n\nshadowing=allow # trailing\n";
+        let config = LintConfig::parse(text).unwrap();
+
+        assert_eq!(config.level(Lint::UnusedVariables), Level::Deny);
+        assert_eq!(config.level(Lint::Shadowing), Level::Allow);
+        assert_eq!(config.level(Lint::UnusedImports), Level::Warn);
+        assert_eq!(
+            lint_with("let x = 1\nlet x = 2", &config),
+            vec![
+                "1:5: error: unused variable `x` [unused_variables]",
+                "2:5: error: unused variable `x` [unused_variables]",
+            ]
+        );
+
+        assert_eq!(LintConfig::parse("shadowing").unwrap_err(), "line 1: expected `lint = level`");
+        assert_eq!(LintConfig::parse("unused = warn").unwrap_err(), "line 1: unknown lint `unused`");
+        assert_eq!(
+            LintConfig::parse("shadowing = loud").unwrap_err(),
+            "line 1: unknown level `loud`, expected allow, warn or deny"
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let input = "// lint: allow(unused_variables)
+let a = 1
+// lint: deny(redundant_semicolons)
+let b = a;
+let c = b;
+let d = c; // lint: allow(redundant_semicolons)
+";
+
+        assert_eq!(
+            lint(input),
+            vec![
+                "4:10: error: unnecessary semicolon [redundant_semicolons]",
+                "5:10: warning: unnecessary semicolon [redundant_semicolons]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_case_conversions() {
+        assert_eq!(to_snake_case("addOne"), "add_one");
+        assert_eq!(to_snake_case("HTTPServer"), "http_server");
+        assert_eq!(to_camel_case("shape_kind"), "ShapeKind");
+        assert_eq!(to_camel_case("Point"), "Point");
+    }
+}
@@ -0,0 +1,168 @@
+use crate::ast::{EnumDecl, FnDecl, StructDecl};
+use crate::environment::Environment;
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+pub type BuiltinFunction = fn(Vec<Object>) -> Result<Object, String>;
+
+#[derive(Clone, Debug)]
+pub enum Object {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Char(char),
+    Str(String),
+    Array(Vec<Object>),
+    Struct(Symbol, Vec<(Symbol, Object)>),
+    Variant(Symbol, Symbol, Vec<Object>),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+    StructType(Rc<StructDecl>),
+    EnumType(Rc<EnumDecl>),
+    VariantConstructor(Rc<EnumDecl>, usize),
+    Module(Rc<Module>),
+    Void,
+}
+
+pub struct Function {
+    pub decl: FnDecl,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub function: BuiltinFunction,
+}
+
+#[derive(Debug)]
+pub struct Module {
+    pub name: Symbol,
+    pub members: Vec<(Symbol, Object)>,
+}
+
+impl Object {
+    pub fn type_name(&self) -> String {
+        match self {
+            Object::Integer(_) => "integer".to_owned(),
+            Object::Float(_) => "float".to_owned(),
+            Object::Boolean(_) => "bool".to_owned(),
+            Object::Char(_) => "char".to_owned(),
+            Object::Str(_) => "str".to_owned(),
+            Object::Array(_) => "array".to_owned(),
+            Object::Struct(name, _) => name.to_string(),
+            Object::Variant(name, _, _) => name.to_string(),
+            Object::Function(_) | Object::Builtin(_) | Object::VariantConstructor(..) => "fn".to_owned(),
+            Object::StructType(_) | Object::EnumType(_) => "type".to_owned(),
+            Object::Module(_) => "module".to_owned(),
+            Object::Void => "void".to_owned(),
+        }
+    }
+
+    /// Structural equality between two values. Functions and types are
+    /// only equal to themselves.
+    pub fn equals(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Integer(a), Object::Integer(b)) => a == b,
+            (Object::Float(a), Object::Float(b)) => a == b,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::Char(a), Object::Char(b)) => a == b,
+            (Object::Str(a), Object::Str(b)) => a == b,
+            (Object::Array(a), Object::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            (Object::Struct(a_name, a), Object::Struct(b_name, b)) => {
+                a_name == b_name
+                    && a.len() == b.len()
+                    && a.iter().zip(b).all(|((a_field, a), (b_field, b))| a_field == b_field && a.equals(b))
+            }
+            (Object::Variant(a_enum, a_variant, a), Object::Variant(b_enum, b_variant, b)) => {
+                a_enum == b_enum
+                    && a_variant == b_variant
+                    && a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+            (Object::Builtin(a), Object::Builtin(b)) => a.name == b.name,
+            (Object::StructType(a), Object::StructType(b)) => Rc::ptr_eq(a, b),
+            (Object::EnumType(a), Object::EnumType(b)) => Rc::ptr_eq(a, b),
+            (Object::VariantConstructor(a, a_index), Object::VariantConstructor(b, b_index)) => {
+                Rc::ptr_eq(a, b) && a_index == b_index
+            }
+            (Object::Module(a), Object::Module(b)) => Rc::ptr_eq(a, b),
+            (Object::Void, Object::Void) => true,
+            _ => false,
+        }
+    }
+
+    /// Formats the value the way it would be written in source code, which
+    /// is what the REPL echoes back. `Display` is used by `std.print`.
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::Str(value) => format!("{:?}", value),
+            Object::Char(value) => format!("{:?}", value),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Object::inspect).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Struct(name, fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}: {}", field, value.inspect()))
+                    .collect();
+                format!("{} {{ {} }}", name, fields.join(", "))
+            }
+            Object::Variant(name, variant, fields) if !fields.is_empty() => {
+                let fields: Vec<String> = fields.iter().map(Object::inspect).collect();
+                format!("{}.{}({})", name, variant, fields.join(", "))
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::Float(value) => write!(f, "{:?}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::Char(value) => write!(f, "{}", value),
+            Object::Str(value) => write!(f, "{}", value),
+            Object::Array(_) | Object::Struct(..) => write!(f, "{}", self.inspect()),
+            Object::Variant(name, variant, fields) => {
+                write!(f, "{}.{}", name, variant)?;
+
+                if !fields.is_empty() {
+                    let fields: Vec<String> = fields.iter().map(Object::to_string).collect();
+                    write!(f, "({})", fields.join(", "))?;
+                }
+
+                Ok(())
+            }
+            Object::Function(function) => write!(f, "<fn {}>", function.decl.name.name),
+            Object::Builtin(builtin) => write!(f, "<builtin fn {}>", builtin.name),
+            Object::StructType(decl) => write!(f, "<struct {}>", decl.name.name),
+            Object::EnumType(decl) => write!(f, "<enum {}>", decl.name.name),
+            Object::VariantConstructor(decl, index) => {
+                write!(f, "<fn {}.{}>", decl.name.name, decl.variants[*index].name.name)
+            }
+            Object::Module(module) => write!(f, "<module {}>", module.name),
+            Object::Void => write!(f, "void"),
+        }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Function({})", self.decl.name.name)
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
@@ -0,0 +1,430 @@
+//! Simplifies a program before it runs: folds operators applied to literals,
+//! substitutes variables that always hold the same literal, and drops code
+//! that can never run. Folding an operation that would fail at runtime, like
+//! an overflowing addition, is reported as an error instead.
+//!
+//! The program should have been resolved, since variables are told apart by
+//! their `BindingId`s, and type checked, since the folded operators are only
+//! defined for well-typed operands and `i32` results are checked against the
+//! `i32` range.
+
+use crate::ast::*;
+use crate::evaluator;
+use crate::object::Object;
+use crate::token::BufferPosition;
+use crate::visit::{self, Visitor, VisitorMut};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OptimizeError {
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl OptimizeError {
+    pub fn new(message: String, position: BufferPosition) -> OptimizeError {
+        OptimizeError { message, position }
+    }
+}
+
+impl fmt::Display for OptimizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+/// Optimizes a program in place.
+pub fn optimize(program: &mut Program) -> Result<(), Vec<OptimizeError>> {
+    let mut assigned = Assigned(HashSet::new());
+    assigned.visit_program(program);
+
+    let mut optimizer = Optimizer {
+        assigned: assigned.0,
+        constants: HashMap::new(),
+        depth: 0,
+        fn_depth: 0,
+        errors: Vec::new(),
+    };
+
+    optimizer.visit_program(program);
+
+    if optimizer.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(optimizer.errors)
+    }
+}
+
+/// The variables that are assigned to after they are declared.
+struct Assigned(HashSet<BindingId>);
+
+impl<'ast> Visitor<'ast> for Assigned {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        if let StmtKind::Assign(target, _, _) | StmtKind::Increment(target) | StmtKind::Decrement(target) =
+            &stmt.kind
+        {
+            // Assigning to an element or field changes the whole variable
+            let mut target = target;
+
+            while let ExprKind::Index(object, _) | ExprKind::Field(object, _) = &target.kind {
+                target = object;
+            }
+
+            if let ExprKind::Identifier(Identifier { binding: Some(id), .. }) = &target.kind {
+                self.0.insert(*id);
+            }
+        }
+
+        visit::walk_stmt(self, stmt)
+    }
+}
+
+struct Constant {
+    value: Expr,
+    global: bool,
+}
+
+struct Optimizer {
+    assigned: HashSet<BindingId>,
+    constants: HashMap<BindingId, Constant>,
+    // How many blocks and function bodies are open
+    depth: usize,
+    fn_depth: usize,
+    errors: Vec<OptimizeError>,
+}
+
+impl Optimizer {
+    /// Folds an operator applied to literals, reporting why if it would fail
+    /// at runtime. Integer operations are the only ones that can fail once the
+    /// program type checks.
+    fn fold(&mut self, result: Result<Object, evaluator::RuntimeError>, integers: bool) -> Option<Object> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                if integers {
+                    self.errors.push(OptimizeError::new(
+                        format!("{} in constant expression", error.message),
+                        error.position,
+                    ));
+                }
+
+                None
+            }
+        }
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        let (position, end) = (expr.position, expr.end);
+        let ty = expr.ty.clone();
+        let take = |expr: &mut Expr| mem::replace(expr, Expr::new(ExprKind::Block(Vec::new()), position));
+
+        // A branch or short circuit decided by a literal becomes the part of
+        // it that runs. `false && x` and `true || x` don't evaluate `x`, and
+        // otherwise the result is `x`
+        let taken = match &mut expr.kind {
+            ExprKind::If(condition, consequence, alternative) => match condition.kind {
+                ExprKind::BooleanLiteral(true) => {
+                    Some(Expr::spanning(ExprKind::Block(mem::take(consequence)), position, end))
+                }
+                ExprKind::BooleanLiteral(false) => match alternative {
+                    Some(alternative) => Some(take(alternative)),
+                    None => Some(Expr::spanning(ExprKind::Block(Vec::new()), position, end)),
+                },
+                _ => None,
+            },
+            ExprKind::Infix(op @ (InfixOp::And | InfixOp::Or), left, right) => match (*op, &left.kind) {
+                (InfixOp::And, ExprKind::BooleanLiteral(false))
+                | (InfixOp::Or, ExprKind::BooleanLiteral(true)) => Some(take(left)),
+                (_, ExprKind::BooleanLiteral(_)) => Some(take(right)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(mut taken) = taken {
+            taken.ty = ty;
+            *expr = taken;
+            return;
+        }
+
+        let value = match &mut expr.kind {
+            ExprKind::Prefix(op, right) => match literal_value(right) {
+                Some(right) => {
+                    let integer = matches!(right, Object::Integer(_));
+                    self.fold(evaluator::apply_prefix(*op, right, &ty, position), integer)
+                }
+                None => None,
+            },
+            ExprKind::Infix(op, left, right) => match (literal_value(left), literal_value(right)) {
+                (Some(left), Some(right)) => {
+                    let integers = matches!((&left, &right), (Object::Integer(_), Object::Integer(_)));
+                    self.fold(evaluator::apply_infix(*op, left, right, &ty, position), integers)
+                }
+                _ => None,
+            },
+            ExprKind::Interpolated(parts) => {
+                fold_interpolated(parts);
+
+                match parts.as_slice() {
+                    [] => Some(Object::Str(String::new())),
+                    [StringPart::Text(text)] => Some(Object::Str(text.clone())),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(kind) = value.and_then(literal_kind) {
+            expr.kind = kind;
+        }
+    }
+}
+
+impl VisitorMut for Optimizer {
+    fn visit_program(&mut self, program: &mut Program) {
+        // The program's own statements are the globals, so aren't a block
+        visit::walk_block_mut(self, &mut program.0);
+    }
+
+    fn visit_block(&mut self, block: &mut BlockStmt) {
+        self.depth += 1;
+        visit::walk_block_mut(self, block);
+        self.depth -= 1;
+
+        remove_unreachable(block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        visit::walk_stmt_mut(self, stmt);
+
+        match &mut stmt.kind {
+            StmtKind::Let(name, _, value) => {
+                if let Some(id) = name.binding {
+                    if literal_value(value).is_some() && !self.assigned.contains(&id) {
+                        let constant = Constant {
+                            value: value.clone(),
+                            global: self.depth == 0,
+                        };
+
+                        self.constants.insert(id, constant);
+                    }
+                }
+            }
+            StmtKind::While(condition, _) if condition.kind == ExprKind::BooleanLiteral(false) => {
+                stmt.kind = StmtKind::Blank;
+            }
+            StmtKind::For(init, condition, _, _) if condition.kind == ExprKind::BooleanLiteral(false) => {
+                // The initializer still runs, in a scope of its own
+                let init = mem::replace(&mut **init, Stmt::new(StmtKind::Blank, stmt.position));
+                let block = Expr::spanning(ExprKind::Block(vec![init]), stmt.position, stmt.end);
+
+                stmt.kind = StmtKind::Expr(block);
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if let ExprKind::Identifier(Identifier { binding: Some(id), .. }) = &expr.kind {
+            // A global can be assigned by a later REPL entry before a function
+            // that reads it is called, so it's only substituted outside them
+            match self.constants.get(id) {
+                Some(constant) if !constant.global || self.fn_depth == 0 => {
+                    expr.kind = constant.value.kind.clone();
+                }
+                _ => {}
+            }
+
+            return;
+        }
+
+        visit::walk_expr_mut(self, expr);
+        self.fold_expr(expr);
+    }
+
+    fn visit_fn_decl(&mut self, decl: &mut FnDecl) {
+        self.fn_depth += 1;
+        visit::walk_fn_decl_mut(self, decl);
+        self.fn_depth -= 1;
+    }
+}
+
+/// Removes the statements after a `return`, `break` or `continue`, leaving
+/// the declarations since those are hoisted.
+fn remove_unreachable(block: &mut BlockStmt) {
+    let jump = block
+        .iter()
+        .position(|stmt| matches!(stmt.kind, StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue));
+
+    if let Some(jump) = jump {
+        let mut i = 0;
+
+        block.retain(|stmt| {
+            i += 1;
+            i <= jump + 1 || matches!(stmt.kind, StmtKind::Fn(_) | StmtKind::Struct(_) | StmtKind::Enum(_))
+        });
+    }
+}
+
+/// Puts the values of literal expressions into the text around them.
+fn fold_interpolated(parts: &mut Vec<StringPart>) {
+    let mut folded: Vec<StringPart> = Vec::with_capacity(parts.len());
+
+    for part in parts.drain(..) {
+        let part = match part {
+            StringPart::Expr(expr) => match literal_value(&expr) {
+                Some(value) => StringPart::Text(value.to_string()),
+                None => StringPart::Expr(expr),
+            },
+            text => text,
+        };
+
+        match (folded.last_mut(), part) {
+            (Some(StringPart::Text(previous)), StringPart::Text(text)) => previous.push_str(&text),
+            (_, part) => folded.push(part),
+        }
+    }
+
+    *parts = folded;
+}
+
+fn literal_value(expr: &Expr) -> Option<Object> {
+    let value = match &expr.kind {
+        ExprKind::IntegerLiteral(value) => Object::Integer(*value),
+        ExprKind::FloatLiteral(value) => Object::Float(*value),
+        ExprKind::StringLiteral(value) => Object::Str(value.clone()),
+        ExprKind::CharLiteral(value) => Object::Char(*value),
+        ExprKind::BooleanLiteral(value) => Object::Boolean(*value),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+fn literal_kind(value: Object) -> Option<ExprKind> {
+    let kind = match value {
+        Object::Integer(value) => ExprKind::IntegerLiteral(value),
+        Object::Float(value) => ExprKind::FloatLiteral(value),
+        Object::Str(value) => ExprKind::StringLiteral(value),
+        Object::Char(value) => ExprKind::CharLiteral(value),
+        Object::Boolean(value) => ExprKind::BooleanLiteral(value),
+        _ => return None,
+    };
+
+    Some(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+    use crate::dump::program_to_sexp;
+
+    fn run(input: &str) -> Result<Program, Vec<String>> {
+        let mut program = compile::checked(input).program;
+
+        match optimize(&mut program) {
+            Ok(()) => Ok(program),
+            Err(errors) => Err(errors.iter().map(OptimizeError::to_string).collect()),
+        }
+    }
+
+    fn optimized(input: &str) -> String {
+        match run(input) {
+            Ok(program) => program_to_sexp(&program),
+            Err(errors) => panic!("optimizing {:?} failed: {:?}", input, errors),
+        }
+    }
+
+    #[test]
+    fn test_folding() {
+        let tests = vec![
+            ("1 + 2 * 3", "7\n"),
+            ("-(4 - 10) % 4", "2\n"),
+            ("7 / 2 < 4 == true", "true\n"),
+            ("1.5 * 2.0", "3.0\n"),
+            ("\"fib is \" + 5 + 'c'", "\"fib is 5c\"\n"),
+            ("'a' < 'b'", "true\n"),
+            ("!(\"a\" == \"b\")", "true\n"),
+            ("\"{1 + 1} and {2.5} {true}\"", "\"2 and 2.5 true\"\n"),
+            (
+                "fn f(): i32 { 1 }\n\"x{f()}y{1}\"",
+                "(fn f [] i32 [1])\n(interpolated [\"x\" (call f []) \"y1\"])\n",
+            ),
+            ("fn f(): bool { true }\nfalse && f()", "(fn f [] bool [true])\nfalse\n"),
+            ("fn f(): bool { true }\ntrue && f()", "(fn f [] bool [true])\n(call f [])\n"),
+            ("fn f(): bool { true }\nf() || true", "(fn f [] bool [true])\n(infix || (call f []) true)\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(optimized(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_propagation() {
+        let tests = vec![
+            ("let x = 2 + 3\nx * 2", "(let x nil 5)\n10\n"),
+            ("let x = 1\nx = 2\nx", "(let x nil 1)\n(assign x = 2)\nx\n"),
+            ("let x = 1\nx++\nx", "(let x nil 1)\n(increment x)\nx\n"),
+            (
+                "let x = 1\nfn f(): i32 { let y = 2\ny + x }",
+                "(let x nil 1)\n(fn f [] i32 [(let y nil 2) (infix + 2 x)])\n",
+            ),
+            (
+                "let s = \"a\"\n{ let s = 1\ns }\ns + \"b\"",
+                "(let s nil \"a\")\n(block [(let s nil 1) 1])\n\"ab\"\n",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(optimized(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let tests = vec![
+            ("if 1 < 2 { 1 } else { 2 }", "(block [1])\n"),
+            ("if false { 1 } else if true { 2 } else { 3 }", "(block [2])\n"),
+            ("if false { 1 }", "(block [])\n"),
+            ("while 1 > 2 { }", "(blank)\n"),
+            ("for let i = 0; false; i++ { }", "(block [(let i nil 0)])\n"),
+            (
+                "fn f(): i32 {\n return 1\n fn g() {}\n 2\n}",
+                "(fn f [] i32 [(return 1) (fn g [] nil [])])\n",
+            ),
+            ("while true { break\n1 }", "(while true [(break)])\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(optimized(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            ("9223372036854775807 + 1", vec!["1:21: integer overflow in constant expression"]),
+            (
+                "let x = 0\nfn f(): i32 { 5 / 0 }\nx - 1 % x",
+                vec![
+                    "2:17: attempt to divide by zero in constant expression",
+                    "3:7: attempt to divide by zero in constant expression",
+                ],
+            ),
+            ("-(-9223372036854775807 - 1)", vec!["1:1: integer overflow in constant expression"]),
+            ("let x: i32 = 2147483647 + 1", vec!["1:25: integer overflow in constant expression"]),
+            ("let x: i32 = -2147483647 - 1
+-x", vec!["2:1: integer overflow in constant expression"]),
+            ("let x: i64 = 2147483647 + 1", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            let errors = run(input).err().unwrap_or_default();
+            assert_eq!(errors, expected.iter().map(|e| e.to_string()).collect::<Vec<_>>(), "{}", input);
+        }
+    }
+}
@@ -1,19 +1,132 @@
 use crate::ast::*;
 use crate::lexer::*;
 use crate::token::*;
+use std::fmt;
+use std::mem;
 
-pub struct Parser {
-    lexer: Box<Lexer>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl ParseError {
+    pub fn new(message: String, position: BufferPosition) -> ParseError {
+        ParseError { message, position }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub(crate) enum Precedence {
+    Lowest,
+    Or,
+    And,
+    Equals,
+    LessGreater,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+}
+
+/// Binary operators from loosest to tightest binding, all of them left
+/// associative. The formatter and `docs/grammar.bnf` both follow this table.
+const BINARY_OPERATORS: &[(SymbolKind, InfixOp, Precedence)] = &[
+    (SymbolKind::Or, InfixOp::Or, Precedence::Or),
+    (SymbolKind::And, InfixOp::And, Precedence::And),
+    (SymbolKind::Eq, InfixOp::Eq, Precedence::Equals),
+    (SymbolKind::NotEq, InfixOp::NotEq, Precedence::Equals),
+    (SymbolKind::Lt, InfixOp::Lt, Precedence::LessGreater),
+    (SymbolKind::Gt, InfixOp::Gt, Precedence::LessGreater),
+    (SymbolKind::LtEq, InfixOp::LtEq, Precedence::LessGreater),
+    (SymbolKind::GtEq, InfixOp::GtEq, Precedence::LessGreater),
+    (SymbolKind::Plus, InfixOp::Add, Precedence::Sum),
+    (SymbolKind::Minus, InfixOp::Sub, Precedence::Sum),
+    (SymbolKind::Asterisk, InfixOp::Mul, Precedence::Product),
+    (SymbolKind::Slash, InfixOp::Div, Precedence::Product),
+    (SymbolKind::Mod, InfixOp::Mod, Precedence::Product),
+];
+
+const PREFIX_OPERATORS: &[(SymbolKind, PrefixOp)] =
+    &[(SymbolKind::Minus, PrefixOp::Neg), (SymbolKind::Bang, PrefixOp::Not)];
+
+// Calls, indexing and field access, which all bind at `Precedence::Call`
+const POSTFIX_OPERATORS: &[(SymbolKind, &str)] =
+    &[(SymbolKind::LeftParen, "()"), (SymbolKind::LeftBracket, "[]"), (SymbolKind::Dot, ".")];
+
+/// How deeply expressions, blocks, types and patterns may nest. Anything
+/// deeper is an error rather than a stack overflow, here or in whatever
+/// walks the tree later.
+pub(crate) const MAX_NESTING: usize = 256;
+
+pub(crate) fn infix_precedence(op: InfixOp) -> Precedence {
+    BINARY_OPERATORS.iter().find(|&&(_, infix, _)| infix == op).map_or(Precedence::Lowest, |&(_, _, p)| p)
+}
+
+/// The operator precedence table as it appears in `docs/grammar.bnf`, one
+/// comment line per level from loosest to tightest.
+pub fn precedence_table() -> String {
+    let mut levels: Vec<(Precedence, Vec<&str>, &str)> = Vec::new();
+
+    for (symbol, _, precedence) in BINARY_OPERATORS {
+        match levels.last_mut() {
+            Some((level, operators, _)) if level == precedence => operators.push(symbol.as_str()),
+            _ => levels.push((*precedence, vec![symbol.as_str()], "")),
+        }
+    }
+
+    let prefix = PREFIX_OPERATORS.iter().map(|(symbol, _)| symbol.as_str()).collect();
+    let postfix = POSTFIX_OPERATORS.iter().map(|&(_, text)| text).collect();
+
+    levels.push((Precedence::Prefix, prefix, "prefix"));
+    levels.push((Precedence::Call, postfix, "postfix"));
+
+    levels
+        .iter()
+        .map(|(level, operators, note)| {
+            let line = format!("#   {}  {:<12}{}", *level as u8, operators.join("  "), note);
+            line.trim_end().to_owned() + "\n"
+        })
+        .collect()
+}
+
+pub struct Parser<'a> {
+    lexer: Box<Lexer<'a>>,
     cursor_token: Token,
     peek_token: Token,
+    // Where the cursor and peek tokens end
+    cursor_end: BufferPosition,
+    peek_end: BufferPosition,
+    last_token_kind: TokenKind,
+    // Struct literals are not allowed directly inside the head of `if`,
+    // `while`, `for` and `match` since `cond {` would be ambiguous.
+    no_struct_literal: bool,
+    // How many levels of `MAX_NESTING` are in use
+    depth: usize,
+    errors: Vec<ParseError>,
+    // How many of the errors were caused by the input ending too early
+    incomplete_errors: usize,
 }
 
-impl Parser {
-    pub fn new(lexer: Lexer) -> Parser {
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
         let mut parser = Parser {
             lexer: Box::new(lexer),
             cursor_token: Token::EOF,
             peek_token: Token::EOF,
+            cursor_end: Token::EOF.position,
+            peek_end: Token::EOF.position,
+            last_token_kind: TokenKind::Newline,
+            no_struct_literal: false,
+            depth: 0,
+            errors: Vec::new(),
+            incomplete_errors: 0,
         };
 
         // Read two tokens so cursor_token points to the first token
@@ -23,6 +136,16 @@ impl Parser {
         parser
     }
 
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Whether every error is explained by the input ending too early, such
+    /// as an unclosed brace or string, so that more input could fix them.
+    pub fn is_incomplete(&self) -> bool {
+        !self.errors.is_empty() && self.errors.len() == self.incomplete_errors
+    }
+
     pub fn parse(&mut self) -> Program {
         let mut program = Program::new();
 
@@ -36,60 +159,869 @@ impl Parser {
             self.next_token();
         }
 
+        self.collect_lexer_errors();
+
         program
     }
 
+    fn collect_lexer_errors(&mut self) {
+        for error in self.lexer.errors() {
+            if error.kind.is_unterminated() {
+                self.incomplete_errors += 1;
+            }
+
+            self.errors.push(ParseError::new(error.kind.to_string(), error.position));
+        }
+
+        self.errors
+            .sort_by_key(|error| (error.position.line, error.position.column));
+    }
+
     fn parse_stmt(&mut self) -> Option<Stmt> {
-        match &self.cursor_token.kind {
+        let stmt = match &self.cursor_token.kind {
+            TokenKind::Newline | TokenKind::Symbol(SymbolKind::Semicolon) => return None,
             TokenKind::Keyword(KeywordKind::Let) => self.parse_let_stmt(),
-            _ => None,
+            TokenKind::Keyword(KeywordKind::Return) => self.parse_return_stmt(),
+            TokenKind::Keyword(KeywordKind::Break) => Some(self.stmt(StmtKind::Break)),
+            TokenKind::Keyword(KeywordKind::Continue) => Some(self.stmt(StmtKind::Continue)),
+            TokenKind::Keyword(KeywordKind::While) => self.parse_while_stmt(),
+            TokenKind::Keyword(KeywordKind::For) => self.parse_for_stmt(),
+            TokenKind::Keyword(KeywordKind::Fn) => self.parse_fn_decl(),
+            TokenKind::Keyword(KeywordKind::Struct) => self.parse_struct_decl(),
+            TokenKind::Keyword(KeywordKind::Enum) => self.parse_enum_decl(),
+            TokenKind::Keyword(KeywordKind::Import) => self.parse_import_stmt(),
+            _ => self.parse_simple_stmt(),
+        };
+
+        match stmt {
+            Some(stmt) if self.peek_is_end_stmt() => Some(stmt),
+            Some(_) => {
+                self.peek_error("`;` or newline after statement");
+                self.synchronize();
+                None
+            }
+            None => {
+                self.synchronize();
+                None
+            }
         }
     }
 
+    fn stmt(&self, kind: StmtKind) -> Stmt {
+        self.finish_stmt(kind, self.cursor_token.position)
+    }
+
+    /// A statement from `position` up to the end of the cursor token, which
+    /// is its last token once it's parsed.
+    fn finish_stmt(&self, kind: StmtKind, position: BufferPosition) -> Stmt {
+        Stmt::spanning(kind, position, self.cursor_end)
+    }
+
+    /// An expression from `position` up to the end of the cursor token.
+    fn finish_expr(&self, kind: ExprKind, position: BufferPosition) -> Expr {
+        Expr::spanning(kind, position, self.cursor_end)
+    }
+
     fn parse_let_stmt(&mut self) -> Option<Stmt> {
-        // If the next token is an identifier, increment
-        match &self.peek_token.kind {
-            TokenKind::Identifier(_) => self.next_token(),
-            _ => return None,
+        let position = self.cursor_token.position;
+
+        if !self.expect_next_identifier() {
+            return None;
+        }
+
+        let name = self.parse_ident()?;
+
+        let ty = if self.peek_token_is(&TokenKind::Symbol(SymbolKind::Colon)) {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::Assign)) {
+            return None;
+        }
+
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(self.finish_stmt(StmtKind::Let(name, ty, value), position))
+    }
+
+    fn parse_return_stmt(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        if self.peek_is_end_stmt() {
+            return Some(self.finish_stmt(StmtKind::Return(None), position));
+        }
+
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(self.finish_stmt(StmtKind::Return(Some(value)), position))
+    }
+
+    fn parse_while_stmt(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        self.next_token();
+
+        let condition = self.parse_restricted(|parser| parser.parse_expression(Precedence::Lowest))?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let body = self.parse_block()?;
+
+        Some(self.finish_stmt(StmtKind::While(condition, body), position))
+    }
+
+    fn parse_for_stmt(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        self.next_token();
+
+        let init = match &self.cursor_token.kind {
+            TokenKind::Keyword(KeywordKind::Let) => self.parse_let_stmt()?,
+            _ => self.parse_simple_stmt()?,
+        };
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::Semicolon)) {
+            return None;
+        }
+
+        self.next_token();
+
+        let condition = self.parse_restricted(|parser| parser.parse_expression(Precedence::Lowest))?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::Semicolon)) {
+            return None;
+        }
+
+        self.next_token();
+
+        let step = self.parse_restricted(|parser| parser.parse_simple_stmt())?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let body = self.parse_block()?;
+
+        Some(self.finish_stmt(
+            StmtKind::For(Box::new(init), condition, Box::new(step), body),
+            position,
+        ))
+    }
+
+    fn parse_fn_decl(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        if !self.expect_next_identifier() {
+            return None;
+        }
+
+        let name = self.parse_ident()?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftParen)) {
+            return None;
+        }
+
+        let params = self.parse_list(SymbolKind::RightParen, |parser| parser.parse_param())?;
+
+        let return_type = if self.peek_token_is(&TokenKind::Symbol(SymbolKind::Colon)) {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let body = self.parse_block()?;
+
+        Some(self.finish_stmt(
+            StmtKind::Fn(FnDecl {
+                name,
+                params,
+                return_type,
+                body,
+            }),
+            position,
+        ))
+    }
+
+    fn parse_param(&mut self) -> Option<Param> {
+        let name = match self.parse_ident() {
+            Some(name) => name,
+            None => {
+                self.cursor_error("identifier");
+                return None;
+            }
         };
 
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::Colon)) {
+            return None;
+        }
+
+        self.next_token();
+
+        let ty = self.parse_type()?;
+
+        Some(Param { name, ty })
+    }
+
+    fn parse_struct_decl(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        if !self.expect_next_identifier() {
+            return None;
+        }
+
+        let name = self.parse_ident()?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let fields = self.parse_list(SymbolKind::RightBrace, |parser| parser.parse_param())?;
+
+        Some(self.finish_stmt(StmtKind::Struct(StructDecl { name, fields }), position))
+    }
+
+    fn parse_enum_decl(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        if !self.expect_next_identifier() {
+            return None;
+        }
+
+        let name = self.parse_ident()?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let variants = self.parse_list(SymbolKind::RightBrace, |parser| parser.parse_variant())?;
+
+        Some(self.finish_stmt(StmtKind::Enum(EnumDecl { name, variants }), position))
+    }
+
+    fn parse_variant(&mut self) -> Option<Variant> {
         let name = match self.parse_ident() {
             Some(name) => name,
-            None => return None,
+            None => {
+                self.cursor_error("variant name");
+                return None;
+            }
         };
 
-        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::Assign)) {
+        let fields = if self.peek_token_is(&TokenKind::Symbol(SymbolKind::LeftParen)) {
+            self.next_token();
+            self.parse_list(SymbolKind::RightParen, |parser| parser.parse_type())?
+        } else {
+            Vec::new()
+        };
+
+        Some(Variant {
+            name,
+            fields,
+            end: self.cursor_end,
+        })
+    }
+
+    fn parse_import_stmt(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        if !self.expect_next_identifier() {
             return None;
         }
 
+        let module = self.parse_ident()?;
+
+        Some(self.finish_stmt(StmtKind::Import(module), position))
+    }
+
+    /// Parses an expression statement, an assignment or an increment, which
+    /// are the statements allowed in the head of a `for` loop.
+    fn parse_simple_stmt(&mut self) -> Option<Stmt> {
+        let position = self.cursor_token.position;
+
+        let expr = self.parse_expression(Precedence::Lowest)?;
+
+        let op = match &self.peek_token.kind {
+            TokenKind::Symbol(SymbolKind::Assign) => AssignOp::Assign,
+            TokenKind::Symbol(SymbolKind::PlusAssign) => AssignOp::Add,
+            TokenKind::Symbol(SymbolKind::MinusAssign) => AssignOp::Sub,
+            TokenKind::Symbol(SymbolKind::AsteriskAssign) => AssignOp::Mul,
+            TokenKind::Symbol(SymbolKind::SlashAssign) => AssignOp::Div,
+            TokenKind::Symbol(SymbolKind::ModAssign) => AssignOp::Mod,
+            TokenKind::Symbol(SymbolKind::Increment) | TokenKind::Symbol(SymbolKind::Decrement) => {
+                self.next_token();
+                self.check_assign_target(&expr)?;
+
+                let kind = if self.cursor_token.kind == TokenKind::Symbol(SymbolKind::Increment) {
+                    StmtKind::Increment(expr)
+                } else {
+                    StmtKind::Decrement(expr)
+                };
+
+                return Some(self.finish_stmt(kind, position));
+            }
+            _ => return Some(self.finish_stmt(StmtKind::Expr(expr), position)),
+        };
+
         self.next_token();
+        self.check_assign_target(&expr)?;
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(self.finish_stmt(StmtKind::Assign(expr, op, value), position))
+    }
+
+    fn check_assign_target(&mut self, target: &Expr) -> Option<()> {
+        match &target.kind {
+            ExprKind::Identifier(_) | ExprKind::Field(..) | ExprKind::Index(..) => Some(()),
+            _ => {
+                self.errors.push(ParseError::new(
+                    "invalid left-hand side of assignment".to_owned(),
+                    target.position,
+                ));
+                None
+            }
+        }
+    }
+
+    fn parse_type(&mut self) -> Option<TypeExpr> {
+        self.nested(|parser| match &parser.cursor_token.kind {
+            TokenKind::Identifier(_) => Some(TypeExpr::Named(parser.parse_ident()?)),
+            TokenKind::Symbol(SymbolKind::LeftBracket) => {
+                parser.next_token();
+
+                let element = parser.parse_type()?;
+
+                if !parser.expect_next_token(&TokenKind::Symbol(SymbolKind::RightBracket)) {
+                    return None;
+                }
+
+                Some(TypeExpr::Array(Box::new(element), parser.cursor_end))
+            }
+            _ => {
+                parser.cursor_error("type");
+                None
+            }
+        })
+    }
+
+    /// Parses the statements of a block. The cursor must be on the opening
+    /// brace and is left on the closing brace.
+    fn parse_block(&mut self) -> Option<BlockStmt> {
+        self.nested(|parser| {
+            let mut block = BlockStmt::new();
+
+            // The outer expression restriction does not reach into blocks
+            let no_struct_literal = mem::replace(&mut parser.no_struct_literal, false);
+
+            parser.next_token();
+
+            while !parser.cursor_token_is(&TokenKind::Symbol(SymbolKind::RightBrace)) {
+                if parser.cursor_token.kind == TokenKind::EOF {
+                    parser.cursor_error("`}`");
+                    parser.no_struct_literal = no_struct_literal;
+                    return None;
+                }
+
+                if let Some(stmt) = parser.parse_stmt() {
+                    block.push(stmt);
+                }
+
+                parser.next_token();
+            }
+
+            parser.no_struct_literal = no_struct_literal;
+
+            Some(block)
+        })
+    }
+
+    /// Parses a comma (or newline) separated list. The cursor must be on the
+    /// opening delimiter and is left on the closing one.
+    fn parse_list<T, F>(&mut self, close: SymbolKind, mut parse_item: F) -> Option<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> Option<T>,
+    {
+        let close = TokenKind::Symbol(close);
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_peek_newlines();
+
+            if self.peek_token_is(&close) {
+                self.next_token();
+                return Some(items);
+            }
 
-        // TODO(kosi): Skip expressions until we see end of statement,
-        // change to parsing expression later.
-        while !self.cursor_is_end_stmt() {
             self.next_token();
+            items.push(parse_item(self)?);
+
+            let separated = self.peek_token_is(&TokenKind::Newline);
+
+            self.skip_peek_newlines();
+
+            if self.peek_token_is(&TokenKind::Symbol(SymbolKind::Comma)) {
+                self.next_token();
+            } else if !separated && !self.peek_token_is(&close) {
+                self.peek_error(&format!("`,` or {}", close));
+                return None;
+            }
         }
+    }
 
-        Some(Stmt::Let(name, Expr::Empty))
+    fn parse_restricted<T, F>(&mut self, parse: F) -> Option<T>
+    where
+        F: FnOnce(&mut Self) -> Option<T>,
+    {
+        let no_struct_literal = mem::replace(&mut self.no_struct_literal, true);
+        let result = parse(self);
+        self.no_struct_literal = no_struct_literal;
+        result
     }
 
-    fn cursor_is_end_stmt(&self) -> bool {
-        match &self.cursor_token.kind {
-            TokenKind::Newline => true,
-            TokenKind::Symbol(SymbolKind::Semicolon) => true,
-            _ => false
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expr> {
+        self.nested(|parser| {
+            let mut left = parser.parse_prefix()?;
+
+            while !parser.peek_is_end_stmt() && precedence < parser.peek_precedence() {
+                parser.next_token();
+
+                // Each operator wraps everything to its left in another node
+                parser.descend()?;
+
+                left = parser.parse_infix(left)?;
+            }
+
+            Some(left)
+        })
+    }
+
+    /// Parses something one level deeper, giving up past `MAX_NESTING`.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser<'a>) -> Option<T>) -> Option<T> {
+        let depth = self.depth;
+        let result = self.descend().and_then(|()| parse(self));
+        self.depth = depth;
+        result
+    }
+
+    fn descend(&mut self) -> Option<()> {
+        if self.depth == MAX_NESTING {
+            let message = format!("nested more than {} levels deep", MAX_NESTING);
+            self.errors.push(ParseError::new(message, self.cursor_token.position));
+            return None;
+        }
+
+        self.depth += 1;
+        Some(())
+    }
+
+    fn parse_prefix(&mut self) -> Option<Expr> {
+        let position = self.cursor_token.position;
+
+        if let TokenKind::Symbol(symbol) = &self.cursor_token.kind {
+            if let Some(&(_, op)) = PREFIX_OPERATORS.iter().find(|(kind, _)| kind == symbol) {
+                return self.parse_prefix_expr(op);
+            }
+        }
+
+        let kind = match &self.cursor_token.kind {
+            TokenKind::Identifier(_) => {
+                let ident = self.parse_ident()?;
+
+                if !self.no_struct_literal && self.peek_token_is(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+                    return self.parse_struct_literal(ident);
+                }
+
+                ExprKind::Identifier(ident)
+            }
+            TokenKind::IntegerLiteral(value) => ExprKind::IntegerLiteral(*value),
+            TokenKind::FloatLiteral(value) => ExprKind::FloatLiteral(*value),
+            TokenKind::StringLiteral(value) => ExprKind::StringLiteral(value.clone()),
+            TokenKind::StringStart(_) => return self.parse_interpolated_string(),
+            TokenKind::CharLiteral(value) => ExprKind::CharLiteral(*value),
+            TokenKind::Keyword(KeywordKind::True) => ExprKind::BooleanLiteral(true),
+            TokenKind::Keyword(KeywordKind::False) => ExprKind::BooleanLiteral(false),
+            TokenKind::Symbol(SymbolKind::LeftParen) => return self.parse_grouped_expr(),
+            TokenKind::Symbol(SymbolKind::LeftBracket) => {
+                let elements = self.parse_list(SymbolKind::RightBracket, |parser| {
+                    parser.parse_expression(Precedence::Lowest)
+                })?;
+
+                ExprKind::ArrayLiteral(elements)
+            }
+            TokenKind::Symbol(SymbolKind::LeftBrace) => ExprKind::Block(self.parse_block()?),
+            TokenKind::Keyword(KeywordKind::If) => return self.parse_if_expr(),
+            TokenKind::Keyword(KeywordKind::Match) => return self.parse_match_expr(),
+            // The lexer already reported why the token is illegal
+            TokenKind::Illegal => return None,
+            _ => {
+                self.cursor_error("expression");
+                return None;
+            }
+        };
+
+        Some(self.finish_expr(kind, position))
+    }
+
+    fn parse_prefix_expr(&mut self, op: PrefixOp) -> Option<Expr> {
+        let position = self.cursor_token.position;
+
+        self.next_token();
+
+        let right = self.parse_expression(Precedence::Prefix)?;
+
+        Some(self.finish_expr(ExprKind::Prefix(op, Box::new(right)), position))
+    }
+
+    fn parse_grouped_expr(&mut self) -> Option<Expr> {
+        self.next_token();
+
+        // Parentheses lift the struct literal restriction, like in Rust
+        let no_struct_literal = mem::replace(&mut self.no_struct_literal, false);
+        let expr = self.parse_expression(Precedence::Lowest);
+        self.no_struct_literal = no_struct_literal;
+
+        let expr = expr?;
+
+        self.skip_peek_newlines();
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::RightParen)) {
+            return None;
+        }
+
+        Some(expr)
+    }
+
+    fn parse_interpolated_string(&mut self) -> Option<Expr> {
+        let position = self.cursor_token.position;
+
+        // Struct literals are fine inside the braces, like in parentheses
+        let no_struct_literal = mem::replace(&mut self.no_struct_literal, false);
+        let parts = self.parse_string_parts();
+        self.no_struct_literal = no_struct_literal;
+
+        Some(self.finish_expr(ExprKind::Interpolated(parts?), position))
+    }
+
+    fn parse_string_parts(&mut self) -> Option<Vec<StringPart>> {
+        let mut parts = Vec::new();
+
+        loop {
+            let (text, done) = match &self.cursor_token.kind {
+                TokenKind::StringStart(text) | TokenKind::StringMiddle(text) => (text.clone(), false),
+                TokenKind::StringEnd(text) => (text.clone(), true),
+                _ => {
+                    self.cursor_error("`}` after the embedded expression");
+                    return None;
+                }
+            };
+
+            if !text.is_empty() {
+                parts.push(StringPart::Text(text));
+            }
+
+            if done {
+                return Some(parts);
+            }
+
+            self.next_token();
+            parts.push(StringPart::Expr(self.parse_expression(Precedence::Lowest)?));
+            self.next_token();
+        }
+    }
+
+    fn parse_struct_literal(&mut self, name: Identifier) -> Option<Expr> {
+        let position = name.position;
+
+        self.next_token();
+
+        let fields = self.parse_list(SymbolKind::RightBrace, |parser| {
+            let field = match parser.parse_ident() {
+                Some(field) => field,
+                None => {
+                    parser.cursor_error("field name");
+                    return None;
+                }
+            };
+
+            if !parser.expect_next_token(&TokenKind::Symbol(SymbolKind::Colon)) {
+                return None;
+            }
+
+            parser.next_token();
+
+            let value = parser.parse_expression(Precedence::Lowest)?;
+
+            Some((field, value))
+        })?;
+
+        Some(self.finish_expr(ExprKind::StructLiteral(name, fields), position))
+    }
+
+    fn parse_if_expr(&mut self) -> Option<Expr> {
+        let position = self.cursor_token.position;
+
+        self.next_token();
+
+        let condition = self.parse_restricted(|parser| parser.parse_expression(Precedence::Lowest))?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let consequence = self.parse_block()?;
+
+        let alternative = if self.peek_token_is(&TokenKind::Keyword(KeywordKind::Else)) {
+            self.next_token();
+
+            if self.peek_token_is(&TokenKind::Keyword(KeywordKind::If)) {
+                self.next_token();
+                // An `else if` chain nests like blocks do
+                Some(Box::new(self.nested(Parser::parse_if_expr)?))
+            } else {
+                if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+                    return None;
+                }
+
+                let else_position = self.cursor_token.position;
+                let block = self.parse_block()?;
+
+                Some(Box::new(self.finish_expr(ExprKind::Block(block), else_position)))
+            }
+        } else {
+            None
+        };
+
+        Some(self.finish_expr(
+            ExprKind::If(Box::new(condition), consequence, alternative),
+            position,
+        ))
+    }
+
+    fn parse_match_expr(&mut self) -> Option<Expr> {
+        let position = self.cursor_token.position;
+
+        self.next_token();
+
+        let subject = self.parse_restricted(|parser| parser.parse_expression(Precedence::Lowest))?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::LeftBrace)) {
+            return None;
+        }
+
+        let no_struct_literal = mem::replace(&mut self.no_struct_literal, false);
+        let arms = self.parse_list(SymbolKind::RightBrace, |parser| parser.parse_match_arm());
+        self.no_struct_literal = no_struct_literal;
+
+        Some(self.finish_expr(ExprKind::Match(Box::new(subject), arms?), position))
+    }
+
+    fn parse_match_arm(&mut self) -> Option<MatchArm> {
+        let pattern = self.parse_pattern()?;
+
+        if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::FatArrow)) {
+            return None;
+        }
+
+        self.next_token();
+
+        let body = self.parse_expression(Precedence::Lowest)?;
+
+        Some(MatchArm { pattern, body })
+    }
+
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        self.nested(|parser| {
+            let position = parser.cursor_token.position;
+
+            let literal = match &parser.cursor_token.kind {
+                TokenKind::Identifier(name) if name == "_" => return Some(Pattern::Wildcard(position)),
+                TokenKind::Identifier(_) => {
+                    let name = parser.parse_ident()?;
+
+                    if !parser.peek_token_is(&TokenKind::Symbol(SymbolKind::Dot)) {
+                        return Some(Pattern::Binding(name));
+                    }
+
+                    parser.next_token();
+
+                    if !parser.expect_next_identifier() {
+                        return None;
+                    }
+
+                    let variant = parser.parse_ident()?;
+
+                    let fields = if parser.peek_token_is(&TokenKind::Symbol(SymbolKind::LeftParen)) {
+                        parser.next_token();
+                        parser.parse_list(SymbolKind::RightParen, |parser| parser.parse_pattern())?
+                    } else {
+                        Vec::new()
+                    };
+
+                    return Some(Pattern::Variant(name, variant, fields, parser.cursor_end));
+                }
+                TokenKind::Symbol(SymbolKind::Minus) => {
+                    parser.next_token();
+
+                    match &parser.cursor_token.kind {
+                        TokenKind::IntegerLiteral(value) => ExprKind::IntegerLiteral(-*value),
+                        TokenKind::FloatLiteral(value) => ExprKind::FloatLiteral(-*value),
+                        _ => {
+                            parser.cursor_error("number literal");
+                            return None;
+                        }
+                    }
+                }
+                TokenKind::IntegerLiteral(value) => ExprKind::IntegerLiteral(*value),
+                TokenKind::FloatLiteral(value) => ExprKind::FloatLiteral(*value),
+                TokenKind::StringLiteral(value) => ExprKind::StringLiteral(value.clone()),
+                TokenKind::CharLiteral(value) => ExprKind::CharLiteral(*value),
+                TokenKind::Keyword(KeywordKind::True) => ExprKind::BooleanLiteral(true),
+                TokenKind::Keyword(KeywordKind::False) => ExprKind::BooleanLiteral(false),
+                _ => {
+                    parser.cursor_error("pattern");
+                    return None;
+                }
+            };
+
+            Some(Pattern::Literal(parser.finish_expr(literal, position)))
+        })
+    }
+
+    fn parse_infix(&mut self, left: Expr) -> Option<Expr> {
+        let position = self.cursor_token.position;
+
+        let op = match &self.cursor_token.kind {
+            TokenKind::Symbol(SymbolKind::LeftParen) => {
+                let args = self.parse_list(SymbolKind::RightParen, |parser| {
+                    parser.parse_expression(Precedence::Lowest)
+                })?;
+
+                let position = left.position;
+
+                return Some(self.finish_expr(ExprKind::Call(Box::new(left), args), position));
+            }
+            TokenKind::Symbol(SymbolKind::LeftBracket) => {
+                self.next_token();
+
+                let index = self.parse_expression(Precedence::Lowest)?;
+
+                if !self.expect_next_token(&TokenKind::Symbol(SymbolKind::RightBracket)) {
+                    return None;
+                }
+
+                let position = left.position;
+
+                return Some(self.finish_expr(ExprKind::Index(Box::new(left), Box::new(index)), position));
+            }
+            TokenKind::Symbol(SymbolKind::Dot) => {
+                if !self.expect_next_identifier() {
+                    return None;
+                }
+
+                let field = self.parse_ident()?;
+                let position = left.position;
+
+                return Some(self.finish_expr(ExprKind::Field(Box::new(left), field), position));
+            }
+            TokenKind::Symbol(symbol) => match Parser::infix_op(symbol) {
+                Some(op) => op,
+                None => {
+                    self.cursor_error("operator");
+                    return None;
+                }
+            },
+            _ => {
+                self.cursor_error("operator");
+                return None;
+            }
+        };
+
+        let precedence = self.cursor_precedence();
+
+        self.next_token();
+
+        let right = self.parse_expression(precedence)?;
+
+        Some(self.finish_expr(ExprKind::Infix(op, Box::new(left), Box::new(right)), position))
+    }
+
+    fn infix_op(symbol: &SymbolKind) -> Option<InfixOp> {
+        BINARY_OPERATORS.iter().find(|(kind, _, _)| kind == symbol).map(|&(_, op, _)| op)
+    }
+
+    pub(crate) fn token_precedence(kind: &TokenKind) -> Precedence {
+        let symbol = match kind {
+            TokenKind::Symbol(symbol) => symbol,
+            _ => return Precedence::Lowest,
+        };
+
+        if let Some(&(_, _, precedence)) = BINARY_OPERATORS.iter().find(|(kind, _, _)| kind == symbol) {
+            precedence
+        } else if POSTFIX_OPERATORS.iter().any(|(kind, _)| kind == symbol) {
+            Precedence::Call
+        } else {
+            Precedence::Lowest
+        }
+    }
+
+    fn peek_precedence(&self) -> Precedence {
+        Parser::token_precedence(&self.peek_token.kind)
+    }
+
+    fn cursor_precedence(&self) -> Precedence {
+        Parser::token_precedence(&self.cursor_token.kind)
+    }
+
+    fn peek_is_end_stmt(&self) -> bool {
+        matches!(
+            &self.peek_token.kind,
+            TokenKind::Newline
+                | TokenKind::EOF
+                | TokenKind::Symbol(SymbolKind::Semicolon)
+                | TokenKind::Symbol(SymbolKind::RightBrace)
+        )
+    }
+
+    /// Skips ahead to the end of the current statement after an error so
+    /// the rest of the input can still be parsed.
+    fn synchronize(&mut self) {
+        while !self.peek_is_end_stmt() {
+            self.next_token();
         }
     }
 
     fn parse_ident(&mut self) -> Option<Identifier> {
         match &self.cursor_token.kind {
-            TokenKind::Identifier(ref ident) => Some(Identifier::new(ident.clone())),
+            TokenKind::Identifier(ref ident) => {
+                Some(Identifier::new(ident.clone(), self.cursor_token.position))
+            }
             _ => None,
         }
     }
 
-    fn peek_token_is(&self, _kind: &TokenKind) -> bool {
-        matches!(&self.peek_token.kind, _kind)
+    fn cursor_token_is(&self, kind: &TokenKind) -> bool {
+        &self.cursor_token.kind == kind
+    }
+
+    fn peek_token_is(&self, kind: &TokenKind) -> bool {
+        &self.peek_token.kind == kind
     }
 
     fn expect_next_token(&mut self, kind: &TokenKind) -> bool {
@@ -97,21 +1029,146 @@ impl Parser {
             self.next_token();
             true
         } else {
-            // TODO(kosi): Add error handling here
+            self.peek_error(&kind.to_string());
             false
         }
     }
 
+    fn expect_next_identifier(&mut self) -> bool {
+        if let TokenKind::Identifier(_) = self.peek_token.kind {
+            self.next_token();
+            true
+        } else {
+            self.peek_error("identifier");
+            false
+        }
+    }
+
+    fn cursor_error(&mut self, expected: &str) {
+        let token = self.cursor_token.clone();
+        self.unexpected_token_error(expected, &token);
+    }
+
+    fn peek_error(&mut self, expected: &str) {
+        let token = self.peek_token.clone();
+        self.unexpected_token_error(expected, &token);
+    }
+
+    fn unexpected_token_error(&mut self, expected: &str, token: &Token) {
+        if token.kind == TokenKind::EOF {
+            self.incomplete_errors += 1;
+        }
+
+        let message = format!("expected {}, found {}", expected, token.kind);
+        self.errors.push(ParseError::new(message, token.position));
+    }
+
+    fn skip_peek_newlines(&mut self) {
+        while self.peek_token.kind == TokenKind::Newline {
+            let (token, end) = self.read_token();
+            self.peek_token = token;
+            self.peek_end = end;
+        }
+    }
+
     fn next_token(&mut self) {
-        self.cursor_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        let (peek_token, peek_end) = self.read_token();
+        self.cursor_token = mem::replace(&mut self.peek_token, peek_token);
+        self.cursor_end = mem::replace(&mut self.peek_end, peek_end);
+    }
+
+    /// Reads the next token from the lexer, dropping newlines that do not
+    /// terminate a statement under the semicolon elision rule. Also returns
+    /// where the token ends.
+    fn read_token(&mut self) -> (Token, BufferPosition) {
+        loop {
+            let token = self.lexer.next_token();
+
+            if token.kind == TokenKind::Newline && !Parser::ends_stmt(&self.last_token_kind) {
+                continue;
+            }
+
+            self.last_token_kind = token.kind.clone();
+
+            return (token, self.lexer.current_position());
+        }
+    }
+
+    /// Whether a newline after a token of this kind ends the statement.
+    pub(crate) fn ends_stmt(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Identifier(_)
+                | TokenKind::IntegerLiteral(_)
+                | TokenKind::FloatLiteral(_)
+                | TokenKind::StringLiteral(_)
+                | TokenKind::StringEnd(_)
+                | TokenKind::CharLiteral(_)
+                | TokenKind::Keyword(KeywordKind::True)
+                | TokenKind::Keyword(KeywordKind::False)
+                | TokenKind::Keyword(KeywordKind::Break)
+                | TokenKind::Keyword(KeywordKind::Continue)
+                | TokenKind::Keyword(KeywordKind::Return)
+                | TokenKind::Symbol(SymbolKind::Increment)
+                | TokenKind::Symbol(SymbolKind::Decrement)
+                | TokenKind::Symbol(SymbolKind::RightParen)
+                | TokenKind::Symbol(SymbolKind::RightBracket)
+                | TokenKind::Symbol(SymbolKind::RightBrace)
+        )
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    fn parse(input: &str) -> Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse();
+
+        assert!(
+            parser.errors().is_empty(),
+            "Parser reported errors: {:?}",
+            parser.errors()
+        );
+
+        program
+    }
+
+    fn parse_expr(input: &str) -> Expr {
+        let program = parse(input);
+
+        assert_eq!(program.0.len(), 1, "Expected one statement. Got {:?}", program);
+
+        match &program.0[0].kind {
+            StmtKind::Expr(expr) => expr.clone(),
+            other => panic!("stmt is not StmtKind::Expr. Got StmtKind::{:?}", other),
+        }
+    }
+
+    /// Renders an expression with explicit parentheses so precedence can be
+    /// checked without spelling out the whole tree.
+    fn group(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Identifier(ident) => ident.name.to_string(),
+            ExprKind::IntegerLiteral(value) => value.to_string(),
+            ExprKind::BooleanLiteral(value) => value.to_string(),
+            ExprKind::Prefix(op, right) => format!("({}{})", op.as_str(), group(right)),
+            ExprKind::Infix(op, left, right) => {
+                format!("({} {} {})", group(left), op.as_str(), group(right))
+            }
+            ExprKind::Call(function, args) => {
+                let args: Vec<String> = args.iter().map(group).collect();
+                format!("{}({})", group(function), args.join(", "))
+            }
+            ExprKind::Field(object, field) => format!("{}.{}", group(object), field.name),
+            ExprKind::Index(object, index) => format!("{}[{}]", group(object), group(index)),
+            other => format!("{:?}", other),
+        }
+    }
+
     #[test]
     fn test_let_statements() {
         let input = "
@@ -134,7 +1191,7 @@ mod tests {
             program.0.len()
         );
 
-        let tests = vec!["x", "y", "foobar"];
+        let tests = ["x", "y", "foobar"];
 
         for (i, test) in tests.iter().enumerate() {
             let stmt = &program.0[i];
@@ -144,14 +1201,235 @@ mod tests {
     }
 
     fn test_let_statement(stmt: &Stmt, identifier: &str) {
-        if let Stmt::Let(name, _value) = stmt {
+        if let StmtKind::Let(name, _ty, _value) = &stmt.kind {
             assert_eq!(
-                name.0, identifier,
+                name.name, identifier,
                 "Name is not {}. Got {}",
-                identifier, name.0
+                identifier, name.name
             );
         } else {
-            assert!(false, "stmt is not Stmt::Let. Got Stmt::{:?}", *stmt);
+            panic!("stmt is not StmtKind::Let. Got StmtKind::{:?}", stmt.kind);
+        }
+    }
+
+    #[test]
+    fn test_let_statement_errors() {
+        let input = "
+        let = 5
+        let x 10
+        let 838383
+        ";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors().iter().map(|e| e.message.clone()).collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "expected identifier, found `=`",
+                "expected `=`, found integer `10`",
+                "expected identifier, found integer `838383`",
+            ]
+        );
+        assert_eq!(parser.errors()[0].position, BufferPosition::new(2, 13));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let tests = vec![
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b + c", "((a + b) + c)"),
+            ("a + b * c - d / e", "((a + (b * c)) - (d / e))"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("a < b && c || !d", "(((a < b) && c) || (!d))"),
+            ("(5 + 5) * 2", "((5 + 5) * 2)"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            ("std.println(a[1 + 2], b.c)", "std.println(a[(1 + 2)], b.c)"),
+            ("1 +\n 2", "(1 + 2)"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(group(&parse_expr(input)), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_semicolon_elision() {
+        let program = parse(
+            "
+            fn main(): void {
+                let five: i32 = 5; five = 15
+                five += 1
+
+                five++
+            }
+            ",
+        );
+
+        assert_eq!(program.0.len(), 1);
+
+        if let StmtKind::Fn(decl) = &program.0[0].kind {
+            let kinds: Vec<&StmtKind> = decl.body.iter().map(|stmt| &stmt.kind).collect();
+
+            assert_eq!(kinds.len(), 4);
+            assert!(matches!(kinds[0], StmtKind::Let(_, Some(TypeExpr::Named(_)), _)));
+            assert!(matches!(kinds[1], StmtKind::Assign(_, AssignOp::Assign, _)));
+            assert!(matches!(kinds[2], StmtKind::Assign(_, AssignOp::Add, _)));
+            assert!(matches!(kinds[3], StmtKind::Increment(_)));
+        } else {
+            panic!("stmt is not StmtKind::Fn. Got StmtKind::{:?}", program.0[0].kind);
+        }
+    }
+
+    #[test]
+    fn test_if_and_match_expressions() {
+        let expr = parse_expr("if x < y { x } else if y < x { y } else { 0 }");
+
+        match expr.kind {
+            ExprKind::If(condition, consequence, Some(alternative)) => {
+                assert_eq!(group(&condition), "(x < y)");
+                assert_eq!(consequence.len(), 1);
+                assert!(matches!(alternative.kind, ExprKind::If(_, _, Some(_))));
+            }
+            other => panic!("expr is not ExprKind::If. Got ExprKind::{:?}", other),
+        }
+
+        let expr = parse_expr(
+            "match n {
+                0 => 1
+                -1 => { 2 }
+                Shape.Circle(r) => r,
+                _ => n
+            }",
+        );
+
+        match expr.kind {
+            ExprKind::Match(_, arms) => {
+                assert_eq!(arms.len(), 4);
+                assert!(matches!(arms[1].pattern, Pattern::Literal(Expr { kind: ExprKind::IntegerLiteral(-1), .. })));
+                assert!(matches!(&arms[2].pattern, Pattern::Variant(_, _, fields, _) if fields.len() == 1));
+                assert!(matches!(arms[3].pattern, Pattern::Wildcard(_)));
+            }
+            other => panic!("expr is not ExprKind::Match. Got ExprKind::{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let expr = parse_expr("\"sum: {a + b * 2}, {f(x)}!\"");
+
+        match expr.kind {
+            ExprKind::Interpolated(parts) => {
+                assert_eq!(parts.len(), 5);
+                assert!(matches!(&parts[0], StringPart::Text(text) if text == "sum: "));
+                assert!(matches!(&parts[1], StringPart::Expr(expr) if group(expr) == "(a + (b * 2))"));
+                assert!(matches!(&parts[2], StringPart::Text(text) if text == ", "));
+                assert!(matches!(&parts[3], StringPart::Expr(expr) if group(expr) == "f(x)"));
+                assert!(matches!(&parts[4], StringPart::Text(text) if text == "!"));
+            }
+            other => panic!("expr is not ExprKind::Interpolated. Got ExprKind::{:?}", other),
+        }
+
+        // Struct literals are allowed inside, even in a condition
+        let expr = parse_expr("if \"{P { x: 1 }.x}\" == \"1\" { }");
+        assert!(matches!(expr.kind, ExprKind::If(..)));
+    }
+
+    #[test]
+    fn test_declarations() {
+        let program = parse(
+            "
+            import std
+
+            struct Point {
+                x: i32,
+                y: i32
+            }
+
+            enum Shape { Circle(f64), Empty }
+
+            fn origin(): Point {
+                return Point { x: 0, y: 0 }
+            }
+
+            for let i = 0; i < 10; i++ {
+                if (Point { x: i, y: i }).x > 5 { break }
+            }
+            ",
+        );
+
+        let kinds: Vec<&StmtKind> = program.0.iter().map(|stmt| &stmt.kind).collect();
+
+        assert_eq!(kinds.len(), 5);
+        assert!(matches!(kinds[0], StmtKind::Import(_)));
+        assert!(matches!(kinds[1], StmtKind::Struct(decl) if decl.fields.len() == 2));
+        assert!(matches!(kinds[2], StmtKind::Enum(decl) if decl.variants[0].fields.len() == 1));
+        assert!(matches!(kinds[3], StmtKind::Fn(decl) if decl.return_type.is_some()));
+        assert!(matches!(kinds[4], StmtKind::For(..)));
+    }
+
+    /// Programs that nest something `depth` levels deep.
+    pub(crate) fn deeply_nested(depth: usize) -> Vec<String> {
+        let nest = |open: &str, inner: &str, close: &str| open.repeat(depth) + inner + &close.repeat(depth);
+
+        vec![
+            nest("(", "1", ")"),
+            nest("[", "", "]"),
+            nest("{", "", "}"),
+            nest("!", "x", ""),
+            nest("\"{", "1", "}\""),
+            nest("fn f() {", "", "}"),
+            format!("1{}", " + 1".repeat(depth)),
+            format!("x{}", ".a".repeat(depth)),
+            format!("let x: {} = 1", nest("[", "i32", "]")),
+            format!("match x {{ {} => 1 }}", nest("E.V(", "_", ")")),
+            format!("if a {{}}{}", " else if a {}".repeat(depth)),
+        ]
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        for source in deeply_nested(MAX_NESTING / 2) {
+            let mut parser = Parser::new(Lexer::new(&source));
+            parser.parse();
+
+            assert_eq!(parser.errors(), &[], "{}", source);
+        }
+
+        for source in deeply_nested(MAX_NESTING + 1) {
+            let mut parser = Parser::new(Lexer::new(&source));
+            parser.parse();
+
+            let message = format!("nested more than {} levels deep", MAX_NESTING);
+            assert!(parser.errors().iter().any(|error| error.message == message), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let tests = vec![
+            ("fn main(): void {", true),
+            ("1 +", true),
+            ("foo(1,", true),
+            ("let xs = [1, 2", true),
+            ("\"abc", true),
+            ("/* comment", true),
+            ("fn main(): void {}", false),
+            ("1 + 2", false),
+            ("let = 5", false),
+            ("1 + ) {", false),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse();
+
+            assert_eq!(parser.is_incomplete(), expected, "{}", input);
         }
     }
 }
@@ -0,0 +1,215 @@
+//! A small Wadler-style pretty printer. A `Doc` describes text along with
+//! the places where it may be broken into lines; `render` picks, group by
+//! group, whether everything fits on the current line or must be broken.
+
+pub const INDENT: &str = "    ";
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Doc {
+    Nil,
+    Text(String),
+    // Text that is never measured, such as a comment at the end of a line,
+    // so that it doesn't cause the code before it to be broken up.
+    Suffix(String),
+    // A space, or a newline when the enclosing group is broken
+    Line,
+    // Nothing, or a newline when the enclosing group is broken
+    SoftLine,
+    HardLine,
+    Concat(Vec<Doc>),
+    Nest(Box<Doc>),
+    Group(Box<Doc>),
+    // Only printed when the enclosing group is broken, like a trailing comma
+    IfBreak(Box<Doc>),
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+pub fn text<S: Into<String>>(text: S) -> Doc {
+    Doc::Text(text.into())
+}
+
+pub fn concat(docs: Vec<Doc>) -> Doc {
+    Doc::Concat(docs)
+}
+
+pub fn nest(doc: Doc) -> Doc {
+    Doc::Nest(Box::new(doc))
+}
+
+pub fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+pub fn if_break(doc: Doc) -> Doc {
+    Doc::IfBreak(Box::new(doc))
+}
+
+/// Joins documents with a separator between each of them.
+pub fn join(docs: Vec<Doc>, separator: Doc) -> Doc {
+    let mut joined = Vec::new();
+
+    for (i, doc) in docs.into_iter().enumerate() {
+        if i > 0 {
+            joined.push(separator.clone());
+        }
+
+        joined.push(doc);
+    }
+
+    Doc::Concat(joined)
+}
+
+fn text_width(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Whether `doc` fits in `remaining` columns when printed flat, followed by
+/// the commands still on the stack up to their first line break.
+fn fits(doc: &Doc, rest: &[(usize, Mode, &Doc)], remaining: usize) -> bool {
+    let mut remaining = remaining as isize;
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+
+    loop {
+        let (mode, doc) = match stack.pop() {
+            Some(command) => command,
+            None => match rest.next() {
+                Some((_, mode, doc)) => (*mode, *doc),
+                None => return true,
+            },
+        };
+
+        match doc {
+            Doc::Nil | Doc::Suffix(_) => {}
+            Doc::Text(text) => {
+                // Only a multi-line comment contains newlines
+                if text.contains('\n') {
+                    return mode == Mode::Break;
+                }
+
+                remaining -= text_width(text) as isize;
+            }
+            Doc::Line if mode == Mode::Flat => remaining -= 1,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine => return true,
+            // A hard line can't be flattened, so a group holding one is
+            // always broken
+            Doc::HardLine => return mode == Mode::Break,
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+            Doc::Nest(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+            Doc::IfBreak(doc) => {
+                if mode == Mode::Break {
+                    stack.push((mode, doc));
+                }
+            }
+        }
+
+        if remaining < 0 {
+            return false;
+        }
+    }
+}
+
+fn newline(output: &mut String, indent: usize) -> usize {
+    let trimmed = output.trim_end_matches(' ').len();
+    output.truncate(trimmed);
+    output.push('\n');
+
+    for _ in 0..indent {
+        output.push_str(INDENT);
+    }
+
+    indent * INDENT.len()
+}
+
+/// Lays out a document so that, where possible, no line is longer than
+/// `width` columns.
+pub fn render(doc: &Doc, width: usize) -> String {
+    let mut output = String::new();
+    let mut column = 0;
+    let mut stack = vec![(0, Mode::Break, doc)];
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Nil => {}
+            Doc::Text(text) | Doc::Suffix(text) => {
+                output.push_str(text);
+
+                column = match text.rfind('\n') {
+                    Some(index) => text_width(&text[index + 1..]),
+                    None => column + text_width(text),
+                };
+            }
+            Doc::Line | Doc::SoftLine if mode == Mode::Flat => {
+                if *doc == Doc::Line {
+                    output.push(' ');
+                    column += 1;
+                }
+            }
+            Doc::Line | Doc::SoftLine | Doc::HardLine => column = newline(&mut output, indent),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Nest(doc) => stack.push((indent + 1, mode, doc)),
+            Doc::Group(inner) => {
+                let mode = if mode == Mode::Flat || fits(inner, &stack, width.saturating_sub(column)) {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+
+                stack.push((indent, mode, inner));
+            }
+            Doc::IfBreak(doc) => {
+                if mode == Mode::Break {
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[&str]) -> Doc {
+        let args = args.iter().map(|arg| text(*arg)).collect();
+
+        group(concat(vec![
+            text(name),
+            text("("),
+            nest(concat(vec![
+                Doc::SoftLine,
+                join(args, concat(vec![text(","), Doc::Line])),
+                if_break(text(",")),
+            ])),
+            Doc::SoftLine,
+            text(")"),
+        ]))
+    }
+
+    #[test]
+    fn test_render() {
+        let doc = call("foo", &["alpha", "beta", "gamma"]);
+
+        assert_eq!(render(&doc, 80), "foo(alpha, beta, gamma)");
+        assert_eq!(render(&doc, 10), "foo(\n    alpha,\n    beta,\n    gamma,\n)");
+
+        // The text after a group counts towards whether it fits
+        let doc = concat(vec![call("f", &["x"]), text(" {")]);
+        assert_eq!(render(&doc, 5), "f(\n    x,\n) {");
+
+        // But comments at the end of the line do not
+        let doc = concat(vec![call("f", &["x"]), Doc::Suffix(" // a long comment".to_owned())]);
+        assert_eq!(render(&doc, 6), "f(x) // a long comment");
+
+        let doc = group(concat(vec![text("{"), Doc::Line, text("a"), Doc::HardLine, text("}")]));
+        assert_eq!(render(&doc, 80), "{\na\n}");
+    }
+}
@@ -0,0 +1,613 @@
+//! Name resolution. Builds the lexical scopes of a program and links every
+//! name that is declared or used to a `Binding`, recording its `BindingId` on
+//! the identifier so later passes don't have to look names up again.
+
+use crate::ast::*;
+use crate::symbol::Symbol;
+use crate::token::BufferPosition;
+use crate::types::Type;
+use crate::visit::{self, VisitorMut};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResolveError {
+    pub message: String,
+    pub position: BufferPosition,
+}
+
+impl ResolveError {
+    pub fn new(message: String, position: BufferPosition) -> ResolveError {
+        ResolveError { message, position }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BindingKind {
+    Let,
+    Param,
+    Function,
+    Struct,
+    Enum,
+    Module,
+    // A name bound by a match arm's pattern
+    Pattern,
+}
+
+impl BindingKind {
+    /// Whether this is a value bound in a function body rather than an item.
+    pub fn is_variable(&self) -> bool {
+        matches!(self, BindingKind::Let | BindingKind::Param | BindingKind::Pattern)
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Binding {
+    pub name: Symbol,
+    pub kind: BindingKind,
+    pub position: BufferPosition,
+}
+
+#[derive(Clone, Default)]
+struct Scope {
+    names: HashMap<Symbol, BindingId>,
+    // How many `let`s of each name are still to come in the block, so that
+    // using one too early can be told apart from a name that doesn't exist
+    pending: HashMap<Symbol, usize>,
+}
+
+/// Resolves programs one after another, like the entries of a REPL. The
+/// global scope persists between calls to `resolve_program`.
+#[derive(Clone)]
+pub struct Resolver {
+    bindings: Vec<Binding>,
+    scopes: Vec<Scope>,
+    errors: Vec<ResolveError>,
+    shadowed: Vec<(BindingId, BindingId)>,
+}
+
+impl Default for Resolver {
+    fn default() -> Resolver {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            bindings: Vec::new(),
+            scopes: vec![Scope::default()],
+            errors: Vec::new(),
+            shadowed: Vec::new(),
+        }
+    }
+
+    /// Resolves the names in a program against the globals declared so far.
+    /// The program's own globals are only kept if it has no errors.
+    pub fn resolve_program(&mut self, program: &mut Program) -> Result<(), Vec<ResolveError>> {
+        let saved = (self.scopes[0].clone(), self.bindings.len());
+
+        self.errors.clear();
+        self.shadowed.clear();
+        self.visit_program(program);
+        self.scopes.truncate(1);
+        self.scopes[0].pending.clear();
+
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        self.scopes[0] = saved.0;
+        self.bindings.truncate(saved.1);
+
+        // Hoisted declarations are checked first, so put things back in order
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|error| (error.position.line, error.position.column));
+
+        Err(errors)
+    }
+
+    pub fn binding(&self, id: BindingId) -> &Binding {
+        &self.bindings[id.0]
+    }
+
+    /// Every binding declared so far, indexed by `BindingId`.
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// The variables in the last program that shadow another variable, each
+    /// paired with the one it shadows.
+    pub fn shadowed(&self) -> &[(BindingId, BindingId)] {
+        &self.shadowed
+    }
+
+    fn error(&mut self, message: String, position: BufferPosition) {
+        self.errors.push(ResolveError::new(message, position));
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("the global scope is never popped")
+    }
+
+    fn lookup(&self, name: &Symbol) -> Option<BindingId> {
+        self.scopes.iter().rev().find_map(|scope| scope.names.get(name).copied())
+    }
+
+    /// Declares a name in the innermost scope, shadowing any other binding of
+    /// it there.
+    fn declare(&mut self, name: &mut Identifier, kind: BindingKind) {
+        let id = BindingId(self.bindings.len());
+
+        if let Some(previous) = self.lookup(&name.name) {
+            if kind.is_variable() && self.bindings[previous.0].kind.is_variable() {
+                self.shadowed.push((id, previous));
+            }
+        }
+
+        self.bindings.push(Binding {
+            name: name.name.clone(),
+            kind,
+            position: name.position,
+        });
+
+        self.scope().names.insert(name.name.clone(), id);
+        name.binding = Some(id);
+    }
+
+    /// Declares a name that must be unique in its scope, like a parameter.
+    fn declare_unique(&mut self, name: &mut Identifier, kind: BindingKind, list: &str) {
+        if self.scope().names.contains_key(&name.name) {
+            self.error(
+                format!("identifier `{}` is bound more than once in the same {}", name.name, list),
+                name.position,
+            );
+        }
+
+        self.declare(name, kind);
+    }
+
+    /// Links a use of a name to its binding, reporting it if there is none.
+    fn resolve(&mut self, name: &mut Identifier, what: &str) {
+        name.binding = self.lookup(&name.name);
+
+        if name.binding.is_some() {
+            return;
+        }
+
+        if self.scopes.iter().any(|scope| scope.pending.contains_key(&name.name)) {
+            self.error(format!("cannot use `{}` before it is declared", name.name), name.position);
+            return;
+        }
+
+        let mut message = format!("cannot find {} `{}` in this scope", what, name.name);
+
+        if let Some(suggestion) = self.suggest(&name.name, what == "type") {
+            message.push_str(&format!(", did you mean `{}`?", suggestion));
+        }
+
+        self.error(message, name.position);
+    }
+
+    /// The visible name closest to `name` by edit distance, if any is close
+    /// enough to be a likely typo. Inner scopes win ties.
+    fn suggest(&self, name: &Symbol, types: bool) -> Option<Symbol> {
+        let name = name.as_str();
+        let limit = std::cmp::max(1, name.chars().count() / 3);
+        let mut best: Option<(usize, Symbol)> = None;
+
+        let mut consider = |candidate: Symbol| {
+            let distance = edit_distance(name, candidate.as_str());
+
+            if distance <= limit && best.as_ref().is_none_or(|(best, _)| distance < *best) {
+                best = Some((distance, candidate));
+            }
+        };
+
+        if types {
+            for primitive in PRIMITIVES {
+                consider(Symbol::intern(primitive));
+            }
+        }
+
+        for scope in self.scopes.iter().rev() {
+            let mut names: Vec<(Symbol, BindingId)> = scope.names.iter().map(|(k, v)| (k.clone(), *v)).collect();
+            names.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+            for (candidate, id) in names {
+                let kind = self.bindings[id.0].kind;
+
+                if !types || kind == BindingKind::Struct || kind == BindingKind::Enum {
+                    consider(candidate);
+                }
+            }
+        }
+
+        best.map(|(_, name)| name)
+    }
+
+    /// Declares the structs, enums and functions of a block up front, the
+    /// same way the evaluator hoists them, and notes which `let`s are to come.
+    fn declare_items(&mut self, block: &mut BlockStmt) {
+        let mut items: HashMap<Symbol, BufferPosition> = HashMap::new();
+
+        for stmt in block.iter_mut() {
+            let (name, kind) = match &mut stmt.kind {
+                StmtKind::Fn(decl) => (&mut decl.name, BindingKind::Function),
+                StmtKind::Struct(decl) => (&mut decl.name, BindingKind::Struct),
+                StmtKind::Enum(decl) => (&mut decl.name, BindingKind::Enum),
+                StmtKind::Let(name, _, _) => {
+                    *self.scope().pending.entry(name.name.clone()).or_insert(0) += 1;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if let Some(previous) = items.insert(name.name.clone(), name.position) {
+                self.error(
+                    format!("the name `{}` is defined multiple times, first at {}", name.name, previous),
+                    name.position,
+                );
+            }
+
+            self.declare(name, kind);
+        }
+    }
+
+    /// Resolves a block in the current scope.
+    fn resolve_block(&mut self, block: &mut BlockStmt) {
+        self.declare_items(block);
+        visit::walk_block_mut(self, block);
+    }
+
+    /// Reports fields or variants that share a name.
+    fn check_unique<'a>(&mut self, names: impl Iterator<Item = &'a Identifier>, what: &str) {
+        let mut seen = HashMap::new();
+
+        for name in names {
+            if let Some(previous) = seen.insert(name.name.clone(), name.position) {
+                self.error(
+                    format!("{} `{}` is declared more than once, first at {}", what, name.name, previous),
+                    name.position,
+                );
+            }
+        }
+    }
+}
+
+const PRIMITIVES: &[&str] = &["i32", "i64", "f64", "bool", "char", "str", "void"];
+
+/// The number of single character insertions, deletions and substitutions
+/// needed to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + (a != *b) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+impl VisitorMut for Resolver {
+    fn visit_program(&mut self, program: &mut Program) {
+        self.resolve_block(&mut program.0);
+    }
+
+    fn visit_block(&mut self, block: &mut BlockStmt) {
+        self.push_scope();
+        self.resolve_block(block);
+        self.pop_scope();
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Let(name, ty, value) => {
+                if let Some(ty) = ty {
+                    self.visit_type_expr(ty);
+                }
+
+                self.visit_expr(value);
+
+                let scope = self.scope();
+
+                if let Some(count) = scope.pending.get_mut(&name.name) {
+                    *count -= 1;
+
+                    if *count == 0 {
+                        scope.pending.remove(&name.name);
+                    }
+                }
+
+                self.declare(name, BindingKind::Let);
+            }
+            StmtKind::For(..) => {
+                self.push_scope();
+                visit::walk_stmt_mut(self, stmt);
+                self.pop_scope();
+            }
+            StmtKind::Import(module) => self.declare(module, BindingKind::Module),
+            _ => visit::walk_stmt_mut(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Identifier(name) => self.resolve(name, "value"),
+            ExprKind::StructLiteral(name, fields) => {
+                self.resolve(name, "struct");
+
+                for (_, value) in fields {
+                    self.visit_expr(value);
+                }
+            }
+            _ => visit::walk_expr_mut(self, expr),
+        }
+    }
+
+    fn visit_type_expr(&mut self, ty: &mut TypeExpr) {
+        match ty {
+            TypeExpr::Named(name) if Type::primitive(name.name.as_str()).is_some() => {}
+            TypeExpr::Named(name) => self.resolve(name, "type"),
+            TypeExpr::Array(element, _) => self.visit_type_expr(element),
+        }
+    }
+
+    fn visit_fn_decl(&mut self, decl: &mut FnDecl) {
+        for param in &mut decl.params {
+            self.visit_type_expr(&mut param.ty);
+        }
+
+        if let Some(ty) = &mut decl.return_type {
+            self.visit_type_expr(ty);
+        }
+
+        self.push_scope();
+
+        for param in &mut decl.params {
+            self.declare_unique(&mut param.name, BindingKind::Param, "parameter list");
+        }
+
+        self.resolve_block(&mut decl.body);
+        self.pop_scope();
+    }
+
+    fn visit_struct_decl(&mut self, decl: &mut StructDecl) {
+        self.check_unique(decl.fields.iter().map(|field| &field.name), "field");
+
+        for field in &mut decl.fields {
+            self.visit_type_expr(&mut field.ty);
+        }
+    }
+
+    fn visit_enum_decl(&mut self, decl: &mut EnumDecl) {
+        self.check_unique(decl.variants.iter().map(|variant| &variant.name), "variant");
+        visit::walk_enum_decl_mut(self, decl);
+    }
+
+    fn visit_match_arm(&mut self, arm: &mut MatchArm) {
+        self.push_scope();
+        visit::walk_match_arm_mut(self, arm);
+        self.pop_scope();
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Binding(name) => self.declare_unique(name, BindingKind::Pattern, "pattern"),
+            Pattern::Variant(enum_name, _, fields, _) => {
+                self.resolve(enum_name, "enum");
+
+                for field in fields {
+                    self.visit_pattern(field);
+                }
+            }
+            _ => visit::walk_pattern_mut(self, pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::visit::Visitor;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        assert!(
+            parser.errors().is_empty(),
+            "Parser reported errors: {:?}",
+            parser.errors()
+        );
+
+        program
+    }
+
+    fn resolve_errors(input: &str) -> Vec<String> {
+        match Resolver::new().resolve_program(&mut parse(input)) {
+            Ok(()) => panic!("resolving {:?} succeeded", input),
+            Err(errors) => errors.into_iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    /// Every resolved name in source order, as `name@line:column` of the
+    /// declaration it refers to.
+    struct Links<'r>(&'r Resolver, Vec<String>);
+
+    impl<'ast> Visitor<'ast> for Links<'_> {
+        fn visit_identifier(&mut self, identifier: &'ast Identifier) {
+            if let Some(id) = identifier.binding {
+                let binding = self.0.binding(id);
+                self.1.push(format!("{}@{}", binding.name, binding.position));
+            }
+        }
+    }
+
+    fn links(input: &str) -> Vec<String> {
+        let mut resolver = Resolver::new();
+        let mut program = parse(input);
+
+        if let Err(errors) = resolver.resolve_program(&mut program) {
+            panic!("resolving {:?} failed: {:?}", input, errors);
+        }
+
+        let mut links = Links(&resolver, Vec::new());
+        links.visit_program(&program);
+        links.1
+    }
+
+    #[test]
+    fn test_links_uses_to_declarations() {
+        let input = "
+            let x = 1
+            fn f(x: i32): i32 { x + g() }
+            fn g(): i32 { x }
+            match f(x) { y => y, _ => x }
+        ";
+
+        assert_eq!(
+            links(input),
+            vec![
+                "x@2:17", "f@3:16", "x@3:18", "x@3:18", "g@4:16", "g@4:16", "x@2:17", "f@3:16", "x@2:17",
+                "y@5:26", "y@5:26", "x@2:17",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        let input = "
+            let x = 1
+            { let x = 2; x }
+            x
+            for let i = 0; i < 3; i++ { i }
+            struct P { a: i32 }
+            enum E { A(P) }
+            fn h(e: E): P { match e { E.A(p) => p } }
+        ";
+
+        assert_eq!(
+            links(input),
+            vec![
+                "x@2:17", "x@3:19", "x@3:19", "x@2:17", "i@5:21", "i@5:21", "i@5:21", "i@5:21", "P@6:20",
+                "E@7:18", "P@6:20", "h@8:16", "e@8:18", "E@7:18", "P@6:20", "e@8:18", "E@7:18", "p@8:43",
+                "p@8:43",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            ("y", vec!["1:1: cannot find value `y` in this scope"]),
+            (
+                "let count = 1\ncoumt",
+                vec!["2:1: cannot find value `coumt` in this scope, did you mean `count`?"],
+            ),
+            (
+                "struct Point { x: i32 }\nlet p: Pont = Point { x: 1 }",
+                vec!["2:8: cannot find type `Pont` in this scope, did you mean `Point`?"],
+            ),
+            ("let x: u32 = 1", vec!["1:8: cannot find type `u32` in this scope, did you mean `i32`?"]),
+            ("match 1 { Color.Red => 1 }", vec!["1:11: cannot find enum `Color` in this scope"]),
+            ("x + 1\nlet x = 2", vec!["1:1: cannot use `x` before it is declared"]),
+            ("let x = x", vec!["1:9: cannot use `x` before it is declared"]),
+            ("fn f(): i32 { x }\nlet x = 1", vec!["1:15: cannot use `x` before it is declared"]),
+            (
+                "fn f() {}\nstruct f {}",
+                vec!["2:8: the name `f` is defined multiple times, first at 1:4"],
+            ),
+            (
+                "fn f(a: i32, a: i32) {}",
+                vec!["1:14: identifier `a` is bound more than once in the same parameter list"],
+            ),
+            (
+                "enum E { V(i32, i32) }\nmatch E.V(1, 2) { E.V(a, a) => a }",
+                vec!["2:26: identifier `a` is bound more than once in the same pattern"],
+            ),
+            (
+                "struct S { a: i32, a: i32 }",
+                vec!["1:20: field `a` is declared more than once, first at 1:12"],
+            ),
+            ("enum E { A, A }", vec!["1:13: variant `A` is declared more than once, first at 1:10"]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(resolve_errors(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_shadowing_is_allowed() {
+        assert_eq!(links("let x = 1\nlet x = x + 1\nx"), vec!["x@1:5", "x@2:5", "x@1:5", "x@2:5"]);
+        assert_eq!(links("fn f(a: i32) { let a = a }"), vec!["f@1:4", "a@1:6", "a@1:20", "a@1:6"]);
+    }
+
+    #[test]
+    fn test_shadowed() {
+        let mut resolver = Resolver::new();
+        let mut program = parse("let x = 1\nfn f(x: i32) { let x = x }\nlet x = 2\nfn x() {}");
+
+        assert!(resolver.resolve_program(&mut program).is_ok());
+
+        let shadowed: Vec<(String, String)> = resolver
+            .shadowed()
+            .iter()
+            .map(|(id, previous)| {
+                (resolver.binding(*id).position.to_string(), resolver.binding(*previous).position.to_string())
+            })
+            .collect();
+
+        assert_eq!(
+            shadowed,
+            vec![
+                ("2:6".to_owned(), "1:5".to_owned()),
+                ("2:20".to_owned(), "2:6".to_owned()),
+                ("3:5".to_owned(), "1:5".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_globals_persist_only_without_errors() {
+        let mut resolver = Resolver::new();
+
+        assert!(resolver.resolve_program(&mut parse("let x = 1")).is_ok());
+        assert!(resolver.resolve_program(&mut parse("let y = 2\nz")).is_err());
+        assert!(resolver.resolve_program(&mut parse("x")).is_ok());
+        assert!(resolver.resolve_program(&mut parse("y")).is_err());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("count", "cuont"), 2);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+}
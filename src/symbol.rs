@@ -0,0 +1,160 @@
+//! Interned names. Identifiers are interned as they are lexed, so a name
+//! is a `Symbol` everywhere after the lexer: comparing and hashing one is as
+//! cheap as for a pointer, and reading its string takes no lock. Every symbol
+//! comes from the one program-wide interner, and a string is freed once the
+//! last symbol for it is dropped.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+
+// How many strings an interner holds before it first drops the unused ones
+const MIN_CAPACITY: usize = 256;
+
+/// A handle to an interned string. Two symbols are equal exactly when the
+/// strings they were interned from are.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+/// Hands out one shared copy of each string that has a symbol. Symbols
+/// from different interners would never be equal, so there is only the
+/// global one outside of tests.
+struct Interner {
+    strings: HashSet<Arc<str>>,
+    // When to next drop the strings no symbol refers to any more
+    limit: usize,
+}
+
+impl Interner {
+    fn new() -> Interner {
+        Interner {
+            strings: HashSet::new(),
+            limit: MIN_CAPACITY,
+        }
+    }
+
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(shared) = self.strings.get(string) {
+            return Symbol(shared.clone());
+        }
+
+        if self.strings.len() >= self.limit {
+            // Only the interner can hand out another symbol for a string it
+            // alone holds, so none can appear while it is dropped
+            self.strings.retain(|shared| Arc::strong_count(shared) > 1);
+            self.limit = MIN_CAPACITY.max(self.strings.len() * 2);
+        }
+
+        let shared: Arc<str> = string.into();
+        self.strings.insert(shared.clone());
+        Symbol(shared)
+    }
+
+    /// The interner's copy of a symbol's string.
+    #[cfg(test)]
+    fn resolve(&self, symbol: &Symbol) -> &str {
+        self.strings.get(symbol.as_str()).expect("the symbol should come from this interner")
+    }
+}
+
+/// The interner shared by the whole program, only locked to intern.
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| Mutex::new(Interner::new()))
+}
+
+impl Symbol {
+    pub fn intern(string: &str) -> Symbol {
+        interner().lock().unwrap().intern(string)
+    }
+
+    /// The string the symbol was interned from.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Interning keeps a single copy of each string, so symbols are compared by
+/// which copy they share.
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Symbol {
+        Symbol::intern(string)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Shows the string rather than the address, which would differ between runs.
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interning() {
+        let mut interner = Interner::new();
+
+        let a = interner.intern("alpha");
+        let b = interner.intern("beta");
+
+        assert_eq!(interner.intern("alpha"), a);
+        assert_ne!(a, b);
+        assert_eq!(interner.resolve(&b), "beta");
+
+        let symbol = Symbol::intern("gamma");
+        assert_eq!(Symbol::intern(&String::from("gamma")), symbol);
+        assert_eq!(symbol, "gamma");
+        assert_eq!(format!("{} {:?}", symbol, symbol), "gamma \"gamma\"");
+    }
+
+    #[test]
+    fn test_freeing() {
+        let mut interner = Interner::new();
+        let kept = interner.intern("kept");
+
+        for i in 0..MIN_CAPACITY * 4 {
+            interner.intern(&format!("name{}", i));
+        }
+
+        // Only the strings with symbols left survive
+        assert!(interner.strings.len() <= MIN_CAPACITY);
+        assert_eq!(interner.intern("kept"), kept);
+        assert_eq!(Arc::strong_count(&kept.0), 2);
+    }
+}
@@ -1,4 +1,7 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use crate::symbol::Symbol;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct BufferPosition {
     pub line: usize,
     pub column: usize,
@@ -10,6 +13,12 @@ impl BufferPosition {
     }
 }
 
+impl fmt::Display for BufferPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum KeywordKind {
     Fn,
@@ -26,6 +35,7 @@ pub enum KeywordKind {
     False,
     Match,
     Return,
+    Import,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -43,6 +53,8 @@ pub enum SymbolKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Bang,
     Eq,
     NotEq,
@@ -50,15 +62,32 @@ pub enum SymbolKind {
     Gt,
     LtEq,
     GtEq,
+    And,
+    Or,
+    Dot,
+    Question,
+    FatArrow,
+    Increment,
+    Decrement,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    ModAssign,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
-    Identifier(String),
+    Identifier(Symbol),
     Keyword(KeywordKind),
     IntegerLiteral(i64),
     FloatLiteral(f64),
     StringLiteral(String),
+    // The pieces of an interpolated string like `"a{x}b{y}c"`: `"a{`, then
+    // the tokens of `x`, then `}b{`, the tokens of `y`, and finally `}c"`
+    StringStart(String),
+    StringMiddle(String),
+    StringEnd(String),
     CharLiteral(char),
     Symbol(SymbolKind),
     Newline,
@@ -72,6 +101,30 @@ pub struct Token {
     pub position: BufferPosition,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+}
+
+/// Source text between tokens that the parser doesn't look at.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+/// A token along with its exact source text and the trivia around it, so
+/// that the input can be reproduced from the tokens.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LosslessToken {
+    pub token: Token,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
 impl Token {
     pub const EOF: Token = Token {
         kind: TokenKind::EOF,
@@ -82,3 +135,85 @@ impl Token {
         Token { kind, position }
     }
 }
+
+impl KeywordKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeywordKind::Fn => "fn",
+            KeywordKind::If => "if",
+            KeywordKind::Else => "else",
+            KeywordKind::For => "for",
+            KeywordKind::While => "while",
+            KeywordKind::Enum => "enum",
+            KeywordKind::Struct => "struct",
+            KeywordKind::Break => "break",
+            KeywordKind::Continue => "continue",
+            KeywordKind::Let => "let",
+            KeywordKind::True => "true",
+            KeywordKind::False => "false",
+            KeywordKind::Match => "match",
+            KeywordKind::Return => "return",
+            KeywordKind::Import => "import",
+        }
+    }
+}
+
+impl SymbolKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymbolKind::Plus => "+",
+            SymbolKind::Minus => "-",
+            SymbolKind::Slash => "/",
+            SymbolKind::Mod => "%",
+            SymbolKind::Asterisk => "*",
+            SymbolKind::Semicolon => ";",
+            SymbolKind::Colon => ":",
+            SymbolKind::Assign => "=",
+            SymbolKind::Comma => ",",
+            SymbolKind::LeftParen => "(",
+            SymbolKind::RightParen => ")",
+            SymbolKind::LeftBrace => "{",
+            SymbolKind::RightBrace => "}",
+            SymbolKind::LeftBracket => "[",
+            SymbolKind::RightBracket => "]",
+            SymbolKind::Bang => "!",
+            SymbolKind::Eq => "==",
+            SymbolKind::NotEq => "!=",
+            SymbolKind::Lt => "<",
+            SymbolKind::Gt => ">",
+            SymbolKind::LtEq => "<=",
+            SymbolKind::GtEq => ">=",
+            SymbolKind::And => "&&",
+            SymbolKind::Or => "||",
+            SymbolKind::Dot => ".",
+            SymbolKind::Question => "?",
+            SymbolKind::FatArrow => "=>",
+            SymbolKind::Increment => "++",
+            SymbolKind::Decrement => "--",
+            SymbolKind::PlusAssign => "+=",
+            SymbolKind::MinusAssign => "-=",
+            SymbolKind::AsteriskAssign => "*=",
+            SymbolKind::SlashAssign => "/=",
+            SymbolKind::ModAssign => "%=",
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Identifier(name) => write!(f, "identifier `{}`", name),
+            TokenKind::Keyword(keyword) => write!(f, "`{}`", keyword.as_str()),
+            TokenKind::IntegerLiteral(value) => write!(f, "integer `{}`", value),
+            TokenKind::FloatLiteral(value) => write!(f, "float `{:?}`", value),
+            TokenKind::StringLiteral(value) => write!(f, "string {:?}", value),
+            TokenKind::StringStart(value) => write!(f, "string {:?}", value),
+            TokenKind::StringMiddle(_) | TokenKind::StringEnd(_) => write!(f, "`}}`"),
+            TokenKind::CharLiteral(value) => write!(f, "char {:?}", value),
+            TokenKind::Symbol(symbol) => write!(f, "`{}`", symbol.as_str()),
+            TokenKind::Newline => write!(f, "newline"),
+            TokenKind::Illegal => write!(f, "illegal token"),
+            TokenKind::EOF => write!(f, "end of input"),
+        }
+    }
+}
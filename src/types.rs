@@ -0,0 +1,105 @@
+use crate::symbol::Symbol;
+use std::fmt;
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum Type {
+    I32,
+    I64,
+    F64,
+    Bool,
+    Char,
+    Str,
+    Void,
+    Array(Box<Type>),
+    Struct(Symbol),
+    Enum(Symbol),
+    Fn(Vec<Type>, Box<Type>),
+    Builtin(Symbol),
+    Module(Symbol),
+    StructType(Symbol),
+    EnumType(Symbol),
+    // The type of `return`, `break` and `continue`, which never produce a
+    // value and so fit wherever a value is expected.
+    Never,
+    // Stands in for the type of an erroneous expression so a single mistake
+    // is only reported once.
+    Unknown,
+}
+
+impl Type {
+    /// Looks up one of the built-in primitive types by name.
+    pub fn primitive(name: &str) -> Option<Type> {
+        let ty = match name {
+            "i32" => Type::I32,
+            "i64" => Type::I64,
+            "f64" => Type::F64,
+            "bool" => Type::Bool,
+            "char" => Type::Char,
+            "str" => Type::Str,
+            "void" => Type::Void,
+            _ => return None,
+        };
+
+        Some(ty)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::I32 | Type::I64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::I32 | Type::I64 | Type::F64)
+    }
+
+    /// Whether a value of type `other` can be used where `self` is expected.
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) | (_, Type::Never) => true,
+            (Type::Array(a), Type::Array(b)) => a.accepts(b),
+            (Type::Fn(a_params, a_ret), Type::Fn(b_params, b_ret)) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(b_params).all(|(a, b)| a == b)
+                    && a_ret.accepts(b_ret)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// The more specific of two compatible types, used when merging the
+    /// branches of an `if` or `match`.
+    pub fn join(&self, other: &Type) -> Option<Type> {
+        match (self, other) {
+            (Type::Never, ty) | (ty, Type::Never) => Some(ty.clone()),
+            (Type::Unknown, _) | (_, Type::Unknown) => Some(Type::Unknown),
+            (Type::Array(a), Type::Array(b)) => a.join(b).map(|ty| Type::Array(Box::new(ty))),
+            (a, b) if a == b => Some(a.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::Str => write!(f, "str"),
+            Type::Void => write!(f, "void"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Struct(name) | Type::Enum(name) => write!(f, "{}", name),
+            Type::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                write!(f, "fn({}): {}", params.join(", "), ret)
+            }
+            Type::Builtin(name) => write!(f, "builtin fn std.{}", name),
+            Type::Module(name) => write!(f, "module {}", name),
+            Type::StructType(name) => write!(f, "struct {}", name),
+            Type::EnumType(name) => write!(f, "enum {}", name),
+            Type::Never => write!(f, "never"),
+            Type::Unknown => write!(f, "{{unknown}}"),
+        }
+    }
+}
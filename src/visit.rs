@@ -0,0 +1,602 @@
+//! Traversals of the syntax tree.
+//!
+//! A pass implements `Visitor` or `VisitorMut` and overrides the methods for
+//! the nodes it cares about. Each method defaults to the matching `walk_*`
+//! function, which visits the node's children in source order, so an
+//! override calls it to keep descending.
+//!
+//! Every name is visited with `visit_identifier`, whether it declares,
+//! uses, or is a field or variant of something.
+
+use crate::ast::*;
+
+pub trait Visitor<'ast>: Sized {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_block(&mut self, block: &'ast [Stmt]) {
+        walk_block(self, block)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_string_part(&mut self, part: &'ast StringPart) {
+        walk_string_part(self, part)
+    }
+
+    fn visit_identifier(&mut self, _identifier: &'ast Identifier) {}
+
+    fn visit_type_expr(&mut self, ty: &'ast TypeExpr) {
+        walk_type_expr(self, ty)
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_fn_decl(&mut self, decl: &'ast FnDecl) {
+        walk_fn_decl(self, decl)
+    }
+
+    fn visit_struct_decl(&mut self, decl: &'ast StructDecl) {
+        walk_struct_decl(self, decl)
+    }
+
+    fn visit_enum_decl(&mut self, decl: &'ast EnumDecl) {
+        walk_enum_decl(self, decl)
+    }
+
+    fn visit_variant(&mut self, variant: &'ast Variant) {
+        walk_variant(self, variant)
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        walk_pattern(self, pattern)
+    }
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast>>(visitor: &mut V, program: &'ast Program) {
+    visitor.visit_block(&program.0);
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast>>(visitor: &mut V, block: &'ast [Stmt]) {
+    for stmt in block {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast>>(visitor: &mut V, stmt: &'ast Stmt) {
+    match &stmt.kind {
+        StmtKind::Blank | StmtKind::Break | StmtKind::Continue => {}
+        StmtKind::Let(name, ty, value) => {
+            visitor.visit_identifier(name);
+
+            if let Some(ty) = ty {
+                visitor.visit_type_expr(ty);
+            }
+
+            visitor.visit_expr(value);
+        }
+        StmtKind::Assign(target, _, value) => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        StmtKind::Increment(target) | StmtKind::Decrement(target) => visitor.visit_expr(target),
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::While(condition, body) => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        }
+        StmtKind::For(init, condition, post, body) => {
+            visitor.visit_stmt(init);
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(post);
+            visitor.visit_block(body);
+        }
+        StmtKind::Fn(decl) => visitor.visit_fn_decl(decl),
+        StmtKind::Struct(decl) => visitor.visit_struct_decl(decl),
+        StmtKind::Enum(decl) => visitor.visit_enum_decl(decl),
+        StmtKind::Import(name) => visitor.visit_identifier(name),
+        StmtKind::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast>>(visitor: &mut V, expr: &'ast Expr) {
+    match &expr.kind {
+        ExprKind::Identifier(name) => visitor.visit_identifier(name),
+        ExprKind::IntegerLiteral(_)
+        | ExprKind::FloatLiteral(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::CharLiteral(_)
+        | ExprKind::BooleanLiteral(_) => {}
+        ExprKind::Interpolated(parts) => {
+            for part in parts {
+                visitor.visit_string_part(part);
+            }
+        }
+        ExprKind::ArrayLiteral(elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::StructLiteral(name, fields) => {
+            visitor.visit_identifier(name);
+
+            for (field, value) in fields {
+                visitor.visit_identifier(field);
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Prefix(_, right) => visitor.visit_expr(right),
+        ExprKind::Infix(_, left, right) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Call(function, args) => {
+            visitor.visit_expr(function);
+
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Index(object, index) => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Field(object, field) => {
+            visitor.visit_expr(object);
+            visitor.visit_identifier(field);
+        }
+        ExprKind::Block(block) => visitor.visit_block(block),
+        ExprKind::If(condition, consequence, alternative) => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(consequence);
+
+            if let Some(alternative) = alternative {
+                visitor.visit_expr(alternative);
+            }
+        }
+        ExprKind::Match(scrutinee, arms) => {
+            visitor.visit_expr(scrutinee);
+
+            for arm in arms {
+                visitor.visit_match_arm(arm);
+            }
+        }
+    }
+}
+
+pub fn walk_string_part<'ast, V: Visitor<'ast>>(visitor: &mut V, part: &'ast StringPart) {
+    match part {
+        StringPart::Text(_) => {}
+        StringPart::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_type_expr<'ast, V: Visitor<'ast>>(visitor: &mut V, ty: &'ast TypeExpr) {
+    match ty {
+        TypeExpr::Named(name) => visitor.visit_identifier(name),
+        TypeExpr::Array(element, _) => visitor.visit_type_expr(element),
+    }
+}
+
+pub fn walk_param<'ast, V: Visitor<'ast>>(visitor: &mut V, param: &'ast Param) {
+    visitor.visit_identifier(&param.name);
+    visitor.visit_type_expr(&param.ty);
+}
+
+pub fn walk_fn_decl<'ast, V: Visitor<'ast>>(visitor: &mut V, decl: &'ast FnDecl) {
+    visitor.visit_identifier(&decl.name);
+
+    for param in &decl.params {
+        visitor.visit_param(param);
+    }
+
+    if let Some(ty) = &decl.return_type {
+        visitor.visit_type_expr(ty);
+    }
+
+    visitor.visit_block(&decl.body);
+}
+
+pub fn walk_struct_decl<'ast, V: Visitor<'ast>>(visitor: &mut V, decl: &'ast StructDecl) {
+    visitor.visit_identifier(&decl.name);
+
+    for field in &decl.fields {
+        visitor.visit_param(field);
+    }
+}
+
+pub fn walk_enum_decl<'ast, V: Visitor<'ast>>(visitor: &mut V, decl: &'ast EnumDecl) {
+    visitor.visit_identifier(&decl.name);
+
+    for variant in &decl.variants {
+        visitor.visit_variant(variant);
+    }
+}
+
+pub fn walk_variant<'ast, V: Visitor<'ast>>(visitor: &mut V, variant: &'ast Variant) {
+    visitor.visit_identifier(&variant.name);
+
+    for field in &variant.fields {
+        visitor.visit_type_expr(field);
+    }
+}
+
+pub fn walk_match_arm<'ast, V: Visitor<'ast>>(visitor: &mut V, arm: &'ast MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    visitor.visit_expr(&arm.body);
+}
+
+pub fn walk_pattern<'ast, V: Visitor<'ast>>(visitor: &mut V, pattern: &'ast Pattern) {
+    match pattern {
+        Pattern::Wildcard(_) => {}
+        Pattern::Literal(expr) => visitor.visit_expr(expr),
+        Pattern::Binding(name) => visitor.visit_identifier(name),
+        Pattern::Variant(enum_name, variant, fields, _) => {
+            visitor.visit_identifier(enum_name);
+            visitor.visit_identifier(variant);
+
+            for field in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+    }
+}
+
+/// Like `Visitor`, but able to change the tree in place. Blocks are passed
+/// as vectors so statements can be added or removed.
+pub trait VisitorMut: Sized {
+    fn visit_program(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_block(&mut self, block: &mut BlockStmt) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_string_part(&mut self, part: &mut StringPart) {
+        walk_string_part_mut(self, part)
+    }
+
+    fn visit_identifier(&mut self, _identifier: &mut Identifier) {}
+
+    fn visit_type_expr(&mut self, ty: &mut TypeExpr) {
+        walk_type_expr_mut(self, ty)
+    }
+
+    fn visit_param(&mut self, param: &mut Param) {
+        walk_param_mut(self, param)
+    }
+
+    fn visit_fn_decl(&mut self, decl: &mut FnDecl) {
+        walk_fn_decl_mut(self, decl)
+    }
+
+    fn visit_struct_decl(&mut self, decl: &mut StructDecl) {
+        walk_struct_decl_mut(self, decl)
+    }
+
+    fn visit_enum_decl(&mut self, decl: &mut EnumDecl) {
+        walk_enum_decl_mut(self, decl)
+    }
+
+    fn visit_variant(&mut self, variant: &mut Variant) {
+        walk_variant_mut(self, variant)
+    }
+
+    fn visit_match_arm(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm)
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern)
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    visitor.visit_block(&mut program.0);
+}
+
+pub fn walk_block_mut<V: VisitorMut>(visitor: &mut V, block: &mut BlockStmt) {
+    for stmt in block {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Blank | StmtKind::Break | StmtKind::Continue => {}
+        StmtKind::Let(name, ty, value) => {
+            visitor.visit_identifier(name);
+
+            if let Some(ty) = ty {
+                visitor.visit_type_expr(ty);
+            }
+
+            visitor.visit_expr(value);
+        }
+        StmtKind::Assign(target, _, value) => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        StmtKind::Increment(target) | StmtKind::Decrement(target) => visitor.visit_expr(target),
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::While(condition, body) => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        }
+        StmtKind::For(init, condition, post, body) => {
+            visitor.visit_stmt(init);
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(post);
+            visitor.visit_block(body);
+        }
+        StmtKind::Fn(decl) => visitor.visit_fn_decl(decl),
+        StmtKind::Struct(decl) => visitor.visit_struct_decl(decl),
+        StmtKind::Enum(decl) => visitor.visit_enum_decl(decl),
+        StmtKind::Import(name) => visitor.visit_identifier(name),
+        StmtKind::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Identifier(name) => visitor.visit_identifier(name),
+        ExprKind::IntegerLiteral(_)
+        | ExprKind::FloatLiteral(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::CharLiteral(_)
+        | ExprKind::BooleanLiteral(_) => {}
+        ExprKind::Interpolated(parts) => {
+            for part in parts {
+                visitor.visit_string_part(part);
+            }
+        }
+        ExprKind::ArrayLiteral(elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        ExprKind::StructLiteral(name, fields) => {
+            visitor.visit_identifier(name);
+
+            for (field, value) in fields {
+                visitor.visit_identifier(field);
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Prefix(_, right) => visitor.visit_expr(right),
+        ExprKind::Infix(_, left, right) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Call(function, args) => {
+            visitor.visit_expr(function);
+
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Index(object, index) => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Field(object, field) => {
+            visitor.visit_expr(object);
+            visitor.visit_identifier(field);
+        }
+        ExprKind::Block(block) => visitor.visit_block(block),
+        ExprKind::If(condition, consequence, alternative) => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(consequence);
+
+            if let Some(alternative) = alternative {
+                visitor.visit_expr(alternative);
+            }
+        }
+        ExprKind::Match(scrutinee, arms) => {
+            visitor.visit_expr(scrutinee);
+
+            for arm in arms {
+                visitor.visit_match_arm(arm);
+            }
+        }
+    }
+}
+
+pub fn walk_string_part_mut<V: VisitorMut>(visitor: &mut V, part: &mut StringPart) {
+    match part {
+        StringPart::Text(_) => {}
+        StringPart::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_type_expr_mut<V: VisitorMut>(visitor: &mut V, ty: &mut TypeExpr) {
+    match ty {
+        TypeExpr::Named(name) => visitor.visit_identifier(name),
+        TypeExpr::Array(element, _) => visitor.visit_type_expr(element),
+    }
+}
+
+pub fn walk_param_mut<V: VisitorMut>(visitor: &mut V, param: &mut Param) {
+    visitor.visit_identifier(&mut param.name);
+    visitor.visit_type_expr(&mut param.ty);
+}
+
+pub fn walk_fn_decl_mut<V: VisitorMut>(visitor: &mut V, decl: &mut FnDecl) {
+    visitor.visit_identifier(&mut decl.name);
+
+    for param in &mut decl.params {
+        visitor.visit_param(param);
+    }
+
+    if let Some(ty) = &mut decl.return_type {
+        visitor.visit_type_expr(ty);
+    }
+
+    visitor.visit_block(&mut decl.body);
+}
+
+pub fn walk_struct_decl_mut<V: VisitorMut>(visitor: &mut V, decl: &mut StructDecl) {
+    visitor.visit_identifier(&mut decl.name);
+
+    for field in &mut decl.fields {
+        visitor.visit_param(field);
+    }
+}
+
+pub fn walk_enum_decl_mut<V: VisitorMut>(visitor: &mut V, decl: &mut EnumDecl) {
+    visitor.visit_identifier(&mut decl.name);
+
+    for variant in &mut decl.variants {
+        visitor.visit_variant(variant);
+    }
+}
+
+pub fn walk_variant_mut<V: VisitorMut>(visitor: &mut V, variant: &mut Variant) {
+    visitor.visit_identifier(&mut variant.name);
+
+    for field in &mut variant.fields {
+        visitor.visit_type_expr(field);
+    }
+}
+
+pub fn walk_match_arm_mut<V: VisitorMut>(visitor: &mut V, arm: &mut MatchArm) {
+    visitor.visit_pattern(&mut arm.pattern);
+    visitor.visit_expr(&mut arm.body);
+}
+
+pub fn walk_pattern_mut<V: VisitorMut>(visitor: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Wildcard(_) => {}
+        Pattern::Literal(expr) => visitor.visit_expr(expr),
+        Pattern::Binding(name) => visitor.visit_identifier(name),
+        Pattern::Variant(enum_name, variant, fields, _) => {
+            visitor.visit_identifier(enum_name);
+            visitor.visit_identifier(variant);
+
+            for field in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::symbol::Symbol;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+
+        assert!(parser.errors().is_empty(), "Parser reported errors: {:?}", parser.errors());
+
+        program
+    }
+
+    #[derive(Default)]
+    struct Names<'ast>(Vec<&'ast str>);
+
+    impl<'ast> Visitor<'ast> for Names<'ast> {
+        fn visit_identifier(&mut self, identifier: &'ast Identifier) {
+            self.0.push(identifier.name.as_str());
+        }
+    }
+
+    #[test]
+    fn test_visits_every_name_in_order() {
+        let program = parse(
+            "import std
+            struct P { x: i32 }
+            enum E { A([P]), B }
+            fn f(p: P): i32 {
+                let q: P = P { x: p.x }
+                match E.B { E.A(y) => 1, z => \"{q}\".len }
+                for let i = 0; i < n; i++ { xs[i] += -k }
+                return g(h)
+            }",
+        );
+
+        let mut names = Names::default();
+        names.visit_program(&program);
+
+        let expected = "std P x i32 E A P B f p P i32 q P P x p x E B E A y z q len i i n i xs i k g h";
+        assert_eq!(names.0.join(" "), expected);
+    }
+
+    /// Doubles every integer literal and stops at function bodies.
+    struct Double;
+
+    impl VisitorMut for Double {
+        fn visit_expr(&mut self, expr: &mut Expr) {
+            if let ExprKind::IntegerLiteral(value) = &mut expr.kind {
+                *value *= 2;
+            }
+
+            walk_expr_mut(self, expr);
+        }
+
+        fn visit_fn_decl(&mut self, _decl: &mut FnDecl) {}
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        let mut program = parse(
+            "let x = [1, 2 + f(3)]\nfn f(n: i32): i32 { n + 4 }\nif x { 4 } else { \"{1}\" }",
+        );
+        let expected = parse(
+            "let x = [2, 4 + f(6)]\nfn f(n: i32): i32 { n + 4 }\nif x { 8 } else { \"{2}\" }",
+        );
+
+        Double.visit_program(&mut program);
+        assert_eq!(program, expected);
+
+        // Blocks can be rewritten as a whole
+        struct Remove(Symbol);
+
+        impl VisitorMut for Remove {
+            fn visit_block(&mut self, block: &mut BlockStmt) {
+                let name = self.0.clone();
+                block.retain(|stmt| !matches!(&stmt.kind, StmtKind::Let(x, _, _) if x.name == name));
+                walk_block_mut(self, block);
+            }
+        }
+
+        let mut program = parse("let a = 1\nwhile true { let a = 2\nlet b = 3 }");
+        Remove(Symbol::intern("a")).visit_program(&mut program);
+        assert_eq!(program.0.len(), 1);
+        assert!(matches!(&program.0[0].kind, StmtKind::While(_, body) if body.len() == 1));
+    }
+}
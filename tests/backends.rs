@@ -0,0 +1,236 @@
+//! Compiles every program in `samples/` and `tests/corpus/` that gets
+//! through the front end with each backend, and checks that the result
+//! prints what the interpreter prints and fails when the interpreter does.
+//! Every such program must lower to a valid module.
+
+use k::ast::Program;
+use k::backend::{BuildError, TempDir};
+use k::{backend_c, backend_wasm, backend_x86};
+use k::builtins;
+use k::compile::{self, Checked};
+use k::evaluator::{self, Evaluator};
+use k::ir::Module;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use wasmi::{Caller, Config, Engine, Extern, Linker, Memory, StackLimits, Store};
+
+const DIRECTORIES: &[&str] = &["samples", "tests/corpus"];
+
+/// The programs without any errors, with their names.
+fn programs() -> Vec<(String, Checked)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<PathBuf> = Vec::new();
+
+    for directory in DIRECTORIES {
+        for entry in fs::read_dir(root.join(directory)).expect("program directory should exist") {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_some_and(|extension| extension == "k") {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            let checked = compile::front_end(&source, true).ok()?;
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            Some((name, checked))
+        })
+        .collect()
+}
+
+/// Whether a program fails, and what it prints, under the interpreter.
+fn interpret(program: Program) -> (bool, String) {
+    // Deeply recursive programs need a bigger stack than the test harness
+    // gives its threads
+    thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(move || {
+            let (result, output) = builtins::capture_output(|| Evaluator::new().run(&program));
+            (result.is_err(), output)
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+/// Runs every program with `run`, which gives whether it failed and what it
+/// printed, comparing the results with the interpreter's.
+fn compare(run: impl Fn(&Module) -> Result<(bool, String), BuildError>) {
+    compare_supported(|_| true, run);
+}
+
+/// Like `compare`, but only for the programs a backend `supports`, giving
+/// their names.
+fn compare_supported(
+    supports: impl Fn(&Module) -> bool,
+    run: impl Fn(&Module) -> Result<(bool, String), BuildError>,
+) -> Vec<String> {
+    let mut compared = Vec::new();
+    let mut failures = Vec::new();
+
+    for (name, checked) in programs() {
+        let module = match compile::lower(&checked, true) {
+            Ok(module) => module,
+            Err(error) => {
+                failures.push(format!("{}: {} errors: {}", name, error.stage(), error.messages().join(", ")));
+                continue;
+            }
+        };
+
+        if !supports(&module) {
+            continue;
+        }
+
+        compared.push(name.clone());
+
+        let actual = match run(&module) {
+            Ok(actual) => actual,
+            Err(error) => {
+                failures.push(format!("{}: {}", name, error));
+                continue;
+            }
+        };
+
+        let expected = interpret(checked.program);
+
+        if actual != expected {
+            failures.push(format!("{}: expected {:?}, got {:?}", name, expected, actual));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    compared
+}
+
+/// Builds an executable with `build` and runs it.
+fn run_executable(
+    module: &Module,
+    build: fn(&Module, &Path) -> Result<(), BuildError>,
+) -> Result<(bool, String), BuildError> {
+    let dir = TempDir::new()?;
+    let output = dir.path().join("program");
+
+    build(module, &output)?;
+
+    let result = Command::new(&output).output()?;
+    Ok((!result.status.success(), String::from_utf8_lossy(&result.stdout).into_owned()))
+}
+
+/// What a WebAssembly module has printed, and the runtime error it stopped
+/// with.
+#[derive(Default)]
+struct Host {
+    output: String,
+    error: Option<String>,
+}
+
+fn memory(caller: &Caller<Host>) -> Memory {
+    caller.get_export("memory").and_then(Extern::into_memory).expect("modules export their memory")
+}
+
+fn read_string(caller: &Caller<Host>, ptr: i32, len: i32) -> String {
+    let bytes = &memory(caller).data(caller)[ptr as usize..(ptr + len) as usize];
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn wasm_error(error: impl fmt::Display) -> BuildError {
+    BuildError::new(error.to_string())
+}
+
+/// Runs a module's WebAssembly with wasmi, with host functions that print to
+/// a string.
+fn run_wasm(module: &Module) -> Result<(bool, String), BuildError> {
+    let binary = backend_wasm::assemble(&backend_wasm::emit(module)?)?;
+    // Recursive programs need more room than wasmi gives them by default
+    let mut config = Config::default();
+    let limits = StackLimits::new(1 << 10, 1 << 24, 1 << 16).unwrap();
+    config.set_stack_limits(limits);
+
+    let engine = Engine::new(&config);
+    let wasm = wasmi::Module::new(&engine, &binary[..]).map_err(wasm_error)?;
+    let mut store = Store::new(&engine, Host::default());
+    let mut linker = Linker::<Host>::new(&engine);
+
+    linker
+        .func_wrap("k", "write", |caller: Caller<Host>, ptr: i32, len: i32| {
+            let text = read_string(&caller, ptr, len);
+            let mut caller = caller;
+            caller.data_mut().output.push_str(&text);
+        })
+        .map_err(wasm_error)?;
+
+    linker
+        .func_wrap("k", "trap", |mut caller: Caller<Host>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+            let message = read_string(&caller, ptr, len);
+            caller.data_mut().error = Some(message.clone());
+            Err(wasmi::Error::new(message))
+        })
+        .map_err(wasm_error)?;
+
+    linker
+        .func_wrap("k", "format_f64", |mut caller: Caller<Host>, value: f64, ptr: i32| -> i32 {
+            let text = format!("{:?}", value);
+            let memory = memory(&caller);
+            let data = memory.data_mut(&mut caller);
+
+            data[ptr as usize..ptr as usize + text.len()].copy_from_slice(text.as_bytes());
+            text.len() as i32
+        })
+        .map_err(wasm_error)?;
+
+    let instance = linker.instantiate(&mut store, &wasm).and_then(|pre| pre.start(&mut store));
+    let run = instance.map_err(wasm_error)?.get_typed_func::<(), ()>(&store, "run").map_err(wasm_error)?;
+
+    match run.call(&mut store, ()) {
+        Ok(()) => Ok((false, store.into_data().output)),
+        Err(_) if store.data().error.is_some() => Ok((true, store.into_data().output)),
+        Err(error) => Err(wasm_error(error)),
+    }
+}
+
+fn has_tool(name: &str) -> bool {
+    Command::new(name).arg("--version").output().is_ok()
+}
+
+#[test]
+fn test_c_backend() {
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    if !has_tool(&cc) {
+        eprintln!("skipping the C backend, since there is no `{}`", cc);
+        return;
+    }
+
+    compare(|module| run_executable(module, backend_c::build));
+}
+
+#[test]
+fn test_wasm_backend() {
+    compare(run_wasm);
+}
+
+#[test]
+fn test_x86_backend() {
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) || !has_tool("as") || !has_tool("ld") {
+        eprintln!("skipping the x86-64 backend, since it can't build executables here");
+        return;
+    }
+
+    // The backend only supports integers, booleans and strings
+    let supports = |module: &Module| backend_x86::emit(module).is_ok();
+    let compared = compare_supported(supports, |module| run_executable(module, backend_x86::build));
+
+    for name in &["arithmetic.k", "fib.k", "hello.k", "runtime_error.k"] {
+        assert!(compared.iter().any(|compared| compared == name), "{} should be supported", name);
+    }
+}
@@ -0,0 +1,498 @@
+//! Grammar conformance. Random programs are generated from the rules in
+//! `docs/grammar.bnf` and both parsers must accept every one of them, so the
+//! grammar can't drift away from what the language really is.
+
+use k::cst_parser;
+use k::lexer::Lexer;
+use k::parser::{self, Parser};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const PROGRAMS: u64 = 1000;
+// Past this many nested rules the generator takes the shortest way out
+const MAX_DEPTH: usize = 16;
+
+// Stand-ins for the rules that are only described in prose
+const SAMPLES: &[(&str, &[&str])] = &[
+    ("unicode_char", &["a", " ", "\t", "é", "😀", "\"", "'", "\\", "{", "}", "#"]),
+    ("any_char", &["a", " ", "\n", "é", "😀", "\"", "'", "\\", "{", "}", "#"]),
+    ("xid_start", &["a", "n", "x", "Z", "_", "é", "π"]),
+    ("xid_continue", &["a", "r", "z", "0", "9", "_", "é", "π"]),
+    ("char_char", &["a", " ", "\"", "{", "}", "é", "😀"]),
+    ("string_char", &["a", " ", "'", "}", "#", "é", "😀", "\n"]),
+    ("raw_char", &["a", " ", "'", "\\", "{", "#", "é", "\n"]),
+    ("multiline_char", &["a", " ", "'", "{", "}", "é", "\n"]),
+    ("scalar_value", &["0", "41", "7f", "E9", "D7FF", "e000", "1F600", "10FFFF"]),
+];
+
+#[derive(Debug)]
+enum Expr {
+    Alt(Vec<Expr>),
+    Seq(Vec<Expr>),
+    Opt(Box<Expr>),
+    Star(Box<Expr>),
+    Plus(Box<Expr>),
+    Text(String),
+    Range(char, char),
+    Ref(String),
+}
+
+impl Expr {
+    /// Whether every way of matching this needs a newline token.
+    fn requires_newline(&self) -> bool {
+        match self {
+            Expr::Ref(name) => name == "newline",
+            Expr::Seq(items) => items.iter().any(Expr::requires_newline),
+            Expr::Alt(alternatives) => alternatives.iter().all(Expr::requires_newline),
+            Expr::Plus(inner) => inner.requires_newline(),
+            _ => false,
+        }
+    }
+}
+
+struct Rule {
+    // `None` for rules described in prose
+    body: Option<Expr>,
+    // Whether the rule matches tokens rather than characters
+    syntactic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Name(String),
+    Ref(String),
+    Text(String),
+    Punct(char),
+    Ellipsis,
+}
+
+fn tokenize(source: &str) -> Vec<(usize, Piece)> {
+    let mut pieces = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        let piece = match ch {
+            '#' => {
+                while chars.next_if(|&(_, ch)| ch != '\n').is_some() {}
+                continue;
+            }
+            '"' => {
+                let mut text = String::new();
+
+                loop {
+                    match chars.next().expect("unterminated string in grammar") {
+                        (_, '"') => break,
+                        (_, '\\') => text.push(chars.next().unwrap().1),
+                        (_, ch) => text.push(ch),
+                    }
+                }
+
+                Piece::Text(text)
+            }
+            '<' => {
+                let mut name = String::new();
+
+                while let Some((_, ch)) = chars.next_if(|&(_, ch)| ch != '>') {
+                    name.push(ch);
+                }
+
+                chars.next();
+                Piece::Ref(name)
+            }
+            '.' => {
+                chars.next();
+                chars.next();
+                Piece::Ellipsis
+            }
+            'U' if chars.peek().is_some_and(|&(_, ch)| ch == '+') => {
+                chars.next();
+
+                let mut digits = String::new();
+
+                while let Some((_, ch)) = chars.next_if(|(_, ch)| ch.is_ascii_hexdigit()) {
+                    digits.push(ch);
+                }
+
+                let code = u32::from_str_radix(&digits, 16).unwrap();
+                Piece::Text(char::from_u32(code).unwrap().to_string())
+            }
+            ch if ch.is_alphanumeric() || ch == '_' => {
+                let mut name = ch.to_string();
+
+                while let Some((_, ch)) = chars.next_if(|(_, ch)| ch.is_alphanumeric() || *ch == '_') {
+                    name.push(ch);
+                }
+
+                Piece::Name(name)
+            }
+            ch if ch.is_whitespace() => continue,
+            ch => Piece::Punct(ch),
+        };
+
+        pieces.push((offset, piece));
+    }
+
+    pieces
+}
+
+struct GrammarParser {
+    pieces: Vec<(usize, Piece)>,
+    index: usize,
+}
+
+impl GrammarParser {
+    fn peek(&self) -> Option<&Piece> {
+        self.pieces.get(self.index).map(|(_, piece)| piece)
+    }
+
+    fn next(&mut self) -> Piece {
+        self.index += 1;
+        self.pieces[self.index - 1].1.clone()
+    }
+
+    fn expect(&mut self, punct: char) {
+        assert_eq!(self.next(), Piece::Punct(punct), "grammar piece {}", self.index);
+    }
+
+    fn rules(&mut self, syntactic_from: usize) -> HashMap<String, Rule> {
+        let mut rules = HashMap::new();
+
+        while self.index < self.pieces.len() {
+            let syntactic = self.pieces[self.index].0 > syntactic_from;
+            let name = match self.next() {
+                Piece::Name(name) => name,
+                piece => panic!("expected a rule name, found {:?}", piece),
+            };
+
+            self.expect('=');
+
+            // A prose rule is followed directly by the next rule's name
+            let prose = match self.pieces.get(self.index..self.index + 2) {
+                None | Some([_]) => true,
+                Some([(_, Piece::Name(_)), (_, next)]) => *next == Piece::Punct('='),
+                _ => false,
+            };
+
+            let body = if prose {
+                None
+            } else {
+                let body = self.alternatives();
+                self.expect(';');
+                Some(body)
+            };
+
+            rules.insert(name, Rule { body, syntactic });
+        }
+
+        rules
+    }
+
+    fn alternatives(&mut self) -> Expr {
+        let mut alternatives = vec![self.sequence()];
+
+        while self.peek() == Some(&Piece::Punct('|')) {
+            self.next();
+            alternatives.push(self.sequence());
+        }
+
+        if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Expr::Alt(alternatives)
+        }
+    }
+
+    fn sequence(&mut self) -> Expr {
+        let mut items = Vec::new();
+
+        while !matches!(self.peek(), Some(Piece::Punct('|' | ')' | ';'))) {
+            let mut item = self.atom();
+
+            loop {
+                item = match self.peek() {
+                    Some(Piece::Punct('?')) => Expr::Opt(Box::new(item)),
+                    Some(Piece::Punct('*')) => Expr::Star(Box::new(item)),
+                    Some(Piece::Punct('+')) => Expr::Plus(Box::new(item)),
+                    _ => break,
+                };
+
+                self.next();
+            }
+
+            items.push(item);
+        }
+
+        Expr::Seq(items)
+    }
+
+    fn atom(&mut self) -> Expr {
+        match self.next() {
+            Piece::Text(text) if self.peek() == Some(&Piece::Ellipsis) => {
+                self.next();
+
+                match self.next() {
+                    Piece::Text(last) => {
+                        Expr::Range(text.chars().next().unwrap(), last.chars().next().unwrap())
+                    }
+                    piece => panic!("expected the end of a range, found {:?}", piece),
+                }
+            }
+            Piece::Text(text) => Expr::Text(text),
+            Piece::Ref(name) => Expr::Ref(name),
+            Piece::Punct('(') => {
+                let group = self.alternatives();
+                self.expect(')');
+                group
+            }
+            piece => panic!("unexpected {:?} in grammar", piece),
+        }
+    }
+}
+
+struct Grammar {
+    rules: HashMap<String, Rule>,
+    // The fewest nested rules needed to finish matching each rule
+    heights: HashMap<String, usize>,
+}
+
+impl Grammar {
+    fn load() -> Grammar {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/grammar.bnf");
+        let source = fs::read_to_string(path).unwrap();
+        let syntactic_from = source
+            .find("# Syntactic grammar")
+            .expect("grammar should have a syntactic section");
+
+        let mut parser = GrammarParser { pieces: tokenize(&source), index: 0 };
+        let rules = parser.rules(syntactic_from);
+        let mut heights: HashMap<String, usize> =
+            rules.keys().map(|name| (name.clone(), usize::MAX)).collect();
+
+        loop {
+            let mut changed = false;
+
+            for (name, rule) in &rules {
+                let height = rule.body.as_ref().map_or(0, |body| Grammar::height(&heights, body));
+
+                if height < heights[name] {
+                    heights.insert(name.clone(), height);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        for (name, height) in &heights {
+            assert_ne!(*height, usize::MAX, "rule {} never finishes", name);
+        }
+
+        Grammar { rules, heights }
+    }
+
+    fn height(heights: &HashMap<String, usize>, expr: &Expr) -> usize {
+        match expr {
+            Expr::Ref(name) => match heights.get(name) {
+                Some(height) => height.saturating_add(1),
+                None => panic!("undefined rule <{}>", name),
+            },
+            Expr::Seq(items) => items.iter().map(|item| Grammar::height(heights, item)).max().unwrap_or(0),
+            Expr::Alt(alternatives) => {
+                alternatives.iter().map(|alternative| Grammar::height(heights, alternative)).min().unwrap()
+            }
+            Expr::Plus(inner) => Grammar::height(heights, inner),
+            _ => 0,
+        }
+    }
+
+    fn alternatives(&self, rule: &str) -> Vec<String> {
+        let alternatives = match &self.rules[rule].body {
+            Some(Expr::Alt(alternatives)) => alternatives,
+            body => panic!("<{}> should be a list of alternatives, found {:?}", rule, body),
+        };
+
+        alternatives
+            .iter()
+            .map(|alternative| match alternative {
+                Expr::Seq(items) => match items.as_slice() {
+                    [Expr::Text(text)] => text.clone(),
+                    _ => panic!("<{}> should only list text", rule),
+                },
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+}
+
+struct Generator<'g> {
+    grammar: &'g Grammar,
+    state: u64,
+    // Newlines are whitespace inside interpolations, so there are no
+    // newline tokens to generate
+    interpolating: bool,
+}
+
+impl<'g> Generator<'g> {
+    fn random(&mut self, bound: usize) -> usize {
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % bound as u64) as usize
+    }
+
+    fn rule(&mut self, name: &str, depth: usize) -> String {
+        let rule = &self.grammar.rules[name];
+
+        let body = match &rule.body {
+            Some(body) => body,
+            None => {
+                let samples = SAMPLES
+                    .iter()
+                    .find(|(sample, _)| *sample == name)
+                    .unwrap_or_else(|| panic!("no samples for <{}>", name))
+                    .1;
+
+                return samples[self.random(samples.len())].to_owned();
+            }
+        };
+
+        let interpolating = self.interpolating;
+        self.interpolating |= name == "interpolation";
+
+        let text = loop {
+            let mut pieces = Vec::new();
+            self.expr(body, depth + 1, &mut pieces);
+
+            let text = if rule.syntactic { pieces.join(" ") } else { pieces.concat() };
+
+            // `_` is a name too, but a pattern takes it for a wildcard
+            if name != "identifier" || !(text == "_" || Lexer::keywords().any(|keyword| keyword == text)) {
+                break text;
+            }
+        };
+
+        self.interpolating = interpolating;
+        text
+    }
+
+    fn allowed(&self, expr: &Expr) -> bool {
+        !self.interpolating || !expr.requires_newline()
+    }
+
+    fn expr(&mut self, expr: &Expr, depth: usize, pieces: &mut Vec<String>) {
+        let shallow = depth >= MAX_DEPTH;
+
+        match expr {
+            Expr::Alt(alternatives) => {
+                let allowed: Vec<&Expr> =
+                    alternatives.iter().filter(|alternative| self.allowed(alternative)).collect();
+
+                let chosen = if shallow {
+                    let heights = &self.grammar.heights;
+                    *allowed.iter().min_by_key(|alternative| Grammar::height(heights, alternative)).unwrap()
+                } else {
+                    allowed[self.random(allowed.len())]
+                };
+
+                self.expr(chosen, depth, pieces);
+            }
+            Expr::Seq(items) => {
+                for item in items {
+                    self.expr(item, depth, pieces);
+                }
+            }
+            Expr::Opt(inner) | Expr::Star(inner) | Expr::Plus(inner) => {
+                let (least, most) = match expr {
+                    Expr::Opt(_) => (0, 1),
+                    Expr::Star(_) => (0, 2),
+                    _ => (1, 3),
+                };
+
+                let count = if shallow || !self.allowed(inner) {
+                    least
+                } else {
+                    least + self.random(most - least + 1)
+                };
+
+                for _ in 0..count {
+                    self.expr(inner, depth, pieces);
+                }
+            }
+            Expr::Text(text) => pieces.push(text.clone()),
+            Expr::Range(first, last) => {
+                let code = *first as usize + self.random(*last as usize - *first as usize + 1);
+                pieces.push(char::from_u32(code as u32).unwrap().to_string());
+            }
+            Expr::Ref(name) => {
+                assert!(self.allowed(expr), "generated a newline inside an interpolation");
+                let text = self.rule(name, depth);
+                pieces.push(text);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_generated_programs_parse() {
+    let grammar = Grammar::load();
+
+    for seed in 1..=PROGRAMS {
+        let mut generator = Generator {
+            grammar: &grammar,
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15),
+            interpolating: false,
+        };
+
+        let source = generator.rule("program", 0);
+
+        let mut parser = Parser::new(Lexer::new(&source));
+        parser.parse();
+        assert_eq!(parser.errors(), &[], "seed {}:\n{}", seed, source);
+
+        let parse = cst_parser::parse(&source);
+        assert_eq!(parse.errors(), &[], "seed {}:\n{}", seed, source);
+        assert_eq!(parse.syntax().text(), source, "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_keywords() {
+    let grammar = Grammar::load();
+    let mut keywords: Vec<String> = Lexer::keywords().map(str::to_owned).collect();
+    let mut documented = grammar.alternatives("keyword");
+
+    keywords.sort();
+    documented.sort();
+
+    assert_eq!(documented, keywords);
+}
+
+#[test]
+fn test_precedence_table() {
+    let grammar = Grammar::load();
+    let table = parser::precedence_table();
+    let source = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/grammar.bnf")).unwrap();
+
+    assert!(source.contains(&table), "docs/grammar.bnf should contain the precedence table:\n{}", table);
+
+    // The operator rules list the same operators as the table
+    let mut binary: Vec<&str> = Vec::new();
+    let mut prefix: Vec<&str> = Vec::new();
+
+    for line in table.lines() {
+        let words: Vec<&str> = line.split_whitespace().skip(2).collect();
+
+        match words.split_last() {
+            Some((&"prefix", operators)) => prefix.extend(operators.iter().copied()),
+            Some((&"postfix", _)) => {}
+            _ => binary.extend(words.iter().copied()),
+        }
+    }
+
+    assert_eq!(grammar.alternatives("binary_op"), binary);
+    assert_eq!(grammar.alternatives("prefix_op"), prefix);
+}
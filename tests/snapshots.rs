@@ -0,0 +1,185 @@
+//! Golden-file tests. Every `.k` file in `samples/` and `tests/corpus/` is
+//! lexed, parsed, resolved, checked, optimized and run, and what each stage
+//! produced is compared with the `.expected` file next to it. Programs are
+//! run both with and without optimizations, which must not change what they
+//! print. Programs that lower to the IR must verify before and after the IR
+//! passes run.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1` to write the `.expected` files instead, then
+//! review the changes to them like any other diff.
+
+use k::ast::Program;
+use k::builtins;
+use k::checker::Checker;
+use k::dump::{program_to_sexp, tokens_to_sexp};
+use k::evaluator::{self, Evaluator};
+use k::compile::{self, Checked, CompileError};
+use k::ir_passes;
+use k::ir_verify;
+use k::resolver::Resolver;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const DIRECTORIES: &[&str] = &["samples", "tests/corpus"];
+
+fn section(snapshot: &mut String, name: &str, body: &str) {
+    snapshot.push_str(&format!("-- {} --\n", name));
+    snapshot.push_str(body);
+
+    if !body.is_empty() && !body.ends_with('\n') {
+        snapshot.push('\n');
+    }
+}
+
+fn lines<T: ToString>(items: &[T]) -> String {
+    items.iter().map(|item| item.to_string() + "\n").collect()
+}
+
+/// Ends a snapshot with the errors of the stage that failed.
+fn failed(mut snapshot: String, error: CompileError) -> String {
+    section(&mut snapshot, &format!("{} errors", error.stage()), &lines(&error.messages()));
+    snapshot
+}
+
+/// What each stage makes of `source`, stopping at the first that fails.
+fn snapshot(source: &str) -> String {
+    let mut snapshot = String::new();
+
+    section(&mut snapshot, "tokens", &tokens_to_sexp(source));
+
+    let mut program = match compile::parse(source) {
+        Ok(program) => program,
+        Err(error) => return failed(snapshot, error),
+    };
+
+    section(&mut snapshot, "ast", &program_to_sexp(&program));
+
+    let mut checker = Checker::new();
+
+    let ty = match compile::check(&mut Resolver::new(), &mut checker, &mut program) {
+        Ok(ty) => ty,
+        Err(error) => return failed(snapshot, error),
+    };
+
+    section(&mut snapshot, "type", &ty.to_string());
+
+    let mut optimized = program.clone();
+
+    if let Err(error) = compile::fold(&mut optimized) {
+        return failed(snapshot, error);
+    }
+
+    if optimized != program {
+        section(&mut snapshot, "optimized ast", &program_to_sexp(&optimized));
+    }
+
+    let (result, output) = run(&optimized);
+
+    section(&mut snapshot, "output", &output);
+
+    if let Some(error) = &result {
+        section(&mut snapshot, "runtime error", error);
+    }
+
+    assert_eq!(run(&program), (result, output), "optimizing changed what the program does");
+
+    let checked = Checked { program: optimized, ty, types: checker.binding_types().clone() };
+
+    match compile::lower(&checked, false) {
+        Ok(mut module) => {
+            ir_passes::optimize(&mut module);
+            assert_eq!(ir_verify::verify(&module), Ok(()), "the IR passes gave an invalid module:\n{}", module);
+        }
+        Err(error @ CompileError::Verify(_)) => panic!("lowering gave an invalid module: {:?}", error.messages()),
+        Err(_) => {}
+    }
+
+    snapshot
+}
+
+/// Runs a program, returning its runtime error if it has one and what it
+/// printed.
+fn run(program: &Program) -> (Option<String>, String) {
+    let (result, output) = builtins::capture_output(|| Evaluator::new().run(program));
+    (result.err().map(|error| error.to_string()), output)
+}
+
+fn sources() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = Vec::new();
+
+    for directory in DIRECTORIES {
+        for entry in fs::read_dir(root.join(directory)).expect("snapshot directory should exist") {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_some_and(|extension| extension == "k") {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths.sort();
+    paths
+}
+
+/// Describes where `actual` first differs from `expected`.
+fn difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(a), Some(b)) if a == b => line += 1,
+            (a, b) => {
+                return format!(
+                    "line {}:\n  expected: {}\n  actual:   {}",
+                    line,
+                    a.unwrap_or("<end of file>"),
+                    b.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn test_snapshots() {
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    // Deeply recursive programs need a bigger stack than the test harness
+    // gives its threads
+    let failures = thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(move || {
+            let mut failures = Vec::new();
+
+            for path in sources() {
+                let source = fs::read_to_string(&path).unwrap();
+                let actual = snapshot(&source);
+                let expected_path = path.with_extension("expected");
+
+                match fs::read_to_string(&expected_path) {
+                    Ok(expected) if expected == actual => {}
+                    _ if update => fs::write(&expected_path, &actual).unwrap(),
+                    Ok(expected) => {
+                        failures.push(format!("{}: {}", path.display(), difference(&expected, &actual)))
+                    }
+                    Err(_) => failures.push(format!("{}: no {}", path.display(), expected_path.display())),
+                }
+            }
+
+            failures
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nrun with UPDATE_SNAPSHOTS=1 to accept the new output",
+        failures.join("\n")
+    );
+}